@@ -15,7 +15,7 @@ typedef struct UsmHandle UsmHandle;
 typedef struct {
     char *id;
     char *template_id;
-    char *display_name;
+    char *display_name;  // instance display_name, else the template's, else id
     uint16_t port;
     int32_t status;      // 0=stopped, 1=running, 2=error, 3=starting, 4=stopping, 5=unknown
     double cpu_percent;
@@ -29,6 +29,16 @@ typedef struct {
     size_t capacity;
 } CServiceArray;
 
+// Metrics for a single instance
+typedef struct {
+    double cpu_percent;
+    uint64_t memory_bytes;
+    double memory_percent;
+    uint32_t threads;
+    uint32_t open_files;
+    uint64_t uptime_seconds;
+} CInstanceMetrics;
+
 // Status codes
 #define USM_STATUS_STOPPED  0
 #define USM_STATUS_RUNNING  1
@@ -37,28 +47,58 @@ typedef struct {
 #define USM_STATUS_STOPPING 4
 #define USM_STATUS_UNKNOWN  5
 
+// Result codes for the service control functions
+#define USM_OK                  0
+#define USM_ERR_NOT_FOUND       1
+#define USM_ERR_ALREADY_RUNNING 2
+#define USM_ERR_START_FAILED    3
+#define USM_ERR_INVALID_HANDLE  4
+#define USM_ERR_INVALID_UTF8    5
+#define USM_ERR_STOP_FAILED     6
+#define USM_ERR_NULL_ARGUMENT   7
+
+// Shutdown policies for usm_destroy_with_shutdown
+#define USM_SHUTDOWN_LEAVE          0
+#define USM_SHUTDOWN_STOP_ALL       1
+#define USM_SHUTDOWN_STOP_EPHEMERAL 2
+
 // Lifecycle functions
 UsmHandle* usm_create(const char* config_path);
 void usm_destroy(UsmHandle* handle);
+void usm_destroy_with_shutdown(UsmHandle* handle, int policy);
 
 // Service query functions
 // Memory ownership: usm_get_services allocates a CServiceArray and all embedded
 // C strings (id, template_id, display_name). The caller takes ownership and MUST
 // call usm_free_services to release all memory. Do not free individual strings
-// after calling usm_free_services.
+// after calling usm_free_services. NUL bytes inside a string are replaced
+// with U+FFFD rather than truncating or blanking it.
 CServiceArray* usm_get_services(const UsmHandle* handle);
 
 // Free a CServiceArray and all embedded strings. After this call, the array
 // pointer and all string pointers within it are invalid.
 void usm_free_services(CServiceArray* array);
 
-// Service control functions (return 0 on success, -1 on error)
+// Metrics for one instance, or NULL if it isn't found or isn't running.
+// The caller owns the result and MUST release it with usm_free_instance_metrics.
+CInstanceMetrics* usm_get_instance_metrics(const UsmHandle* handle, const char* instance_id);
+void usm_free_instance_metrics(CInstanceMetrics* metrics);
+
+// Service control functions (return USM_OK on success, otherwise an error
+// code; usm_last_error_message describes the error)
 int32_t usm_start_service(UsmHandle* handle, const char* instance_id);
 int32_t usm_stop_service(UsmHandle* handle, const char* instance_id);
 int32_t usm_restart_service(UsmHandle* handle, const char* instance_id);
 
+// Description of the last control function error on the calling thread
+// ("" after success). Valid until the next control call on that thread.
+const char* usm_last_error_message(void);
+
 // Utility functions
 uint16_t usm_get_server_port(void);
+// Both strings live for the whole process; don't free them
 const char* usm_version(void);
+// JSON: {"version": ..., "git_commit": ..., "build_timestamp": ...}
+const char* usm_build_info(void);
 
 #endif // USM_FFI_H
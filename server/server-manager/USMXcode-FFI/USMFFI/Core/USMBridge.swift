@@ -88,7 +88,7 @@ final class USMBridge {
                 print("[USMBridge] Started service: \(instanceId)")
                 return true
             } else {
-                print("[USMBridge] Failed to start service: \(instanceId)")
+                print("[USMBridge] Failed to start service: \(instanceId) (\(result)): \(Self.lastErrorMessage)")
                 return false
             }
         }
@@ -109,7 +109,7 @@ final class USMBridge {
                 print("[USMBridge] Stopped service: \(instanceId)")
                 return true
             } else {
-                print("[USMBridge] Failed to stop service: \(instanceId)")
+                print("[USMBridge] Failed to stop service: \(instanceId) (\(result)): \(Self.lastErrorMessage)")
                 return false
             }
         }
@@ -130,12 +130,20 @@ final class USMBridge {
                 print("[USMBridge] Restarted service: \(instanceId)")
                 return true
             } else {
-                print("[USMBridge] Failed to restart service: \(instanceId)")
+                print("[USMBridge] Failed to restart service: \(instanceId) (\(result)): \(Self.lastErrorMessage)")
                 return false
             }
         }
     }
 
+    /// Description of the last failed control call on this thread
+    static var lastErrorMessage: String {
+        guard let messagePtr = usm_last_error_message() else {
+            return ""
+        }
+        return String(cString: messagePtr)
+    }
+
     /// Get the USM Core server port
     static var serverPort: Int {
         Int(usm_get_server_port())
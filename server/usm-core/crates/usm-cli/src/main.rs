@@ -2,30 +2,59 @@
 //!
 //! A CLI tool for managing services through USM Core.
 
+mod watch;
+
+use std::collections::HashMap;
+use std::net::IpAddr;
 use std::path::PathBuf;
 
-use clap::{Parser, Subcommand};
+use clap::{Parser, Subcommand, ValueEnum};
 use tracing::info;
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
 
-use usm_core::{InstanceConfig, ServiceStatus, UsmCore};
+use usm_core::config::ConfigManager;
+use usm_core::{
+    ForcedStop, InstanceConfig, InstanceFilter, ServiceCategory, ServiceInstance, ServiceStatus,
+    TagMatch, UsmCore,
+};
 
 #[derive(Parser)]
 #[command(name = "usm")]
 #[command(author, version, about = "USM Core - Universal Service Manager", long_about = None)]
 struct Cli {
     /// Path to the configuration file
-    #[arg(short, long, default_value = "config/services.toml")]
+    #[arg(short, long, global = true, default_value = "config/services.toml")]
     config: PathBuf,
 
     /// Enable verbose output
     #[arg(short, long)]
     verbose: bool,
 
+    /// Profile to apply (overrides `[defaults] profile` in the config)
+    #[arg(long, global = true)]
+    profile: Option<String>,
+
+    /// Directory to capture instance output to (overrides `[logs] dir`)
+    #[arg(long, global = true)]
+    log_dir: Option<PathBuf>,
+
+    /// Output format for `templates`, `instances` and `metrics`
+    #[arg(short, long, global = true, value_enum, default_value_t = OutputFormat::Table)]
+    output: OutputFormat,
+
     #[command(subcommand)]
     command: Commands,
 }
 
+/// How listing commands print their results
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum OutputFormat {
+    /// Fixed-width tables for reading
+    Table,
+    /// JSON on stdout for scripts
+    Json,
+}
+
 #[derive(Subcommand)]
 enum Commands {
     /// Start the USM Core server
@@ -33,36 +62,87 @@ enum Commands {
         /// Port to listen on
         #[arg(short, long, default_value = "8767")]
         port: u16,
+
+        /// Address to listen on (overrides `[server] bind_addr`, default
+        /// 127.0.0.1; 0.0.0.0 for every interface)
+        #[arg(long)]
+        bind: Option<IpAddr>,
+
+        /// Require this bearer token on the API and WebSocket routes
+        /// (overrides `[server] auth_token`; also read from USM_AUTH_TOKEN)
+        #[arg(long)]
+        auth_token: Option<String>,
     },
 
     /// List all templates
-    Templates,
+    Templates {
+        /// Only templates in this category (core, development, database,
+        /// infrastructure, custom)
+        #[arg(long)]
+        category: Option<ServiceCategory>,
+    },
 
     /// List all instances
     Instances {
+        /// Only instances in this group
+        #[arg(short, long)]
+        group: Option<String>,
+
         /// Filter by template ID
         #[arg(short, long)]
         template: Option<String>,
 
-        /// Filter by tag
-        #[arg(long)]
-        tag: Option<String>,
+        /// Filter by tag (repeatable or comma-separated)
+        #[arg(long, value_delimiter = ',')]
+        tag: Vec<String>,
+
+        /// How several tags combine: any (default) or all
+        #[arg(long, default_value = "any")]
+        tag_mode: TagMatch,
 
         /// Filter by status (running, stopped, error)
         #[arg(short, long)]
         status: Option<String>,
     },
 
-    /// Start a service instance
+    /// Find instances whose id, template or tags contain a string
+    Find {
+        /// Text to look for (case-insensitive)
+        query: String,
+    },
+
+    /// Check each instance's stored PID against the running processes
+    Doctor,
+
+    /// Show details for one instance, including notes, labels and metadata
+    Status {
+        /// Instance ID to show
+        instance_id: String,
+    },
+
+    /// Start a service instance (after its dependencies are healthy)
     Start {
         /// Instance ID to start
         instance_id: String,
+
+        /// Seconds to wait for each dependency to become healthy
+        #[arg(long, default_value_t = 60)]
+        timeout: u64,
+
+        /// Print the command, environment and working directory that would
+        /// be used, without starting anything
+        #[arg(long)]
+        dry_run: bool,
     },
 
     /// Stop a service instance
     Stop {
         /// Instance ID to stop
         instance_id: String,
+
+        /// Print what would be run or signalled, without stopping anything
+        #[arg(long)]
+        dry_run: bool,
     },
 
     /// Restart a service instance
@@ -71,6 +151,35 @@ enum Commands {
         instance_id: String,
     },
 
+    /// Ask a running instance to reload its config (its template's
+    /// reload_signal, SIGHUP by default)
+    Reload {
+        /// Instance ID to reload
+        instance_id: String,
+    },
+
+    /// Move an instance to a different port (takes effect on next start)
+    SetPort {
+        /// Instance ID to move
+        instance_id: String,
+
+        /// New port
+        port: u16,
+    },
+
+    /// Send a signal (e.g. SIGUSR1) to a running instance
+    Signal {
+        /// Instance ID to signal
+        instance_id: String,
+
+        /// Signal name (SIGUSR1, USR2, HUP, ...)
+        signal: String,
+
+        /// Signal the instance's whole process group
+        #[arg(long)]
+        group: bool,
+    },
+
     /// Show system and instance metrics
     Metrics {
         /// Instance ID (optional, shows system metrics if not specified)
@@ -87,7 +196,11 @@ enum Commands {
         #[arg(short, long)]
         id: Option<String>,
 
-        /// Port to use (uses template default if not specified)
+        /// Group to create the instance in (its ID becomes group/id)
+        #[arg(short, long)]
+        group: Option<String>,
+
+        /// Port to use (next free port in the template range if not specified)
         #[arg(short, long)]
         port: Option<u16>,
 
@@ -98,6 +211,52 @@ enum Commands {
         /// Auto-start the instance
         #[arg(long)]
         auto_start: bool,
+
+        /// Human-readable name shown in UIs (defaults to the template's name)
+        #[arg(long)]
+        name: Option<String>,
+
+        /// Operator notes
+        #[arg(long)]
+        notes: Option<String>,
+
+        /// Label as key=value (repeatable)
+        #[arg(long = "label", value_parser = parse_label)]
+        labels: Vec<(String, String)>,
+    },
+
+    /// Clone an existing instance
+    Clone {
+        /// Instance ID to clone
+        source_id: String,
+
+        /// Instance ID for the clone (auto-generated if not specified)
+        #[arg(short, long)]
+        id: Option<String>,
+
+        /// Group for the clone (the source's if not specified)
+        #[arg(short, long)]
+        group: Option<String>,
+
+        /// Port to use (next free port in the template range if not specified)
+        #[arg(short, long)]
+        port: Option<u16>,
+
+        /// Tags (comma-separated, added to the source's unless --no-inherit)
+        #[arg(long)]
+        tags: Option<String>,
+
+        /// Version identifier for the clone
+        #[arg(long = "version")]
+        version: Option<String>,
+
+        /// Working directory (the source's if not specified)
+        #[arg(long)]
+        working_dir: Option<PathBuf>,
+
+        /// Don't copy tags and environment from the source
+        #[arg(long)]
+        no_inherit: bool,
     },
 
     /// Remove an instance
@@ -105,23 +264,86 @@ enum Commands {
         /// Instance ID to remove
         instance_id: String,
 
-        /// Force removal even if running
+        /// Remove even if stopping the running instance fails, killing
+        /// its process
         #[arg(short, long)]
         force: bool,
+
+        /// Don't ask for confirmation before a forced removal
+        #[arg(short, long)]
+        yes: bool,
     },
 
     /// Start all instances matching criteria
     StartAll {
-        /// Filter by tag
-        #[arg(long)]
-        tag: Option<String>,
+        /// Only instances in this group
+        #[arg(short, long)]
+        group: Option<String>,
+
+        /// Filter by template ID
+        #[arg(short, long)]
+        template: Option<String>,
+
+        /// Filter by tag (repeatable or comma-separated)
+        #[arg(long, value_delimiter = ',')]
+        tag: Vec<String>,
+
+        /// How several tags combine: any (default) or all
+        #[arg(long, default_value = "any")]
+        tag_mode: TagMatch,
     },
 
     /// Stop all instances matching criteria
     StopAll {
-        /// Filter by tag
+        /// Only instances in this group
+        #[arg(short, long)]
+        group: Option<String>,
+
+        /// Filter by template ID
+        #[arg(short, long)]
+        template: Option<String>,
+
+        /// Filter by tag (repeatable or comma-separated)
+        #[arg(long, value_delimiter = ',')]
+        tag: Vec<String>,
+
+        /// How several tags combine: any (default) or all
+        #[arg(long, default_value = "any")]
+        tag_mode: TagMatch,
+
+        /// Don't ask for confirmation before stopping
+        #[arg(short, long)]
+        yes: bool,
+    },
+
+    /// Check the config file and report every problem, without creating or
+    /// starting anything (exits non-zero if it is invalid)
+    Validate,
+
+    /// Print every template and instance as JSON, e.g. `usm export > backup.json`
+    Export,
+
+    /// Add the templates and instances from a `usm export` file (existing
+    /// ids and taken ports are reported as conflicts and skipped)
+    Import {
+        /// JSON file written by `usm export`
+        file: PathBuf,
+    },
+
+    /// Print live events from a running server
+    Watch {
+        /// WebSocket endpoint of the server
+        #[arg(long, default_value = "ws://127.0.0.1:8767/ws")]
+        url: String,
+
+        /// Only show events for this instance
+        #[arg(short, long)]
+        filter: Option<String>,
+
+        /// Bearer token, if the server requires one (also read from
+        /// USM_AUTH_TOKEN)
         #[arg(long)]
-        tag: Option<String>,
+        token: Option<String>,
     },
 }
 
@@ -129,37 +351,79 @@ enum Commands {
 async fn main() -> anyhow::Result<()> {
     let cli = Cli::parse();
 
-    // Initialize logging
+    // Initialize logging (on stderr, so stdout stays parseable with `-o json`)
     let filter = if cli.verbose { "debug" } else { "info" };
     tracing_subscriber::registry()
-        .with(tracing_subscriber::fmt::layer())
+        .with(tracing_subscriber::fmt::layer().with_writer(std::io::stderr))
         .with(tracing_subscriber::EnvFilter::new(filter))
         .init();
 
+    // Refuse to serve on top of a broken config: every operation would fail.
+    // A missing file is fine, a default one is created on load.
+    if matches!(cli.command, Commands::Server { .. }) && cli.config.exists() {
+        ConfigManager::validate_file(&cli.config)?;
+    }
+
+    if let Commands::Validate = cli.command {
+        ConfigManager::validate_file(&cli.config)?;
+        println!("Config '{}' is valid.", cli.config.display());
+        return Ok(());
+    }
+
+    // Watching talks to a running server, not to the config
+    if let Commands::Watch { url, filter, token } = &cli.command {
+        let token = token.clone().or_else(env_auth_token);
+        return watch::run(url, filter.as_deref(), token.as_deref()).await;
+    }
+
     // Load USM Core
-    let core = UsmCore::new(&cli.config).await?;
+    let core = UsmCore::builder()
+        .config_path(&cli.config)
+        .profile(cli.profile.as_deref())
+        .log_dir(cli.log_dir.as_ref())
+        .build()
+        .await?;
 
     match cli.command {
-        Commands::Server { port } => {
+        Commands::Server {
+            port,
+            bind,
+            auth_token,
+        } => {
+            let outcomes = core.start_autostart_instances().await;
+            if !outcomes.is_empty() {
+                println!("Auto-start:");
+                for (id, outcome) in &outcomes {
+                    println!("  {:<30} {}", id, outcome);
+                }
+            }
+
             info!(port = port, "Starting USM Core server");
-            core.start_server(port).await?;
+            core.start_server(port, bind, auth_token.or_else(env_auth_token))
+                .await?;
         },
 
-        Commands::Templates => {
-            let templates = core.list_templates().await;
-            if templates.is_empty() {
+        Commands::Templates { category } => {
+            let templates = match category {
+                Some(category) => core.list_templates_by_category(category).await,
+                None => core.list_templates().await,
+            };
+            if cli.output == OutputFormat::Json {
+                print_json(serde_json::to_value(&templates)?)?;
+            } else if templates.is_empty() {
                 println!("No templates registered.");
             } else {
                 println!(
-                    "{:<20} {:<30} {:<10} {:<10}",
-                    "ID", "Name", "Port", "Multiple"
+                    "{:<20} {:<30} {:<15} {:<10} {:<10}",
+                    "ID", "Name", "Category", "Port", "Multiple"
                 );
-                println!("{}", "-".repeat(70));
+                println!("{}", "-".repeat(85));
                 for t in templates {
                     println!(
-                        "{:<20} {:<30} {:<10} {:<10}",
+                        "{:<20} {:<30} {:<15} {:<10} {:<10}",
                         t.id,
                         t.display_name,
+                        t.category.to_string(),
                         t.default_port,
                         if t.supports_multiple { "Yes" } else { "No" }
                     );
@@ -168,69 +432,191 @@ async fn main() -> anyhow::Result<()> {
         },
 
         Commands::Instances {
+            group,
             template,
             tag,
+            tag_mode,
             status,
         } => {
-            let instances = core.list_instances(template.as_deref()).await;
+            let filter = InstanceFilter {
+                group,
+                template,
+                tags: tag,
+                tag_match: tag_mode,
+                status: status.as_deref().map(str::parse).transpose()?,
+            };
+            let filtered = core.query_instances(&filter).await;
+            print_instances(&filtered, cli.output)?;
+        },
+
+        Commands::Find { query } => {
+            let found = core.search_instances(&query).await;
+            print_instances(&found, cli.output)?;
+        },
 
-            let filtered: Vec<_> = instances
+        Commands::Doctor => {
+            let statuses: HashMap<String, ServiceStatus> = core
+                .list_instances(None)
+                .await
                 .into_iter()
-                .filter(|i| {
-                    if let Some(ref t) = tag {
-                        if !i.has_tag(t) {
-                            return false;
-                        }
-                    }
-                    if let Some(ref s) = status {
-                        let status_match = match s.as_str() {
-                            "running" => i.status == ServiceStatus::Running,
-                            "stopped" => i.status == ServiceStatus::Stopped,
-                            "error" => i.status == ServiceStatus::Error,
-                            _ => true,
-                        };
-                        if !status_match {
-                            return false;
-                        }
-                    }
-                    true
+                .map(|i| (i.id, i.status))
+                .collect();
+            let report: Vec<_> = core
+                .reconcile()
+                .await
+                .into_iter()
+                .map(|(id, pid, alive)| {
+                    let status = statuses.get(&id).copied().unwrap_or_default();
+                    let drift = status == ServiceStatus::Running && pid.is_some() && !alive;
+                    (id, status, pid, alive, drift)
                 })
                 .collect();
 
-            if filtered.is_empty() {
+            if cli.output == OutputFormat::Json {
+                let entries: Vec<_> = report
+                    .iter()
+                    .map(|(id, status, pid, alive, drift)| {
+                        serde_json::json!({
+                            "instance_id": id,
+                            "status": status,
+                            "pid": pid,
+                            "alive": alive,
+                            "drift": drift
+                        })
+                    })
+                    .collect();
+                print_json(serde_json::Value::Array(entries))?;
+            } else if report.is_empty() {
                 println!("No instances found.");
             } else {
-                println!(
-                    "{:<25} {:<20} {:<8} {:<10} {:<20}",
-                    "ID", "Template", "Port", "Status", "Tags"
-                );
-                println!("{}", "-".repeat(85));
-                for i in filtered {
-                    let status = match i.status {
-                        ServiceStatus::Running => "Running",
-                        ServiceStatus::Stopped => "Stopped",
-                        ServiceStatus::Error => "Error",
-                        _ => "Unknown",
-                    };
+                println!("{:<25} {:<10} {:<8} {:<6}", "ID", "Status", "PID", "Alive");
+                println!("{}", "-".repeat(52));
+                for (id, status, pid, alive, drift) in &report {
+                    let pid = pid.map_or_else(|| "-".to_string(), |p| p.to_string());
+                    let alive = if *alive { "yes" } else { "no" };
+                    let note = if *drift { "  <- process is gone" } else { "" };
+                    println!(
+                        "{:<25} {:<10} {:<8} {:<6}{}",
+                        id,
+                        status.to_string(),
+                        pid,
+                        alive,
+                        note
+                    );
+                }
+                let drifted = report.iter().filter(|r| r.4).count();
+                if drifted > 0 {
                     println!(
-                        "{:<25} {:<20} {:<8} {:<10} {:<20}",
-                        i.id,
-                        i.template_id,
-                        i.port,
-                        status,
-                        i.tags.join(", ")
+                        "\n{} instance(s) shown as running have no live process",
+                        drifted
                     );
                 }
             }
         },
 
-        Commands::Start { instance_id } => {
+        Commands::Status { instance_id } => {
+            let i = core
+                .get_instance(&instance_id)
+                .await
+                .ok_or_else(|| anyhow::anyhow!("Instance '{}' not found", instance_id))?;
+
+            println!("Instance: {}", i.id);
+            println!("  Name: {}", core.display_name(&i).await);
+            println!("  Template: {}", i.template_id);
+            println!("  Port: {}", i.port);
+            println!("  Status: {}", i.status);
+            if let Some(pid) = i.pid {
+                println!("  PID: {}", pid);
+            }
+            if !i.tags.is_empty() {
+                println!("  Tags: {}", i.tags.join(", "));
+            }
+            if !i.labels.is_empty() {
+                let mut labels: Vec<_> = i.labels.iter().collect();
+                labels.sort();
+                println!("  Labels:");
+                for (key, value) in labels {
+                    println!("    {}={}", key, value);
+                }
+            }
+            if !i.metadata.is_empty() {
+                let mut metadata: Vec<_> = i.metadata.iter().collect();
+                metadata.sort();
+                println!("  Metadata:");
+                for (key, value) in metadata {
+                    println!("    {}={}", key, value);
+                }
+            }
+            if let Some(notes) = &i.notes {
+                println!("  Notes: {}", notes);
+            }
+            if let Some(schedule) = &i.schedule {
+                println!("  Schedule:");
+                for (label, expr) in [("start", &schedule.start), ("stop", &schedule.stop)] {
+                    if let Some(expr) = expr {
+                        println!("    {}: {}", label, expr);
+                    }
+                }
+                if let Some(tz) = &schedule.timezone {
+                    println!("    timezone: {}", tz);
+                }
+            }
+        },
+
+        Commands::Start {
+            instance_id,
+            dry_run: true,
+            ..
+        } => {
+            let plan = core.dry_run_start(&instance_id).await?;
+            if cli.output == OutputFormat::Json {
+                print_json(serde_json::to_value(&plan)?)?;
+            } else {
+                println!("Would run: {}", plan.command);
+                match &plan.working_dir {
+                    Some(dir) => println!("  Working dir: {}", dir.display()),
+                    None => println!("  Working dir: (inherited)"),
+                }
+                for (key, value) in &plan.env {
+                    println!("  {}={}", key, value);
+                }
+            }
+        },
+
+        Commands::Start {
+            instance_id,
+            timeout,
+            dry_run: false,
+        } => {
             info!(instance = %instance_id, "Starting instance");
-            core.start_instance(&instance_id).await?;
+            core.start_with_dependencies(&instance_id, std::time::Duration::from_secs(timeout))
+                .await?;
             println!("Started instance: {}", instance_id);
         },
 
-        Commands::Stop { instance_id } => {
+        Commands::Stop {
+            instance_id,
+            dry_run: true,
+        } => {
+            let plan = core.dry_run_stop(&instance_id).await?;
+            if cli.output == OutputFormat::Json {
+                print_json(serde_json::to_value(&plan)?)?;
+            } else {
+                match (&plan.command, plan.pid) {
+                    (Some(command), _) => println!("Would run: {}", command),
+                    (None, Some(pid)) => println!(
+                        "Would send SIGTERM to PID {} (SIGKILL after {}ms)",
+                        pid, plan.stop_timeout_ms
+                    ),
+                    (None, None) => println!("Nothing to stop: instance has no PID"),
+                }
+            }
+        },
+
+        Commands::Stop {
+            instance_id,
+            dry_run: false,
+        } => {
             info!(instance = %instance_id, "Stopping instance");
             core.stop_instance(&instance_id).await?;
             println!("Stopped instance: {}", instance_id);
@@ -242,8 +628,51 @@ async fn main() -> anyhow::Result<()> {
             println!("Restarted instance: {}", instance_id);
         },
 
+        Commands::Reload { instance_id } => {
+            info!(instance = %instance_id, "Reloading instance");
+            let signal = core.reload_instance(&instance_id).await?;
+            println!("Sent {} to instance: {}", signal, instance_id);
+        },
+
+        Commands::SetPort { instance_id, port } => {
+            info!(instance = %instance_id, port, "Reassigning instance port");
+            let old_port = core.reassign_port(&instance_id, port).await?;
+            if old_port == port {
+                println!("Instance {} already uses port {}", instance_id, port);
+            } else {
+                println!(
+                    "Moved instance {} from port {} to {}",
+                    instance_id, old_port, port
+                );
+                let running = core
+                    .get_instance(&instance_id)
+                    .await
+                    .is_some_and(|i| i.status == ServiceStatus::Running);
+                if running {
+                    println!("Restart the instance to apply the new port");
+                }
+            }
+        },
+
+        Commands::Signal {
+            instance_id,
+            signal,
+            group,
+        } => {
+            info!(instance = %instance_id, signal = %signal, "Signaling instance");
+            core.signal_instance(&instance_id, &signal, group).await?;
+            println!("Sent {} to instance: {}", signal, instance_id);
+        },
+
         Commands::Metrics { instance_id } => {
-            if let Some(id) = instance_id {
+            if cli.output == OutputFormat::Json {
+                // An instance without metrics prints `null`
+                let value = match instance_id {
+                    Some(id) => serde_json::to_value(core.get_instance_metrics(&id).await)?,
+                    None => serde_json::to_value(core.get_system_metrics())?,
+                };
+                print_json(value)?;
+            } else if let Some(id) = instance_id {
                 if let Some(metrics) = core.get_instance_metrics(&id).await {
                     println!("Instance: {}", id);
                     println!("  CPU: {:.1}%", metrics.cpu_percent);
@@ -262,15 +691,21 @@ async fn main() -> anyhow::Result<()> {
                     metrics.memory_total_gb(),
                     metrics.memory_percent
                 );
+                let (one, five, fifteen) = metrics.load_average_tuple();
+                println!("  Load: {:.2} {:.2} {:.2} (1m 5m 15m)", one, five, fifteen);
             }
         },
 
         Commands::Create {
             template,
             id,
+            group,
             port,
             tags,
             auto_start,
+            name,
+            notes,
+            labels,
         } => {
             let instance_id =
                 id.unwrap_or_else(|| format!("{}-{}", template, chrono::Utc::now().timestamp()));
@@ -281,6 +716,7 @@ async fn main() -> anyhow::Result<()> {
 
             let config = InstanceConfig {
                 instance_id: instance_id.clone(),
+                group,
                 template_id: template,
                 port,
                 working_dir: None,
@@ -290,37 +726,328 @@ async fn main() -> anyhow::Result<()> {
                 tags: tag_vec,
                 auto_start,
                 env_vars: Default::default(),
+                display_name: name,
+                notes,
+                labels: labels.into_iter().collect(),
+                metadata: Default::default(),
+                depends_on: Vec::new(),
+                schedule: None,
+                restart: None,
+                start_command_override: None,
+                stop_command_override: None,
+                cpu_limit_percent: None,
+                memory_limit_mb: None,
+                limit_action: None,
             };
 
             let created_id = core.create_instance(config).await?;
             println!("Created instance: {}", created_id);
         },
 
-        Commands::Remove { instance_id, force } => {
+        Commands::Clone {
+            source_id,
+            id,
+            group,
+            port,
+            tags,
+            version,
+            working_dir,
+            no_inherit,
+        } => {
+            // Generated names drop the source's group, which the clone joins
+            let instance_id = id.unwrap_or_else(|| {
+                let name = source_id.rsplit('/').next().unwrap_or(&source_id);
+                format!("{}-{}", name, chrono::Utc::now().timestamp())
+            });
+
+            let tag_vec: Vec<String> = tags
+                .map(|t| t.split(',').map(|s| s.trim().to_string()).collect())
+                .unwrap_or_default();
+
+            let config = InstanceConfig {
+                instance_id,
+                group,
+                template_id: String::new(), // inherited from the source
+                port,
+                working_dir,
+                config_path: None,
+                version,
+                git_branch: None,
+                tags: tag_vec,
+                auto_start: false,
+                env_vars: Default::default(),
+                display_name: None,
+                notes: None,
+                labels: Default::default(),
+                metadata: Default::default(),
+                depends_on: Vec::new(),
+                schedule: None,
+                restart: None,
+                start_command_override: None,
+                stop_command_override: None,
+                cpu_limit_percent: None,
+                memory_limit_mb: None,
+                limit_action: None,
+            };
+
+            let created_id = core.clone_instance(&source_id, config, !no_inherit).await?;
+            println!("Cloned {} as {}", source_id, created_id);
+        },
+
+        Commands::Remove {
+            instance_id,
+            force,
+            yes,
+        } => {
+            if force && !yes {
+                let instance = core
+                    .get_instance(&instance_id)
+                    .await
+                    .ok_or_else(|| anyhow::anyhow!("Instance '{}' not found", instance_id))?;
+                print_instances(std::slice::from_ref(&instance), OutputFormat::Table)?;
+                if !confirm("Remove this instance even if it can't be stopped cleanly?")? {
+                    anyhow::bail!("Aborted");
+                }
+            }
             if force {
-                // Stop first if running
-                let _ = core.stop_instance(&instance_id).await;
+                match core.force_remove_instance(&instance_id).await? {
+                    ForcedStop::Stopped => {},
+                    ForcedStop::Killed { pid, error } => {
+                        eprintln!("Stopping failed ({}), killed process {}", error, pid)
+                    },
+                    ForcedStop::Failed(error) => {
+                        eprintln!(
+                            "Warning: could not stop the service, it may still be running: {}",
+                            error
+                        )
+                    },
+                }
+            } else {
+                // Keeps the instance if it can't be stopped
+                core.remove_instance(&instance_id).await?;
             }
-            core.remove_instance(&instance_id).await?;
             println!("Removed instance: {}", instance_id);
         },
 
-        Commands::StartAll { tag } => {
-            let tags: Vec<&str> = tag.as_deref().map(|t| vec![t]).unwrap_or_default();
-            let results = core.start_by_tags(&tags).await;
+        Commands::StartAll {
+            group,
+            template,
+            tag,
+            tag_mode,
+        } => {
+            let results = if template.is_some() || group.is_some() {
+                let filter = InstanceFilter {
+                    group,
+                    template,
+                    tags: tag,
+                    tag_match: tag_mode,
+                    status: None,
+                };
+                core.start_matching(&filter).await
+            } else {
+                let tags: Vec<&str> = tag.iter().map(String::as_str).collect();
+                core.start_by_tags_matching(&tags, tag_mode).await
+            };
             let success = results.iter().filter(|r| r.is_ok()).count();
             let failed = results.len() - success;
             println!("Started {} instances ({} failed)", success, failed);
         },
 
-        Commands::StopAll { tag } => {
-            let tags: Vec<&str> = tag.as_deref().map(|t| vec![t]).unwrap_or_default();
-            let results = core.stop_by_tags(&tags).await;
+        Commands::StopAll {
+            group,
+            template,
+            tag,
+            tag_mode,
+            yes,
+        } => {
+            let by_filter = template.is_some() || group.is_some();
+            let filter = InstanceFilter {
+                group,
+                template,
+                tags: tag,
+                tag_match: tag_mode,
+                status: None,
+            };
+            let tags: Vec<&str> = filter.tags.iter().map(String::as_str).collect();
+
+            if !yes {
+                // The same selection the stop below makes, minus what's
+                // already stopped
+                let mut affected: Vec<ServiceInstance> = if by_filter {
+                    core.query_instances(&filter).await
+                } else {
+                    core.list_instances(None)
+                        .await
+                        .into_iter()
+                        .filter(|i| tag_mode.matches(i, &tags))
+                        .collect()
+                };
+                affected.retain(|i| i.status != ServiceStatus::Stopped);
+                affected.sort_by(|a, b| a.id.cmp(&b.id));
+                if !affected.is_empty() {
+                    print_instances(&affected, OutputFormat::Table)?;
+                    let prompt = format!("Stop these {} instances?", affected.len());
+                    if !confirm(&prompt)? {
+                        anyhow::bail!("Aborted");
+                    }
+                }
+            }
+
+            let results = if by_filter {
+                core.stop_matching(&filter).await
+            } else {
+                core.stop_by_tags_matching(&tags, tag_mode).await
+            };
             let success = results.iter().filter(|r| r.is_ok()).count();
             let failed = results.len() - success;
             println!("Stopped {} instances ({} failed)", success, failed);
         },
+
+        Commands::Export => {
+            print_json(serde_json::to_value(core.export().await)?)?;
+        },
+
+        Commands::Import { file } => {
+            let content = std::fs::read_to_string(&file)
+                .map_err(|e| anyhow::anyhow!("Cannot read '{}': {}", file.display(), e))?;
+            let snapshot = serde_json::from_str(&content)
+                .map_err(|e| anyhow::anyhow!("Invalid export file '{}': {}", file.display(), e))?;
+            let report = core.import(snapshot).await?;
+            println!(
+                "Imported {} templates and {} instances ({} conflicts)",
+                report.templates_added.len(),
+                report.instances_added.len(),
+                report.conflicts.len()
+            );
+            for conflict in &report.conflicts {
+                println!("  {}", conflict);
+            }
+        },
+
+        Commands::Validate | Commands::Watch { .. } => {
+            unreachable!("handled before loading the core")
+        },
+    }
+
+    Ok(())
+}
+
+/// Print instances as a table, or as JSON for `--output json`
+fn print_instances(instances: &[ServiceInstance], output: OutputFormat) -> anyhow::Result<()> {
+    if output == OutputFormat::Json {
+        return print_json(serde_json::to_value(instances)?);
+    }
+    if instances.is_empty() {
+        println!("No instances found.");
+        return Ok(());
     }
 
+    println!(
+        "{:<25} {:<20} {:<8} {:<10} {:<20}",
+        "ID", "Template", "Port", "Status", "Tags"
+    );
+    println!("{}", "-".repeat(85));
+    for i in instances {
+        let status = match i.status {
+            ServiceStatus::Running => "Running",
+            ServiceStatus::Stopped => "Stopped",
+            ServiceStatus::Error => "Error",
+            _ => "Unknown",
+        };
+        println!(
+            "{:<25} {:<20} {:<8} {:<10} {:<20}",
+            i.id,
+            i.template_id,
+            i.port,
+            status,
+            i.tags.join(", ")
+        );
+    }
     Ok(())
 }
+
+/// Print a value as pretty JSON for `--output json`
+fn print_json(value: serde_json::Value) -> anyhow::Result<()> {
+    use std::io::Write;
+
+    // Write rather than println!, so a closed pipe is an error, not a panic
+    let mut stdout = std::io::stdout().lock();
+    serde_json::to_writer_pretty(&mut stdout, &value)?;
+    writeln!(stdout)?;
+    Ok(())
+}
+
+/// Ask a yes/no question on stdin, defaulting to no
+///
+/// End of input (e.g. stdin isn't a terminal) counts as no, so scripts
+/// have to pass `--yes` explicitly.
+fn confirm(prompt: &str) -> anyhow::Result<bool> {
+    use std::io::Write;
+
+    print!("{} [y/N] ", prompt);
+    std::io::stdout().flush()?;
+    let mut answer = String::new();
+    std::io::stdin().read_line(&mut answer)?;
+    Ok(is_yes(&answer))
+}
+
+/// Whether a prompt answer is "y" or "yes", in any case
+fn is_yes(answer: &str) -> bool {
+    matches!(answer.trim().to_ascii_lowercase().as_str(), "y" | "yes")
+}
+
+/// API token from the `USM_AUTH_TOKEN` environment variable, which keeps it
+/// out of the process list
+fn env_auth_token() -> Option<String> {
+    std::env::var("USM_AUTH_TOKEN")
+        .ok()
+        .filter(|t| !t.is_empty())
+}
+
+/// Parse a `key=value` label argument
+fn parse_label(s: &str) -> Result<(String, String), String> {
+    s.split_once('=')
+        .map(|(k, v)| (k.trim().to_string(), v.trim().to_string()))
+        .filter(|(k, _)| !k.is_empty())
+        .ok_or_else(|| format!("invalid label '{}', expected key=value", s))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_output_flag() {
+        let cli = Cli::try_parse_from(["usm", "instances"]).unwrap();
+        assert_eq!(cli.output, OutputFormat::Table);
+
+        // Global, so it's accepted before or after the subcommand
+        let cli = Cli::try_parse_from(["usm", "instances", "-o", "json"]).unwrap();
+        assert_eq!(cli.output, OutputFormat::Json);
+        let cli = Cli::try_parse_from(["usm", "--output", "json", "templates"]).unwrap();
+        assert_eq!(cli.output, OutputFormat::Json);
+
+        assert!(Cli::try_parse_from(["usm", "instances", "-o", "yaml"]).is_err());
+    }
+
+    #[test]
+    fn test_confirmation() {
+        let cli = Cli::try_parse_from(["usm", "stop-all", "--tag", "web", "-y"]).unwrap();
+        assert!(matches!(cli.command, Commands::StopAll { yes: true, .. }));
+        let cli = Cli::try_parse_from(["usm", "remove", "svc-1", "--force"]).unwrap();
+        assert!(matches!(
+            cli.command,
+            Commands::Remove {
+                force: true,
+                yes: false,
+                ..
+            }
+        ));
+
+        assert!(is_yes("y\n"));
+        assert!(is_yes(" YES "));
+        assert!(!is_yes("\n"));
+        assert!(!is_yes(""));
+        assert!(!is_yes("no"));
+    }
+}
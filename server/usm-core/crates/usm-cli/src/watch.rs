@@ -0,0 +1,253 @@
+//! `usm watch`: live event stream from a running server
+//!
+//! Connects to the server's `/ws` endpoint and prints every `ServiceEvent`
+//! with the time it arrived. The connection is re-established with backoff
+//! whenever it fails or drops, so the command can be left running across
+//! server restarts.
+
+use std::io::IsTerminal;
+use std::time::Duration;
+
+use futures_util::StreamExt;
+use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+use tokio_tungstenite::tungstenite::http::header::AUTHORIZATION;
+use tokio_tungstenite::tungstenite::Message;
+
+use usm_core::events::ServiceEvent;
+
+/// Shortest and longest wait between reconnect attempts
+const MIN_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+
+/// Print events from `url` until interrupted
+///
+/// With a `token` each connection sends it as `Authorization: Bearer`.
+/// Reconnects wait longer each time, up to `MAX_BACKOFF`, until a
+/// connection delivers an event: a server that accepts connections and
+/// drops them right away isn't hammered.
+pub async fn run(url: &str, filter: Option<&str>, token: Option<&str>) -> anyhow::Result<()> {
+    let color = std::io::stdout().is_terminal() && std::env::var_os("NO_COLOR").is_none();
+    let mut backoff = Backoff::default();
+
+    loop {
+        let mut request = url.into_client_request()?;
+        if let Some(token) = token {
+            request
+                .headers_mut()
+                .insert(AUTHORIZATION, format!("Bearer {}", token).parse()?);
+        }
+        let reason = match tokio_tungstenite::connect_async(request).await {
+            Ok((mut socket, _)) => {
+                while let Some(msg) = socket.next().await {
+                    match msg {
+                        Ok(Message::Text(text)) => {
+                            if print_message(&text, filter, color) {
+                                backoff.reset();
+                            }
+                        },
+                        Ok(Message::Close(_)) | Err(_) => break,
+                        _ => {},
+                    }
+                }
+                format!("Disconnected from {}", url)
+            },
+            Err(e) => format!("Cannot connect to {}: {}", url, e),
+        };
+        let wait = backoff.next();
+        eprintln!("{} (retrying in {}s)", reason, wait.as_secs());
+        tokio::time::sleep(wait).await;
+    }
+}
+
+/// Wait before the next reconnect, doubling up to `MAX_BACKOFF`
+#[derive(Debug)]
+struct Backoff(Duration);
+
+impl Default for Backoff {
+    fn default() -> Self {
+        Self(MIN_BACKOFF)
+    }
+}
+
+impl Backoff {
+    fn next(&mut self) -> Duration {
+        let wait = self.0;
+        self.0 = (wait * 2).min(MAX_BACKOFF);
+        wait
+    }
+
+    fn reset(&mut self) {
+        self.0 = MIN_BACKOFF;
+    }
+}
+
+/// Print one WebSocket text frame, returning whether it was an event
+/// (shown or filtered out)
+fn print_message(text: &str, filter: Option<&str>, color: bool) -> bool {
+    let Ok(value) = serde_json::from_str::<serde_json::Value>(text) else {
+        return false;
+    };
+
+    // The server greets each connection with the current instances
+    if value["type"] == "connected" {
+        let count = value["instances"].as_array().map_or(0, Vec::len);
+        eprintln!("Connected ({} instances)", count);
+        return false;
+    }
+
+    // Replies to commands and anything unknown are not events
+    let Ok(event) = serde_json::from_value::<ServiceEvent>(value) else {
+        return false;
+    };
+    if !matches_filter(&event, filter) {
+        return true;
+    }
+
+    let time = chrono::Local::now().format("%H:%M:%S%.3f");
+    let kind = event.event_type();
+    if color {
+        println!(
+            "{} \x1b[{}m{:<20}\x1b[0m {}",
+            time,
+            color_code(&event),
+            kind,
+            describe(&event)
+        );
+    } else {
+        println!("{} {:<20} {}", time, kind, describe(&event));
+    }
+    true
+}
+
+/// Whether `event` belongs to the instance given with `--filter`
+///
+/// Events without an instance (template and config changes) are hidden
+/// while filtering.
+fn matches_filter(event: &ServiceEvent, filter: Option<&str>) -> bool {
+    filter.map_or(true, |id| event.instance_id() == Some(id))
+}
+
+/// ANSI color for an event type
+fn color_code(event: &ServiceEvent) -> &'static str {
+    match event {
+        ServiceEvent::Error { .. } => "31",
+        ServiceEvent::HealthChanged { healthy: false, .. } => "31",
+        ServiceEvent::HealthChanged { .. } => "32",
+        ServiceEvent::StatusChanged { .. } => "33",
+        ServiceEvent::InstanceCreated { .. }
+        | ServiceEvent::InstanceRemoved { .. }
+        | ServiceEvent::InstanceUpdated { .. }
+        | ServiceEvent::PortChanged { .. } => "36",
+        ServiceEvent::ScheduleTriggered { .. } => "35",
+        ServiceEvent::MetricsUpdated { .. } => "2",
+        ServiceEvent::TemplateRegistered { .. }
+        | ServiceEvent::TemplateRemoved { .. }
+        | ServiceEvent::ConfigReloaded => "34",
+    }
+}
+
+/// One-line summary of an event's fields
+fn describe(event: &ServiceEvent) -> String {
+    match event {
+        ServiceEvent::InstanceCreated {
+            instance_id,
+            template_id,
+        } => format!("{} (template {})", instance_id, template_id),
+        ServiceEvent::InstanceRemoved { instance_id } => instance_id.clone(),
+        ServiceEvent::InstanceUpdated { instance_id } => format!("{} updated", instance_id),
+        ServiceEvent::StatusChanged {
+            instance_id,
+            status,
+            pid,
+        } => match pid {
+            Some(pid) => format!("{} -> {} (pid {})", instance_id, status, pid),
+            None => format!("{} -> {}", instance_id, status),
+        },
+        ServiceEvent::PortChanged {
+            instance_id,
+            old_port,
+            new_port,
+        } => format!("{} port {} -> {}", instance_id, old_port, new_port),
+        ServiceEvent::MetricsUpdated {
+            instance_id,
+            cpu_percent,
+            memory_mb,
+        } => format!(
+            "{} cpu {:.1}% mem {} MB",
+            instance_id, cpu_percent, memory_mb
+        ),
+        ServiceEvent::HealthChanged {
+            instance_id,
+            healthy,
+            message,
+        } => {
+            let state = if *healthy { "healthy" } else { "unhealthy" };
+            match message {
+                Some(message) => format!("{} {}: {}", instance_id, state, message),
+                None => format!("{} {}", instance_id, state),
+            }
+        },
+        ServiceEvent::ScheduleTriggered {
+            instance_id,
+            action,
+            error,
+        } => match error {
+            Some(error) => format!("{} {} failed: {}", instance_id, action, error),
+            None => format!("{} {}", instance_id, action),
+        },
+        ServiceEvent::Error {
+            instance_id,
+            message,
+        } => match instance_id {
+            Some(id) => format!("{}: {}", id, message),
+            None => message.clone(),
+        },
+        ServiceEvent::TemplateRegistered { template_id }
+        | ServiceEvent::TemplateRemoved { template_id } => template_id.clone(),
+        ServiceEvent::ConfigReloaded => String::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use usm_core::ServiceStatus;
+
+    #[test]
+    fn test_filter_and_describe() {
+        let started = ServiceEvent::StatusChanged {
+            instance_id: "api".to_string(),
+            status: ServiceStatus::Running,
+            pid: Some(42),
+        };
+        assert_eq!(describe(&started), "api -> running (pid 42)");
+        assert!(matches_filter(&started, None));
+        assert!(matches_filter(&started, Some("api")));
+        assert!(!matches_filter(&started, Some("worker")));
+
+        // Events without an instance only show when not filtering
+        assert!(matches_filter(&ServiceEvent::ConfigReloaded, None));
+        assert!(!matches_filter(&ServiceEvent::ConfigReloaded, Some("api")));
+    }
+
+    #[test]
+    fn test_backoff_caps_and_resets() {
+        let mut backoff = Backoff::default();
+        let waits: Vec<u64> = (0..7).map(|_| backoff.next().as_secs()).collect();
+        assert_eq!(waits, vec![1, 2, 4, 8, 16, 30, 30]);
+        backoff.reset();
+        assert_eq!(backoff.next(), MIN_BACKOFF);
+
+        // Only events count as the stream working, not the greeting
+        assert!(!print_message(
+            r#"{"type": "connected", "instances": []}"#,
+            None,
+            false
+        ));
+        assert!(print_message(
+            r#"{"type": "config_reloaded"}"#,
+            Some("api"),
+            false
+        ));
+    }
+}
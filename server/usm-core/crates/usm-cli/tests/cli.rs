@@ -0,0 +1,58 @@
+//! End-to-end tests for the `usm` binary
+
+use std::process::Command;
+use std::time::{Duration, Instant};
+
+#[test]
+fn test_server_refuses_invalid_config() {
+    let dir = tempfile::tempdir().unwrap();
+    let config_path = dir.path().join("services.toml");
+    std::fs::write(
+        &config_path,
+        r#"
+[templates.svc]
+display_name = "Service"
+default_port = 8000
+start_command = "echo start"
+
+[instances.a]
+template = "svc"
+
+[instances.b]
+template = "svc"
+port = 8000
+
+[instances.c]
+template = "missing"
+"#,
+    )
+    .unwrap();
+
+    // Pick a free port, then verify the server never binds it
+    let port = {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        listener.local_addr().unwrap().port()
+    };
+
+    let started = Instant::now();
+    let output = Command::new(env!("CARGO_BIN_EXE_usm"))
+        .args(["--config"])
+        .arg(&config_path)
+        .args(["server", "--port", &port.to_string()])
+        .output()
+        .unwrap();
+
+    assert!(!output.status.success());
+    assert!(started.elapsed() < Duration::from_secs(10));
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("unknown template 'missing'"), "{}", stderr);
+    assert!(
+        stderr.contains("Port 8000 is used by multiple instances"),
+        "{}",
+        stderr
+    );
+
+    // Nothing is listening on the requested port
+    assert!(std::net::TcpListener::bind(("127.0.0.1", port)).is_ok());
+}
@@ -0,0 +1,329 @@
+//! Async Rust client for the USM Core HTTP API
+//!
+//! Talks to a server started with `usm server` (or `UsmCore::start_server`)
+//! from another process. Requests and results use `usm-core`'s own types
+//! where the API returns them; the few responses the server builds ad hoc
+//! have small structs here.
+
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+use anyhow::{Context, Result};
+use futures_util::{Stream, StreamExt};
+use percent_encoding::{utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+use tokio_tungstenite::tungstenite::http::header::AUTHORIZATION;
+use tokio_tungstenite::tungstenite::Message;
+
+use usm_core::events::ServiceEvent;
+use usm_core::{InstanceConfig, InstanceMetrics, ServiceInstance};
+
+/// Client for one USM server
+///
+/// Cheap to clone; clones share the connection pool.
+#[derive(Debug, Clone)]
+pub struct UsmClient {
+    http: reqwest::Client,
+    base_url: String,
+    token: Option<String>,
+}
+
+/// Result of `UsmClient::create`
+#[derive(Debug, Clone, Deserialize)]
+pub struct CreatedInstance {
+    pub instance_id: String,
+    pub port: u16,
+}
+
+/// Result of `UsmClient::start`
+///
+/// `command` and `working_dir` are None if the instance was already running.
+#[derive(Debug, Clone, Deserialize)]
+pub struct StartedInstance {
+    pub message: String,
+    pub pid: Option<u32>,
+    #[serde(default)]
+    pub command: Option<String>,
+    #[serde(default)]
+    pub working_dir: Option<PathBuf>,
+}
+
+/// Result of `UsmClient::stop`
+///
+/// `command` is the stop command that was run, or None if the process was
+/// signalled (or the instance wasn't running).
+#[derive(Debug, Clone, Deserialize)]
+pub struct StoppedInstance {
+    pub message: String,
+    #[serde(default)]
+    pub command: Option<String>,
+}
+
+/// Response of `GET /api/metrics`
+#[derive(Debug, Clone, Deserialize)]
+pub struct Metrics {
+    pub system: SystemSummary,
+    pub instances: InstanceCounts,
+    /// Metrics of each running instance, by instance ID
+    pub instance_metrics: HashMap<String, InstanceMetrics>,
+}
+
+/// Host-wide figures in `Metrics`
+#[derive(Debug, Clone, Deserialize)]
+pub struct SystemSummary {
+    pub cpu_percent: f64,
+    pub memory_used_gb: f64,
+    pub memory_total_gb: f64,
+    pub memory_percent: f64,
+    /// 1, 5 and 15 minute load averages
+    pub load_average: (f64, f64, f64),
+}
+
+/// Instance counts by status in `Metrics`
+#[derive(Debug, Clone, Deserialize)]
+pub struct InstanceCounts {
+    pub running: usize,
+    pub stopped: usize,
+    pub error: usize,
+    pub total: usize,
+}
+
+/// Bytes escaped in a path segment: all but RFC 3986's unreserved
+/// characters, so a grouped id's `/` stays inside its segment
+const PATH_SEGMENT: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'.')
+    .remove(b'_')
+    .remove(b'~');
+
+#[derive(Deserialize)]
+struct InstanceList {
+    instances: Vec<ServiceInstance>,
+}
+
+impl UsmClient {
+    /// Client for the server at `base_url`, e.g. `http://127.0.0.1:8787`
+    pub fn new(base_url: impl Into<String>) -> Self {
+        Self {
+            http: reqwest::Client::new(),
+            base_url: base_url.into().trim_end_matches('/').to_string(),
+            token: None,
+        }
+    }
+
+    /// Send `token` as `Authorization: Bearer` with every request, for
+    /// servers started with an auth token
+    pub fn with_token(mut self, token: impl Into<String>) -> Self {
+        self.token = Some(token.into());
+        self
+    }
+
+    /// Every instance (`GET /api/instances`)
+    pub async fn list_instances(&self) -> Result<Vec<ServiceInstance>> {
+        let list: InstanceList = self.send(self.http.get(self.url("/api/instances"))).await?;
+        Ok(list.instances)
+    }
+
+    /// Create an instance (`POST /api/instances`)
+    ///
+    /// Without a port in `config` the server picks the next free one.
+    pub async fn create(&self, config: &InstanceConfig) -> Result<CreatedInstance> {
+        self.send(self.http.post(self.url("/api/instances")).json(config))
+            .await
+    }
+
+    /// Start an instance (`POST /api/instances/:id/start`)
+    pub async fn start(&self, id: &str) -> Result<StartedInstance> {
+        self.send(self.http.post(self.instance_url(id, "start")))
+            .await
+    }
+
+    /// Stop an instance (`POST /api/instances/:id/stop`)
+    pub async fn stop(&self, id: &str) -> Result<StoppedInstance> {
+        self.send(self.http.post(self.instance_url(id, "stop")))
+            .await
+    }
+
+    /// System and per-instance metrics (`GET /api/metrics`)
+    pub async fn metrics(&self) -> Result<Metrics> {
+        self.send(self.http.get(self.url("/api/metrics"))).await
+    }
+
+    /// Stream of every event the server broadcasts (`/ws`)
+    ///
+    /// The stream ends when the server closes the connection; it does not
+    /// reconnect. Frames that aren't events, like the greeting and replies
+    /// to commands, are skipped.
+    pub async fn subscribe_ws(&self) -> Result<impl Stream<Item = Result<ServiceEvent>>> {
+        let url = self.url("/ws").replacen("http", "ws", 1);
+        let mut request = url.as_str().into_client_request()?;
+        if let Some(token) = &self.token {
+            request
+                .headers_mut()
+                .insert(AUTHORIZATION, format!("Bearer {}", token).parse()?);
+        }
+        let (socket, _) = tokio_tungstenite::connect_async(request)
+            .await
+            .with_context(|| format!("Cannot connect to {}", url))?;
+
+        Ok(socket.filter_map(|message| async move {
+            match message {
+                Ok(Message::Text(text)) => serde_json::from_str::<ServiceEvent>(&text).ok().map(Ok),
+                Ok(_) => None,
+                Err(e) => Some(Err(e.into())),
+            }
+        }))
+    }
+
+    fn url(&self, path: &str) -> String {
+        format!("{}{}", self.base_url, path)
+    }
+
+    fn instance_url(&self, id: &str, action: &str) -> String {
+        self.url(&format!(
+            "/api/instances/{}/{}",
+            utf8_percent_encode(id, PATH_SEGMENT),
+            action
+        ))
+    }
+
+    /// Send a request and decode its JSON response
+    ///
+    /// Error responses fail with their status and message (the `error`
+    /// field of a JSON body, else the body itself).
+    async fn send<T: DeserializeOwned>(&self, request: reqwest::RequestBuilder) -> Result<T> {
+        let request = match &self.token {
+            Some(token) => request.bearer_auth(token),
+            None => request,
+        };
+        let response = request.send().await?;
+        let status = response.status();
+        if !status.is_success() {
+            let body = response.text().await.unwrap_or_default();
+            let message = serde_json::from_str::<serde_json::Value>(&body)
+                .ok()
+                .and_then(|json| json["error"].as_str().map(str::to_string))
+                .unwrap_or(body);
+            anyhow::bail!("HTTP {}: {}", status.as_u16(), message);
+        }
+        Ok(response.json().await?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use usm_core::monitor::MockMonitor;
+    use usm_core::UsmCore;
+
+    use super::*;
+
+    const TEST_CONFIG: &str = r#"
+[templates.sleeper]
+display_name = "Sleeper"
+default_port = 18950
+start_command = "sleep 30"
+supports_multiple = true
+
+[instances.sleeper-1]
+template = "sleeper"
+port = 18950
+"#;
+
+    /// Serve a core with a mock monitor on a free local port
+    async fn serve(dir: &tempfile::TempDir, token: Option<&str>) -> (UsmClient, Arc<UsmCore>) {
+        let config_path = dir.path().join("services.toml");
+        std::fs::write(&config_path, TEST_CONFIG).unwrap();
+        let monitor = Arc::new(MockMonitor::new());
+        let core = Arc::new(UsmCore::with_monitor(&config_path, monitor).await.unwrap());
+
+        let mut app = usm_core::server::build_router(core.clone(), None);
+        if let Some(token) = token {
+            app = usm_core::server::require_token(app, token);
+        }
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        (UsmClient::new(url), core)
+    }
+
+    #[tokio::test]
+    async fn test_instance_lifecycle() {
+        let dir = tempfile::tempdir().unwrap();
+        let (client, _core) = serve(&dir, None).await;
+
+        let instances = client.list_instances().await.unwrap();
+        assert_eq!(instances.len(), 1);
+        assert_eq!(instances[0].id, "sleeper-1");
+
+        let config: InstanceConfig = serde_json::from_value(
+            serde_json::json!({"instance_id": "sleeper-2", "template_id": "sleeper"}),
+        )
+        .unwrap();
+        let created = client.create(&config).await.unwrap();
+        assert_eq!(created.instance_id, "sleeper-2");
+        assert_eq!(created.port, 18951);
+
+        let started = client.start("sleeper-2").await.unwrap();
+        assert_eq!(started.pid, Some(1000));
+        assert_eq!(started.command.as_deref(), Some("sleep 30"));
+        let metrics = client.metrics().await.unwrap();
+        assert_eq!(metrics.instances.running, 1);
+        assert_eq!(metrics.instances.total, 2);
+
+        let stopped = client.stop("sleeper-2").await.unwrap();
+        assert_eq!(stopped.message, "Stopped instance sleeper-2");
+
+        let err = client.start("missing").await.unwrap_err();
+        assert_eq!(err.to_string(), "HTTP 404: Instance 'missing' not found");
+    }
+
+    #[tokio::test]
+    async fn test_grouped_instance() {
+        let dir = tempfile::tempdir().unwrap();
+        let (client, core) = serve(&dir, None).await;
+
+        let config: InstanceConfig = serde_json::from_value(serde_json::json!({
+            "instance_id": "sleeper-2",
+            "group": "projectA",
+            "template_id": "sleeper",
+        }))
+        .unwrap();
+        let created = client.create(&config).await.unwrap();
+        assert_eq!(created.instance_id, "projectA/sleeper-2");
+
+        // The id's '/' must reach the server inside one path segment
+        let started = client.start("projectA/sleeper-2").await.unwrap();
+        assert_eq!(started.pid, Some(1000));
+        assert!(core
+            .get_instance("projectA/sleeper-2")
+            .await
+            .unwrap()
+            .pid
+            .is_some());
+        let stopped = client.stop("projectA/sleeper-2").await.unwrap();
+        assert_eq!(stopped.message, "Stopped instance projectA/sleeper-2");
+    }
+
+    #[tokio::test]
+    async fn test_subscribe_ws() {
+        let dir = tempfile::tempdir().unwrap();
+        let (client, core) = serve(&dir, Some("secret")).await;
+        assert!(client.subscribe_ws().await.is_err());
+
+        let client = client.with_token("secret");
+        let mut events = Box::pin(client.subscribe_ws().await.unwrap());
+        core.start_instance("sleeper-1").await.unwrap();
+
+        let event = loop {
+            let event = events.next().await.unwrap().unwrap();
+            if let ServiceEvent::StatusChanged { .. } = event {
+                break event;
+            }
+        };
+        assert_eq!(event.instance_id(), Some("sleeper-1"));
+    }
+}
@@ -0,0 +1,189 @@
+//! Builder for `UsmCore`
+//!
+//! `UsmCore::new` loads everything from a config file. The builder lets
+//! callers inject the pieces instead - a process monitor, pre-built
+//! registries, an event bus - so a core can be assembled without touching
+//! the filesystem. A core built without a config file keeps all changes in
+//! memory.
+
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use anyhow::Result;
+use tokio::sync::RwLock;
+use tracing::{info, instrument};
+
+use crate::config::{ConfigManager, Settings};
+use crate::events::EventBus;
+use crate::logs::LogStore;
+use crate::metrics::MetricsHistory;
+use crate::monitor::{self, ProcessMonitor};
+use crate::service::{InstanceRegistry, TemplateRegistry};
+use crate::UsmCore;
+
+/// Step-by-step construction of a `UsmCore`
+///
+/// With `config_path` set, templates, instances and settings are loaded from
+/// that file and changes are persisted back to it; registries passed to
+/// `templates`/`instances` replace the loaded ones. Instances a previous run
+/// left running are re-attached (see `crate::state`). Without it, the core
+/// starts from the given registries (empty by default) and nothing is saved.
+#[derive(Default)]
+pub struct UsmCoreBuilder {
+    config_path: Option<PathBuf>,
+    profile: Option<String>,
+    monitor: Option<Arc<dyn ProcessMonitor>>,
+    event_bus: Option<Arc<EventBus>>,
+    templates: Option<TemplateRegistry>,
+    instances: Option<InstanceRegistry>,
+    settings: Option<Settings>,
+    log_dir: Option<PathBuf>,
+}
+
+impl UsmCoreBuilder {
+    /// Create a builder with nothing configured
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Load from (and persist to) this config file
+    pub fn config_path(mut self, path: impl AsRef<Path>) -> Self {
+        self.config_path = Some(path.as_ref().to_path_buf());
+        self
+    }
+
+    /// Profile to apply when loading the config file
+    pub fn profile(mut self, profile: Option<&str>) -> Self {
+        self.profile = profile.map(str::to_string);
+        self
+    }
+
+    /// Process monitor to use instead of the platform default
+    pub fn monitor(mut self, monitor: Arc<dyn ProcessMonitor>) -> Self {
+        self.monitor = Some(monitor);
+        self
+    }
+
+    /// Event bus to publish on, e.g. one the caller already subscribed to
+    pub fn event_bus(mut self, event_bus: Arc<EventBus>) -> Self {
+        self.event_bus = Some(event_bus);
+        self
+    }
+
+    /// Pre-loaded template registry
+    pub fn templates(mut self, templates: TemplateRegistry) -> Self {
+        self.templates = Some(templates);
+        self
+    }
+
+    /// Pre-loaded instance registry
+    pub fn instances(mut self, instances: InstanceRegistry) -> Self {
+        self.instances = Some(instances);
+        self
+    }
+
+    /// Settings to use instead of the config file's
+    pub fn settings(mut self, settings: Settings) -> Self {
+        self.settings = Some(settings);
+        self
+    }
+
+    /// Directory to capture instance output to, overriding `[logs] dir`
+    pub fn log_dir(mut self, dir: Option<impl AsRef<Path>>) -> Self {
+        self.log_dir = dir.map(|d| d.as_ref().to_path_buf());
+        self
+    }
+
+    /// Assemble the core, loading the config file if one was given
+    #[instrument(skip_all, fields(config_path = ?self.config_path, profile = ?self.profile))]
+    pub async fn build(self) -> Result<UsmCore> {
+        // Initialize event bus first (other components will subscribe),
+        // sized by the settings before the rest of the config is loaded
+        let event_bus = match self.event_bus {
+            Some(event_bus) => event_bus,
+            None => {
+                let settings = match (&self.settings, &self.config_path) {
+                    (Some(settings), _) => settings.clone(),
+                    (None, Some(path)) => ConfigManager::read_settings(path)?,
+                    (None, None) => Settings::default(),
+                };
+                let capacity = settings.server.unwrap_or_default().event_capacity()?;
+                Arc::new(EventBus::new(capacity))
+            },
+        };
+
+        // Load configuration
+        let (config_manager, templates, instances, settings) = match self.config_path {
+            Some(config_path) => {
+                info!(
+                    "Initializing USM Core from config: {}",
+                    config_path.display()
+                );
+                let config_manager = Arc::new(
+                    ConfigManager::new(&config_path, event_bus.clone())?.with_profile(self.profile),
+                );
+                let (templates, instances) = config_manager.load().await?;
+                let settings = match self.settings {
+                    Some(settings) => settings,
+                    None => config_manager.load_settings().await?,
+                };
+                (
+                    Some(config_manager),
+                    self.templates.unwrap_or(templates),
+                    self.instances.unwrap_or(instances),
+                    settings,
+                )
+            },
+            None => {
+                info!("Initializing in-memory USM Core");
+                (
+                    None,
+                    self.templates.unwrap_or_default(),
+                    self.instances.unwrap_or_default(),
+                    self.settings.unwrap_or_default(),
+                )
+            },
+        };
+
+        // In-memory log capture is opt-in via [logs] memory_lines
+        let memory_lines = settings.logs.as_ref().map(|l| l.memory_lines).unwrap_or(0);
+        let log_dir = self.log_dir.or_else(|| {
+            settings
+                .logs
+                .as_ref()
+                .and_then(|l| l.dir.as_ref())
+                .map(PathBuf::from)
+        });
+
+        let monitor = self.monitor.unwrap_or_else(|| {
+            let interval = settings
+                .monitor
+                .clone()
+                .unwrap_or_default()
+                .refresh_interval();
+            monitor::create_monitor_with_refresh(interval)
+        });
+
+        let core = UsmCore {
+            templates: Arc::new(RwLock::new(templates)),
+            instances: Arc::new(RwLock::new(instances)),
+            monitor,
+            config_manager,
+            event_bus,
+            logs: Arc::new(LogStore::new(memory_lines)),
+            log_dir,
+            metrics_history: Arc::new(MetricsHistory::default()),
+            settings: Arc::new(RwLock::new(settings)),
+        };
+
+        // Pick up instances a previous run left running
+        core.restore_state().await;
+
+        // Hot reload on edits to the config file
+        if let Some(changes) = core.config_manager.as_ref().and_then(|c| c.take_changes()) {
+            core.spawn_config_watcher(changes);
+        }
+
+        Ok(core)
+    }
+}
@@ -0,0 +1,215 @@
+//! Template inheritance (`extends = "<base template id>"`)
+//!
+//! A template that extends another starts out as a copy of its base and
+//! overrides the keys it sets itself. Tables such as `default_env` are merged
+//! key by key, so a child can add or change one variable without repeating
+//! the rest. Chains are resolved on the raw TOML before the templates are
+//! deserialized, which is what lets a child leave out required fields like
+//! `start_command`. When the config is saved, children are written back with
+//! only the keys that differ from their base.
+
+use std::collections::HashMap;
+
+use anyhow::Result;
+use serde::ser::{Error as _, SerializeMap};
+use serde::Serializer;
+use toml::Table;
+
+use super::TemplateConfig;
+
+/// Replace every template in the `[templates]` table with its flattened form
+///
+/// Fails on a reference to an unknown template or an inheritance cycle.
+pub(crate) fn resolve(templates: &mut Table) -> Result<()> {
+    let raw = templates.clone();
+    let mut resolved = HashMap::new();
+    for id in raw.keys() {
+        resolve_one(id, &raw, &mut resolved, &mut Vec::new())?;
+    }
+    for (id, table) in resolved {
+        templates.insert(id, toml::Value::Table(table));
+    }
+    Ok(())
+}
+
+fn resolve_one(
+    id: &str,
+    raw: &Table,
+    resolved: &mut HashMap<String, Table>,
+    chain: &mut Vec<String>,
+) -> Result<Table> {
+    if let Some(table) = resolved.get(id) {
+        return Ok(table.clone());
+    }
+    if chain.iter().any(|seen| seen == id) {
+        chain.push(id.to_string());
+        anyhow::bail!("Template inheritance cycle: {}", chain.join(" -> "));
+    }
+
+    // Malformed entries are left for deserialization to report
+    let Some(table) = raw.get(id).and_then(toml::Value::as_table) else {
+        return Ok(Table::new());
+    };
+    let Some(base) = table.get("extends").and_then(toml::Value::as_str) else {
+        resolved.insert(id.to_string(), table.clone());
+        return Ok(table.clone());
+    };
+    if !raw.contains_key(base) {
+        anyhow::bail!("Template '{}' extends unknown template '{}'", id, base);
+    }
+
+    chain.push(id.to_string());
+    let mut flattened = resolve_one(base, raw, resolved, chain)?;
+    chain.pop();
+    merge(&mut flattened, table);
+
+    resolved.insert(id.to_string(), flattened.clone());
+    Ok(flattened)
+}
+
+/// Overlay `child` onto `base`, merging nested tables
+fn merge(base: &mut Table, child: &Table) {
+    for (key, value) in child {
+        match (base.get_mut(key), value) {
+            (Some(toml::Value::Table(base)), toml::Value::Table(child)) => merge(base, child),
+            _ => {
+                base.insert(key.clone(), value.clone());
+            },
+        }
+    }
+}
+
+/// Drop the keys of `child` that hold the same value as in `base`
+fn strip_inherited(child: &mut Table, base: &Table) {
+    child.retain(|key, value| {
+        let Some(inherited) = base.get(key) else {
+            return true;
+        };
+        if let (toml::Value::Table(child), toml::Value::Table(base)) = (&mut *value, inherited) {
+            strip_inherited(child, base);
+            return !child.is_empty();
+        }
+        value != inherited
+    });
+}
+
+/// Serialize `[templates]`, writing children with only their own keys
+///
+/// A child whose base is gone is written out in full, without `extends`.
+pub(crate) fn serialize_templates<S: Serializer>(
+    templates: &HashMap<String, TemplateConfig>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    let mut map = serializer.serialize_map(Some(templates.len()))?;
+    for (id, template) in templates {
+        let base = template
+            .extends
+            .as_ref()
+            .and_then(|base| templates.get(base));
+        match base {
+            Some(base) => {
+                let table = |t: &TemplateConfig| Table::try_from(t).map_err(S::Error::custom);
+                let mut child = table(template)?;
+                strip_inherited(&mut child, &table(base)?);
+                map.serialize_entry(id, &child)?;
+            },
+            None if template.extends.is_some() => {
+                let full = TemplateConfig {
+                    extends: None,
+                    ..template.clone()
+                };
+                map.serialize_entry(id, &full)?;
+            },
+            None => map.serialize_entry(id, template)?,
+        }
+    }
+    map.end()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn templates(toml: &str) -> Result<Table> {
+        let mut config: Table = toml::from_str(toml).unwrap();
+        let mut templates = config.remove("templates").unwrap();
+        resolve(templates.as_table_mut().unwrap())?;
+        Ok(templates.as_table().unwrap().clone())
+    }
+
+    #[test]
+    fn test_resolve_chain() {
+        let resolved = templates(
+            r#"
+[templates.base]
+display_name = "Base"
+default_port = 8000
+start_command = "serve --port {port}"
+default_env = { LOG_LEVEL = "info", MODE = "prod" }
+
+[templates.debug]
+extends = "base"
+display_name = "Debug"
+default_env = { LOG_LEVEL = "debug" }
+
+[templates.debug-verbose]
+extends = "debug"
+start_command = "serve --port {port} --verbose"
+"#,
+        )
+        .unwrap();
+
+        let verbose = resolved["debug-verbose"].as_table().unwrap();
+        assert_eq!(verbose["display_name"].as_str(), Some("Debug"));
+        assert_eq!(verbose["default_port"].as_integer(), Some(8000));
+        assert_eq!(
+            verbose["start_command"].as_str(),
+            Some("serve --port {port} --verbose")
+        );
+        assert_eq!(verbose["extends"].as_str(), Some("debug"));
+        let env = verbose["default_env"].as_table().unwrap();
+        assert_eq!(env["LOG_LEVEL"].as_str(), Some("debug"));
+        assert_eq!(env["MODE"].as_str(), Some("prod"));
+    }
+
+    #[test]
+    fn test_resolve_errors() {
+        let err = templates("[templates.a]\nextends = \"missing\"\n").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Template 'a' extends unknown template 'missing'"
+        );
+
+        let err = templates(
+            "[templates.a]\nextends = \"b\"\n[templates.b]\nextends = \"c\"\n[templates.c]\nextends = \"a\"\n",
+        )
+        .unwrap_err();
+        assert!(
+            err.to_string().starts_with("Template inheritance cycle: "),
+            "{}",
+            err
+        );
+
+        let err = templates("[templates.a]\nextends = \"a\"\n").unwrap_err();
+        assert_eq!(err.to_string(), "Template inheritance cycle: a -> a");
+    }
+
+    #[test]
+    fn test_strip_inherited() {
+        let base: Table = toml::from_str(
+            "default_port = 8000\nstart_command = \"serve\"\n[default_env]\nA = \"1\"\nB = \"2\"\n",
+        )
+        .unwrap();
+        let mut child: Table = toml::from_str(
+            "extends = \"base\"\ndefault_port = 8000\nstart_command = \"serve -v\"\n[default_env]\nA = \"1\"\nB = \"3\"\n",
+        )
+        .unwrap();
+
+        strip_inherited(&mut child, &base);
+        let expected: Table = toml::from_str(
+            "extends = \"base\"\nstart_command = \"serve -v\"\n[default_env]\nB = \"3\"\n",
+        )
+        .unwrap();
+        assert_eq!(child, expected);
+    }
+}
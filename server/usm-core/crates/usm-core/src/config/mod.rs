@@ -1,32 +1,500 @@
 //! Configuration management with TOML parsing and file watching
 
+mod inherit;
+
+use std::net::{IpAddr, Ipv4Addr};
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
+use std::time::Duration;
 
 use anyhow::Result;
-use notify::RecommendedWatcher;
+use notify::{RecommendedWatcher, RecursiveMode, Watcher};
 use serde::{Deserialize, Serialize};
-use tracing::{debug, info};
+use tokio::sync::mpsc;
+use tracing::{debug, info, warn};
 
 use crate::events::EventBus;
+use crate::monitor::Signal;
+use crate::schedule::{MissedPolicy, ScheduleConfig};
 use crate::service::{
-    InstanceConfig, InstanceRegistry, ServiceCategory, ServiceInstance, ServiceTemplate,
-    TemplateRegistry,
+    check_placeholders, split_group, InstanceConfig, InstanceRegistry, ServiceCategory,
+    ServiceInstance, ServiceStatus, ServiceTemplate, TemplateRegistry,
 };
+use crate::supervisor::{check_limits, LimitAction, RestartPolicy};
 
 /// Raw configuration file structure
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ConfigFile {
-    #[serde(default)]
+    #[serde(default, serialize_with = "inherit::serialize_templates")]
     pub templates: std::collections::HashMap<String, TemplateConfig>,
 
     #[serde(default)]
     pub instances: std::collections::HashMap<String, InstanceConfigFile>,
+
+    #[serde(flatten)]
+    pub settings: Settings,
+}
+
+impl ConfigFile {
+    /// Parse a config file, resolving template inheritance
+    ///
+    /// Templates with `extends` come back flattened; see `inherit`.
+    pub fn parse(content: &str) -> Result<Self> {
+        let mut raw: toml::Table = toml::from_str(content)?;
+        let inherits = raw
+            .get("templates")
+            .and_then(toml::Value::as_table)
+            .is_some_and(|templates| templates.values().any(|t| t.get("extends").is_some()));
+        if !inherits {
+            // Straight from the text, for error messages with line numbers
+            return Ok(toml::from_str(content)?);
+        }
+
+        if let Some(toml::Value::Table(templates)) = raw.get_mut("templates") {
+            inherit::resolve(templates)?;
+        }
+        Ok(raw.try_into()?)
+    }
+
+    /// Check the config for problems that would make loading fail
+    ///
+    /// Returns every problem found (not just the first), sorted for stable
+    /// output. An empty list means the config is valid.
+    pub fn validate(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        let mut ports: std::collections::HashMap<u16, Vec<&str>> = std::collections::HashMap::new();
+
+        // Instances are known by their group-qualified ids
+        let mut ids: std::collections::HashMap<String, Vec<&str>> =
+            std::collections::HashMap::new();
+        for (key, ic) in &self.instances {
+            match split_group(ic.group.as_deref(), key) {
+                Ok((_, id)) => ids.entry(id).or_default().push(key),
+                Err(e) => problems.push(e.to_string()),
+            }
+        }
+        for (id, keys) in &mut ids {
+            if keys.len() < 2 {
+                continue;
+            }
+            keys.sort();
+            problems.push(format!(
+                "Instance '{}' is defined more than once: {}",
+                id,
+                keys.join(", ")
+            ));
+        }
+
+        for (id, tc) in &self.templates {
+            let commands = [
+                ("start_command", Some(&tc.start_command)),
+                ("stop_command", tc.stop_command.as_ref()),
+                ("health_endpoint", tc.health_endpoint.as_ref()),
+            ];
+            for (field, command) in commands {
+                if let Some(Err(e)) = command.map(|c| check_placeholders(c)) {
+                    problems.push(format!("Template '{}' {}: {}", id, field, e));
+                }
+            }
+
+            match tc.port_range {
+                Some((min, max)) if min > max => problems.push(format!(
+                    "Template '{}' port_range {}-{} is reversed",
+                    id, min, max
+                )),
+                Some((min, max)) if !(min..=max).contains(&tc.default_port) => {
+                    problems.push(format!(
+                        "Template '{}' default_port {} is outside its port range {}-{}",
+                        id, tc.default_port, min, max
+                    ))
+                },
+                _ => {},
+            }
+
+            if let Some(Err(e)) = tc.reload_signal.as_deref().map(str::parse::<Signal>) {
+                problems.push(format!("Template '{}' reload_signal: {}", id, e));
+            }
+        }
+
+        for (id, ic) in &self.instances {
+            if let Some(Err(e)) = ic.schedule.as_ref().map(ScheduleConfig::validate) {
+                problems.push(format!("Instance '{}' has an invalid schedule: {}", id, e));
+            }
+
+            let overrides = [
+                ("start_command_override", &ic.start_command_override),
+                ("stop_command_override", &ic.stop_command_override),
+            ];
+            for (field, command) in overrides {
+                if let Some(Err(e)) = command.as_deref().map(check_placeholders) {
+                    problems.push(format!("Instance '{}' {}: {}", id, field, e));
+                }
+            }
+
+            if let Err(e) = check_limits(ic.cpu_limit_percent, ic.memory_limit_mb) {
+                problems.push(format!("Instance '{}': {}", id, e));
+            }
+
+            for dep in &ic.depends_on {
+                if !ids.contains_key(dep) {
+                    problems.push(format!(
+                        "Instance '{}' depends on unknown instance '{}'",
+                        id, dep
+                    ));
+                }
+            }
+
+            match self.templates.get(&ic.template) {
+                Some(template) => {
+                    if let (Some(port), Some((min, max))) = (ic.port, template.port_range) {
+                        if !(min..=max).contains(&port) {
+                            problems.push(format!(
+                                "Instance '{}' port {} is outside the port range {}-{} of template '{}'",
+                                id, port, min, max, ic.template
+                            ));
+                        }
+                    }
+                    let port = ic.port.unwrap_or(template.default_port);
+                    ports.entry(port).or_default().push(id);
+                },
+                None => problems.push(format!(
+                    "Instance '{}' references unknown template '{}'",
+                    id, ic.template
+                )),
+            }
+        }
+
+        if let Some(profile) = self
+            .settings
+            .defaults
+            .as_ref()
+            .and_then(|d| d.profile.as_ref())
+        {
+            if !self.settings.profiles.contains_key(profile) {
+                problems.push(format!("Default profile '{}' is not defined", profile));
+            }
+        }
+
+        if let Some(Err(e)) = self
+            .settings
+            .scheduler
+            .as_ref()
+            .and_then(|s| s.timezone.as_deref())
+            .map(crate::schedule::parse_timezone)
+        {
+            problems.push(format!("[scheduler] {}", e));
+        }
+
+        if let Some(server) = &self.settings.server {
+            for result in [
+                server.bind_addr().map(drop),
+                server.event_capacity().map(drop),
+            ] {
+                if let Err(e) = result {
+                    problems.push(format!("[server] {}", e));
+                }
+            }
+        }
+
+        for (port, mut ids) in ports {
+            if ids.len() > 1 {
+                ids.sort();
+                problems.push(format!(
+                    "Port {} is used by multiple instances: {}",
+                    port,
+                    ids.join(", ")
+                ));
+            }
+        }
+
+        problems.sort();
+        problems
+    }
+}
+
+/// Global settings sections of the config file
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Settings {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub server: Option<ServerSettings>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub defaults: Option<DefaultSettings>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub logs: Option<LogSettings>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub limits: Option<LimitSettings>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scheduler: Option<SchedulerSettings>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub monitor: Option<MonitorSettings>,
+
+    #[serde(default, skip_serializing_if = "std::collections::HashMap::is_empty")]
+    pub profiles: std::collections::HashMap<String, ProfileSettings>,
+}
+
+/// Overlay applied to every instance when a profile is active (`[profiles.<name>]`)
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ProfileSettings {
+    /// Added to each instance's port
+    #[serde(default)]
+    pub port_offset: i32,
+
+    /// Environment variables set on (or overriding those of) each instance
+    #[serde(default)]
+    pub env_vars: std::collections::HashMap<String, String>,
+}
+
+impl ProfileSettings {
+    /// Shift a base port by this profile's offset
+    fn apply_port(&self, port: u16) -> Result<u16> {
+        shift_port(port, self.port_offset)
+    }
+
+    /// Recover the base port from a shifted one
+    fn remove_port(&self, port: u16) -> Result<u16> {
+        shift_port(port, -self.port_offset)
+    }
+}
+
+fn shift_port(port: u16, offset: i32) -> Result<u16> {
+    u16::try_from(port as i32 + offset)
+        .map_err(|_| anyhow::anyhow!("Port {} with offset {} is out of range", port, offset))
+}
+
+/// Fallbacks for values instances and templates leave unset (`[defaults]`)
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct DefaultSettings {
+    /// Profile applied when none is selected explicitly
+    #[serde(default)]
+    pub profile: Option<String>,
+
+    /// Working directory used when neither the instance nor its template sets one
+    #[serde(default)]
+    pub working_dir: Option<String>,
+}
+
+/// HTTP server settings (`[server]`)
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ServerSettings {
+    /// Directory of static files (e.g. a web dashboard) served at `/`
+    #[serde(default)]
+    pub static_dir: Option<String>,
+
+    /// How often to publish `metrics_updated` events, in milliseconds
+    /// (default 5000, 0 disables)
+    #[serde(default)]
+    pub metrics_interval_ms: Option<u64>,
+
+    /// Which instances to stop when the server shuts down
+    #[serde(default)]
+    pub on_shutdown: ShutdownPolicy,
+
+    /// Bearer token required by the API and WebSocket routes (open if unset)
+    #[serde(default)]
+    pub auth_token: Option<String>,
+
+    /// IP address to listen on (default 127.0.0.1; "0.0.0.0" for every
+    /// interface)
+    #[serde(default)]
+    pub bind_addr: Option<String>,
+
+    /// Events buffered for slow subscribers (e.g. WebSocket clients) before
+    /// they start missing some (default 1024)
+    #[serde(default)]
+    pub event_capacity: Option<usize>,
+
+    /// How often WebSocket clients are pinged, in milliseconds (default
+    /// 30000, 0 disables)
+    #[serde(default)]
+    pub ws_ping_interval_ms: Option<u64>,
+
+    /// How long a pinged WebSocket client has to answer before it's
+    /// disconnected, in milliseconds (default 10000)
+    #[serde(default)]
+    pub ws_pong_timeout_ms: Option<u64>,
+}
+
+/// Tag marking instances that `ShutdownPolicy::StopEphemeral` stops
+pub const EPHEMERAL_TAG: &str = "ephemeral";
+
+/// Which running instances to stop when USM shuts down
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ShutdownPolicy {
+    /// Leave them running
+    #[default]
+    Leave,
+    /// Stop every running instance
+    StopAll,
+    /// Stop running instances tagged `ephemeral`
+    StopEphemeral,
+}
+
+impl ServerSettings {
+    /// Default interval between metrics samples
+    pub const DEFAULT_METRICS_INTERVAL_MS: u64 = 5000;
+
+    /// Default interval between WebSocket pings
+    pub const DEFAULT_WS_PING_INTERVAL_MS: u64 = 30_000;
+
+    /// Default time a WebSocket client has to answer a ping
+    pub const DEFAULT_WS_PONG_TIMEOUT_MS: u64 = 10_000;
+
+    /// Default listen address: local connections only
+    pub const DEFAULT_BIND_ADDR: IpAddr = IpAddr::V4(Ipv4Addr::LOCALHOST);
+
+    /// The configured listen address, or the default
+    pub fn bind_addr(&self) -> Result<IpAddr> {
+        match &self.bind_addr {
+            Some(addr) => addr
+                .trim()
+                .parse()
+                .map_err(|_| anyhow::anyhow!("Invalid bind address '{}'", addr)),
+            None => Ok(Self::DEFAULT_BIND_ADDR),
+        }
+    }
+
+    /// Interval between WebSocket pings, None if heartbeats are disabled
+    pub fn ws_ping_interval(&self) -> Option<Duration> {
+        match self
+            .ws_ping_interval_ms
+            .unwrap_or(Self::DEFAULT_WS_PING_INTERVAL_MS)
+        {
+            0 => None,
+            ms => Some(Duration::from_millis(ms)),
+        }
+    }
+
+    /// Time a pinged WebSocket client has to answer
+    pub fn ws_pong_timeout(&self) -> Duration {
+        Duration::from_millis(
+            self.ws_pong_timeout_ms
+                .unwrap_or(Self::DEFAULT_WS_PONG_TIMEOUT_MS),
+        )
+    }
+
+    /// The configured event bus capacity, or the default
+    pub fn event_capacity(&self) -> Result<usize> {
+        match self.event_capacity {
+            Some(0) => anyhow::bail!("Invalid event_capacity 0 (must be at least 1)"),
+            Some(capacity) => Ok(capacity),
+            None => Ok(EventBus::DEFAULT_CAPACITY),
+        }
+    }
+}
+
+/// Log capture settings (`[logs]`)
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct LogSettings {
+    /// Number of recent output lines kept in memory per instance (0 disables)
+    #[serde(default)]
+    pub memory_lines: usize,
+
+    /// Directory for `{instance_id}.stdout.log`/`.stderr.log` (per-PID files
+    /// in the temp dir if unset)
+    #[serde(default)]
+    pub dir: Option<String>,
+}
+
+/// Safety limits on instance counts (`[limits]`), unlimited when unset
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct LimitSettings {
+    /// Maximum number of instances that may exist
+    #[serde(default)]
+    pub max_instances: Option<usize>,
+
+    /// Maximum number of instances that may be running at once
+    #[serde(default)]
+    pub max_running: Option<usize>,
+
+    /// How many instances a bulk start/stop works on at once (default 4)
+    #[serde(default)]
+    pub bulk_concurrency: Option<usize>,
+}
+
+impl LimitSettings {
+    /// Default for `bulk_concurrency`
+    pub const DEFAULT_BULK_CONCURRENCY: usize = 4;
+
+    /// Effective `bulk_concurrency`, at least 1
+    pub fn bulk_concurrency(&self) -> usize {
+        self.bulk_concurrency
+            .unwrap_or(Self::DEFAULT_BULK_CONCURRENCY)
+            .max(1)
+    }
+
+    /// Fail if creating another instance would exceed `max_instances`
+    pub fn check_create(&self, instances: &InstanceRegistry) -> Result<()> {
+        match self.max_instances {
+            Some(max) if instances.len() >= max => anyhow::bail!(
+                "Instance limit reached: {} of max_instances = {} exist",
+                instances.len(),
+                max
+            ),
+            _ => Ok(()),
+        }
+    }
+
+    /// Fail if starting another instance would exceed `max_running`
+    ///
+    /// Instances that are still starting count as running.
+    pub fn check_start(&self, instances: &InstanceRegistry) -> Result<()> {
+        let Some(max) = self.max_running else {
+            return Ok(());
+        };
+        let running = instances.list_by_status(ServiceStatus::Running).len()
+            + instances.list_by_status(ServiceStatus::Starting).len();
+        if running >= max {
+            anyhow::bail!(
+                "Running limit reached: {} of max_running = {} are running",
+                running,
+                max
+            );
+        }
+        Ok(())
+    }
+}
+
+/// Instance scheduling settings (`[scheduler]`)
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SchedulerSettings {
+    /// IANA timezone for schedules that don't name one (default UTC)
+    #[serde(default)]
+    pub timezone: Option<String>,
+
+    /// What to do about scheduled times missed while USM was down
+    #[serde(default)]
+    pub missed: MissedPolicy,
+}
+
+/// Process monitor settings (`[monitor]`)
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct MonitorSettings {
+    /// Least time between refreshes of the process table, in milliseconds
+    /// (default 500; 0 refreshes on every lookup)
+    #[serde(default)]
+    pub refresh_interval_ms: Option<u64>,
+}
+
+impl MonitorSettings {
+    /// Effective refresh interval
+    pub fn refresh_interval(&self) -> Duration {
+        self.refresh_interval_ms
+            .map(Duration::from_millis)
+            .unwrap_or(crate::monitor::DEFAULT_REFRESH_INTERVAL)
+    }
 }
 
 /// Template configuration from TOML
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TemplateConfig {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub extends: Option<String>,
     pub display_name: String,
     #[serde(default)]
     pub description: Option<String>,
@@ -40,24 +508,54 @@ pub struct TemplateConfig {
     pub health_endpoint: Option<String>,
     #[serde(default = "default_health_timeout")]
     pub health_timeout_ms: u32,
+    #[serde(default = "default_stop_timeout")]
+    pub stop_timeout_ms: u32,
+    #[serde(default = "default_startup_verify")]
+    pub startup_verify_ms: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reload_signal: Option<String>,
     #[serde(default)]
     pub category: ServiceCategory,
     #[serde(default)]
     pub supports_multiple: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_instances: Option<usize>,
     #[serde(default)]
     pub is_docker: bool,
     #[serde(default)]
     pub default_env: std::collections::HashMap<String, String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub working_dir: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub health_command: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub health_expect_status: Option<u16>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub health_expect_body_contains: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub health_start_period_ms: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub restart: Option<RestartPolicy>,
 }
 
 fn default_health_timeout() -> u32 {
     5000
 }
 
+fn default_stop_timeout() -> u32 {
+    ServiceTemplate::DEFAULT_STOP_TIMEOUT_MS
+}
+
+fn default_startup_verify() -> u32 {
+    ServiceTemplate::DEFAULT_STARTUP_VERIFY_MS
+}
+
 /// Instance configuration from TOML
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct InstanceConfigFile {
     pub template: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub group: Option<String>,
     #[serde(default)]
     pub port: Option<u16>,
     #[serde(default)]
@@ -74,6 +572,30 @@ pub struct InstanceConfigFile {
     pub auto_start: bool,
     #[serde(default)]
     pub env_vars: std::collections::HashMap<String, String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub display_name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub notes: Option<String>,
+    #[serde(default, skip_serializing_if = "std::collections::HashMap::is_empty")]
+    pub labels: std::collections::HashMap<String, String>,
+    #[serde(default, skip_serializing_if = "std::collections::HashMap::is_empty")]
+    pub metadata: std::collections::HashMap<String, String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub depends_on: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub schedule: Option<ScheduleConfig>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub restart: Option<RestartPolicy>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub start_command_override: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stop_command_override: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cpu_limit_percent: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub memory_limit_mb: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub limit_action: Option<LimitAction>,
 
     // Metadata (persisted by USM)
     #[serde(default, rename = "_created_at")]
@@ -85,12 +607,19 @@ pub struct InstanceConfigFile {
 /// Configuration manager with file watching
 pub struct ConfigManager {
     config_path: PathBuf,
+    profile: Option<String>,
     _event_bus: Arc<EventBus>,
     _watcher: Option<RecommendedWatcher>,
+    /// Signalled whenever the config file may have changed on disk
+    changes: std::sync::Mutex<Option<mpsc::UnboundedReceiver<()>>>,
+    /// What USM itself last wrote, so its own saves aren't seen as edits
+    last_written: std::sync::Mutex<Option<String>>,
 }
 
 impl ConfigManager {
     /// Create a new config manager
+    ///
+    /// Also starts watching the config file; see `take_changes`.
     pub fn new(config_path: &Path, event_bus: Arc<EventBus>) -> Result<Self> {
         let config_path = config_path.to_path_buf();
 
@@ -100,17 +629,152 @@ impl ConfigManager {
             Self::create_default_config(&config_path)?;
         }
 
+        let (tx, rx) = mpsc::unbounded_channel();
+        let watcher = match Self::watch(&config_path, tx) {
+            Ok(watcher) => Some(watcher),
+            Err(e) => {
+                warn!(path = %config_path.display(), "Config hot reload disabled: {}", e);
+                None
+            },
+        };
+
         Ok(Self {
             config_path,
+            profile: None,
             _event_bus: event_bus,
-            _watcher: None,
+            _watcher: watcher,
+            changes: std::sync::Mutex::new(Some(rx)),
+            last_written: std::sync::Mutex::new(None),
         })
     }
 
+    /// Watch the config file, signalling `tx` on every change
+    ///
+    /// Watches the parent directory rather than the file itself: editors
+    /// often save by writing a new file and renaming it over the old one,
+    /// which a watch on the original file would miss.
+    fn watch(config_path: &Path, tx: mpsc::UnboundedSender<()>) -> Result<RecommendedWatcher> {
+        let file_name = config_path
+            .file_name()
+            .ok_or_else(|| anyhow::anyhow!("Config path has no file name"))?
+            .to_owned();
+        let dir = match config_path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir.to_path_buf(),
+            _ => PathBuf::from("."),
+        };
+
+        let mut watcher =
+            notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+                let Ok(event) = event else { return };
+                if event.kind.is_access() {
+                    return;
+                }
+                if event
+                    .paths
+                    .iter()
+                    .any(|p| p.file_name() == Some(file_name.as_os_str()))
+                {
+                    let _ = tx.send(());
+                }
+            })?;
+        watcher.watch(&dir, RecursiveMode::NonRecursive)?;
+        Ok(watcher)
+    }
+
+    /// Runtime state file kept next to the config (`services.state.json`)
+    pub fn state_path(&self) -> PathBuf {
+        self.config_path.with_extension("state.json")
+    }
+
+    /// Take the stream of change signals (only the first caller gets it)
+    ///
+    /// Signals arrive in bursts for a single save; consumers should debounce.
+    pub fn take_changes(&self) -> Option<mpsc::UnboundedReceiver<()>> {
+        self.changes.lock().ok()?.take()
+    }
+
+    /// Whether the file on disk differs from what USM last wrote
+    ///
+    /// Always true before USM's first save.
+    pub async fn changed_externally(&self) -> bool {
+        let Ok(content) = tokio::fs::read_to_string(&self.config_path).await else {
+            return false;
+        };
+        let last = self.last_written.lock().map(|l| l.clone()).unwrap_or(None);
+        last.as_deref() != Some(content.as_str())
+    }
+
+    /// Select a profile, overriding the config's `[defaults] profile`
+    pub fn with_profile(mut self, profile: Option<String>) -> Self {
+        self.profile = profile;
+        self
+    }
+
+    /// Find the active profile, if any
+    ///
+    /// An explicitly selected profile wins over the config default. Naming a
+    /// profile that isn't defined is an error.
+    fn active_profile(&self, config: &ConfigFile) -> Result<Option<(String, ProfileSettings)>> {
+        let name = self.profile.clone().or_else(|| {
+            config
+                .settings
+                .defaults
+                .as_ref()
+                .and_then(|d| d.profile.clone())
+        });
+        let Some(name) = name else {
+            return Ok(None);
+        };
+
+        match config.settings.profiles.get(&name) {
+            Some(profile) => Ok(Some((name, profile.clone()))),
+            None => {
+                let mut available: Vec<_> = config.settings.profiles.keys().cloned().collect();
+                available.sort();
+                anyhow::bail!(
+                    "Unknown profile '{}' (available: {})",
+                    name,
+                    if available.is_empty() {
+                        "none".to_string()
+                    } else {
+                        available.join(", ")
+                    }
+                )
+            },
+        }
+    }
+
+    /// Validate a config file without creating or modifying anything
+    ///
+    /// Fails with a message listing every problem found. Unlike `new` and
+    /// `load`, a missing file is an error and nothing is registered.
+    pub fn validate_file(config_path: &Path) -> Result<()> {
+        let content = std::fs::read_to_string(config_path).map_err(|e| {
+            anyhow::anyhow!("Cannot read config '{}': {}", config_path.display(), e)
+        })?;
+        let config = ConfigFile::parse(&content)
+            .map_err(|e| anyhow::anyhow!("Invalid config '{}': {}", config_path.display(), e))?;
+
+        let problems = config.validate();
+        if !problems.is_empty() {
+            anyhow::bail!(
+                "Invalid config '{}':\n  - {}",
+                config_path.display(),
+                problems.join("\n  - ")
+            );
+        }
+        Ok(())
+    }
+
     /// Load templates and instances from config file
     pub async fn load(&self) -> Result<(TemplateRegistry, InstanceRegistry)> {
         let content = tokio::fs::read_to_string(&self.config_path).await?;
-        let config: ConfigFile = toml::from_str(&content)?;
+        let config = ConfigFile::parse(&content)?;
+
+        let profile = self.active_profile(&config)?;
+        if let Some((ref name, _)) = profile {
+            info!(profile = %name, "Applying profile");
+        }
 
         let mut templates = TemplateRegistry::new();
         let mut instances = InstanceRegistry::new();
@@ -127,10 +791,21 @@ impl ConfigManager {
                 stop_command: tc.stop_command,
                 health_endpoint: tc.health_endpoint,
                 health_timeout_ms: tc.health_timeout_ms,
+                stop_timeout_ms: tc.stop_timeout_ms,
+                startup_verify_ms: tc.startup_verify_ms,
+                reload_signal: tc.reload_signal,
+                extends: tc.extends,
                 category: tc.category,
                 supports_multiple: tc.supports_multiple,
+                max_instances: tc.max_instances,
                 is_docker: tc.is_docker,
+                restart: tc.restart,
                 default_env: tc.default_env,
+                health_command: tc.health_command,
+                health_expect_status: tc.health_expect_status,
+                health_expect_body_contains: tc.health_expect_body_contains,
+                health_start_period_ms: tc.health_start_period_ms,
+                working_dir: tc.working_dir.map(|s| self.resolve_path(&s)),
             };
             templates.register(template)?;
         }
@@ -142,10 +817,16 @@ impl ConfigManager {
                 anyhow::anyhow!("Template '{}' not found for instance '{}'", ic.template, id)
             })?;
 
-            let port = ic.port.unwrap_or(template.default_port);
+            let mut port = ic.port.unwrap_or(template.default_port);
+            let mut env_vars = ic.env_vars;
+            if let Some((_, ref overlay)) = profile {
+                port = overlay.apply_port(port)?;
+                env_vars.extend(overlay.env_vars.clone());
+            }
 
-            let instance = ServiceInstance::from_config(InstanceConfig {
+            let mut instance = ServiceInstance::from_config(InstanceConfig {
                 instance_id: id,
+                group: ic.group,
                 template_id: ic.template,
                 port: Some(port),
                 working_dir: ic.working_dir.map(|s| self.resolve_path(&s)),
@@ -154,9 +835,31 @@ impl ConfigManager {
                 git_branch: ic.git_branch,
                 tags: ic.tags,
                 auto_start: ic.auto_start,
-                env_vars: ic.env_vars,
+                env_vars,
+                display_name: ic.display_name,
+                notes: ic.notes,
+                labels: ic.labels,
+                metadata: ic.metadata,
+                depends_on: ic.depends_on,
+                schedule: ic.schedule,
+                restart: ic.restart,
+                start_command_override: ic.start_command_override,
+                stop_command_override: ic.stop_command_override,
+                cpu_limit_percent: ic.cpu_limit_percent,
+                memory_limit_mb: ic.memory_limit_mb,
+                limit_action: ic.limit_action,
             })?;
 
+            // Metadata USM saved earlier; hand-written entries come from the config
+            instance.created_via = ic.created_via.unwrap_or_else(|| "config".to_string());
+            if let Some(created_at) = ic
+                .created_at
+                .as_deref()
+                .and_then(|t| chrono::DateTime::parse_from_rfc3339(t).ok())
+            {
+                instance.created_at = created_at.with_timezone(&chrono::Utc);
+            }
+
             instances.add(instance)?;
         }
 
@@ -169,6 +872,46 @@ impl ConfigManager {
         Ok((templates, instances))
     }
 
+    /// Load the global settings sections from the config file
+    ///
+    /// Path-valued settings are returned with variables like ${PROJECT_ROOT}
+    /// already resolved.
+    pub async fn load_settings(&self) -> Result<Settings> {
+        let content = tokio::fs::read_to_string(&self.config_path).await?;
+        let config = ConfigFile::parse(&content)?;
+        let mut settings = config.settings;
+
+        let resolve = |path: &mut Option<String>| {
+            if let Some(p) = path.as_mut() {
+                *p = self.resolve_path(p).display().to_string();
+            }
+        };
+        if let Some(server) = settings.server.as_mut() {
+            resolve(&mut server.static_dir);
+        }
+        if let Some(defaults) = settings.defaults.as_mut() {
+            resolve(&mut defaults.working_dir);
+        }
+        if let Some(logs) = settings.logs.as_mut() {
+            resolve(&mut logs.dir);
+        }
+
+        Ok(settings)
+    }
+
+    /// Settings of the config file at `config_path`, as written (paths are
+    /// not resolved); the defaults if there is no file yet
+    ///
+    /// For values needed before a `ConfigManager` exists, like the event
+    /// bus capacity.
+    pub fn read_settings(config_path: &Path) -> Result<Settings> {
+        if !config_path.exists() {
+            return Ok(Settings::default());
+        }
+        let content = std::fs::read_to_string(config_path)?;
+        Ok(ConfigFile::parse(&content)?.settings)
+    }
+
     /// Save templates to config file
     pub async fn save_templates(&self, templates: &TemplateRegistry) -> Result<()> {
         self.save_config(Some(templates), None).await
@@ -176,18 +919,26 @@ impl ConfigManager {
 
     /// Save instances to config file
     pub async fn save_instances(&self, instances: &InstanceRegistry) -> Result<()> {
-        self.save_config(None, Some(instances)).await
+        self.save_config(None, Some(SavedInstances::All(instances)))
+            .await
+    }
+
+    /// Save one instance to the config file, leaving the file's other
+    /// instances as they are
+    pub async fn save_instance(&self, instance: &ServiceInstance) -> Result<()> {
+        self.save_config(None, Some(SavedInstances::One(instance)))
+            .await
     }
 
     /// Save both templates and instances
     async fn save_config(
         &self,
         templates: Option<&TemplateRegistry>,
-        instances: Option<&InstanceRegistry>,
+        instances: Option<SavedInstances<'_>>,
     ) -> Result<()> {
         // Read existing config
         let content = tokio::fs::read_to_string(&self.config_path).await?;
-        let mut config: ConfigFile = toml::from_str(&content)?;
+        let mut config = ConfigFile::parse(&content)?;
 
         // Update templates if provided
         if let Some(templates) = templates {
@@ -204,10 +955,24 @@ impl ConfigManager {
                         stop_command: template.stop_command,
                         health_endpoint: template.health_endpoint,
                         health_timeout_ms: template.health_timeout_ms,
+                        stop_timeout_ms: template.stop_timeout_ms,
+                        startup_verify_ms: template.startup_verify_ms,
+                        reload_signal: template.reload_signal,
+                        extends: template.extends,
                         category: template.category,
                         supports_multiple: template.supports_multiple,
+                        max_instances: template.max_instances,
                         is_docker: template.is_docker,
+                        restart: template.restart,
                         default_env: template.default_env,
+                        health_command: template.health_command,
+                        health_expect_status: template.health_expect_status,
+                        health_expect_body_contains: template.health_expect_body_contains,
+                        health_start_period_ms: template.health_start_period_ms,
+                        working_dir: template
+                            .working_dir
+                            .as_ref()
+                            .map(|p| p.display().to_string()),
                     },
                 );
             }
@@ -215,12 +980,34 @@ impl ConfigManager {
 
         // Update instances if provided
         if let Some(instances) = instances {
-            config.instances.clear();
-            for instance in instances.list() {
+            // Strip the active profile's overlay so only base values are persisted
+            let profile = self.active_profile(&config)?.map(|(_, p)| p);
+            let (list, previous) = match instances {
+                SavedInstances::All(registry) => {
+                    (registry.list(), std::mem::take(&mut config.instances))
+                },
+                SavedInstances::One(instance) => (vec![instance.clone()], config.instances.clone()),
+            };
+            for mut instance in list {
+                if let Some(ref overlay) = profile {
+                    instance.port = overlay.remove_port(instance.port)?;
+                    let base_env = previous.get(&instance.id).map(|ic| &ic.env_vars);
+                    for key in overlay.env_vars.keys() {
+                        match base_env.and_then(|env| env.get(key)) {
+                            Some(value) => {
+                                instance.env_vars.insert(key.clone(), value.clone());
+                            },
+                            None => {
+                                instance.env_vars.remove(key);
+                            },
+                        }
+                    }
+                }
                 config.instances.insert(
                     instance.id.clone(),
                     InstanceConfigFile {
                         template: instance.template_id,
+                        group: instance.group,
                         port: Some(instance.port),
                         working_dir: instance
                             .working_dir
@@ -235,6 +1022,18 @@ impl ConfigManager {
                         tags: instance.tags,
                         auto_start: instance.auto_start,
                         env_vars: instance.env_vars,
+                        display_name: instance.display_name,
+                        notes: instance.notes,
+                        labels: instance.labels,
+                        metadata: instance.metadata,
+                        depends_on: instance.depends_on,
+                        schedule: instance.schedule,
+                        restart: instance.restart,
+                        start_command_override: instance.start_command_override,
+                        stop_command_override: instance.stop_command_override,
+                        cpu_limit_percent: instance.cpu_limit_percent,
+                        memory_limit_mb: instance.memory_limit_mb,
+                        limit_action: instance.limit_action,
                         created_at: Some(instance.created_at.to_rfc3339()),
                         created_via: Some(instance.created_via),
                     },
@@ -244,7 +1043,10 @@ impl ConfigManager {
 
         // Write back
         let content = toml::to_string_pretty(&config)?;
-        tokio::fs::write(&self.config_path, content).await?;
+        if let Ok(mut last) = self.last_written.lock() {
+            *last = Some(content.clone());
+        }
+        write_atomic(&self.config_path, &content).await?;
 
         debug!(path = %self.config_path.display(), "Configuration saved");
         Ok(())
@@ -325,11 +1127,93 @@ tags = ["llm"]
     }
 }
 
+/// Instances to write in `ConfigManager::save_config`
+enum SavedInstances<'a> {
+    /// Replace the file's instances with the registry's
+    All(&'a InstanceRegistry),
+    /// Replace or add just this instance
+    One(&'a ServiceInstance),
+}
+
+/// Replace `path` with `content` without ever leaving a partial file
+///
+/// Writes to a temp file next to `path`, syncs it, then renames it over the
+/// target, which is atomic on the same filesystem. A crash leaves either the
+/// old file or the new one; on error the temp file is removed. Each call
+/// gets its own temp file, so concurrent writes of one path can't mix.
+pub(crate) async fn write_atomic(path: &Path, content: &str) -> Result<()> {
+    use std::sync::atomic::{AtomicU64, Ordering};
+    use tokio::io::AsyncWriteExt;
+
+    static NEXT_TMP: AtomicU64 = AtomicU64::new(0);
+
+    let file_name = path
+        .file_name()
+        .ok_or_else(|| anyhow::anyhow!("Invalid config path: {}", path.display()))?;
+    let tmp_path = path.with_file_name(format!(
+        ".{}.tmp-{}-{}",
+        file_name.to_string_lossy(),
+        std::process::id(),
+        NEXT_TMP.fetch_add(1, Ordering::Relaxed)
+    ));
+
+    let result = async {
+        let mut file = tokio::fs::File::create(&tmp_path).await?;
+        file.write_all(content.as_bytes()).await?;
+        file.sync_all().await?;
+        drop(file);
+        tokio::fs::rename(&tmp_path, path).await
+    }
+    .await;
+
+    if let Err(e) = result {
+        let _ = tokio::fs::remove_file(&tmp_path).await;
+        return Err(anyhow::anyhow!("Failed to write {}: {}", path.display(), e));
+    }
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use tempfile::tempdir;
 
+    #[tokio::test]
+    async fn test_write_atomic() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("services.toml");
+        std::fs::write(&path, "old = true\n").unwrap();
+
+        write_atomic(&path, "new = true\n").await.unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "new = true\n");
+
+        // A failed rename (target is a non-empty directory) leaves the target
+        // alone and doesn't leak the temp file
+        let blocked = dir.path().join("blocked.toml");
+        std::fs::create_dir(&blocked).unwrap();
+        std::fs::write(blocked.join("keep"), "").unwrap();
+        assert!(write_atomic(&blocked, "x = 1\n").await.is_err());
+        assert!(blocked.join("keep").exists());
+
+        let mut names: Vec<String> = std::fs::read_dir(dir.path())
+            .unwrap()
+            .map(|e| e.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        names.sort();
+        assert_eq!(names, vec!["blocked.toml", "services.toml"]);
+
+        // Concurrent writes each publish a whole file
+        let contents: Vec<String> = (0..8)
+            .map(|i| format!("n = {}\n{}", i, "# pad\n".repeat(10_000)))
+            .collect();
+        let writes = contents.iter().map(|c| write_atomic(&path, c));
+        for result in futures_util::future::join_all(writes).await {
+            result.unwrap();
+        }
+        let written = std::fs::read_to_string(&path).unwrap();
+        assert!(contents.contains(&written));
+    }
+
     #[tokio::test]
     async fn test_load_config() {
         let dir = tempdir().unwrap();
@@ -363,6 +1247,620 @@ tags = ["test"]
         let instance = instances.get("test-instance").unwrap();
         assert_eq!(instance.port, 8001);
     }
+
+    #[test]
+    fn test_validate_reports_all_problems() {
+        let config: ConfigFile = toml::from_str(
+            r#"
+[templates.svc]
+display_name = "Service"
+default_port = 8000
+start_command = "echo start"
+
+[templates.reloading]
+display_name = "Reloading"
+default_port = 9000
+start_command = "echo start"
+reload_signal = "SIGBOGUS"
+
+[instances.a]
+template = "svc"
+
+[instances.b]
+template = "svc"
+port = 8000
+
+[instances.c]
+template = "missing"
+memory_limit_mb = 0
+"#,
+        )
+        .unwrap();
+
+        let problems = config.validate();
+        assert_eq!(problems.len(), 4);
+        assert!(problems[0].contains("unknown template 'missing'"));
+        assert_eq!(
+            problems[1],
+            "Instance 'c': memory_limit_mb must be greater than 0"
+        );
+        assert!(problems[2].contains("Port 8000 is used by multiple instances: a, b"));
+        assert_eq!(
+            problems[3],
+            "Template 'reloading' reload_signal: Unknown signal 'SIGBOGUS'"
+        );
+    }
+
+    #[test]
+    fn test_validate_grouped_ids() {
+        let config: ConfigFile = toml::from_str(
+            r#"
+[templates.svc]
+display_name = "Service"
+default_port = 8000
+start_command = "echo start"
+
+[instances.api]
+template = "svc"
+group = "projectA"
+port = 8001
+
+[instances."projectA/api"]
+template = "svc"
+port = 8002
+
+[instances."projectB/api"]
+template = "svc"
+port = 8003
+depends_on = ["projectA/api", "api"]
+"#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            config.validate(),
+            vec![
+                "Instance 'projectA/api' is defined more than once: api, projectA/api",
+                "Instance 'projectB/api' depends on unknown instance 'api'",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_validate_port_ranges() {
+        let config: ConfigFile = toml::from_str(
+            r#"
+[templates.svc]
+display_name = "Service"
+default_port = 8000
+port_range = [8000, 8009]
+start_command = "echo start"
+
+[templates.reversed]
+display_name = "Reversed"
+default_port = 9000
+port_range = [9009, 9000]
+start_command = "echo start"
+
+[templates.stray]
+display_name = "Stray"
+default_port = 7000
+port_range = [7001, 7009]
+start_command = "echo start"
+
+[instances.inside]
+template = "svc"
+port = 8009
+
+[instances.outside]
+template = "svc"
+port = 8010
+"#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            config.validate(),
+            vec![
+                "Instance 'outside' port 8010 is outside the port range 8000-8009 of template 'svc'",
+                "Template 'reversed' port_range 9009-9000 is reversed",
+                "Template 'stray' default_port 7000 is outside its port range 7001-7009",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_bind_addr() {
+        assert_eq!(
+            ServerSettings::default().bind_addr().unwrap(),
+            ServerSettings::DEFAULT_BIND_ADDR
+        );
+
+        let config: ConfigFile = toml::from_str("[server]\nbind_addr = \"::\"\n").unwrap();
+        let server = config.settings.server.as_ref().unwrap();
+        assert_eq!(server.bind_addr().unwrap().to_string(), "::");
+        assert!(config.validate().is_empty());
+
+        let config: ConfigFile =
+            toml::from_str("[server]\nbind_addr = \"localhost:80\"\n").unwrap();
+        assert_eq!(
+            config.validate(),
+            vec!["[server] Invalid bind address 'localhost:80'"]
+        );
+    }
+
+    #[test]
+    fn test_validate_file() {
+        let dir = tempdir().unwrap();
+        let config_path = dir.path().join("services.toml");
+
+        // Missing files are reported, not created
+        assert!(ConfigManager::validate_file(&config_path).is_err());
+        assert!(!config_path.exists());
+
+        std::fs::write(&config_path, "[instances.orphan]\ntemplate = \"nope\"\n").unwrap();
+        let err = ConfigManager::validate_file(&config_path).unwrap_err();
+        assert!(err.to_string().contains("unknown template 'nope'"));
+
+        std::fs::write(
+            &config_path,
+            "[templates.svc]\ndisplay_name = \"Service\"\ndefault_port = 8000\nstart_command = \"serve --port {porrt}\"\n",
+        )
+        .unwrap();
+        let err = ConfigManager::validate_file(&config_path).unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("Template 'svc' start_command: unknown placeholder '{porrt}'"));
+
+        std::fs::write(&config_path, "[templates.svc]\nextends = \"svc\"\n").unwrap();
+        let err = ConfigManager::validate_file(&config_path).unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("Template inheritance cycle: svc -> svc"));
+    }
+
+    #[tokio::test]
+    async fn test_template_inheritance_load_and_save() {
+        let dir = tempdir().unwrap();
+        let config_path = dir.path().join("services.toml");
+        std::fs::write(
+            &config_path,
+            r#"
+[templates.api]
+display_name = "API"
+default_port = 8000
+start_command = "serve --port {port}"
+default_env = { LOG_LEVEL = "info" }
+
+[templates.api-debug]
+extends = "api"
+display_name = "API (debug)"
+default_env = { LOG_LEVEL = "debug" }
+
+[instances.api-debug-1]
+template = "api-debug"
+"#,
+        )
+        .unwrap();
+        let event_bus = Arc::new(EventBus::new(10));
+        let manager = ConfigManager::new(&config_path, event_bus).unwrap();
+
+        let (templates, instances) = manager.load().await.unwrap();
+        let debug = templates.get("api-debug").unwrap();
+        assert_eq!(debug.extends.as_deref(), Some("api"));
+        assert_eq!(debug.start_command, "serve --port {port}");
+        assert_eq!(debug.default_env["LOG_LEVEL"], "debug");
+        assert_eq!(instances.get("api-debug-1").unwrap().port, 8000);
+
+        // Saving writes the child back with only its own keys
+        manager.save_templates(&templates).await.unwrap();
+        manager.save_instances(&instances).await.unwrap();
+        let saved: toml::Table =
+            toml::from_str(&std::fs::read_to_string(&config_path).unwrap()).unwrap();
+        let child = saved["templates"]["api-debug"].as_table().unwrap();
+        let mut keys: Vec<&str> = child.keys().map(String::as_str).collect();
+        keys.sort();
+        assert_eq!(keys, vec!["default_env", "display_name", "extends"]);
+
+        let (templates, _) = manager.load().await.unwrap();
+        assert_eq!(templates.get("api-debug").unwrap().default_port, 8000);
+    }
+
+    #[tokio::test]
+    async fn test_load_settings() {
+        let dir = tempdir().unwrap();
+        let config_path = dir.path().join("services.toml");
+        std::fs::write(
+            &config_path,
+            "[server]\nstatic_dir = \"/srv/usm\"\n\n[defaults]\nworking_dir = \"${PROJECT_ROOT}/services\"\n\n[logs]\nmemory_lines = 50\ndir = \"~/usm-logs\"\n",
+        )
+        .unwrap();
+
+        let event_bus = Arc::new(EventBus::new(16));
+        let manager = ConfigManager::new(&config_path, event_bus).unwrap();
+        let settings = manager.load_settings().await.unwrap();
+
+        let logs = settings.logs.unwrap();
+        assert_eq!(logs.memory_lines, 50);
+        let log_dir = logs.dir.unwrap();
+        assert!(!log_dir.starts_with('~'));
+        assert!(log_dir.ends_with("/usm-logs"));
+        assert_eq!(
+            settings.server.unwrap().static_dir.as_deref(),
+            Some("/srv/usm")
+        );
+        let working_dir = settings.defaults.unwrap().working_dir.unwrap();
+        assert!(!working_dir.contains("${PROJECT_ROOT}"));
+        assert!(working_dir.ends_with("/services"));
+    }
+
+    #[test]
+    fn test_monitor_refresh_interval() {
+        let settings: Settings = toml::from_str("[monitor]\nrefresh_interval_ms = 2000\n").unwrap();
+        assert_eq!(
+            settings.monitor.unwrap().refresh_interval(),
+            Duration::from_millis(2000)
+        );
+        assert_eq!(
+            MonitorSettings::default().refresh_interval(),
+            crate::monitor::DEFAULT_REFRESH_INTERVAL
+        );
+    }
+
+    const PROFILE_CONFIG: &str = r#"
+[templates.api]
+display_name = "API"
+default_port = 8000
+start_command = "serve --port {port}"
+
+[instances.api-main]
+template = "api"
+port = 8000
+
+[instances.api-main.env_vars]
+LOG_LEVEL = "info"
+
+[defaults]
+profile = "dev"
+
+[profiles.dev]
+port_offset = 100
+env_vars = { LOG_LEVEL = "debug" }
+
+[profiles.prod]
+env_vars = { LOG_LEVEL = "warn", REGION = "us" }
+"#;
+
+    #[tokio::test]
+    async fn test_profile_overlays_port_and_env() {
+        let dir = tempdir().unwrap();
+        let config_path = dir.path().join("services.toml");
+        std::fs::write(&config_path, PROFILE_CONFIG).unwrap();
+        let event_bus = Arc::new(EventBus::new(16));
+
+        // Config default profile
+        let manager = ConfigManager::new(&config_path, event_bus.clone()).unwrap();
+        let (_, instances) = manager.load().await.unwrap();
+        let api = instances.get("api-main").unwrap();
+        assert_eq!(api.port, 8100);
+        assert_eq!(api.env_vars.get("LOG_LEVEL").unwrap(), "debug");
+
+        // Explicit profile overrides the default
+        let manager = ConfigManager::new(&config_path, event_bus.clone())
+            .unwrap()
+            .with_profile(Some("prod".to_string()));
+        let (_, instances) = manager.load().await.unwrap();
+        let api = instances.get("api-main").unwrap();
+        assert_eq!(api.port, 8000);
+        assert_eq!(api.env_vars.get("LOG_LEVEL").unwrap(), "warn");
+        assert_eq!(api.env_vars.get("REGION").unwrap(), "us");
+
+        // Unknown profile is a clear error
+        let manager = ConfigManager::new(&config_path, event_bus)
+            .unwrap()
+            .with_profile(Some("staging".to_string()));
+        let err = manager.load().await.unwrap_err().to_string();
+        assert!(err.contains("Unknown profile 'staging'"));
+        assert!(err.contains("dev, prod"));
+    }
+
+    #[tokio::test]
+    async fn test_notes_labels_and_metadata_round_trip() {
+        let dir = tempdir().unwrap();
+        let config_path = dir.path().join("services.toml");
+        std::fs::write(
+            &config_path,
+            r#"
+[templates.api]
+display_name = "API"
+default_port = 8000
+start_command = "serve --port {port}"
+
+[instances.api-main]
+template = "api"
+display_name = "Main API"
+notes = "Do not restart during business hours"
+
+[instances.api-main.labels]
+owner = "team-x"
+tier = "gold"
+
+[instances.api-main.metadata]
+deploy_sha = "abc123"
+
+[instances.api-plain]
+template = "api"
+port = 8001
+"#,
+        )
+        .unwrap();
+
+        let event_bus = Arc::new(EventBus::new(16));
+        let manager = ConfigManager::new(&config_path, event_bus).unwrap();
+        let (_, instances) = manager.load().await.unwrap();
+        manager.save_instances(&instances).await.unwrap();
+
+        let (_, reloaded) = manager.load().await.unwrap();
+        let api = reloaded.get("api-main").unwrap();
+        assert_eq!(
+            api.notes.as_deref(),
+            Some("Do not restart during business hours")
+        );
+        assert_eq!(api.labels.get("owner").unwrap(), "team-x");
+        assert_eq!(api.labels.get("tier").unwrap(), "gold");
+        assert_eq!(api.metadata.get("deploy_sha").unwrap(), "abc123");
+        assert!(!api.env_vars.contains_key("deploy_sha"));
+        assert_eq!(api.display_name.as_deref(), Some("Main API"));
+
+        let plain = reloaded.get("api-plain").unwrap();
+        assert!(plain.notes.is_none());
+        assert!(plain.display_name.is_none());
+        assert!(plain.labels.is_empty());
+        assert!(plain.metadata.is_empty());
+
+        // Empty notes/labels/metadata aren't written out
+        let content = std::fs::read_to_string(&config_path).unwrap();
+        assert!(content.contains("[instances.api-main.metadata]"));
+        assert!(!content.contains("[instances.api-plain.labels]"));
+        assert!(!content.contains("[instances.api-plain.metadata]"));
+        assert_eq!(content.matches("notes =").count(), 1);
+        assert_eq!(content.matches("display_name = \"Main API\"").count(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_creation_metadata_round_trip() {
+        let dir = tempdir().unwrap();
+        let config_path = dir.path().join("services.toml");
+        std::fs::write(
+            &config_path,
+            r#"
+[templates.api]
+display_name = "API"
+default_port = 8000
+start_command = "serve --port {port}"
+
+[instances.api-saved]
+template = "api"
+_created_at = "2024-03-01T12:00:00+00:00"
+_created_via = "api"
+
+[instances.api-manual]
+template = "api"
+port = 8001
+"#,
+        )
+        .unwrap();
+
+        let event_bus = Arc::new(EventBus::new(16));
+        let manager = ConfigManager::new(&config_path, event_bus).unwrap();
+        let (_, instances) = manager.load().await.unwrap();
+        let saved = instances.get("api-saved").unwrap();
+        assert_eq!(saved.created_via, "api");
+        assert_eq!(saved.created_at.to_rfc3339(), "2024-03-01T12:00:00+00:00");
+        assert_eq!(instances.get("api-manual").unwrap().created_via, "config");
+
+        manager.save_instances(&instances).await.unwrap();
+        let content = std::fs::read_to_string(&config_path).unwrap();
+        assert!(content.contains("_created_at = \"2024-03-01T12:00:00+00:00\""));
+        assert_eq!(content.matches("_created_via = \"config\"").count(), 1);
+
+        let (_, reloaded) = manager.load().await.unwrap();
+        let manual = reloaded.get("api-manual").unwrap();
+        assert_eq!(manual.created_via, "config");
+        assert_eq!(
+            manual.created_at,
+            instances.get("api-manual").unwrap().created_at
+        );
+
+        // The API sees the clean names, not the config keys
+        let json = serde_json::to_value(saved).unwrap();
+        assert_eq!(json["created_via"], "api");
+        assert!(json.get("created_at").is_some());
+        assert!(json.get("_created_at").is_none());
+        assert!(json.get("_created_via").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_command_overrides_round_trip() {
+        let dir = tempdir().unwrap();
+        let config_path = dir.path().join("services.toml");
+        std::fs::write(
+            &config_path,
+            r#"
+[templates.api]
+display_name = "API"
+default_port = 8000
+start_command = "serve --port {port}"
+
+[instances.api-main]
+template = "api"
+start_command_override = "serve --port {port} --debug"
+stop_command_override = "api-ctl stop {pid}"
+
+[instances.api-plain]
+template = "api"
+port = 8001
+"#,
+        )
+        .unwrap();
+
+        let event_bus = Arc::new(EventBus::new(16));
+        let manager = ConfigManager::new(&config_path, event_bus).unwrap();
+        let (_, instances) = manager.load().await.unwrap();
+        manager.save_instances(&instances).await.unwrap();
+
+        let (_, reloaded) = manager.load().await.unwrap();
+        let api = reloaded.get("api-main").unwrap();
+        assert_eq!(
+            api.start_command_override.as_deref(),
+            Some("serve --port {port} --debug")
+        );
+        assert_eq!(
+            api.stop_command_override.as_deref(),
+            Some("api-ctl stop {pid}")
+        );
+        assert!(reloaded
+            .get("api-plain")
+            .unwrap()
+            .start_command_override
+            .is_none());
+
+        let content = std::fs::read_to_string(&config_path).unwrap();
+        assert_eq!(content.matches("start_command_override =").count(), 1);
+
+        // Overrides are held to the same placeholders as templates
+        std::fs::write(&config_path, content.replace("--debug", "--debug {prot}")).unwrap();
+        let err = ConfigManager::validate_file(&config_path).unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("Instance 'api-main' start_command_override: unknown placeholder '{prot}'"));
+    }
+
+    #[tokio::test]
+    async fn test_restart_policy_round_trip() {
+        let dir = tempdir().unwrap();
+        let config_path = dir.path().join("services.toml");
+        std::fs::write(
+            &config_path,
+            r#"
+[templates.api]
+display_name = "API"
+default_port = 8000
+start_command = "serve --port {port}"
+
+[templates.api.restart]
+policy = "on-failure"
+backoff_ms = 500
+
+[instances.api-main]
+template = "api"
+
+[instances.api-pinned]
+template = "api"
+port = 8001
+
+[instances.api-pinned.restart]
+policy = "no"
+
+[instances.api-limited]
+template = "api"
+port = 8002
+memory_limit_mb = 512
+cpu_limit_percent = 150.0
+limit_action = "stop"
+"#,
+        )
+        .unwrap();
+
+        let event_bus = Arc::new(EventBus::new(16));
+        let manager = ConfigManager::new(&config_path, event_bus).unwrap();
+        let (templates, instances) = manager.load().await.unwrap();
+        manager.save_templates(&templates).await.unwrap();
+        manager.save_instances(&instances).await.unwrap();
+
+        let (templates, instances) = manager.load().await.unwrap();
+        let api = templates.get("api").unwrap();
+        let policy = api
+            .restart_policy(&instances.get("api-main").unwrap())
+            .unwrap();
+        assert_eq!(policy.backoff_ms, 500);
+        assert_eq!(policy.max_retries, 5);
+        // The instance's own policy wins, and "no" disables restarts
+        assert!(api
+            .restart_policy(&instances.get("api-pinned").unwrap())
+            .is_none());
+
+        let limited = instances.get("api-limited").unwrap();
+        assert_eq!(limited.memory_limit_mb, Some(512));
+        assert_eq!(limited.cpu_limit_percent, Some(150.0));
+        assert_eq!(limited.limit_action, Some(LimitAction::Stop));
+        let content = std::fs::read_to_string(&config_path).unwrap();
+        assert_eq!(content.matches("limit_action =").count(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_health_expectations_round_trip() {
+        let dir = tempdir().unwrap();
+        let config_path = dir.path().join("services.toml");
+        std::fs::write(
+            &config_path,
+            r#"
+[templates.api]
+display_name = "API"
+default_port = 8000
+start_command = "serve --port {port}"
+health_endpoint = "http://localhost:{port}/health"
+health_expect_status = 200
+health_expect_body_contains = '"status":"ok"'
+health_start_period_ms = 15000
+
+[templates.plain]
+display_name = "Plain"
+default_port = 9000
+start_command = "serve --port {port}"
+"#,
+        )
+        .unwrap();
+
+        let event_bus = Arc::new(EventBus::new(16));
+        let manager = ConfigManager::new(&config_path, event_bus).unwrap();
+        let (templates, _) = manager.load().await.unwrap();
+        manager.save_templates(&templates).await.unwrap();
+
+        let (templates, _) = manager.load().await.unwrap();
+        let api = templates.get("api").unwrap();
+        assert_eq!(api.health_expect_status, Some(200));
+        assert_eq!(
+            api.health_expect_body_contains.as_deref(),
+            Some(r#""status":"ok""#)
+        );
+        assert_eq!(api.health_start_period_ms, Some(15000));
+        let plain = templates.get("plain").unwrap();
+        assert_eq!(plain.health_expect_status, None);
+        assert_eq!(plain.health_expect_body_contains, None);
+        assert_eq!(plain.health_start_period_ms, None);
+        let content = std::fs::read_to_string(&config_path).unwrap();
+        assert_eq!(content.matches("health_expect_status =").count(), 1);
+        assert_eq!(content.matches("health_start_period_ms =").count(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_profile_overlay_not_persisted() {
+        let dir = tempdir().unwrap();
+        let config_path = dir.path().join("services.toml");
+        std::fs::write(&config_path, PROFILE_CONFIG).unwrap();
+        let event_bus = Arc::new(EventBus::new(16));
+
+        let manager = ConfigManager::new(&config_path, event_bus).unwrap();
+        let (_, instances) = manager.load().await.unwrap();
+        manager.save_instances(&instances).await.unwrap();
+
+        let content = std::fs::read_to_string(&config_path).unwrap();
+        let config: ConfigFile = toml::from_str(&content).unwrap();
+        let api = config.instances.get("api-main").unwrap();
+        assert_eq!(api.port, Some(8000));
+        assert_eq!(api.env_vars.get("LOG_LEVEL").unwrap(), "info");
+    }
 }
 
 /// Property-based tests for configuration management
@@ -453,10 +1951,21 @@ mod property_tests {
                 stop_command: None,
                 health_endpoint: Some(format!("http://localhost:{}/health", port)),
                 health_timeout_ms: 5000,
+                stop_timeout_ms: 10000,
+                startup_verify_ms: 3000,
+                reload_signal: None,
+                extends: None,
                 category: ServiceCategory::Core,
                 supports_multiple: true,
+                max_instances: None,
                 is_docker: false,
+                restart: None,
                 default_env: std::collections::HashMap::new(),
+                health_command: None,
+                health_expect_status: None,
+                health_expect_body_contains: None,
+                health_start_period_ms: None,
+                working_dir: None,
             };
 
             // Serialize to TOML
@@ -480,6 +1989,7 @@ mod property_tests {
         ) {
             let instance = InstanceConfigFile {
                 template: template_id.clone(),
+                group: None,
                 port: Some(port),
                 working_dir: Some("/test/path".to_string()),
                 config: None,
@@ -488,6 +1998,18 @@ mod property_tests {
                 tags: vec!["test".to_string(), "property".to_string()],
                 auto_start: true,
                 env_vars: std::collections::HashMap::new(),
+                display_name: None,
+                notes: None,
+                labels: Default::default(),
+                metadata: Default::default(),
+                depends_on: Vec::new(),
+                schedule: None,
+                restart: None,
+                start_command_override: None,
+                stop_command_override: None,
+                cpu_limit_percent: None,
+                memory_limit_mb: None,
+                limit_action: None,
                 created_at: None,
                 created_via: None,
             };
@@ -585,6 +2107,7 @@ mod property_tests {
             let mut config = ConfigFile {
                 templates: std::collections::HashMap::new(),
                 instances: std::collections::HashMap::new(),
+                settings: Settings::default(),
             };
 
             // Add some templates
@@ -600,10 +2123,21 @@ mod property_tests {
                         stop_command: None,
                         health_endpoint: None,
                         health_timeout_ms: 5000,
+                        stop_timeout_ms: 10000,
+                        startup_verify_ms: 3000,
+                        reload_signal: None,
+                        extends: None,
                         category: ServiceCategory::Core,
                         supports_multiple: false,
+                        max_instances: None,
                         is_docker: false,
+                        restart: None,
                         default_env: std::collections::HashMap::new(),
+                        health_command: None,
+                        health_expect_status: None,
+                        health_expect_body_contains: None,
+                        health_start_period_ms: None,
+                        working_dir: None,
                     },
                 );
             }
@@ -0,0 +1,324 @@
+//! Docker Compose support for `is_docker` templates
+//!
+//! Instances of a Docker template are not host processes: starting one runs
+//! `docker compose up -d`, stopping it runs `docker compose down`, and its
+//! health and metrics come from the project's containers. Each instance is
+//! its own compose project (named after the instance), so several instances
+//! can share a compose file. The instance's `config_path` is the compose
+//! file; without one, compose looks in the working directory.
+
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use anyhow::Result;
+use serde::Deserialize;
+
+use crate::metrics::InstanceMetrics;
+use crate::service::ServiceInstance;
+
+/// The compose project backing one instance
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ComposeProject {
+    /// Compose project name (`-p`)
+    pub name: String,
+    /// Compose file (`-f`), if the instance sets `config_path`
+    pub file: Option<PathBuf>,
+    /// Project directory, used for relative paths and `.env`
+    pub project_dir: Option<PathBuf>,
+}
+
+impl ComposeProject {
+    /// The project for an instance, run from `working_dir`
+    pub fn for_instance(instance: &ServiceInstance, working_dir: Option<&Path>) -> Self {
+        Self {
+            name: project_name(&instance.id),
+            file: instance.config_path.clone(),
+            project_dir: working_dir.map(Path::to_path_buf),
+        }
+    }
+
+    /// `docker compose` with the file and project directory, if any
+    fn base(&self) -> String {
+        let mut cmd = String::from("docker compose");
+        if let Some(file) = &self.file {
+            cmd.push_str(&format!(" -f {}", shell_quote(&file.display().to_string())));
+        }
+        if let Some(dir) = &self.project_dir {
+            cmd.push_str(&format!(
+                " --project-directory {}",
+                shell_quote(&dir.display().to_string())
+            ));
+        }
+        cmd.push_str(&format!(" -p {}", self.name));
+        cmd
+    }
+
+    /// Command that starts the project's containers in the background
+    pub fn up_command(&self) -> String {
+        format!("{} up -d", self.base())
+    }
+
+    /// Command that stops and removes the project's containers
+    pub fn down_command(&self) -> String {
+        format!("{} down", self.base())
+    }
+
+    /// Command listing the project's containers as JSON
+    ///
+    /// Only needs the project name, so it works without the compose file.
+    pub fn ps_command(&self) -> String {
+        format!("docker compose -p {} ps -a --format json", self.name)
+    }
+
+    /// Command printing `docker stats` for the project's running containers
+    pub fn stats_command(&self) -> String {
+        format!(
+            "ids=$(docker compose -p {} ps -q) && [ -n \"$ids\" ] && \
+             docker stats --no-stream --format '{{{{json .}}}}' $ids",
+            self.name
+        )
+    }
+
+    /// Current state of the project's containers
+    pub fn containers(&self) -> Result<Vec<ContainerState>> {
+        parse_ps(&run(&self.ps_command())?)
+    }
+
+    /// Resource usage summed over the project's running containers
+    ///
+    /// None if nothing is running or docker can't be reached.
+    pub fn metrics(&self) -> Option<InstanceMetrics> {
+        let output = run(&self.stats_command()).ok()?;
+        parse_stats(&output)
+    }
+}
+
+/// Compose project name for an instance
+///
+/// Compose only allows lowercase letters, digits, `-` and `_`.
+pub fn project_name(instance_id: &str) -> String {
+    instance_id
+        .chars()
+        .map(|c| match c.to_ascii_lowercase() {
+            c @ ('a'..='z' | '0'..='9' | '-' | '_') => c,
+            _ => '-',
+        })
+        .collect()
+}
+
+/// One container as reported by `docker compose ps --format json`
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct ContainerState {
+    pub name: String,
+    #[serde(default)]
+    pub service: String,
+    /// e.g. "running", "exited", "restarting"
+    pub state: String,
+    /// "healthy", "unhealthy", "starting", or empty without a healthcheck
+    #[serde(default)]
+    pub health: String,
+}
+
+impl ContainerState {
+    /// Running, and healthy if the container defines a healthcheck
+    pub fn is_healthy(&self) -> bool {
+        self.state == "running" && (self.health.is_empty() || self.health == "healthy")
+    }
+}
+
+/// Parse `docker compose ps --format json`
+///
+/// Older compose versions print a JSON array, newer ones one object per line.
+pub fn parse_ps(output: &str) -> Result<Vec<ContainerState>> {
+    let output = output.trim();
+    if output.starts_with('[') {
+        return Ok(serde_json::from_str(output)?);
+    }
+    output
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| Ok(serde_json::from_str(line)?))
+        .collect()
+}
+
+/// Whether a started project has gone down: none of its containers is
+/// running or restarting (one-shot containers may have exited normally)
+pub fn is_down(containers: &[ContainerState]) -> bool {
+    !containers
+        .iter()
+        .any(|c| c.state == "running" || c.state == "restarting")
+}
+
+/// Healthy if the project has containers and all of them are healthy
+pub fn check_health(containers: &[ContainerState]) -> Result<()> {
+    if containers.is_empty() {
+        anyhow::bail!("No containers found for compose project");
+    }
+    match containers.iter().find(|c| !c.is_healthy()) {
+        Some(c) if c.health.is_empty() => anyhow::bail!("Container {} is {}", c.name, c.state),
+        Some(c) => anyhow::bail!("Container {} is {} ({})", c.name, c.state, c.health),
+        None => Ok(()),
+    }
+}
+
+/// One line of `docker stats --format '{{json .}}'`
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct StatsLine {
+    #[serde(rename = "CPUPerc")]
+    cpu_perc: String,
+    mem_usage: String,
+    mem_perc: String,
+    #[serde(rename = "PIDs", default)]
+    pids: String,
+}
+
+/// Sum `docker stats` lines into one set of metrics
+///
+/// Threads are the containers' PID counts; open files and uptime aren't
+/// reported by docker stats and stay 0.
+pub fn parse_stats(output: &str) -> Option<InstanceMetrics> {
+    let lines: Vec<StatsLine> = output
+        .lines()
+        .filter_map(|line| serde_json::from_str(line).ok())
+        .collect();
+    if lines.is_empty() {
+        return None;
+    }
+
+    let mut metrics = InstanceMetrics {
+        cpu_percent: 0.0,
+        memory_bytes: 0,
+        memory_percent: 0.0,
+        threads: 0,
+        open_files: 0,
+        uptime_seconds: 0,
+    };
+    for line in lines {
+        metrics.cpu_percent += parse_percent(&line.cpu_perc);
+        metrics.memory_percent += parse_percent(&line.mem_perc);
+        // "12.5MiB / 7.6GiB": usage before the slash
+        let usage = line.mem_usage.split('/').next().unwrap_or_default();
+        metrics.memory_bytes += parse_size(usage).unwrap_or(0);
+        metrics.threads += line.pids.trim().parse::<u32>().unwrap_or(0);
+    }
+    Some(metrics)
+}
+
+fn parse_percent(value: &str) -> f64 {
+    value.trim().trim_end_matches('%').parse().unwrap_or(0.0)
+}
+
+/// Parse a docker size like "12.5MiB" or "1.2kB" into bytes
+fn parse_size(value: &str) -> Option<u64> {
+    let value = value.trim();
+    let split = value
+        .find(|c: char| c.is_ascii_alphabetic())
+        .unwrap_or(value.len());
+    let (number, unit) = value.split_at(split);
+    let number: f64 = number.trim().parse().ok()?;
+    let multiplier = match unit {
+        "" | "B" => 1.0,
+        "kB" | "KB" => 1e3,
+        "MB" => 1e6,
+        "GB" => 1e9,
+        "TB" => 1e12,
+        "KiB" => 1024.0,
+        "MiB" => 1024.0 * 1024.0,
+        "GiB" => 1024.0 * 1024.0 * 1024.0,
+        "TiB" => 1024.0 * 1024.0 * 1024.0 * 1024.0,
+        _ => return None,
+    };
+    Some((number * multiplier) as u64)
+}
+
+/// Run a shell command and return its stdout
+fn run(command: &str) -> Result<String> {
+    let output = Command::new("/bin/sh").args(["-c", command]).output()?;
+    if !output.status.success() {
+        anyhow::bail!(
+            "Command failed with status {:?}: {}",
+            output.status.code(),
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// Single-quote a value for `sh -c`
+fn shell_quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', r"'\''"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn project(file: Option<&str>, dir: Option<&str>) -> ComposeProject {
+        ComposeProject {
+            name: project_name("Postgres.Main"),
+            file: file.map(PathBuf::from),
+            project_dir: dir.map(PathBuf::from),
+        }
+    }
+
+    #[test]
+    fn test_commands() {
+        let p = project(Some("/srv/db/compose.yml"), Some("/srv/db"));
+        assert_eq!(p.name, "postgres-main");
+        assert_eq!(
+            p.up_command(),
+            "docker compose -f '/srv/db/compose.yml' --project-directory '/srv/db' \
+             -p postgres-main up -d"
+        );
+        assert_eq!(
+            project(None, None).down_command(),
+            "docker compose -p postgres-main down"
+        );
+        assert_eq!(
+            p.ps_command(),
+            "docker compose -p postgres-main ps -a --format json"
+        );
+        assert!(p.stats_command().contains("--format '{{json .}}'"));
+    }
+
+    #[test]
+    fn test_parse_ps_and_health() {
+        // Newer compose: one object per line
+        let ndjson = r#"{"Name":"db-1","Service":"db","State":"running","Health":"healthy"}
+{"Name":"web-1","Service":"web","State":"running","Health":""}"#;
+        let containers = parse_ps(ndjson).unwrap();
+        assert_eq!(containers.len(), 2);
+        assert!(check_health(&containers).is_ok());
+
+        // Older compose: a JSON array
+        let array = r#"[{"Name":"db-1","Service":"db","State":"running","Health":"starting"}]"#;
+        let err = check_health(&parse_ps(array).unwrap()).unwrap_err();
+        assert_eq!(err.to_string(), "Container db-1 is running (starting)");
+
+        let exited = r#"{"Name":"web-1","State":"exited"}"#;
+        let err = check_health(&parse_ps(exited).unwrap()).unwrap_err();
+        assert_eq!(err.to_string(), "Container web-1 is exited");
+
+        assert!(check_health(&parse_ps("").unwrap()).is_err());
+
+        let setup = r#"{"Name":"setup-1","State":"exited"}"#;
+        assert!(is_down(&parse_ps(setup).unwrap()));
+        assert!(!is_down(&parse_ps(&format!("{setup}\n{ndjson}")).unwrap()));
+        assert!(is_down(&[]));
+    }
+
+    #[test]
+    fn test_parse_stats() {
+        let output = r#"{"CPUPerc":"1.50%","MemUsage":"10MiB / 1GiB","MemPerc":"0.98%","PIDs":"4"}
+{"CPUPerc":"0.50%","MemUsage":"1.5kB / 1GiB","MemPerc":"0.00%","PIDs":"1"}"#;
+        let metrics = parse_stats(output).unwrap();
+        assert!((metrics.cpu_percent - 2.0).abs() < 1e-9);
+        assert_eq!(metrics.memory_bytes, 10 * 1024 * 1024 + 1500);
+        assert_eq!(metrics.threads, 5);
+        assert!(parse_stats("").is_none());
+        assert_eq!(parse_size("2GB"), Some(2_000_000_000));
+        assert_eq!(parse_size("3 lightyears"), None);
+    }
+}
@@ -1,7 +1,9 @@
 //! Event bus for broadcasting events to multiple subscribers
 
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+
 use tokio::sync::broadcast;
-use tracing::trace;
+use tracing::{trace, warn};
 
 use super::ServiceEvent;
 
@@ -12,25 +14,64 @@ use super::ServiceEvent;
 /// block the sender).
 pub struct EventBus {
     sender: broadcast::Sender<ServiceEvent>,
+    capacity: usize,
+    /// Set while the buffer is full, so the lag warning is logged once
+    lagging: AtomicBool,
+    /// Times a subscriber reported `RecvError::Lagged` (see `record_lag`)
+    lagged: AtomicU64,
 }
 
 impl EventBus {
+    /// Capacity used when `[server] event_capacity` isn't set
+    pub const DEFAULT_CAPACITY: usize = 1024;
+
     /// Create a new event bus with the specified capacity
     ///
     /// Capacity determines how many events can be buffered before
     /// slow receivers start missing events.
     pub fn new(capacity: usize) -> Self {
         let (sender, _) = broadcast::channel(capacity);
-        Self { sender }
+        Self {
+            sender,
+            capacity,
+            lagging: AtomicBool::new(false),
+            lagged: AtomicU64::new(0),
+        }
     }
 
     /// Send an event to all subscribers
     ///
     /// Returns the number of receivers that received the event.
     /// Returns 0 if there are no active subscribers.
+    ///
+    /// Logs a warning when fewer receivers got the event than were
+    /// subscribed, and when the buffer fills up: from then on the slowest
+    /// receiver misses events until it catches up.
     pub fn send(&self, event: ServiceEvent) -> usize {
-        trace!(event_type = %event.event_type(), "Broadcasting event");
-        self.sender.send(event).unwrap_or(0)
+        let event_type = event.event_type();
+        trace!(event_type = %event_type, "Broadcasting event");
+        let expected = self.subscriber_count();
+        let sent = self.sender.send(event).unwrap_or(0);
+        if sent < expected {
+            warn!(
+                event_type = %event_type,
+                sent,
+                subscribers = expected,
+                "Event reached fewer receivers than subscribed"
+            );
+        }
+
+        let full = sent > 0 && self.sender.len() >= self.capacity;
+        if full && !self.lagging.swap(true, Ordering::Relaxed) {
+            warn!(
+                capacity = self.capacity,
+                subscribers = sent,
+                "Event buffer full, lagging receivers will drop events"
+            );
+        } else if !full {
+            self.lagging.store(false, Ordering::Relaxed);
+        }
+        sent
     }
 
     /// Subscribe to events
@@ -42,15 +83,83 @@ impl EventBus {
         self.sender.subscribe()
     }
 
+    /// Subscribe to the events of one instance (see `InstanceEvents`)
+    pub fn subscribe_instance(&self, instance_id: &str) -> InstanceEvents {
+        InstanceEvents {
+            receiver: self.subscribe(),
+            instance_id: instance_id.to_string(),
+        }
+    }
+
     /// Get the current number of active subscribers
     pub fn subscriber_count(&self) -> usize {
         self.sender.receiver_count()
     }
+
+    /// Get the number of events buffered for slow subscribers
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Count a `RecvError::Lagged` seen by a subscriber, i.e. a receiver
+    /// that fell more than `capacity` events behind and missed some
+    pub fn record_lag(&self) {
+        self.lagged.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// How many times subscribers have reported lagging
+    pub fn lag_count(&self) -> u64 {
+        self.lagged.load(Ordering::Relaxed)
+    }
+}
+
+/// A subscription that only yields events about one instance
+///
+/// Events for other instances, and those about no instance at all (template
+/// and config changes), are skipped, so a client watching one service
+/// doesn't have to filter the whole stream.
+pub struct InstanceEvents {
+    receiver: broadcast::Receiver<ServiceEvent>,
+    instance_id: String,
+}
+
+impl InstanceEvents {
+    /// The instance whose events this yields
+    pub fn instance_id(&self) -> &str {
+        &self.instance_id
+    }
+
+    /// Wait for the instance's next event
+    ///
+    /// Fails like `broadcast::Receiver::recv`; the count in a `Lagged` error
+    /// includes missed events for other instances.
+    pub async fn recv(&mut self) -> Result<ServiceEvent, broadcast::error::RecvError> {
+        loop {
+            let event = self.receiver.recv().await?;
+            if self.matches(&event) {
+                return Ok(event);
+            }
+        }
+    }
+
+    /// The instance's next event if one is already buffered
+    pub fn try_recv(&mut self) -> Result<ServiceEvent, broadcast::error::TryRecvError> {
+        loop {
+            let event = self.receiver.try_recv()?;
+            if self.matches(&event) {
+                return Ok(event);
+            }
+        }
+    }
+
+    fn matches(&self, event: &ServiceEvent) -> bool {
+        event.instance_id() == Some(self.instance_id.as_str())
+    }
 }
 
 impl Default for EventBus {
     fn default() -> Self {
-        Self::new(1024)
+        Self::new(Self::DEFAULT_CAPACITY)
     }
 }
 
@@ -115,6 +224,63 @@ mod tests {
         assert_eq!(e2.event_type(), "instance_created");
     }
 
+    #[tokio::test]
+    async fn test_instance_subscription() {
+        let bus = EventBus::new(16);
+        let mut events = bus.subscribe_instance("api");
+        assert_eq!(events.instance_id(), "api");
+
+        let status = |id: &str| ServiceEvent::StatusChanged {
+            instance_id: id.to_string(),
+            status: ServiceStatus::Running,
+            pid: Some(1),
+        };
+        bus.send(status("api-2"));
+        bus.send(ServiceEvent::ConfigReloaded);
+        bus.send(status("api"));
+        bus.send(ServiceEvent::MetricsUpdated {
+            instance_id: "api".to_string(),
+            cpu_percent: 1.0,
+            memory_mb: 64,
+        });
+        bus.send(ServiceEvent::Error {
+            instance_id: None,
+            message: "boom".to_string(),
+        });
+
+        assert_eq!(events.recv().await.unwrap().event_type(), "status_changed");
+        assert_eq!(events.recv().await.unwrap().event_type(), "metrics_updated");
+        assert!(matches!(
+            events.try_recv(),
+            Err(broadcast::error::TryRecvError::Empty)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_lagging_subscriber() {
+        let bus = EventBus::new(2);
+        assert_eq!(bus.capacity(), 2);
+        let mut rx = bus.subscribe();
+
+        for _ in 0..3 {
+            bus.send(ServiceEvent::ConfigReloaded);
+        }
+        assert!(bus.lagging.load(Ordering::Relaxed));
+        assert!(matches!(
+            rx.recv().await,
+            Err(broadcast::error::RecvError::Lagged(1))
+        ));
+
+        // Draining the backlog clears the lag state on the next send
+        while rx.try_recv().is_ok() {}
+        bus.send(ServiceEvent::ConfigReloaded);
+        assert!(!bus.lagging.load(Ordering::Relaxed));
+
+        assert_eq!(bus.lag_count(), 0);
+        bus.record_lag();
+        assert_eq!(bus.lag_count(), 1);
+    }
+
     #[test]
     fn test_no_subscribers() {
         let bus = EventBus::new(16);
@@ -2,10 +2,11 @@
 
 mod bus;
 
-pub use bus::EventBus;
+pub use bus::{EventBus, InstanceEvents};
 
 use serde::{Deserialize, Serialize};
 
+use crate::schedule::ScheduledAction;
 use crate::service::ServiceStatus;
 
 /// Events that can be broadcast to subscribers
@@ -20,11 +21,19 @@ pub enum ServiceEvent {
     InstanceRemoved {
         instance_id: String,
     },
+    InstanceUpdated {
+        instance_id: String,
+    },
     StatusChanged {
         instance_id: String,
         status: ServiceStatus,
         pid: Option<u32>,
     },
+    PortChanged {
+        instance_id: String,
+        old_port: u16,
+        new_port: u16,
+    },
 
     // Metrics
     MetricsUpdated {
@@ -40,6 +49,14 @@ pub enum ServiceEvent {
         message: Option<String>,
     },
 
+    // Scheduling
+    ScheduleTriggered {
+        instance_id: String,
+        action: ScheduledAction,
+        /// Set if carrying out the action failed
+        error: Option<String>,
+    },
+
     // Errors
     Error {
         instance_id: Option<String>,
@@ -64,9 +81,12 @@ impl ServiceEvent {
         match self {
             ServiceEvent::InstanceCreated { instance_id, .. } => Some(instance_id),
             ServiceEvent::InstanceRemoved { instance_id } => Some(instance_id),
+            ServiceEvent::InstanceUpdated { instance_id } => Some(instance_id),
             ServiceEvent::StatusChanged { instance_id, .. } => Some(instance_id),
+            ServiceEvent::PortChanged { instance_id, .. } => Some(instance_id),
             ServiceEvent::MetricsUpdated { instance_id, .. } => Some(instance_id),
             ServiceEvent::HealthChanged { instance_id, .. } => Some(instance_id),
+            ServiceEvent::ScheduleTriggered { instance_id, .. } => Some(instance_id),
             ServiceEvent::Error { instance_id, .. } => instance_id.as_deref(),
             ServiceEvent::TemplateRegistered { .. } => None,
             ServiceEvent::TemplateRemoved { .. } => None,
@@ -79,9 +99,12 @@ impl ServiceEvent {
         match self {
             ServiceEvent::InstanceCreated { .. } => "instance_created",
             ServiceEvent::InstanceRemoved { .. } => "instance_removed",
+            ServiceEvent::InstanceUpdated { .. } => "instance_updated",
             ServiceEvent::StatusChanged { .. } => "status_changed",
+            ServiceEvent::PortChanged { .. } => "port_changed",
             ServiceEvent::MetricsUpdated { .. } => "metrics_updated",
             ServiceEvent::HealthChanged { .. } => "health_changed",
+            ServiceEvent::ScheduleTriggered { .. } => "schedule_triggered",
             ServiceEvent::Error { .. } => "error",
             ServiceEvent::TemplateRegistered { .. } => "template_registered",
             ServiceEvent::TemplateRemoved { .. } => "template_removed",
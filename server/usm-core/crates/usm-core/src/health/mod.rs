@@ -0,0 +1,338 @@
+//! Health checks for service instances
+//!
+//! A template describes how to probe its instances:
+//! - `health_command` - a shell command, healthy on exit status 0
+//! - `health_endpoint = "http://..."` - healthy on a 2xx response, or on
+//!   `health_expect_status` and a body containing `health_expect_body_contains`
+//!   when the template sets them
+//! - `health_endpoint = "tcp://host:port"` - healthy once it accepts a connection
+//!
+//! All of them support `{port}` substitution. Docker templates without
+//! either are healthy once every container of the instance's compose project
+//! is running (and healthy, if it defines a healthcheck).
+
+use std::time::Duration;
+
+use anyhow::Result;
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::TcpStream;
+
+use crate::docker::{self, ComposeProject};
+use crate::service::{ServiceInstance, ServiceTemplate};
+
+/// Longest response body read when looking for `body_contains`
+const MAX_BODY_BYTES: u64 = 64 * 1024;
+
+/// A single way of probing an instance's health
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HealthCheck {
+    Http(String, HttpExpect),
+    Tcp(String),
+    Command(String),
+    Compose(ComposeProject),
+}
+
+/// What an HTTP health check response must look like to count as healthy
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct HttpExpect {
+    /// Exact status code; any 2xx when unset
+    pub status: Option<u16>,
+    /// Text the response body must contain
+    pub body_contains: Option<String>,
+}
+
+impl HttpExpect {
+    fn for_template(template: &ServiceTemplate) -> Self {
+        Self {
+            status: template.health_expect_status,
+            body_contains: template.health_expect_body_contains.clone(),
+        }
+    }
+}
+
+impl HealthCheck {
+    /// The health check for an instance, if its template defines one
+    ///
+    /// `health_command` takes precedence over `health_endpoint`.
+    pub fn for_instance(template: &ServiceTemplate, instance: &ServiceInstance) -> Option<Self> {
+        if let Some(ref command) = template.health_command {
+            return Some(HealthCheck::Command(
+                command.replace("{port}", &instance.port.to_string()),
+            ));
+        }
+
+        let Some(endpoint) = template.build_health_endpoint(instance) else {
+            return template
+                .is_docker
+                .then(|| HealthCheck::Compose(ComposeProject::for_instance(instance, None)));
+        };
+        match endpoint.strip_prefix("tcp://") {
+            Some(addr) => Some(HealthCheck::Tcp(addr.to_string())),
+            None => Some(HealthCheck::Http(
+                endpoint,
+                HttpExpect::for_template(template),
+            )),
+        }
+    }
+
+    /// Run the check once, failing if it doesn't succeed within `timeout`
+    pub async fn probe(&self, timeout: Duration) -> Result<()> {
+        tokio::time::timeout(timeout, self.probe_inner())
+            .await
+            .map_err(|_| {
+                anyhow::anyhow!("Health check timed out after {}ms", timeout.as_millis())
+            })?
+    }
+
+    async fn probe_inner(&self) -> Result<()> {
+        match self {
+            HealthCheck::Http(url, expect) => {
+                let (status, body) = http_get(url, expect.body_contains.is_some()).await?;
+                match expect.status {
+                    Some(expected) if status != expected => {
+                        anyhow::bail!("{} returned HTTP {} (expected {})", url, status, expected);
+                    },
+                    None if !(200..300).contains(&status) => {
+                        anyhow::bail!("{} returned HTTP {}", url, status);
+                    },
+                    _ => {},
+                }
+                if let Some(ref needle) = expect.body_contains {
+                    if !body.contains(needle.as_str()) {
+                        anyhow::bail!("{} response body does not contain '{}'", url, needle);
+                    }
+                }
+            },
+            HealthCheck::Tcp(addr) => {
+                TcpStream::connect(addr)
+                    .await
+                    .map_err(|e| anyhow::anyhow!("Cannot connect to {}: {}", addr, e))?;
+            },
+            HealthCheck::Command(command) => {
+                let status = tokio::process::Command::new("/bin/sh")
+                    .args(["-c", command])
+                    .stdout(std::process::Stdio::null())
+                    .stderr(std::process::Stdio::null())
+                    .kill_on_drop(true)
+                    .status()
+                    .await?;
+                if !status.success() {
+                    anyhow::bail!("Health command exited with status {:?}", status.code());
+                }
+            },
+            HealthCheck::Compose(project) => {
+                let output = tokio::process::Command::new("/bin/sh")
+                    .args(["-c", &project.ps_command()])
+                    .stderr(std::process::Stdio::null())
+                    .kill_on_drop(true)
+                    .output()
+                    .await?;
+                if !output.status.success() {
+                    anyhow::bail!(
+                        "docker compose ps exited with status {:?}",
+                        output.status.code()
+                    );
+                }
+                docker::check_health(&docker::parse_ps(&String::from_utf8_lossy(&output.stdout))?)?;
+            },
+        }
+        Ok(())
+    }
+}
+
+/// Send a minimal HTTP/1.1 GET and return the response status code, plus
+/// the body (up to `MAX_BODY_BYTES`) if `read_body` is set
+async fn http_get(url: &str, read_body: bool) -> Result<(u16, String)> {
+    let rest = url.strip_prefix("http://").ok_or_else(|| {
+        anyhow::anyhow!("Unsupported health URL '{}' (use http:// or tcp://)", url)
+    })?;
+    let (authority, path) = match rest.find('/') {
+        Some(i) => (&rest[..i], &rest[i..]),
+        None => (rest, "/"),
+    };
+
+    let mut stream = TcpStream::connect(authority)
+        .await
+        .map_err(|e| anyhow::anyhow!("Cannot connect to {}: {}", authority, e))?;
+    let request = format!(
+        "GET {} HTTP/1.1\r\nHost: {}\r\nConnection: close\r\n\r\n",
+        path, authority
+    );
+    stream.write_all(request.as_bytes()).await?;
+
+    let mut reader = BufReader::new(stream);
+    let mut status_line = String::new();
+    reader.read_line(&mut status_line).await?;
+
+    // e.g. "HTTP/1.1 200 OK"
+    let status = status_line
+        .split_whitespace()
+        .nth(1)
+        .and_then(|code| code.parse().ok())
+        .ok_or_else(|| anyhow::anyhow!("Malformed HTTP response from {}", url))?;
+
+    let mut body = String::new();
+    if read_body {
+        let mut rest = Vec::new();
+        reader.take(MAX_BODY_BYTES).read_to_end(&mut rest).await?;
+        let rest = String::from_utf8_lossy(&rest);
+        if let Some((_, content)) = rest.split_once("\r\n\r\n") {
+            body = content.to_string();
+        }
+    }
+    Ok((status, body))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::net::TcpListener;
+
+    /// Serve a fixed status code to every request
+    async fn serve_status(status: &'static str) -> u16 {
+        serve(status, "").await
+    }
+
+    /// Serve a fixed status code and body to every request
+    async fn serve(status: &'static str, body: &'static str) -> u16 {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            while let Ok((mut socket, _)) = listener.accept().await {
+                let mut buf = [0u8; 1024];
+                let _ = socket.read(&mut buf).await;
+                let response = format!(
+                    "HTTP/1.1 {}\r\nContent-Length: {}\r\n\r\n{}",
+                    status,
+                    body.len(),
+                    body
+                );
+                let _ = socket.write_all(response.as_bytes()).await;
+            }
+        });
+        port
+    }
+
+    #[tokio::test]
+    async fn test_http_probe() {
+        let timeout = Duration::from_secs(2);
+
+        let ok = serve_status("200 OK").await;
+        let check = HealthCheck::Http(
+            format!("http://127.0.0.1:{}/health", ok),
+            HttpExpect::default(),
+        );
+        assert!(check.probe(timeout).await.is_ok());
+
+        let failing = serve_status("503 Service Unavailable").await;
+        let check = HealthCheck::Http(
+            format!("http://127.0.0.1:{}/health", failing),
+            HttpExpect::default(),
+        );
+        let err = check.probe(timeout).await.unwrap_err();
+        assert!(err.to_string().contains("HTTP 503"));
+    }
+
+    #[tokio::test]
+    async fn test_http_probe_expectations() {
+        let timeout = Duration::from_secs(2);
+        let healthy = serve("200 OK", r#"{"status":"healthy"}"#).await;
+        let unhealthy = serve("200 OK", r#"{"status":"unhealthy"}"#).await;
+        let accepted = serve("202 Accepted", "").await;
+        let body = HttpExpect {
+            status: None,
+            body_contains: Some(r#""status":"healthy""#.to_string()),
+        };
+
+        let check = HealthCheck::Http(format!("http://127.0.0.1:{}/", healthy), body.clone());
+        assert!(check.probe(timeout).await.is_ok());
+
+        // A 200 whose body reports a problem is unhealthy
+        let check = HealthCheck::Http(format!("http://127.0.0.1:{}/", unhealthy), body);
+        let err = check.probe(timeout).await.unwrap_err();
+        assert!(err.to_string().contains("does not contain"));
+
+        // An explicit status replaces the 2xx default
+        let status = |code| HttpExpect {
+            status: Some(code),
+            body_contains: None,
+        };
+        let url = format!("http://127.0.0.1:{}/", accepted);
+        assert!(HealthCheck::Http(url.clone(), status(202))
+            .probe(timeout)
+            .await
+            .is_ok());
+        let err = HealthCheck::Http(url, status(200))
+            .probe(timeout)
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("HTTP 202 (expected 200)"));
+    }
+
+    #[test]
+    fn test_http_expectations_come_from_template() {
+        let mut template: ServiceTemplate = toml::from_str(
+            r#"
+id = "api"
+display_name = "API"
+default_port = 8000
+start_command = "serve"
+health_endpoint = "http://localhost:{port}/health"
+health_expect_status = 204
+health_expect_body_contains = "ok"
+"#,
+        )
+        .unwrap();
+        let instance = ServiceInstance::from_config(
+            toml::from_str("instance_id = \"api-1\"\ntemplate_id = \"api\"\nport = 8001").unwrap(),
+        )
+        .unwrap();
+        let expect = HttpExpect {
+            status: Some(204),
+            body_contains: Some("ok".to_string()),
+        };
+        assert_eq!(
+            HealthCheck::for_instance(&template, &instance),
+            Some(HealthCheck::Http(
+                "http://localhost:8001/health".to_string(),
+                expect
+            ))
+        );
+
+        template.health_endpoint = Some("tcp://localhost:{port}".to_string());
+        assert_eq!(
+            HealthCheck::for_instance(&template, &instance),
+            Some(HealthCheck::Tcp("localhost:8001".to_string()))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_tcp_probe() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let check = HealthCheck::Tcp(addr.to_string());
+        assert!(check.probe(Duration::from_secs(2)).await.is_ok());
+
+        drop(listener);
+        assert!(check.probe(Duration::from_secs(2)).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_command_probe() {
+        let timeout = Duration::from_secs(2);
+        assert!(HealthCheck::Command("true".to_string())
+            .probe(timeout)
+            .await
+            .is_ok());
+        assert!(HealthCheck::Command("false".to_string())
+            .probe(timeout)
+            .await
+            .is_err());
+
+        let err = HealthCheck::Command("sleep 5".to_string())
+            .probe(Duration::from_millis(100))
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("timed out"));
+    }
+}
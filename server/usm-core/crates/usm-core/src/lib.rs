@@ -4,81 +4,567 @@
 //! without issues. Supports dynamic service templates and instances with
 //! real-time monitoring via WebSocket.
 
+mod builder;
 pub mod config;
+pub mod docker;
 pub mod events;
+pub mod health;
+pub mod logs;
 pub mod metrics;
 pub mod monitor;
+pub mod schedule;
 pub mod server;
 pub mod service;
+pub mod state;
+pub mod supervisor;
 
 // Re-export commonly used types for convenience
+pub use builder::UsmCoreBuilder;
 pub use metrics::{InstanceMetrics, SystemMetrics};
 pub use service::{
-    InstanceConfig, InstanceRegistry, ServiceCategory, ServiceInstance, ServiceStatus,
-    ServiceTemplate, TemplateRegistry,
+    EnvSource, EnvValue, InstanceConfig, InstanceFilter, InstanceRegistry, InstanceUpdate,
+    ServiceCategory, ServiceInstance, ServiceStatus, ServiceTemplate, TagMatch, TemplateRegistry,
 };
 
-use std::path::Path;
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
+use std::time::Duration;
 
 use anyhow::Result;
 use tokio::sync::{broadcast, RwLock};
-use tracing::{info, instrument};
+use tracing::{debug, info, instrument, warn};
 
-use config::ConfigManager;
+use config::{ConfigManager, Settings};
 use events::{EventBus, ServiceEvent};
-use monitor::ProcessMonitor;
+use health::HealthCheck;
+use logs::{LogLine, LogStore};
+use monitor::{ProcessMonitor, Signal, SpawnOptions};
+use schedule::{CompiledSchedule, ScheduledAction, Scheduler};
+use supervisor::{
+    limit_breach, CrashOutcome, LimitAction, RestartPolicy, Supervisor, LIMIT_SAMPLES,
+};
+
+/// Version of USM Core, e.g. "0.1.0"
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// What this build of USM Core is (see `build_info`)
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+pub struct BuildInfo {
+    pub version: &'static str,
+    /// Short hash of the commit it was built from; "unknown" when built
+    /// outside a git checkout without `USM_GIT_COMMIT` set
+    pub git_commit: &'static str,
+    /// When it was built (RFC 3339, UTC; honours `SOURCE_DATE_EPOCH`)
+    pub build_timestamp: String,
+}
+
+/// Version, commit and build time of this binary, for `/api/version`
+pub fn build_info() -> BuildInfo {
+    let built = env!("USM_BUILD_TIMESTAMP")
+        .parse()
+        .ok()
+        .and_then(|secs| chrono::DateTime::from_timestamp(secs, 0))
+        .unwrap_or_default();
+    BuildInfo {
+        version: VERSION,
+        git_commit: env!("USM_GIT_COMMIT"),
+        build_timestamp: built.to_rfc3339_opts(chrono::SecondsFormat::Secs, true),
+    }
+}
+
+/// Why auto-start left an instance alone
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SkipReason {
+    /// The instance doesn't have `auto_start` set
+    Disabled,
+    /// The instance was already running
+    AlreadyRunning,
+}
+
+impl std::fmt::Display for SkipReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SkipReason::Disabled => write!(f, "disabled"),
+            SkipReason::AlreadyRunning => write!(f, "already_running"),
+        }
+    }
+}
+
+/// What auto-start did with a single instance
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AutoStartOutcome {
+    Started(u32),
+    Skipped(SkipReason),
+    Failed(String),
+}
+
+impl std::fmt::Display for AutoStartOutcome {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AutoStartOutcome::Started(pid) => write!(f, "started (pid {})", pid),
+            AutoStartOutcome::Skipped(reason) => write!(f, "skipped ({})", reason),
+            AutoStartOutcome::Failed(error) => write!(f, "failed: {}", error),
+        }
+    }
+}
+
+/// What `UsmCore::force_remove_instance` did with the instance's process
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ForcedStop {
+    /// Stopped cleanly, or nothing was running
+    Stopped,
+    /// Stopping failed with `error`, so the process (group) got SIGKILL
+    Killed { pid: u32, error: String },
+    /// Stopping failed and nothing could be killed; the service may still
+    /// be running
+    Failed(String),
+}
+
+impl std::fmt::Display for ForcedStop {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ForcedStop::Stopped => write!(f, "stopped"),
+            ForcedStop::Killed { pid, error } => {
+                write!(f, "killed (pid {}) after stopping failed: {}", pid, error)
+            },
+            ForcedStop::Failed(error) => write!(f, "not stopped: {}", error),
+        }
+    }
+}
+
+/// What starting an instance would run (see `UsmCore::dry_run_start`)
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+pub struct StartPlan {
+    /// Start command with every placeholder substituted
+    pub command: String,
+    /// Environment set on top of USM's own
+    pub env: BTreeMap<String, String>,
+    /// Directory the command runs in; None inherits USM's CWD
+    pub working_dir: Option<std::path::PathBuf>,
+}
+
+/// What stopping an instance would do (see `UsmCore::dry_run_stop`)
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+pub struct StopPlan {
+    /// Stop command that would run, with `{pid}` substituted
+    pub command: Option<String>,
+    /// Process that would get SIGTERM, and SIGKILL after `stop_timeout_ms`,
+    /// when there's no stop command
+    pub pid: Option<u32>,
+    pub stop_timeout_ms: u32,
+}
+
+/// Events for the templates and instances a config reload adds or removes
+///
+/// Additions come template first, removals instance first, so a client
+/// applying them in order never sees an instance without its template.
+fn reload_events(
+    old_templates: &service::TemplateRegistry,
+    old_instances: &service::InstanceRegistry,
+    new_templates: &service::TemplateRegistry,
+    new_instances: &service::InstanceRegistry,
+) -> Vec<ServiceEvent> {
+    let mut events = Vec::new();
+    let mut template_ids = new_templates.ids();
+    template_ids.sort();
+    for template_id in template_ids {
+        if old_templates.get(&template_id).is_none() {
+            events.push(ServiceEvent::TemplateRegistered { template_id });
+        }
+    }
+
+    let mut added = new_instances.list();
+    added.retain(|i| old_instances.get(&i.id).is_none());
+    added.sort_by(|a, b| a.id.cmp(&b.id));
+    events.extend(added.into_iter().map(|i| ServiceEvent::InstanceCreated {
+        instance_id: i.id,
+        template_id: i.template_id,
+    }));
+    let mut removed = old_instances.list();
+    removed.retain(|i| new_instances.get(&i.id).is_none());
+    removed.sort_by(|a, b| a.id.cmp(&b.id));
+    events.extend(
+        removed
+            .into_iter()
+            .map(|i| ServiceEvent::InstanceRemoved { instance_id: i.id }),
+    );
+
+    let mut template_ids = old_templates.ids();
+    template_ids.sort();
+    for template_id in template_ids {
+        if new_templates.get(&template_id).is_none() {
+            events.push(ServiceEvent::TemplateRemoved { template_id });
+        }
+    }
+    events
+}
+
+/// Fail if a process can't be started in its working directory
+///
+/// Without this the shell reports a missing directory in the captured
+/// output only. Also warns when the command's program isn't on `PATH`, as
+/// far as that can be told from a shell command line.
+fn check_spawnable(instance_id: &str, command: &str, options: &SpawnOptions) -> Result<()> {
+    if let Some(dir) = &options.working_dir {
+        if !dir.exists() {
+            anyhow::bail!("working_dir {} does not exist", dir.display());
+        }
+        if !dir.is_dir() {
+            anyhow::bail!("working_dir {} is not a directory", dir.display());
+        }
+    }
+
+    let Some(program) = command_program(command) else {
+        return Ok(());
+    };
+    let found = if program.contains('/') {
+        let path = Path::new(program);
+        match &options.working_dir {
+            Some(dir) if path.is_relative() => dir.join(path).is_file(),
+            _ => path.is_file(),
+        }
+    } else {
+        let path = options
+            .env
+            .get("PATH")
+            .map(std::ffi::OsString::from)
+            .or_else(|| std::env::var_os("PATH"))
+            .unwrap_or_default();
+        std::env::split_paths(&path).any(|dir| dir.join(program).is_file())
+    };
+    if !found {
+        warn!(
+            instance_id = %instance_id,
+            program = %program,
+            "Start command's program was not found on PATH"
+        );
+    }
+    Ok(())
+}
+
+/// The program a shell command line runs, when it's a plain word
+///
+/// Skips leading `NAME=value` assignments. None for shell builtins and
+/// keywords, and for anything quoted, expanded or grouped, which only the
+/// shell can resolve.
+fn command_program(command: &str) -> Option<&str> {
+    const SHELL_WORDS: &[&str] = &[
+        ".", ":", "[", "alias", "builtin", "cd", "command", "echo", "eval", "exec", "exit",
+        "export", "false", "for", "if", "printf", "pwd", "read", "set", "source", "test", "true",
+        "ulimit", "umask", "unset", "until", "wait", "while",
+    ];
+    let program = command.split_whitespace().find(|word| {
+        let assignment = word.split_once('=').is_some_and(|(name, _)| {
+            !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+        });
+        !assignment
+    })?;
+    let plain = program
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || "/._-+".contains(c));
+    (plain && !SHELL_WORDS.contains(&program)).then_some(program)
+}
+
+/// Every template and instance, for backup or migration (see
+/// `UsmCore::export`)
+///
+/// Unlike the TOML config this is a portable JSON snapshot: templates are
+/// flattened and instances keep their metadata. Runtime state such as
+/// `status` and `pid` is included for reference but ignored on import.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct RegistrySnapshot {
+    pub exported_at: chrono::DateTime<chrono::Utc>,
+    pub templates: Vec<ServiceTemplate>,
+    pub instances: Vec<ServiceInstance>,
+}
+
+/// What `UsmCore::import` did with a snapshot
+#[derive(Debug, Clone, Default, PartialEq, Eq, serde::Serialize)]
+pub struct ImportReport {
+    pub templates_added: Vec<String>,
+    pub instances_added: Vec<String>,
+    /// Why each skipped template or instance was left out, e.g. its id or
+    /// port is already taken
+    pub conflicts: Vec<String>,
+}
 
 /// Main USM Core instance
 ///
-/// Thread-safe, designed for long-running operation.
+/// Thread-safe, designed for long-running operation. Cloning is cheap and
+/// clones share all state.
+#[derive(Clone)]
 pub struct UsmCore {
     templates: Arc<RwLock<TemplateRegistry>>,
     instances: Arc<RwLock<InstanceRegistry>>,
     monitor: Arc<dyn ProcessMonitor>,
-    config_manager: Arc<ConfigManager>,
+    /// `None` for in-memory cores, which never persist changes
+    config_manager: Option<Arc<ConfigManager>>,
     event_bus: Arc<EventBus>,
+    logs: Arc<LogStore>,
+    /// Where instance output is captured (`[logs] dir`, read at startup)
+    log_dir: Option<PathBuf>,
+    metrics_history: Arc<metrics::MetricsHistory>,
+    settings: Arc<RwLock<Settings>>,
 }
 
 impl UsmCore {
     /// Create a new USM Core instance from a config file
-    #[instrument(skip_all, fields(config_path = %config_path.as_ref().display()))]
     pub async fn new(config_path: impl AsRef<Path>) -> Result<Self> {
-        let config_path = config_path.as_ref();
-        info!(
-            "Initializing USM Core from config: {}",
-            config_path.display()
-        );
-
-        // Initialize event bus first (other components will subscribe)
-        let event_bus = Arc::new(EventBus::new(1024));
+        Self::with_profile(config_path, None).await
+    }
 
-        // Load configuration
-        let config_manager = Arc::new(ConfigManager::new(config_path, event_bus.clone())?);
-        let (templates, instances) = config_manager.load().await?;
+    /// Create a new USM Core instance with a named profile applied
+    ///
+    /// `None` falls back to the config's `[defaults] profile`, if any.
+    pub async fn with_profile(
+        config_path: impl AsRef<Path>,
+        profile: Option<&str>,
+    ) -> Result<Self> {
+        UsmCoreBuilder::new()
+            .config_path(config_path)
+            .profile(profile)
+            .build()
+            .await
+    }
 
-        // Create platform-specific process monitor
-        let monitor = monitor::create_monitor();
+    /// Create a new USM Core instance with an injected process monitor
+    ///
+    /// Mainly for tests, e.g. with `monitor::MockMonitor`.
+    pub async fn with_monitor(
+        config_path: impl AsRef<Path>,
+        monitor: Arc<dyn ProcessMonitor>,
+    ) -> Result<Self> {
+        UsmCoreBuilder::new()
+            .config_path(config_path)
+            .monitor(monitor)
+            .build()
+            .await
+    }
 
-        Ok(Self {
-            templates: Arc::new(RwLock::new(templates)),
-            instances: Arc::new(RwLock::new(instances)),
-            monitor,
-            config_manager,
-            event_bus,
-        })
+    /// Start building a core from injected components
+    pub fn builder() -> UsmCoreBuilder {
+        UsmCoreBuilder::new()
     }
 
     /// Start the HTTP/WebSocket server
-    pub async fn start_server(&self, port: u16) -> Result<()> {
-        server::run_server(
-            port,
-            self.templates.clone(),
-            self.instances.clone(),
-            self.monitor.clone(),
-            self.event_bus.clone(),
+    ///
+    /// `bind_addr` overrides `[server] bind_addr`, which defaults to
+    /// 127.0.0.1. `auth_token` overrides `[server] auth_token`; when either is
+    /// set (and not empty) the API requires it as a bearer token.
+    pub async fn start_server(
+        &self,
+        port: u16,
+        bind_addr: Option<std::net::IpAddr>,
+        auth_token: Option<String>,
+    ) -> Result<()> {
+        let bind_addr = match bind_addr {
+            Some(addr) => addr,
+            None => match self.settings.read().await.server.as_ref() {
+                Some(server) => server.bind_addr()?,
+                None => config::ServerSettings::DEFAULT_BIND_ADDR,
+            },
+        };
+
+        let configured_token = self
+            .settings
+            .read()
+            .await
+            .server
+            .as_ref()
+            .and_then(|s| s.auth_token.clone());
+        let auth_token = auth_token.or(configured_token).filter(|t| !t.is_empty());
+
+        let static_dir = self
+            .settings
+            .read()
+            .await
+            .server
+            .as_ref()
+            .and_then(|s| s.static_dir.as_ref())
+            .map(std::path::PathBuf::from);
+
+        let metrics_interval = self
+            .settings
+            .read()
+            .await
+            .server
+            .as_ref()
+            .and_then(|s| s.metrics_interval_ms)
+            .unwrap_or(config::ServerSettings::DEFAULT_METRICS_INTERVAL_MS);
+
+        let scheduler = self.spawn_scheduler();
+        let supervisor = self.spawn_supervisor();
+        let metrics = (metrics_interval > 0)
+            .then(|| self.spawn_metrics_publisher(Duration::from_millis(metrics_interval)));
+        let result = server::run_server(
+            std::net::SocketAddr::new(bind_addr, port),
+            Arc::new(self.clone()),
+            static_dir,
+            auth_token,
         )
-        .await
+        .await;
+        scheduler.abort();
+        supervisor.abort();
+        if let Some(metrics) = metrics {
+            metrics.abort();
+        }
+
+        let policy = self
+            .settings
+            .read()
+            .await
+            .server
+            .as_ref()
+            .map(|s| s.on_shutdown)
+            .unwrap_or_default();
+        self.shutdown(policy).await;
+        result
+    }
+
+    /// Stop the running instances selected by `policy`
+    ///
+    /// Called when the server exits; embedders should call it before
+    /// dropping the core so children aren't orphaned. Failures are logged
+    /// and don't stop the remaining instances. Returns each instance stopped
+    /// with its result.
+    #[instrument(skip(self))]
+    pub async fn shutdown(&self, policy: config::ShutdownPolicy) -> Vec<(String, Result<()>)> {
+        let running = self
+            .instances
+            .read()
+            .await
+            .list_by_status(service::ServiceStatus::Running);
+        let to_stop: Vec<String> = running
+            .into_iter()
+            .filter(|i| match policy {
+                config::ShutdownPolicy::Leave => false,
+                config::ShutdownPolicy::StopAll => true,
+                config::ShutdownPolicy::StopEphemeral => {
+                    i.tags.iter().any(|t| t == config::EPHEMERAL_TAG)
+                },
+            })
+            .map(|i| i.id)
+            .collect();
+
+        let mut results = Vec::new();
+        for id in to_stop {
+            let result = self.stop_instance(&id).await;
+            if let Err(ref e) = result {
+                warn!(instance_id = %id, "Failed to stop instance on shutdown: {}", e);
+            }
+            results.push((id, result));
+        }
+        info!(policy = ?policy, stopped = results.len(), "USM Core shut down");
+        results
+    }
+
+    /// Reload templates and instances from the config file
+    ///
+    /// Runtime state (status, PID, start time) is preserved for instances that
+    /// still exist after the reload. Instances that disappeared from the
+    /// config are kept while they are running, starting or stopping, or
+    /// their process is still alive, so a reload never orphans a process;
+    /// the next reload once they have settled drops them.
+    ///
+    /// Templates and instances the reload added or removed are broadcast as
+    /// individual events before the closing `ConfigReloaded`.
+    #[instrument(skip(self))]
+    pub async fn reload_config(&self) -> Result<()> {
+        let Some(config_manager) = self.config_manager.as_ref() else {
+            anyhow::bail!("No config file to reload from");
+        };
+        let (new_templates, mut new_instances) = config_manager.load().await?;
+        let new_settings = config_manager.load_settings().await?;
+
+        let mut templates = self.templates.write().await;
+        let mut instances = self.instances.write().await;
+
+        for old in instances.list() {
+            if let Some(instance) = new_instances.get_mut(&old.id) {
+                instance.status = old.status;
+                instance.pid = old.pid;
+                instance.started_at = old.started_at;
+                instance.last_pid = old.last_pid;
+                instance.last_exit_code = old.last_exit_code;
+                instance.last_error = old.last_error;
+            } else if self.in_use(&old) {
+                warn!(
+                    instance_id = %old.id,
+                    status = %old.status,
+                    "Instance removed from config is still in use, keeping it"
+                );
+                if let Err(e) = new_instances.add(old) {
+                    warn!("Could not keep instance in use: {}", e);
+                }
+            }
+        }
+
+        let events = reload_events(&templates, &instances, &new_templates, &new_instances);
+        *templates = new_templates;
+        *instances = new_instances;
+        drop(instances);
+        drop(templates);
+        *self.settings.write().await = new_settings;
+
+        for event in events {
+            self.event_bus.send(event);
+        }
+        self.event_bus.send(ServiceEvent::ConfigReloaded);
+
+        info!("Configuration reloaded");
+        Ok(())
+    }
+
+    /// Whether an instance has a process or an operation in flight, so it
+    /// can't just be forgotten
+    fn in_use(&self, instance: &ServiceInstance) -> bool {
+        matches!(
+            instance.status,
+            service::ServiceStatus::Running
+                | service::ServiceStatus::Starting
+                | service::ServiceStatus::Stopping
+        ) || instance
+            .pid
+            .is_some_and(|pid| !self.monitor.service_exited(pid))
+    }
+
+    /// Reload the config whenever it's edited on disk
+    ///
+    /// Change signals are debounced so that one save (often several writes,
+    /// e.g. truncate then write) causes a single reload. USM's own saves are
+    /// ignored.
+    fn spawn_config_watcher(&self, mut changes: tokio::sync::mpsc::UnboundedReceiver<()>) {
+        const DEBOUNCE: Duration = Duration::from_millis(300);
+
+        let core = self.clone();
+        tokio::spawn(async move {
+            while changes.recv().await.is_some() {
+                // Wait until the burst of events for this save is over
+                loop {
+                    match tokio::time::timeout(DEBOUNCE, changes.recv()).await {
+                        Ok(Some(())) => continue,
+                        Ok(None) => return,
+                        Err(_) => break,
+                    }
+                }
+
+                let Some(config_manager) = core.config_manager.as_ref() else {
+                    return;
+                };
+                if !config_manager.changed_externally().await {
+                    continue;
+                }
+                info!("Config file changed, reloading");
+                if let Err(e) = core.reload_config().await {
+                    warn!("Config reload failed, keeping previous config: {}", e);
+                    core.event_bus.send(ServiceEvent::Error {
+                        instance_id: None,
+                        message: format!("Config reload failed: {}", e),
+                    });
+                }
+            }
+        });
     }
 
     // =========================================================================
@@ -90,6 +576,14 @@ impl UsmCore {
         self.templates.read().await.list()
     }
 
+    /// List the registered templates in a category
+    pub async fn list_templates_by_category(
+        &self,
+        category: ServiceCategory,
+    ) -> Vec<ServiceTemplate> {
+        self.templates.read().await.list_by_category(category)
+    }
+
     /// Get a specific template by ID
     pub async fn get_template(&self, id: &str) -> Option<ServiceTemplate> {
         self.templates.read().await.get(id)
@@ -100,8 +594,7 @@ impl UsmCore {
         let mut templates = self.templates.write().await;
         templates.register(template.clone())?;
 
-        // Persist to config file
-        self.config_manager.save_templates(&templates).await?;
+        self.persist_templates(&templates).await?;
 
         // Broadcast event
         self.event_bus.send(ServiceEvent::TemplateRegistered {
@@ -113,18 +606,18 @@ impl UsmCore {
 
     /// Remove a template (only if no instances exist)
     pub async fn remove_template(&self, id: &str) -> Result<()> {
-        // Check for existing instances
+        // Check for existing instances, holding the locks so none can be
+        // created for the template until it's gone. Templates are locked
+        // before instances, as everywhere else.
+        let mut templates = self.templates.write().await;
         let instances = self.instances.read().await;
         if instances.has_instances_for_template(id) {
             anyhow::bail!("Cannot remove template '{}': instances exist", id);
         }
-        drop(instances);
-
-        let mut templates = self.templates.write().await;
         templates.remove(id)?;
+        drop(instances);
 
-        // Persist to config file
-        self.config_manager.save_templates(&templates).await?;
+        self.persist_templates(&templates).await?;
 
         // Broadcast event
         self.event_bus.send(ServiceEvent::TemplateRemoved {
@@ -147,41 +640,147 @@ impl UsmCore {
         }
     }
 
+    /// List instances matching a combined filter
+    pub async fn query_instances(&self, filter: &InstanceFilter) -> Vec<ServiceInstance> {
+        self.instances.read().await.query(filter)
+    }
+
+    /// Search instances by id, template id or tag (see `InstanceRegistry::search`)
+    pub async fn search_instances(&self, query: &str) -> Vec<ServiceInstance> {
+        self.instances.read().await.search(query)
+    }
+
     /// Get a specific instance by ID
     pub async fn get_instance(&self, id: &str) -> Option<ServiceInstance> {
         self.instances.read().await.get(id)
     }
 
+    /// Check every instance's stored PID against the process table
+    ///
+    /// Returns `(instance id, stored PID, alive)` sorted by id. A Running
+    /// instance whose PID is not alive has drifted: its process died without
+    /// USM noticing (only instances with a restart policy are supervised).
+    /// This only reports; nothing is changed.
+    pub async fn reconcile(&self) -> Vec<(String, Option<u32>, bool)> {
+        let mut instances = self.instances.read().await.list();
+        instances.sort_by(|a, b| a.id.cmp(&b.id));
+        instances
+            .into_iter()
+            .map(|instance| {
+                let alive = instance.pid.is_some_and(|pid| self.monitor.is_running(pid));
+                (instance.id, instance.pid, alive)
+            })
+            .collect()
+    }
+
+    /// Zombie (exited but unreaped) processes among USM's spawn wrappers and
+    /// instances' PIDs
+    ///
+    /// Returns `(PID, instance id, tracked)` sorted by PID, where the id is
+    /// that of the instance whose `pid` or `last_pid` it is, and `tracked`
+    /// means an instance still holds it as its `pid`, so it isn't reaped.
+    /// On Linux that's normal for a running instance: the PID USM tracks is
+    /// its spawn wrapper's, which exits right after starting the service.
+    pub async fn zombies(&self) -> Vec<(u32, Option<String>, bool)> {
+        let instances = self.instances.read().await.list();
+        let mut pids = self.monitor.children();
+        pids.extend(instances.iter().flat_map(|i| [i.pid, i.last_pid]).flatten());
+        pids.sort_unstable();
+        pids.dedup();
+        pids.into_iter()
+            .filter(|&pid| self.monitor.is_zombie(pid))
+            .map(|pid| {
+                let owner = instances
+                    .iter()
+                    .find(|i| i.pid == Some(pid))
+                    .or_else(|| instances.iter().find(|i| i.last_pid == Some(pid)));
+                let tracked = owner.is_some_and(|i| i.pid == Some(pid));
+                (pid, owner.map(|i| i.id.clone()), tracked)
+            })
+            .collect()
+    }
+
+    /// Reap spawn wrappers that have exited and no instance tracks anymore
+    ///
+    /// Skipped while an instance is starting, since its new PID isn't
+    /// recorded until the spawn returns. Returns the PIDs reaped; the
+    /// supervisor calls this on every pass.
+    pub async fn reap_zombies(&self) -> Vec<u32> {
+        let keep: Vec<u32> = {
+            let instances = self.instances.read().await.list();
+            if instances
+                .iter()
+                .any(|i| i.status == service::ServiceStatus::Starting)
+            {
+                return Vec::new();
+            }
+            instances.iter().filter_map(|i| i.pid).collect()
+        };
+        let reaped = self.monitor.reap_children(&keep);
+        if !reaped.is_empty() {
+            debug!(pids = ?reaped, "Reaped exited spawn wrappers");
+        }
+        reaped
+    }
+
+    /// Name to show for an instance in UIs
+    ///
+    /// The instance's `display_name`, else its template's, else its ID.
+    pub async fn display_name(&self, instance: &ServiceInstance) -> String {
+        if let Some(name) = &instance.display_name {
+            return name.clone();
+        }
+        self.templates
+            .read()
+            .await
+            .get(&instance.template_id)
+            .map_or_else(|| instance.id.clone(), |t| t.display_name)
+    }
+
     /// Create a new instance from a template
     #[instrument(skip(self, config), fields(instance_id = %config.instance_id, template_id = %config.template_id))]
     pub async fn create_instance(&self, config: service::InstanceConfig) -> Result<String> {
         // Verify template exists
-        let templates = self.templates.read().await;
-        let template = templates
+        let template = self
+            .templates
+            .read()
+            .await
             .get(&config.template_id)
             .ok_or_else(|| anyhow::anyhow!("Template '{}' not found", config.template_id))?;
 
-        // Check if template supports multiple instances
-        if !template.supports_multiple {
-            let instances = self.instances.read().await;
-            if instances.has_instances_for_template(&config.template_id) {
-                anyhow::bail!(
-                    "Template '{}' does not support multiple instances",
-                    config.template_id
-                );
-            }
-        }
-        drop(templates);
+        let limits = self.limits().await;
+        let mut instances = self.instances.write().await;
+        self.add_instance(&mut instances, &template, &limits, config)
+            .await
+    }
+
+    /// Add an instance to `instances`, which the caller holds the write
+    /// lock of, then persist and announce it
+    async fn add_instance(
+        &self,
+        instances: &mut InstanceRegistry,
+        template: &ServiceTemplate,
+        limits: &config::LimitSettings,
+        mut config: service::InstanceConfig,
+    ) -> Result<String> {
+        template.check_instance_cap(instances.count_for_template(&template.id))?;
+
+        // Assign a port under the write lock so rapid creates can't collide
+        let port = match config.port {
+            Some(port) => port,
+            None => self.free_port(template, instances)?,
+        };
+        template.check_port(port)?;
+        config.port = Some(port);
 
         // Create the instance
         let instance = ServiceInstance::from_config(config.clone())?;
         let instance_id = instance.id.clone();
 
-        let mut instances = self.instances.write().await;
+        limits.check_create(instances)?;
         instances.add(instance)?;
 
-        // Persist to config file
-        self.config_manager.save_instances(&instances).await?;
+        self.persist_instances(instances).await?;
 
         // Broadcast event
         self.event_bus.send(ServiceEvent::InstanceCreated {
@@ -193,206 +792,4439 @@ impl UsmCore {
         Ok(instance_id)
     }
 
-    /// Remove an instance (stops if running)
-    #[instrument(skip(self), fields(instance_id = %id))]
-    pub async fn remove_instance(&self, id: &str) -> Result<()> {
-        // Stop if running
-        self.stop_instance(id).await.ok();
+    /// Create an instance, or update it in place if it already exists
+    ///
+    /// An existing instance takes the config's port (if given), tags,
+    /// env_vars and working_dir; its other fields are left alone. Those three
+    /// are replaced, not merged: leaving them out of the config clears them.
+    /// It must use the same template, and a new port must be free like on
+    /// create. Running instances pick up the changes on their next start.
+    /// Returns the instance ID and whether it was created.
+    #[instrument(skip(self, config), fields(instance_id = %config.instance_id, template_id = %config.template_id))]
+    pub async fn upsert_instance(&self, config: service::InstanceConfig) -> Result<(String, bool)> {
+        let id = service::qualified_id(config.group.as_deref(), &config.instance_id);
+        let template = self.templates.read().await.get(&config.template_id);
+        let limits = self.limits().await;
 
+        // Look up and create under one write lock, so concurrent upserts of
+        // a new ID can't both decide to create it
         let mut instances = self.instances.write().await;
-        instances.remove(id)?;
+        let Some(existing) = instances.get(&id) else {
+            let template = template
+                .ok_or_else(|| anyhow::anyhow!("Template '{}' not found", config.template_id))?;
+            return self
+                .add_instance(&mut instances, &template, &limits, config)
+                .await
+                .map(|id| (id, true));
+        };
+
+        if existing.template_id != config.template_id {
+            anyhow::bail!(
+                "Cannot change template of instance '{}' from '{}' to '{}'",
+                existing.id,
+                existing.template_id,
+                config.template_id
+            );
+        }
+        if let Some(port) = config.port {
+            if let Some(template) = &template {
+                template.check_port(port)?;
+            }
+            if let Some(other) = instances.find_by_port(port).filter(|i| i.id != existing.id) {
+                anyhow::bail!("Port {} is already in use by instance '{}'", port, other.id);
+            }
+            if port != existing.port && self.monitor.is_port_in_use(port) {
+                anyhow::bail!("Port {} is already in use by another process", port);
+            }
+        }
 
-        // Persist to config file
-        self.config_manager.save_instances(&instances).await?;
+        let instance = instances.get_mut(&id).expect("instance looked up above");
+        if let Some(port) = config.port {
+            instance.port = port;
+        }
+        instance.tags = config.tags;
+        instance.env_vars = config.env_vars;
+        instance.working_dir = config.working_dir;
 
-        // Broadcast event
-        self.event_bus.send(ServiceEvent::InstanceRemoved {
-            instance_id: id.to_string(),
+        self.persist_instances(&instances).await?;
+        drop(instances);
+
+        self.event_bus.send(ServiceEvent::InstanceUpdated {
+            instance_id: id.clone(),
         });
 
-        info!(instance_id = %id, "Instance removed");
-        Ok(())
+        info!(instance_id = %id, "Instance updated");
+        Ok((id, false))
     }
 
-    /// Start an instance
+    /// First port in the template's range that no instance uses and nothing
+    /// outside USM has bound
+    pub(crate) fn free_port(
+        &self,
+        template: &ServiceTemplate,
+        instances: &InstanceRegistry,
+    ) -> Result<u16> {
+        template
+            .next_available_port_with(&instances.used_ports(), |port| {
+                self.monitor.is_port_in_use(port)
+            })
+            .ok_or_else(|| anyhow::anyhow!("No free port available for template '{}'", template.id))
+    }
+
+    /// Remove an instance, stopping it first if it's running
+    ///
+    /// If it can't be stopped, the error is returned and the instance kept;
+    /// see `force_remove_instance` to remove it anyway.
     #[instrument(skip(self), fields(instance_id = %id))]
-    pub async fn start_instance(&self, id: &str) -> Result<()> {
-        let mut instances = self.instances.write().await;
-        let instance = instances
-            .get_mut(id)
-            .ok_or_else(|| anyhow::anyhow!("Instance '{}' not found", id))?;
+    pub async fn remove_instance(&self, id: &str) -> Result<()> {
+        self.stop_instance(id).await?;
+        self.forget_instance(id).await
+    }
 
-        // Get template for start command
-        let templates = self.templates.read().await;
-        let template = templates
-            .get(&instance.template_id)
-            .ok_or_else(|| anyhow::anyhow!("Template '{}' not found", instance.template_id))?;
+    /// Remove an instance even if stopping it fails
+    ///
+    /// When the clean stop (stop command, or SIGTERM then SIGKILL) fails,
+    /// the instance's live process, and its process group if USM spawned
+    /// one, gets SIGKILL. The instance is removed either way; the outcome
+    /// says whether its service may have been left running.
+    #[instrument(skip(self), fields(instance_id = %id))]
+    pub async fn force_remove_instance(&self, id: &str) -> Result<ForcedStop> {
+        let outcome = match self.stop_instance(id).await {
+            Ok(()) => ForcedStop::Stopped,
+            Err(e) => {
+                let instance = self
+                    .get_instance(id)
+                    .await
+                    .ok_or_else(|| anyhow::anyhow!("Instance '{}' not found", id))?;
+                let template = self.get_template(&instance.template_id).await;
+                let error = e.to_string();
+                match self.stop_pid(template.as_ref(), &instance, instance.status) {
+                    Some(pid) => {
+                        let group = self.monitor.process_group(pid).is_some();
+                        match self.monitor.signal_process(pid, Signal::Kill, group) {
+                            Ok(()) => ForcedStop::Killed { pid, error },
+                            Err(kill) => ForcedStop::Failed(format!(
+                                "{}; SIGKILL to pid {} failed: {}",
+                                error, pid, kill
+                            )),
+                        }
+                    },
+                    None => ForcedStop::Failed(error),
+                }
+            },
+        };
+        if outcome != ForcedStop::Stopped {
+            warn!(instance_id = %id, outcome = %outcome, "Forcing removal");
+        }
+        self.forget_instance(id).await?;
+        Ok(outcome)
+    }
 
-        // Build and execute start command
-        let command = template.build_start_command(instance);
-        let pid = self.monitor.start_process_with_port(
-            &command,
-            instance.working_dir.as_deref(),
-            Some(instance.port),
-        )?;
+    /// Drop a (stopped) instance from the registry, with its logs, output
+    /// files and history
+    async fn forget_instance(&self, id: &str) -> Result<()> {
+        let mut instances = self.instances.write().await;
+        let last_pid = instances.get(id).and_then(|i| i.last_pid);
+        instances.remove(id)?;
+        self.logs.remove(id);
+        if let Some(pid) = last_pid {
+            self.remove_output(pid);
+            self.monitor.remove_exit_status(pid);
+        }
+        self.metrics_history.remove(id);
 
-        // Update instance state
-        instance.status = service::ServiceStatus::Running;
-        instance.pid = Some(pid);
-        instance.started_at = Some(chrono::Utc::now());
+        self.persist_instances(&instances).await?;
+        self.persist_state(&instances).await;
 
         // Broadcast event
-        self.event_bus.send(ServiceEvent::StatusChanged {
+        self.event_bus.send(ServiceEvent::InstanceRemoved {
             instance_id: id.to_string(),
-            status: service::ServiceStatus::Running,
-            pid: Some(pid),
         });
 
-        info!(instance_id = %id, pid = %pid, "Instance started");
+        info!(instance_id = %id, "Instance removed");
         Ok(())
     }
 
-    /// Stop an instance
-    #[instrument(skip(self), fields(instance_id = %id))]
-    pub async fn stop_instance(&self, id: &str) -> Result<()> {
-        let mut instances = self.instances.write().await;
-        let instance = instances
-            .get_mut(id)
-            .ok_or_else(|| anyhow::anyhow!("Instance '{}' not found", id))?;
-
-        if instance.status != service::ServiceStatus::Running {
-            return Ok(()); // Already stopped
+    /// Snapshot every template and instance, sorted by id
+    pub async fn export(&self) -> RegistrySnapshot {
+        let mut templates = self.list_templates().await;
+        templates.sort_by(|a, b| a.id.cmp(&b.id));
+        let mut instances = self.list_instances(None).await;
+        instances.sort_by(|a, b| a.id.cmp(&b.id));
+        RegistrySnapshot {
+            exported_at: chrono::Utc::now(),
+            templates,
+            instances,
         }
+    }
 
-        // Get template for optional custom stop command
-        let templates = self.templates.read().await;
-        let template = templates.get(&instance.template_id);
+    /// Add the templates and instances of a snapshot
+    ///
+    /// Templates are registered first, so instances can use them. Anything
+    /// that can't be added as it is (an existing id, a taken port, a missing
+    /// template, the instance limit) is skipped and listed in the report's
+    /// `conflicts`; nothing already registered is changed. Imported
+    /// instances start out stopped.
+    pub async fn import(&self, snapshot: RegistrySnapshot) -> Result<ImportReport> {
+        let mut report = ImportReport::default();
 
-        // Stop the process
-        if let Some(pid) = instance.pid {
-            if let Some(tmpl) = template {
-                if let Some(stop_cmd) = &tmpl.stop_command {
-                    let cmd = stop_cmd.replace("{pid}", &pid.to_string());
-                    self.monitor.execute_command(&cmd)?;
-                } else {
-                    self.monitor.kill_process(pid)?;
-                }
-            } else {
-                self.monitor.kill_process(pid)?;
+        let mut templates = self.templates.write().await;
+        for template in snapshot.templates {
+            let id = template.id.clone();
+            match templates.register(template) {
+                Ok(()) => report.templates_added.push(id),
+                Err(e) => report.conflicts.push(e.to_string()),
             }
         }
+        if !report.templates_added.is_empty() {
+            self.persist_templates(&templates).await?;
+        }
+        drop(templates);
+        for id in &report.templates_added {
+            self.event_bus.send(ServiceEvent::TemplateRegistered {
+                template_id: id.clone(),
+            });
+        }
 
-        // Update instance state
-        instance.status = service::ServiceStatus::Stopped;
-        instance.pid = None;
-        instance.started_at = None;
-
-        // Broadcast event
-        self.event_bus.send(ServiceEvent::StatusChanged {
-            instance_id: id.to_string(),
-            status: service::ServiceStatus::Stopped,
-            pid: None,
-        });
+        let templates: HashMap<String, ServiceTemplate> = self
+            .list_templates()
+            .await
+            .into_iter()
+            .map(|t| (t.id.clone(), t))
+            .collect();
+        let limits = self.limits().await;
+        let mut instances = self.instances.write().await;
+        let mut created = Vec::new();
+        for instance in snapshot.instances {
+            let id = instance.id.clone();
+            let template_id = instance.template_id.clone();
+            let added = match templates.get(&template_id) {
+                None => Err(anyhow::anyhow!(
+                    "Template '{}' not found for instance '{}'",
+                    template_id,
+                    id
+                )),
+                Some(template) => template
+                    .check_instance_cap(instances.count_for_template(&template_id))
+                    .and_then(|()| template.check_port(instance.port))
+                    .and_then(|()| limits.check_create(&instances))
+                    .and_then(|()| {
+                        instances.add(ServiceInstance {
+                            status: service::ServiceStatus::Stopped,
+                            pid: None,
+                            started_at: None,
+                            ..instance
+                        })
+                    }),
+            };
+            match added {
+                Ok(()) => {
+                    report.instances_added.push(id.clone());
+                    created.push((id, template_id));
+                },
+                Err(e) => report.conflicts.push(e.to_string()),
+            }
+        }
+        if !created.is_empty() {
+            self.persist_instances(&instances).await?;
+        }
+        drop(instances);
 
-        info!(instance_id = %id, "Instance stopped");
-        Ok(())
+        for (instance_id, template_id) in created {
+            self.event_bus.send(ServiceEvent::InstanceCreated {
+                instance_id,
+                template_id,
+            });
+        }
+        info!(
+            templates = report.templates_added.len(),
+            instances = report.instances_added.len(),
+            conflicts = report.conflicts.len(),
+            "Registry imported"
+        );
+        Ok(report)
     }
 
-    /// Restart an instance
-    pub async fn restart_instance(&self, id: &str) -> Result<()> {
-        self.stop_instance(id).await?;
-        tokio::time::sleep(tokio::time::Duration::from_secs(2)).await;
-        self.start_instance(id).await
+    /// Build the start command and spawn options for an instance
+    ///
+    /// The working directory falls back from instance to template to the
+    /// `[defaults]` working_dir before inheriting USM's own CWD. The process
+    /// gets the template's `default_env` overridden by the instance's
+    /// `env_vars`. For Docker templates the command is `docker compose up -d`.
+    pub(crate) async fn prepare_start(
+        &self,
+        template: &ServiceTemplate,
+        instance: &ServiceInstance,
+    ) -> (String, SpawnOptions) {
+        let plan = self.plan_start(template, instance).await;
+        let options = SpawnOptions {
+            working_dir: plan.working_dir,
+            port: Some(instance.port),
+            log_buffer: self.logs.buffer_for(&instance.id),
+            log_files: self
+                .log_dir
+                .as_ref()
+                .map(|dir| monitor::instance_log_paths(dir, &instance.id)),
+            env: plan.env.into_iter().collect(),
+            startup_verify: Some(Duration::from_millis(template.startup_verify_ms.into())),
+        };
+        (plan.command, options)
     }
 
-    /// Clone an instance with different configuration
-    pub async fn clone_instance(
+    /// The command, environment and working directory `prepare_start` uses
+    async fn plan_start(
         &self,
-        source_id: &str,
-        mut new_config: service::InstanceConfig,
-    ) -> Result<String> {
-        let source = self
+        template: &ServiceTemplate,
+        instance: &ServiceInstance,
+    ) -> StartPlan {
+        let working_dir = self.working_dir(template, instance).await;
+
+        let command = if template.is_docker {
+            docker::ComposeProject::for_instance(instance, working_dir.as_deref()).up_command()
+        } else {
+            template.build_start_command_in(instance, working_dir.as_deref())
+        };
+        StartPlan {
+            command,
+            env: template.env(instance).into_iter().collect(),
+            working_dir,
+        }
+    }
+
+    /// Resolve what `start_instance` would run, without running it
+    ///
+    /// Nothing is spawned and the instance is left as it is, so this is
+    /// safe to use on production services to debug template substitution.
+    pub async fn dry_run_start(&self, id: &str) -> Result<StartPlan> {
+        let (template, instance) = self.template_and_instance(id).await?;
+        Ok(self.plan_start(&template, &instance).await)
+    }
+
+    /// Resolve what `stop_instance` would do, without doing it
+    pub async fn dry_run_stop(&self, id: &str) -> Result<StopPlan> {
+        let (template, mut instance) = self.template_and_instance(id).await?;
+        instance.pid = self.stop_pid(Some(&template), &instance, instance.status);
+        let command = self.stop_command(Some(&template), &instance).await;
+        Ok(StopPlan {
+            pid: instance.pid.filter(|_| command.is_none()),
+            command,
+            stop_timeout_ms: template.stop_timeout_ms,
+        })
+    }
+
+    /// An instance and its template, failing if either doesn't exist
+    async fn template_and_instance(&self, id: &str) -> Result<(ServiceTemplate, ServiceInstance)> {
+        let instance = self
+            .get_instance(id)
+            .await
+            .ok_or_else(|| anyhow::anyhow!("Instance '{}' not found", id))?;
+        let template = self
+            .get_template(&instance.template_id)
+            .await
+            .ok_or_else(|| anyhow::anyhow!("Template '{}' not found", instance.template_id))?;
+        Ok((template, instance))
+    }
+
+    /// Working directory for an instance (see `prepare_start`)
+    async fn working_dir(
+        &self,
+        template: &ServiceTemplate,
+        instance: &ServiceInstance,
+    ) -> Option<std::path::PathBuf> {
+        let default_dir = self
+            .settings
+            .read()
+            .await
+            .defaults
+            .as_ref()
+            .and_then(|d| d.working_dir.as_ref())
+            .map(std::path::PathBuf::from);
+        template.resolve_working_dir(instance, default_dir.as_deref())
+    }
+
+    /// Launch an instance's service, returning the PID of its process
+    ///
+    /// Docker templates run `docker compose up -d` instead and have no PID.
+    /// Refuses to start when the instance's port is already bound by some
+    /// other process (see `check_port_free`) or its working directory is
+    /// missing (see `check_spawnable`).
+    pub(crate) async fn launch(
+        &self,
+        template: &ServiceTemplate,
+        instance: &ServiceInstance,
+    ) -> Result<Option<u32>> {
+        let (command, options) = self.prepare_start(template, instance).await;
+        self.launch_prepared(template, instance, &command, &options)
+            .await
+    }
+
+    /// Run a command built by `prepare_start` (see `launch`)
+    ///
+    /// Spawning blocks (monitors wait to verify the process came up), so it
+    /// runs on the blocking pool to let bulk starts overlap.
+    pub(crate) async fn launch_prepared(
+        &self,
+        template: &ServiceTemplate,
+        instance: &ServiceInstance,
+        command: &str,
+        options: &SpawnOptions,
+    ) -> Result<Option<u32>> {
+        let monitor = self.monitor.clone();
+        let command = command.to_string();
+        if template.is_docker {
+            tokio::task::spawn_blocking(move || monitor.execute_command(&command)).await??;
+            return Ok(None);
+        }
+
+        check_spawnable(&instance.id, &command, options)?;
+        self.check_port_free(template, instance)?;
+        let options = options.clone();
+        tokio::task::spawn_blocking(move || monitor.spawn(&command, &options))
+            .await?
+            .map(Some)
+    }
+
+    /// Fail if the instance's port is bound by a process other than its own
+    ///
+    /// Services with a stop command usually wrap a service manager (brew
+    /// services, systemctl) that may already be running the service, so for
+    /// them a bound port is only a warning.
+    fn check_port_free(
+        &self,
+        template: &ServiceTemplate,
+        instance: &ServiceInstance,
+    ) -> Result<()> {
+        let own_process = instance.pid.is_some_and(|pid| self.monitor.is_running(pid));
+        if own_process || !self.monitor.is_port_in_use(instance.port) {
+            return Ok(());
+        }
+        if template.stop_command_for(instance).is_some() {
+            warn!(
+                instance_id = %instance.id,
+                port = instance.port,
+                "Port already in use, starting anyway"
+            );
+            return Ok(());
+        }
+        anyhow::bail!(
+            "Port {} is already in use by another process",
+            instance.port
+        )
+    }
+
+    /// Stop an instance's service
+    ///
+    /// Docker templates run `docker compose down`. Otherwise the instance's
+    /// `stop_command_override` or the template's `stop_command` is used if
+    /// there is one, else the process gets SIGTERM
+    /// and, if it outlives the template's `stop_timeout_ms`, SIGKILL.
+    pub(crate) async fn terminate(
+        &self,
+        template: Option<&ServiceTemplate>,
+        instance: &ServiceInstance,
+    ) -> Result<()> {
+        if let Some(cmd) = self.stop_command(template, instance).await {
+            // Stop commands (e.g. `docker compose down`) can take a while
+            let monitor = self.monitor.clone();
+            return tokio::task::spawn_blocking(move || monitor.execute_command(&cmd)).await?;
+        }
+
+        if let Some(pid) = instance.pid {
+            let grace = template.map_or(ServiceTemplate::DEFAULT_STOP_TIMEOUT_MS, |t| {
+                t.stop_timeout_ms
+            });
+            self.kill_gracefully(pid, Duration::from_millis(grace.into()))
+                .await?;
+        }
+        Ok(())
+    }
+
+    /// The process of an instance believed to be running
+    ///
+    /// Its stored PID while the service started as that PID is alive (see
+    /// `ProcessMonitor::service_exited`), else None. Only when no PID is
+    /// stored at all (e.g. it was lost across a USM restart without state)
+    /// is whatever listens on the instance's port taken to be the service.
+    fn running_pid(&self, instance: &ServiceInstance) -> Option<u32> {
+        if let Some(pid) = instance.pid {
+            return (!self.monitor.service_exited(pid)).then_some(pid);
+        }
+        let found = self.monitor.find_by_port(instance.port)?;
+        info!(
+            instance_id = %instance.id,
+            pid = found.pid,
+            port = instance.port,
+            "No stored PID, using the process on the instance's port"
+        );
+        Some(found.pid)
+    }
+
+    /// The PID `stop_instance` signals for an instance with `status`
+    ///
+    /// Only a live process is signalled: killing a dead PID would fail and
+    /// leave a crashed instance stuck in Error. Docker instances have none.
+    fn stop_pid(
+        &self,
+        template: Option<&ServiceTemplate>,
+        instance: &ServiceInstance,
+        status: service::ServiceStatus,
+    ) -> Option<u32> {
+        match status {
+            service::ServiceStatus::Running if !template.is_some_and(|t| t.is_docker) => {
+                self.running_pid(instance)
+            },
+            _ => instance
+                .pid
+                .filter(|&pid| !self.monitor.service_exited(pid)),
+        }
+    }
+
+    /// The command `terminate` runs for an instance, with `{pid}` filled in
+    ///
+    /// Without a PID, a stop command that doesn't need one (e.g. `brew
+    /// services stop`) still runs. None when the process is stopped with
+    /// signals instead, or when the command needs a PID that isn't known.
+    pub(crate) async fn stop_command(
+        &self,
+        template: Option<&ServiceTemplate>,
+        instance: &ServiceInstance,
+    ) -> Option<String> {
+        if let Some(tmpl) = template.filter(|t| t.is_docker) {
+            let working_dir = self.working_dir(tmpl, instance).await;
+            let project = docker::ComposeProject::for_instance(instance, working_dir.as_deref());
+            return Some(project.down_command());
+        }
+
+        let stop_command = match template {
+            Some(t) => t.stop_command_for(instance),
+            None => instance.stop_command_override.as_deref(),
+        }?;
+        match instance.pid {
+            Some(pid) => Some(stop_command.replace("{pid}", &pid.to_string())),
+            None if stop_command.contains("{pid}") => {
+                warn!(
+                    instance_id = %instance.id,
+                    command = %stop_command,
+                    "Stop command needs {{pid}} but the PID is unknown, not running it"
+                );
+                None
+            },
+            None => Some(stop_command.to_string()),
+        }
+    }
+
+    /// The last `lines` lines an instance wrote to stderr, oldest first
+    ///
+    /// Prefers the in-memory buffer and falls back to the captured output
+    /// files of the most recent run. Empty if nothing was captured.
+    pub(crate) fn stderr_tail(&self, instance: &ServiceInstance, lines: usize) -> Vec<String> {
+        if let Some(recent) = self.logs.recent(&instance.id) {
+            let stderr: Vec<String> = recent
+                .into_iter()
+                .filter(|l| l.stream == logs::LogStream::Stderr)
+                .map(|l| l.line)
+                .collect();
+            return stderr[stderr.len().saturating_sub(lines)..].to_vec();
+        }
+        instance
+            .last_pid
+            .and_then(|pid| self.read_captured(&instance.id, pid, lines).ok())
+            .map(|logs| logs.stderr)
+            .unwrap_or_default()
+    }
+
+    /// Files an instance's output is captured to for its run as `pid`
+    ///
+    /// Per-instance files in `[logs] dir` if one is configured, otherwise
+    /// the per-PID files in the temp dir (see `monitor::log_paths`).
+    pub fn log_paths(&self, id: &str, pid: u32) -> (PathBuf, PathBuf) {
+        match &self.log_dir {
+            Some(dir) => monitor::instance_log_paths(dir, id),
+            None => monitor::log_paths(pid),
+        }
+    }
+
+    /// Delete the per-PID output files of a run no instance shows anymore
+    ///
+    /// With a `[logs] dir`, each run truncates its instance's files instead.
+    fn remove_output(&self, pid: u32) {
+        if self.log_dir.is_none() {
+            self.monitor.remove_logs(pid);
+        }
+    }
+
+    /// The last `lines` lines of the output captured for `id`'s run as `pid`
+    fn read_captured(&self, id: &str, pid: u32, lines: usize) -> Result<logs::CapturedLogs> {
+        match &self.log_dir {
+            Some(_) => logs::read_captured(&self.log_paths(id, pid), lines),
+            None => self.monitor.read_logs(pid, lines),
+        }
+    }
+
+    /// SIGTERM `pid`, escalating to SIGKILL if its service hasn't exited
+    /// after `grace`
+    ///
+    /// Both signals go to the process group USM spawned it in, if any, and
+    /// the service counts as exited once nothing in that group is left.
+    async fn kill_gracefully(&self, pid: u32, grace: Duration) -> Result<()> {
+        const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+        self.monitor.kill_process(pid)?;
+        let deadline = tokio::time::Instant::now() + grace;
+        while !self.monitor.service_exited(pid) {
+            if tokio::time::Instant::now() >= deadline {
+                warn!(
+                    pid = pid,
+                    grace_ms = grace.as_millis() as u64,
+                    "Process ignored SIGTERM, sending SIGKILL"
+                );
+                let group = self.monitor.process_group(pid).is_some();
+                return self.monitor.signal_process(pid, Signal::Kill, group);
+            }
+            tokio::time::sleep(POLL_INTERVAL).await;
+        }
+        Ok(())
+    }
+
+    /// Start an instance
+    ///
+    /// The instance is `Starting` while its process is launched, then
+    /// `Running`, or `Error` if the launch failed; each transition is
+    /// broadcast. The registry isn't locked meanwhile, so several starts can
+    /// overlap.
+    #[instrument(skip(self), fields(instance_id = %id))]
+    pub async fn start_instance(&self, id: &str) -> Result<()> {
+        let (starting, previous) = self.begin_start(id).await?;
+
+        // Get template for start command, then build and execute it
+        let result = match self.get_template(&starting.template_id).await {
+            Some(template) => self.launch(&template, &starting).await,
+            None => Err(anyhow::anyhow!(
+                "Template '{}' not found",
+                starting.template_id
+            )),
+        };
+        let pid = self.finish_start(id, previous, result).await?;
+
+        info!(instance_id = %id, pid = ?pid, "Instance started");
+        Ok(())
+    }
+
+    /// Mark an instance `Starting`, returning it and the status it had
+    ///
+    /// Fails if it's already starting or still stopping, or if starting it
+    /// would break `[limits]`. Every call must be followed by `finish_start`.
+    pub(crate) async fn begin_start(
+        &self,
+        id: &str,
+    ) -> Result<(ServiceInstance, service::ServiceStatus)> {
+        let limits = self.limits().await;
+        let mut instances = self.instances.write().await;
+        let status = instances
+            .get(id)
+            .ok_or_else(|| anyhow::anyhow!("Instance '{}' not found", id))?
+            .status;
+        match status {
+            service::ServiceStatus::Stopping => {
+                anyhow::bail!("Instance '{}' is still stopping", id)
+            },
+            service::ServiceStatus::Starting => {
+                anyhow::bail!("Instance '{}' is already starting", id)
+            },
+            service::ServiceStatus::Running => {},
+            _ => limits.check_start(&instances)?,
+        }
+        let instance = instances.get_mut(id).expect("instance looked up above");
+        instance.status = service::ServiceStatus::Starting;
+        self.event_bus.send(ServiceEvent::StatusChanged {
+            instance_id: id.to_string(),
+            status: service::ServiceStatus::Starting,
+            pid: instance.pid,
+        });
+        Ok((instance.clone(), status))
+    }
+
+    /// Settle a start begun by `begin_start` with the launch's result
+    ///
+    /// On success the instance is `Running` with the new PID. On failure
+    /// the reason is recorded and it's `Error`, unless it was running all
+    /// along. Returns the PID, or the launch error.
+    pub(crate) async fn finish_start(
+        &self,
+        id: &str,
+        previous: service::ServiceStatus,
+        result: Result<Option<u32>>,
+    ) -> Result<Option<u32>> {
+        let mut instances = self.instances.write().await;
+        let instance = instances
+            .get_mut(id)
+            .ok_or_else(|| anyhow::anyhow!("Instance '{}' was removed while starting", id))?;
+        let outcome = match result {
+            Ok(pid) => {
+                if let Some(last_pid) = instance
+                    .last_pid
+                    .filter(|&last| pid.is_some_and(|p| p != last))
+                {
+                    // The new run's output replaces the old one's
+                    self.remove_output(last_pid);
+                }
+                instance.status = service::ServiceStatus::Running;
+                instance.pid = pid;
+                instance.last_pid = pid.or(instance.last_pid);
+                instance.started_at = Some(chrono::Utc::now());
+                instance.clear_failure();
+                Ok(pid)
+            },
+            Err(e) => {
+                instance.status = match previous {
+                    service::ServiceStatus::Running => service::ServiceStatus::Running,
+                    _ => service::ServiceStatus::Error,
+                };
+                instance.record_start_failure(&e);
+                Err(e)
+            },
+        };
+
+        self.event_bus.send(ServiceEvent::StatusChanged {
+            instance_id: id.to_string(),
+            status: instance.status,
+            pid: instance.pid,
+        });
+        self.persist_state(&instances).await;
+        outcome
+    }
+
+    /// Stop an instance
+    ///
+    /// The instance is `Stopping` while its process is given time to exit,
+    /// then `Stopped`, or back to what it was if stopping failed; each
+    /// transition is broadcast. The registry isn't locked meanwhile. A
+    /// running instance whose PID is unknown is stopped through the process
+    /// on its port (see `running_pid`).
+    #[instrument(skip(self), fields(instance_id = %id))]
+    pub async fn stop_instance(&self, id: &str) -> Result<()> {
+        let (stopping, previous) = {
+            let mut instances = self.instances.write().await;
+            let instance = instances
+                .get_mut(id)
+                .ok_or_else(|| anyhow::anyhow!("Instance '{}' not found", id))?;
+
+            // A crashed (Error) instance has no process left, but stopping it
+            // settles it as Stopped and cancels any supervised restart
+            if !matches!(
+                instance.status,
+                service::ServiceStatus::Running | service::ServiceStatus::Error
+            ) {
+                return Ok(()); // Already stopped (or being stopped)
+            }
+            let previous = instance.status;
+            instance.status = service::ServiceStatus::Stopping;
+            self.event_bus.send(ServiceEvent::StatusChanged {
+                instance_id: id.to_string(),
+                status: service::ServiceStatus::Stopping,
+                pid: instance.pid,
+            });
+            (instance.clone(), previous)
+        };
+
+        // Get template for optional custom stop command
+        let template = self.get_template(&stopping.template_id).await;
+        let mut stopping = stopping;
+        stopping.pid = self.stop_pid(template.as_ref(), &stopping, previous);
+
+        // Stop the process
+        let result = self.terminate(template.as_ref(), &stopping).await;
+
+        let mut instances = self.instances.write().await;
+        let Some(instance) = instances.get_mut(id) else {
+            return result; // Removed meanwhile
+        };
+        if let Err(e) = result {
+            instance.status = previous;
+            self.event_bus.send(ServiceEvent::StatusChanged {
+                instance_id: id.to_string(),
+                status: previous,
+                pid: instance.pid,
+            });
+            return Err(e);
+        }
+
+        // Update instance state; the stopped service's exit code (killed by
+        // USM) says nothing
+        if let Some(pid) = instance.pid {
+            self.monitor.remove_exit_status(pid);
+        }
+        instance.status = service::ServiceStatus::Stopped;
+        instance.pid = None;
+        instance.started_at = None;
+
+        // Broadcast event
+        self.event_bus.send(ServiceEvent::StatusChanged {
+            instance_id: id.to_string(),
+            status: service::ServiceStatus::Stopped,
+            pid: None,
+        });
+        self.persist_state(&instances).await;
+
+        info!(instance_id = %id, "Instance stopped");
+        Ok(())
+    }
+
+    /// Clear an instance's error state, leaving it `Stopped`
+    ///
+    /// A process it may still have is stopped first (see `stop_instance`),
+    /// and its last exit code and error are forgotten. Stopped instances are
+    /// only cleared; anything else in progress or running is refused.
+    #[instrument(skip(self), fields(instance_id = %id))]
+    pub async fn reset_instance(&self, id: &str) -> Result<()> {
+        let instance = self
+            .get_instance(id)
+            .await
+            .ok_or_else(|| anyhow::anyhow!("Instance '{}' not found", id))?;
+        match instance.status {
+            service::ServiceStatus::Error => self.stop_instance(id).await?,
+            service::ServiceStatus::Stopped => {},
+            status => anyhow::bail!("Cannot reset instance '{}' while it is {}", id, status),
+        }
+
+        let mut instances = self.instances.write().await;
+        let instance = instances
+            .get_mut(id)
+            .ok_or_else(|| anyhow::anyhow!("Instance '{}' not found", id))?;
+        instance.clear_failure();
+        self.event_bus.send(ServiceEvent::InstanceUpdated {
+            instance_id: id.to_string(),
+        });
+        self.persist_state(&instances).await;
+
+        info!(instance_id = %id, "Instance reset");
+        Ok(())
+    }
+
+    /// Restart an instance
+    pub async fn restart_instance(&self, id: &str) -> Result<()> {
+        self.stop_instance(id).await?;
+        tokio::time::sleep(tokio::time::Duration::from_secs(2)).await;
+        self.start_instance(id).await
+    }
+
+    /// Send a Unix signal (e.g. `SIGUSR1`) to a running instance
+    ///
+    /// When `group` is set, the signal goes to the instance's whole process
+    /// group rather than just its main process. Returns the PID signalled.
+    #[instrument(skip(self), fields(instance_id = %id))]
+    pub async fn signal_instance(&self, id: &str, signal: &str, group: bool) -> Result<u32> {
+        let signal: Signal = signal.parse()?;
+
+        let instances = self.instances.read().await;
+        let instance = instances
+            .get(id)
+            .ok_or_else(|| anyhow::anyhow!("Instance '{}' not found", id))?;
+
+        let pid = match (instance.status, instance.pid) {
+            (service::ServiceStatus::Running, Some(pid)) => pid,
+            _ => anyhow::bail!("Instance '{}' is not running", id),
+        };
+        drop(instances);
+
+        self.monitor.signal_process(pid, signal, group)?;
+
+        info!(instance_id = %id, pid = %pid, signal = %signal, "Signal sent");
+        Ok(pid)
+    }
+
+    /// Ask a running instance to reload its config without restarting
+    ///
+    /// Sends its template's `reload_signal` (SIGHUP when unset) to the
+    /// instance's main process, returning the signal sent.
+    #[instrument(skip(self), fields(instance_id = %id))]
+    pub async fn reload_instance(&self, id: &str) -> Result<Signal> {
+        let instance = self
+            .get_instance(id)
+            .await
+            .ok_or_else(|| anyhow::anyhow!("Instance '{}' not found", id))?;
+        let signal = match self
+            .get_template(&instance.template_id)
+            .await
+            .and_then(|t| t.reload_signal)
+        {
+            Some(name) => name.parse()?,
+            None => Signal::Hup,
+        };
+
+        self.signal_instance(id, signal.name(), false).await?;
+        Ok(signal)
+    }
+
+    /// Clone an instance with different configuration
+    ///
+    /// The clone always uses the source's template, and its working directory
+    /// unless `new_config` sets one. A clone whose id names no group joins
+    /// the source's group. If the new config omits a port, the next
+    /// free port in the template's range is used. With `inherit`, the source's
+    /// tags and environment are copied over (values in `new_config` take
+    /// precedence).
+    pub async fn clone_instance(
+        &self,
+        source_id: &str,
+        mut new_config: service::InstanceConfig,
+        inherit: bool,
+    ) -> Result<String> {
+        let source = self
             .get_instance(source_id)
             .await
-            .ok_or_else(|| anyhow::anyhow!("Source instance '{}' not found", source_id))?;
+            .ok_or_else(|| anyhow::anyhow!("Source instance '{}' not found", source_id))?;
+
+        let template = self
+            .get_template(&source.template_id)
+            .await
+            .ok_or_else(|| anyhow::anyhow!("Template '{}' not found", source.template_id))?;
+
+        if !template.supports_multiple {
+            anyhow::bail!(
+                "Cannot clone '{}': template '{}' does not support multiple instances",
+                source_id,
+                template.id
+            );
+        }
+
+        // Inherit template and working directory from source
+        new_config.template_id = source.template_id;
+        if new_config.working_dir.is_none() {
+            new_config.working_dir = source.working_dir;
+        }
+        if new_config.group.is_none() && !new_config.instance_id.contains('/') {
+            new_config.group = source.group;
+        }
+
+        if inherit {
+            for tag in source.tags {
+                if !new_config.tags.contains(&tag) {
+                    new_config.tags.push(tag);
+                }
+            }
+            for (key, value) in source.env_vars {
+                new_config.env_vars.entry(key).or_insert(value);
+            }
+        }
+
+        self.create_instance(new_config).await
+    }
+
+    /// An instance's effective environment, with where each value came from
+    pub async fn instance_env(&self, id: &str) -> Result<BTreeMap<String, EnvValue>> {
+        let instance = self
+            .get_instance(id)
+            .await
+            .ok_or_else(|| anyhow::anyhow!("Instance '{}' not found", id))?;
+        let template = self
+            .get_template(&instance.template_id)
+            .await
+            .ok_or_else(|| anyhow::anyhow!("Template '{}' not found", instance.template_id))?;
+        Ok(template.effective_env(&instance))
+    }
+
+    /// Set (`Some`) or unset (`None`) keys of an instance's `env_vars`
+    ///
+    /// Unsetting a key lets the template's default show through again.
+    /// Running instances keep their environment; changes take effect on the
+    /// next start. Returns the new effective environment.
+    #[instrument(skip(self, changes), fields(instance_id = %id))]
+    pub async fn patch_instance_env(
+        &self,
+        id: &str,
+        changes: HashMap<String, Option<String>>,
+    ) -> Result<BTreeMap<String, EnvValue>> {
+        if let Some(key) = changes.keys().find(|k| k.is_empty() || k.contains('=')) {
+            anyhow::bail!("Invalid environment variable name '{}'", key);
+        }
+
+        let mut instances = self.instances.write().await;
+        let instance = instances
+            .get_mut(id)
+            .ok_or_else(|| anyhow::anyhow!("Instance '{}' not found", id))?;
+
+        for (key, value) in changes {
+            match value {
+                Some(value) => instance.env_vars.insert(key, value),
+                None => instance.env_vars.remove(&key),
+            };
+        }
+
+        self.persist_instances(&instances).await?;
+        drop(instances);
+
+        info!(instance_id = %id, "Instance environment updated");
+        self.instance_env(id).await
+    }
+
+    /// Apply a partial update to an instance (see `InstanceRegistry::update`)
+    ///
+    /// The change is saved to the config file. Like env edits, a running
+    /// instance keeps its environment and working directory until it's
+    /// restarted. Returns the updated instance.
+    #[instrument(skip(self, update), fields(instance_id = %id))]
+    pub async fn update_instance(
+        &self,
+        id: &str,
+        update: service::InstanceUpdate,
+    ) -> Result<ServiceInstance> {
+        let mut instances = self.instances.write().await;
+        instances.update(id, update)?;
+        self.persist_instances(&instances).await?;
+        let instance = instances.get(id).expect("instance updated above");
+        drop(instances);
+
+        self.event_bus.send(ServiceEvent::InstanceUpdated {
+            instance_id: id.to_string(),
+        });
+
+        info!(instance_id = %id, "Instance updated");
+        Ok(instance)
+    }
+
+    /// Move an instance to another port, returning its old one
+    ///
+    /// The port must be inside the template's port range, unused by other
+    /// instances and not bound by anything outside USM. A running instance
+    /// keeps its old port until it's restarted. Only this instance's entry
+    /// in the config file is rewritten, so edits made to the file since it
+    /// was loaded survive; when such an edit was rejected for putting two
+    /// instances on one port, moving one of them here resolves the clash.
+    #[instrument(skip(self), fields(instance_id = %id))]
+    pub async fn reassign_port(&self, id: &str, port: u16) -> Result<u16> {
+        let mut instances = self.instances.write().await;
+        let instance = instances
+            .get(id)
+            .ok_or_else(|| anyhow::anyhow!("Instance '{}' not found", id))?;
+        let old_port = instance.port;
+        if port == old_port {
+            return Ok(old_port);
+        }
+
+        if let Some(template) = self.templates.read().await.get(&instance.template_id) {
+            template.check_port(port)?;
+        }
+        if let Some(other) = instances.find_by_port(port) {
+            anyhow::bail!("Port {} is already in use by instance '{}'", port, other.id);
+        }
+        if self.monitor.is_port_in_use(port) {
+            anyhow::bail!("Port {} is already in use by another process", port);
+        }
+
+        let instance = instances.get_mut(id).expect("instance looked up above");
+        instance.port = port;
+        let instance = instance.clone();
+        self.persist_instance(&instance).await?;
+        drop(instances);
+
+        self.event_bus.send(ServiceEvent::PortChanged {
+            instance_id: id.to_string(),
+            old_port,
+            new_port: port,
+        });
+
+        info!(instance_id = %id, old_port, new_port = port, "Instance port changed");
+        Ok(old_port)
+    }
+
+    // =========================================================================
+    // HEALTH & DEPENDENCIES
+    // =========================================================================
+
+    /// Core instances that are in Error, sorted by id
+    ///
+    /// An instance counts as core when it is marked `auto_start` or tagged
+    /// `critical`; the HTTP health check reports the node as degraded while
+    /// any of them has failed.
+    pub async fn unhealthy_instances(&self) -> Vec<String> {
+        let mut ids: Vec<String> = self
+            .instances
+            .read()
+            .await
+            .list_by_status(service::ServiceStatus::Error)
+            .into_iter()
+            .filter(|i| i.auto_start || i.tags.iter().any(|t| t == "critical"))
+            .map(|i| i.id)
+            .collect();
+        ids.sort();
+        ids
+    }
+
+    /// Wait until an instance passes its health check
+    ///
+    /// Polls the template's health check (HTTP, TCP or command) until it
+    /// succeeds or `timeout` elapses. Instances whose template defines no
+    /// health check count as healthy once they are Running. The wait never
+    /// gives up before the template's `health_start_period_ms` is over.
+    #[instrument(skip(self), fields(instance_id = %id))]
+    pub async fn wait_for_healthy(&self, id: &str, timeout: Duration) -> Result<()> {
+        const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+        let mut deadline = tokio::time::Instant::now() + timeout;
+        loop {
+            let instance = self
+                .get_instance(id)
+                .await
+                .ok_or_else(|| anyhow::anyhow!("Instance '{}' not found", id))?;
+            let template = self
+                .get_template(&instance.template_id)
+                .await
+                .ok_or_else(|| anyhow::anyhow!("Template '{}' not found", instance.template_id))?;
+            let grace = template.health_grace_remaining(&instance, chrono::Utc::now());
+            deadline = deadline.max(tokio::time::Instant::now() + grace);
+
+            let result = if instance.status != service::ServiceStatus::Running {
+                Err(anyhow::anyhow!("Instance '{}' is not running", id))
+            } else {
+                match HealthCheck::for_instance(&template, &instance) {
+                    Some(check) => {
+                        let remaining =
+                            deadline.saturating_duration_since(tokio::time::Instant::now());
+                        let probe_timeout =
+                            Duration::from_millis(template.health_timeout_ms as u64).min(remaining);
+                        check.probe(probe_timeout).await
+                    },
+                    None => Ok(()),
+                }
+            };
+
+            match result {
+                Ok(()) => {
+                    self.event_bus.send(ServiceEvent::HealthChanged {
+                        instance_id: id.to_string(),
+                        healthy: true,
+                        message: None,
+                    });
+                    info!(instance_id = %id, "Instance healthy");
+                    return Ok(());
+                },
+                Err(e) if tokio::time::Instant::now() + POLL_INTERVAL >= deadline => {
+                    let message = format!(
+                        "Instance '{}' did not become healthy within {}ms: {}",
+                        id,
+                        timeout.as_millis(),
+                        e
+                    );
+                    self.event_bus.send(ServiceEvent::HealthChanged {
+                        instance_id: id.to_string(),
+                        healthy: false,
+                        message: Some(message.clone()),
+                    });
+                    anyhow::bail!(message);
+                },
+                Err(_) => tokio::time::sleep(POLL_INTERVAL).await,
+            }
+        }
+    }
+
+    /// Start an instance after its dependencies
+    ///
+    /// Dependencies are started tier by tier; each tier must become healthy
+    /// (within `timeout` per instance) before the next one starts. Instances
+    /// that are already running are not restarted.
+    #[instrument(skip(self), fields(instance_id = %id))]
+    pub async fn start_with_dependencies(&self, id: &str, timeout: Duration) -> Result<()> {
+        let tiers = self.instances.read().await.dependency_tiers(id)?;
+        let (target, dependencies) = tiers.split_last().expect("tiers include the target");
+
+        for tier in dependencies {
+            for dep in tier {
+                if !self.is_running(dep).await {
+                    self.start_instance(dep).await.map_err(|e| {
+                        anyhow::anyhow!("Dependency '{}' failed to start: {}", dep, e)
+                    })?;
+                }
+            }
+            for dep in tier {
+                self.wait_for_healthy(dep, timeout).await.map_err(|e| {
+                    anyhow::anyhow!("Dependency '{}' failed to become healthy: {}", dep, e)
+                })?;
+            }
+        }
+
+        for instance_id in target {
+            if !self.is_running(instance_id).await {
+                self.start_instance(instance_id).await?;
+            }
+        }
+        Ok(())
+    }
+
+    async fn is_running(&self, id: &str) -> bool {
+        self.get_instance(id)
+            .await
+            .is_some_and(|i| i.status == service::ServiceStatus::Running)
+    }
+
+    /// Current `[limits]`, unlimited if the section is absent
+    pub(crate) async fn limits(&self) -> config::LimitSettings {
+        self.settings
+            .read()
+            .await
+            .limits
+            .clone()
+            .unwrap_or_default()
+    }
+
+    /// Persist templates to the config file, if there is one
+    async fn persist_templates(&self, templates: &TemplateRegistry) -> Result<()> {
+        match &self.config_manager {
+            Some(config_manager) => config_manager.save_templates(templates).await,
+            None => Ok(()),
+        }
+    }
+
+    /// Persist instances to the config file, if there is one
+    async fn persist_instances(&self, instances: &InstanceRegistry) -> Result<()> {
+        match &self.config_manager {
+            Some(config_manager) => config_manager.save_instances(instances).await,
+            None => Ok(()),
+        }
+    }
+
+    /// Persist one instance to the config file, if there is one, keeping
+    /// the file's other instances as they are
+    async fn persist_instance(&self, instance: &ServiceInstance) -> Result<()> {
+        match &self.config_manager {
+            Some(config_manager) => config_manager.save_instance(instance).await,
+            None => Ok(()),
+        }
+    }
+
+    /// Save instances' runtime state next to the config file, if there is one
+    ///
+    /// Failures are only logged: the operation that changed the state has
+    /// already happened.
+    pub(crate) async fn persist_state(&self, instances: &InstanceRegistry) {
+        let Some(config_manager) = &self.config_manager else {
+            return;
+        };
+        let path = config_manager.state_path();
+        let state =
+            state::RuntimeState::capture(instances, |pid| self.monitor.process_start_time(pid));
+        if let Err(e) = state.save(&path).await {
+            warn!(path = %path.display(), "Failed to save runtime state: {}", e);
+        }
+    }
+
+    /// Re-attach to instances a previous USM run left running
+    ///
+    /// Instances whose process is gone, or whose PID now belongs to a
+    /// process that started later, come back Stopped.
+    pub(crate) async fn restore_state(&self) {
+        let Some(config_manager) = &self.config_manager else {
+            return;
+        };
+        let saved = state::RuntimeState::load(&config_manager.state_path()).await;
+        let mut instances = self.instances.write().await;
+        let attached = saved.restore(&mut instances, |pid| self.monitor.process_start_time(pid));
+        for id in &attached {
+            info!(instance_id = %id, "Re-attached to running instance");
+        }
+        if attached.len() != saved.instances.len() {
+            self.persist_state(&instances).await;
+        }
+    }
+
+    // =========================================================================
+    // BULK OPERATIONS
+    // =========================================================================
+
+    /// Start all instances matching any of the given tags
+    pub async fn start_by_tags(&self, tags: &[&str]) -> Vec<Result<()>> {
+        self.start_by_tags_matching(tags, TagMatch::Any).await
+    }
+
+    /// Start all instances whose tags match `tags` under `tag_match`
+    pub async fn start_by_tags_matching(
+        &self,
+        tags: &[&str],
+        tag_match: TagMatch,
+    ) -> Vec<Result<()>> {
+        self.start_ids(self.ids_by_tags(tags, tag_match).await)
+            .await
+    }
+
+    /// Start all instances of a template
+    pub async fn start_by_template(&self, template_id: &str) -> Vec<Result<()>> {
+        self.start_matching(&InstanceFilter {
+            template: Some(template_id.to_string()),
+            ..Default::default()
+        })
+        .await
+    }
+
+    /// Start all instances matching a filter
+    ///
+    /// Unlike the tag versions, an empty filter selects every instance.
+    pub async fn start_matching(&self, filter: &InstanceFilter) -> Vec<Result<()>> {
+        self.start_ids(self.ids_matching(filter).await).await
+    }
+
+    async fn start_ids(&self, ids: Vec<String>) -> Vec<Result<()>> {
+        self.run_bulk(&ids, false, |id| self.start_instance(id))
+            .await
+    }
+
+    /// Run `op` on every instance in `ids`, returning results in `ids` order
+    ///
+    /// Up to `[limits] bulk_concurrency` instances are handled at once. An
+    /// instance waits for those in `ids` it depends on (or, with `reverse`,
+    /// for those that depend on it), so starts go dependencies-first and
+    /// stops dependents-first.
+    async fn run_bulk<'a, F, Fut>(&self, ids: &'a [String], reverse: bool, op: F) -> Vec<Result<()>>
+    where
+        F: Fn(&'a str) -> Fut,
+        Fut: std::future::Future<Output = Result<()>>,
+    {
+        use futures_util::StreamExt;
+
+        let concurrency = self.limits().await.bulk_concurrency();
+        let mut waves = self.instances.read().await.bulk_waves(ids);
+        if reverse {
+            waves.reverse();
+        }
+
+        let mut results: Vec<Option<Result<()>>> = ids.iter().map(|_| None).collect();
+        for wave in waves {
+            let outcomes: Vec<Result<()>> =
+                futures_util::stream::iter(wave.iter().map(|&i| op(&ids[i])))
+                    .buffered(concurrency)
+                    .collect()
+                    .await;
+            for (i, outcome) in wave.into_iter().zip(outcomes) {
+                results[i] = Some(outcome);
+            }
+        }
+        results
+            .into_iter()
+            .map(|r| r.expect("every instance is in a wave"))
+            .collect()
+    }
+
+    /// IDs of the instances matching a filter, sorted
+    async fn ids_matching(&self, filter: &InstanceFilter) -> Vec<String> {
+        let mut ids: Vec<String> = self
+            .query_instances(filter)
+            .await
+            .into_iter()
+            .map(|i| i.id)
+            .collect();
+        ids.sort();
+        ids
+    }
+
+    /// IDs of the instances whose tags match `tags` under `tag_match`
+    async fn ids_by_tags(&self, tags: &[&str], tag_match: TagMatch) -> Vec<String> {
+        self.instances
+            .read()
+            .await
+            .list()
+            .into_iter()
+            .filter(|i| tag_match.matches(i, tags))
+            .map(|i| i.id)
+            .collect()
+    }
+
+    /// Start every instance marked `auto_start`
+    ///
+    /// Returns an outcome for every instance, sorted by instance ID, so
+    /// callers can report what came up and why the rest didn't.
+    #[instrument(skip(self))]
+    pub async fn start_autostart_instances(&self) -> Vec<(String, AutoStartOutcome)> {
+        let mut all = self.instances.read().await.list();
+        all.sort_by(|a, b| a.id.cmp(&b.id));
+
+        let mut outcomes = Vec::with_capacity(all.len());
+        for instance in all {
+            let outcome = if !instance.auto_start {
+                AutoStartOutcome::Skipped(SkipReason::Disabled)
+            } else if instance.status == service::ServiceStatus::Running {
+                AutoStartOutcome::Skipped(SkipReason::AlreadyRunning)
+            } else {
+                match self.start_instance(&instance.id).await {
+                    Ok(()) => {
+                        let pid = self
+                            .get_instance(&instance.id)
+                            .await
+                            .and_then(|i| i.pid)
+                            .unwrap_or(0);
+                        AutoStartOutcome::Started(pid)
+                    },
+                    Err(e) => {
+                        warn!(instance_id = %instance.id, "Auto-start failed: {}", e);
+                        AutoStartOutcome::Failed(e.to_string())
+                    },
+                }
+            };
+            outcomes.push((instance.id, outcome));
+        }
+        outcomes
+    }
+
+    /// Stop all instances matching any of the given tags
+    pub async fn stop_by_tags(&self, tags: &[&str]) -> Vec<Result<()>> {
+        self.stop_by_tags_matching(tags, TagMatch::Any).await
+    }
+
+    /// Stop all instances whose tags match `tags` under `tag_match`
+    pub async fn stop_by_tags_matching(
+        &self,
+        tags: &[&str],
+        tag_match: TagMatch,
+    ) -> Vec<Result<()>> {
+        self.stop_ids(self.ids_by_tags(tags, tag_match).await).await
+    }
+
+    /// Stop all instances of a template
+    pub async fn stop_by_template(&self, template_id: &str) -> Vec<Result<()>> {
+        self.stop_matching(&InstanceFilter {
+            template: Some(template_id.to_string()),
+            ..Default::default()
+        })
+        .await
+    }
+
+    /// Stop all instances matching a filter
+    ///
+    /// Unlike the tag versions, an empty filter selects every instance.
+    pub async fn stop_matching(&self, filter: &InstanceFilter) -> Vec<Result<()>> {
+        self.stop_ids(self.ids_matching(filter).await).await
+    }
+
+    async fn stop_ids(&self, ids: Vec<String>) -> Vec<Result<()>> {
+        self.run_bulk(&ids, true, |id| self.stop_instance(id)).await
+    }
+
+    // =========================================================================
+    // SCHEDULING
+    // =========================================================================
+
+    /// A scheduler configured from the `[scheduler]` settings
+    pub async fn new_scheduler(&self) -> Scheduler {
+        let policy = self
+            .settings
+            .read()
+            .await
+            .scheduler
+            .as_ref()
+            .map(|s| s.missed)
+            .unwrap_or_default();
+        Scheduler::new(policy)
+    }
+
+    /// Carry out the scheduled actions due at `now`
+    ///
+    /// Starts instances that aren't running and stops those that are; an
+    /// action that's already satisfied is a no-op but still reported. Each
+    /// action fired emits `ScheduleTriggered`. Returns the actions fired.
+    #[instrument(skip(self, scheduler))]
+    pub async fn run_schedules(
+        &self,
+        scheduler: &mut Scheduler,
+        now: chrono::DateTime<chrono::Utc>,
+    ) -> Vec<(String, ScheduledAction)> {
+        let default_tz = self
+            .settings
+            .read()
+            .await
+            .scheduler
+            .as_ref()
+            .and_then(|s| s.timezone.as_deref())
+            .and_then(|tz| {
+                schedule::parse_timezone(tz)
+                    .map_err(|e| warn!("{}, using UTC", e))
+                    .ok()
+            })
+            .unwrap_or(chrono_tz::Tz::UTC);
+
+        let mut schedules = Vec::new();
+        for instance in self.instances.read().await.list() {
+            let Some(config) = instance.schedule else {
+                continue;
+            };
+            match CompiledSchedule::compile(&config, default_tz) {
+                Ok(compiled) => schedules.push((instance.id, compiled)),
+                Err(e) => warn!(instance_id = %instance.id, "Ignoring schedule: {}", e),
+            }
+        }
+
+        let due = scheduler.tick(schedules.iter().map(|(id, s)| (id.as_str(), s)), now);
+        for (id, action) in &due {
+            let running = self.is_running(id).await;
+            let result = match action {
+                ScheduledAction::Start if !running => self.start_instance(id).await,
+                ScheduledAction::Stop if running => self.stop_instance(id).await,
+                _ => Ok(()),
+            };
+            if let Err(ref e) = result {
+                warn!(instance_id = %id, action = %action, "Scheduled action failed: {}", e);
+            } else {
+                info!(instance_id = %id, action = %action, "Scheduled action fired");
+            }
+            self.event_bus.send(ServiceEvent::ScheduleTriggered {
+                instance_id: id.clone(),
+                action: *action,
+                error: result.err().map(|e| e.to_string()),
+            });
+        }
+        due
+    }
+
+    /// Evaluate schedules once a minute in the background
+    ///
+    /// The first evaluation happens immediately, which is when missed
+    /// actions are caught up under `missed = "catch_up"`.
+    pub fn spawn_scheduler(&self) -> tokio::task::JoinHandle<()> {
+        let core = self.clone();
+        tokio::spawn(async move {
+            let mut scheduler = core.new_scheduler().await;
+            let mut interval = tokio::time::interval(Duration::from_secs(60));
+            interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+            loop {
+                interval.tick().await;
+                core.run_schedules(&mut scheduler, chrono::Utc::now()).await;
+            }
+        })
+    }
+
+    // =========================================================================
+    // SUPERVISION
+    // =========================================================================
+
+    /// Detect crashed instances and carry out due restarts at `now`
+    ///
+    /// Only instances with an enabled restart policy are checked. A Running
+    /// instance whose process is gone, or for Docker templates whose
+    /// containers have all stopped (see `docker::is_down`), is marked Error
+    /// and a restart is
+    /// scheduled with backoff; when the retries run out an `Error` event is
+    /// emitted and the instance stays in Error. Resource limits are checked
+    /// and exited spawn wrappers reaped on the same pass (see
+    /// `enforce_limits` and `reap_zombies`). Returns the instances a restart
+    /// was attempted for.
+    #[instrument(skip(self, supervisor))]
+    pub async fn supervise(
+        &self,
+        supervisor: &mut Supervisor,
+        now: chrono::DateTime<chrono::Utc>,
+    ) -> Vec<String> {
+        let supervised: Vec<(ServiceInstance, RestartPolicy)> = {
+            let templates = self.templates.read().await;
+            let instances = self.instances.read().await;
+            instances
+                .list()
+                .into_iter()
+                .filter_map(|instance| {
+                    let policy = templates
+                        .get(&instance.template_id)?
+                        .restart_policy(&instance)?;
+                    Some((instance, policy))
+                })
+                .collect()
+        };
+
+        for (instance, policy) in &supervised {
+            let crashed = match (instance.status, instance.pid) {
+                (service::ServiceStatus::Running, Some(pid)) => !self.monitor.is_running(pid),
+                (service::ServiceStatus::Running, None) if self.is_docker(instance).await => {
+                    self.compose_down(instance).await
+                },
+                _ => false,
+            };
+            if !crashed || !self.mark_crashed(&instance.id, instance.pid).await {
+                continue;
+            }
+            warn!(instance_id = %instance.id, pid = ?instance.pid, "Instance exited unexpectedly");
+            self.record_failure(supervisor, &instance.id, policy, now);
+        }
+
+        self.enforce_limits(supervisor).await;
+        self.reap_zombies().await;
+
+        let due = supervisor.due(now);
+        for id in &due {
+            // Skip instances someone started or stopped in the meantime
+            let Some((_, policy)) = supervised.iter().find(|(i, _)| &i.id == id) else {
+                supervisor.forget(id);
+                continue;
+            };
+            if self.get_instance(id).await.map(|i| i.status) != Some(service::ServiceStatus::Error)
+            {
+                supervisor.forget(id);
+                continue;
+            }
+
+            info!(instance_id = %id, "Restarting crashed instance");
+            if let Err(e) = self.start_instance(id).await {
+                warn!(instance_id = %id, "Restart failed: {}", e);
+                self.record_failure(supervisor, id, policy, now);
+            }
+        }
+        due
+    }
+
+    /// Sample Running instances that have resource limits and act on those
+    /// that stayed over them for `LIMIT_SAMPLES` checks in a row
+    ///
+    /// Emits an `Error` event, then restarts or stops the instance if its
+    /// `limit_action` says so. The restart or stop runs as its own task, so a
+    /// slow one doesn't hold up supervision of other instances. Returns the
+    /// instances that were acted on.
+    pub async fn enforce_limits(&self, supervisor: &mut Supervisor) -> Vec<String> {
+        let mut limited: Vec<ServiceInstance> = self
+            .instances
+            .read()
+            .await
+            .list_by_status(service::ServiceStatus::Running)
+            .into_iter()
+            .filter(|i| i.cpu_limit_percent.is_some() || i.memory_limit_mb.is_some())
+            .collect();
+        limited.sort_by(|a, b| a.id.cmp(&b.id));
+        supervisor.retain_samples(&limited.iter().map(|i| i.id.clone()).collect::<Vec<_>>());
+
+        let mut acted = Vec::new();
+        for instance in limited {
+            let Some((cpu, memory_bytes)) = self.resource_usage(&instance).await else {
+                continue;
+            };
+            let breach = limit_breach(
+                instance.cpu_limit_percent,
+                instance.memory_limit_mb,
+                cpu,
+                memory_bytes,
+            );
+            if !supervisor.on_sample(&instance.id, breach.is_some()) {
+                continue;
+            }
+
+            let action = instance.limit_action.unwrap_or_default();
+            let message = format!(
+                "Instance '{}' over its resource limits for {} checks ({}), action: {}",
+                instance.id,
+                LIMIT_SAMPLES,
+                breach.unwrap_or_default(),
+                action
+            );
+            warn!(instance_id = %instance.id, "{}", message);
+            self.event_bus.send(ServiceEvent::Error {
+                instance_id: Some(instance.id.clone()),
+                message,
+            });
+
+            if action != LimitAction::Warn {
+                let core = self.clone();
+                let id = instance.id.clone();
+                tokio::spawn(async move {
+                    let result = match action {
+                        LimitAction::Restart => core.restart_instance(&id).await,
+                        _ => core.stop_instance(&id).await,
+                    };
+                    if let Err(e) = result {
+                        warn!(instance_id = %id, "Limit action '{}' failed: {}", action, e);
+                    }
+                });
+                supervisor.reset_samples(&instance.id);
+            }
+            acted.push(instance.id);
+        }
+        acted
+    }
+
+    /// Move a Running instance to Error after its process died
+    ///
+    /// Returns false if it changed in the meantime (e.g. was stopped).
+    async fn mark_crashed(&self, id: &str, pid: Option<u32>) -> bool {
+        let mut instances = self.instances.write().await;
+        let Some(instance) = instances.get_mut(id) else {
+            return false;
+        };
+        if instance.status != service::ServiceStatus::Running || instance.pid != pid {
+            return false;
+        }
+        instance.status = service::ServiceStatus::Error;
+        instance.pid = None;
+        instance.started_at = None;
+        instance.record_exit(pid.and_then(|pid| self.monitor.exit_code(pid)));
+        if let Some(pid) = pid {
+            self.monitor.remove_exit_status(pid);
+        }
+        self.persist_state(&instances).await;
+        drop(instances);
+
+        self.event_bus.send(ServiceEvent::StatusChanged {
+            instance_id: id.to_string(),
+            status: service::ServiceStatus::Error,
+            pid: None,
+        });
+        true
+    }
+
+    /// Schedule the next restart for a failed instance, or give up
+    fn record_failure(
+        &self,
+        supervisor: &mut Supervisor,
+        id: &str,
+        policy: &RestartPolicy,
+        now: chrono::DateTime<chrono::Utc>,
+    ) {
+        match supervisor.on_crash(id, policy, now) {
+            CrashOutcome::Retry { attempt, at } => {
+                info!(instance_id = %id, attempt, at = %at, "Restart scheduled");
+            },
+            CrashOutcome::GiveUp { attempts } => {
+                let message = format!(
+                    "Instance '{}' kept crashing, giving up after {} restarts",
+                    id, attempts
+                );
+                warn!(instance_id = %id, "{}", message);
+                self.event_bus.send(ServiceEvent::Error {
+                    instance_id: Some(id.to_string()),
+                    message,
+                });
+            },
+        }
+    }
+
+    /// Supervise instances once a second in the background
+    pub fn spawn_supervisor(&self) -> tokio::task::JoinHandle<()> {
+        let core = self.clone();
+        tokio::spawn(async move {
+            let mut supervisor = Supervisor::new();
+            let mut interval = tokio::time::interval(Duration::from_secs(1));
+            interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+            loop {
+                interval.tick().await;
+                core.supervise(&mut supervisor, chrono::Utc::now()).await;
+            }
+        })
+    }
+
+    // =========================================================================
+    // EVENTS & METRICS
+    // =========================================================================
+
+    /// Subscribe to service events
+    pub fn subscribe(&self) -> broadcast::Receiver<ServiceEvent> {
+        self.event_bus.subscribe()
+    }
+
+    /// Subscribe to the events of one instance, including its metrics
+    /// updates
+    ///
+    /// The instance doesn't have to exist yet; its creation is delivered too.
+    pub fn subscribe_instance(&self, id: &str) -> events::InstanceEvents {
+        self.event_bus.subscribe_instance(id)
+    }
+
+    /// Broadcast `MetricsUpdated` for running instances whose usage changed
+    ///
+    /// `last` holds what was last published per instance (CPU rounded to
+    /// 0.1%, memory in MB); unchanged instances are skipped and stopped ones
+    /// are dropped from it. Every sample, changed or not, is also recorded in
+    /// the metrics history. Returns the number of events sent.
+    pub async fn publish_metrics(&self, last: &mut HashMap<String, (f64, u64)>) -> usize {
+        let (running, known) = {
+            let instances = self.instances.read().await;
+            let known: Vec<String> = instances.list().into_iter().map(|i| i.id).collect();
+            (
+                instances.list_by_status(service::ServiceStatus::Running),
+                known,
+            )
+        };
+        last.retain(|id, _| running.iter().any(|i| &i.id == id));
+        // Instances removed behind our back (e.g. by a config reload)
+        self.metrics_history
+            .retain(|id| known.iter().any(|known| known == id));
+
+        let now = chrono::Utc::now();
+        let system = self.monitor.get_system_metrics();
+        self.metrics_history.record_system(metrics::MetricsSample {
+            timestamp: now,
+            cpu_percent: system.cpu_percent,
+            memory_bytes: system.memory_used_bytes,
+        });
+
+        let mut sent = 0;
+        for instance in running {
+            let Some((cpu, memory_bytes)) = self.resource_usage(&instance).await else {
+                continue;
+            };
+            self.metrics_history.record_instance(
+                &instance.id,
+                metrics::MetricsSample {
+                    timestamp: now,
+                    cpu_percent: cpu,
+                    memory_bytes,
+                },
+            );
+            let sample = ((cpu * 10.0).round() / 10.0, memory_bytes / (1024 * 1024));
+            if last.get(&instance.id) == Some(&sample) {
+                continue;
+            }
+            last.insert(instance.id.clone(), sample);
+            self.event_bus.send(ServiceEvent::MetricsUpdated {
+                instance_id: instance.id,
+                cpu_percent: sample.0,
+                memory_mb: sample.1,
+            });
+            sent += 1;
+        }
+        sent
+    }
+
+    /// Publish instance metrics every `interval` in the background
+    pub fn spawn_metrics_publisher(&self, interval: Duration) -> tokio::task::JoinHandle<()> {
+        let core = self.clone();
+        tokio::spawn(async move {
+            let mut last = HashMap::new();
+            let mut interval = tokio::time::interval(interval);
+            interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+            loop {
+                interval.tick().await;
+                core.publish_metrics(&mut last).await;
+            }
+        })
+    }
+
+    /// Recent system-wide samples, oldest first
+    pub fn system_metrics_history(&self) -> Vec<metrics::MetricsSample> {
+        self.metrics_history.system()
+    }
+
+    /// Recent samples for an instance, oldest first
+    pub async fn instance_metrics_history(&self, id: &str) -> Result<Vec<metrics::MetricsSample>> {
+        if self.get_instance(id).await.is_none() {
+            anyhow::bail!("Instance '{}' not found", id);
+        }
+        Ok(self.metrics_history.instance(id))
+    }
+
+    /// Get system-wide metrics
+    pub fn get_system_metrics(&self) -> metrics::SystemMetrics {
+        self.monitor.get_system_metrics()
+    }
+
+    /// Get the most recent output lines captured in memory for an instance
+    ///
+    /// Returns None if in-memory capture is disabled or nothing has been
+    /// captured for the instance yet.
+    pub fn recent_logs(&self, id: &str) -> Option<Vec<LogLine>> {
+        self.logs.recent(id)
+    }
+
+    /// The last `lines` lines of an instance's captured stdout/stderr
+    ///
+    /// Reads the output of the current run, or of the most recent one if
+    /// the instance has stopped or crashed. Returns the PID it belongs to.
+    pub async fn captured_logs(&self, id: &str, lines: usize) -> Result<(u32, logs::CapturedLogs)> {
+        let instance = self
+            .get_instance(id)
+            .await
+            .ok_or_else(|| anyhow::anyhow!("Instance '{}' not found", id))?;
+        let pid = instance
+            .last_pid
+            .ok_or_else(|| anyhow::anyhow!("No captured output: instance '{}' has not run", id))?;
+        let logs = self.read_captured(id, pid, lines)?;
+        Ok((pid, logs))
+    }
+
+    /// Current CPU percent and memory bytes of a running instance
+    ///
+    /// Looks the process up by port first (reliable for services that fork
+    /// children), then falls back to the stored PID. Docker instances report
+    /// their containers' usage. Returns None for instances that aren't
+    /// running or can't be found.
+    pub async fn resource_usage(&self, instance: &ServiceInstance) -> Option<(f64, u64)> {
+        if instance.status != service::ServiceStatus::Running {
+            return None;
+        }
+        if self.is_docker(instance).await {
+            return self
+                .docker_metrics(instance)
+                .await
+                .map(|m| (m.cpu_percent, m.memory_bytes));
+        }
+        if let Some(info) = self.monitor.find_by_port(instance.port) {
+            return Some((info.cpu_percent, info.memory_bytes));
+        }
+        instance
+            .pid
+            .and_then(|pid| self.monitor.get_process_metrics(pid))
+            .map(|m| (m.cpu_percent, m.memory_bytes))
+    }
+
+    /// Get metrics for a specific instance
+    ///
+    /// Returns None if the instance doesn't exist or isn't running.
+    pub async fn get_instance_metrics(&self, id: &str) -> Option<metrics::InstanceMetrics> {
+        let instance = self.get_instance(id).await?;
+        if instance.status != service::ServiceStatus::Running {
+            return None;
+        }
+        if self.is_docker(&instance).await {
+            return self.docker_metrics(&instance).await;
+        }
+        instance
+            .pid
+            .and_then(|pid| self.monitor.get_process_metrics(pid))
+    }
+
+    /// Metrics of every running instance, keyed by instance ID
+    ///
+    /// Processes are measured in one pass over the process table, rather
+    /// than one per instance as repeated `get_instance_metrics` calls would.
+    pub async fn all_instance_metrics(&self) -> HashMap<String, metrics::InstanceMetrics> {
+        let running = self
+            .instances
+            .read()
+            .await
+            .list_by_status(service::ServiceStatus::Running);
+
+        let mut all = HashMap::new();
+        let mut by_pid = Vec::new();
+        for instance in running {
+            if self.is_docker(&instance).await {
+                if let Some(metrics) = self.docker_metrics(&instance).await {
+                    all.insert(instance.id, metrics);
+                }
+            } else if let Some(pid) = instance.pid {
+                by_pid.push((instance.id, pid));
+            }
+        }
+
+        let pids: Vec<u32> = by_pid.iter().map(|&(_, pid)| pid).collect();
+        let mut measured = self.monitor.get_processes_metrics(&pids);
+        for (id, pid) in by_pid {
+            if let Some(metrics) = measured.remove(&pid) {
+                all.insert(id, metrics);
+            }
+        }
+        all
+    }
+
+    /// Whether an instance's template is a Docker Compose one
+    async fn is_docker(&self, instance: &ServiceInstance) -> bool {
+        self.templates
+            .read()
+            .await
+            .get(&instance.template_id)
+            .is_some_and(|t| t.is_docker)
+    }
+
+    /// Whether a Running Docker instance's containers have all stopped
+    ///
+    /// False when `docker compose ps` fails, since an unreachable daemon
+    /// says nothing about the containers.
+    async fn compose_down(&self, instance: &ServiceInstance) -> bool {
+        let monitor = self.monitor.clone();
+        let project = docker::ComposeProject::for_instance(instance, None);
+        match tokio::task::spawn_blocking(move || monitor.compose_containers(&project)).await {
+            Ok(Ok(containers)) => docker::is_down(&containers),
+            Ok(Err(e)) => {
+                debug!(instance_id = %instance.id, "Cannot list containers: {}", e);
+                false
+            },
+            Err(_) => false,
+        }
+    }
+
+    /// Usage of a Docker instance's containers (`docker stats` is slow, so
+    /// it runs off the async threads)
+    async fn docker_metrics(&self, instance: &ServiceInstance) -> Option<metrics::InstanceMetrics> {
+        let project = docker::ComposeProject::for_instance(instance, None);
+        tokio::task::spawn_blocking(move || project.metrics())
+            .await
+            .ok()
+            .flatten()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[allow(unused_imports)]
+    use super::*;
+
+    fn svc_template() -> ServiceTemplate {
+        toml::from_str(
+            r#"
+id = "svc"
+display_name = "Service"
+default_port = 8000
+start_command = "serve --port {port}"
+supports_multiple = true
+"#,
+        )
+        .unwrap()
+    }
+
+    fn svc_instance(id: &str, port: u16) -> InstanceConfig {
+        InstanceConfig {
+            instance_id: id.to_string(),
+            group: None,
+            template_id: "svc".to_string(),
+            port: Some(port),
+            working_dir: None,
+            config_path: None,
+            version: None,
+            git_branch: None,
+            tags: Vec::new(),
+            auto_start: false,
+            env_vars: Default::default(),
+            display_name: None,
+            notes: None,
+            labels: Default::default(),
+            metadata: Default::default(),
+            depends_on: Vec::new(),
+            schedule: None,
+            restart: None,
+            start_command_override: None,
+            stop_command_override: None,
+            cpu_limit_percent: None,
+            memory_limit_mb: None,
+            limit_action: None,
+        }
+    }
+
+    /// An in-memory core whose processes are only pretended (see
+    /// `mock_core` for one backed by a config file)
+    async fn mock_memory_core() -> (UsmCore, Arc<monitor::MockMonitor>) {
+        let monitor = Arc::new(monitor::MockMonitor::new());
+        let core = UsmCore::builder()
+            .monitor(monitor.clone())
+            .build()
+            .await
+            .unwrap();
+        (core, monitor)
+    }
+
+    #[tokio::test]
+    async fn test_usm_core_creation() {
+        let mut templates = TemplateRegistry::new();
+        templates.register(svc_template()).unwrap();
+        let mut instances = InstanceRegistry::new();
+        instances
+            .add(ServiceInstance::from_config(svc_instance("svc-main", 8001)).unwrap())
+            .unwrap();
+
+        let core = UsmCore::builder()
+            .monitor(Arc::new(monitor::MockMonitor::new()))
+            .templates(templates)
+            .instances(instances)
+            .build()
+            .await
+            .unwrap();
+
+        assert_eq!(core.list_templates().await.len(), 1);
+        assert_eq!(core.get_instance("svc-main").await.unwrap().port, 8001);
+        assert!(core.reload_config().await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_event_capacity_from_config() {
+        let dir = tempfile::tempdir().unwrap();
+        let config_path = dir.path().join("services.toml");
+        let build = |capacity: &str| {
+            std::fs::write(
+                &config_path,
+                format!("[server]\nevent_capacity = {}\n", capacity),
+            )
+            .unwrap();
+            UsmCore::builder()
+                .config_path(&config_path)
+                .monitor(Arc::new(monitor::MockMonitor::new()))
+                .build()
+        };
+
+        let core = build("8").await.unwrap();
+        assert_eq!(core.event_bus.capacity(), 8);
+        let err = build("0").await.err().unwrap();
+        assert!(err.to_string().contains("event_capacity"), "{}", err);
+
+        let (core, _) = mock_memory_core().await;
+        assert_eq!(core.event_bus.capacity(), EventBus::DEFAULT_CAPACITY);
+    }
+
+    #[tokio::test]
+    async fn test_in_memory_create_start_stop() {
+        let monitor = Arc::new(monitor::MockMonitor::new());
+        let event_bus = Arc::new(EventBus::new(16));
+        let mut events = event_bus.subscribe();
+        let core = UsmCore::builder()
+            .monitor(monitor.clone())
+            .event_bus(event_bus)
+            .build()
+            .await
+            .unwrap();
+
+        core.register_template(svc_template()).await.unwrap();
+        core.create_instance(svc_instance("svc-a", 8010))
+            .await
+            .unwrap();
+        assert!(matches!(
+            events.recv().await.unwrap(),
+            ServiceEvent::TemplateRegistered { .. }
+        ));
+        assert!(matches!(
+            events.recv().await.unwrap(),
+            ServiceEvent::InstanceCreated { .. }
+        ));
+
+        core.start_instance("svc-a").await.unwrap();
+        let instance = core.get_instance("svc-a").await.unwrap();
+        assert_eq!(instance.status, ServiceStatus::Running);
+        assert_eq!(instance.pid, Some(1000));
+        assert_eq!(monitor.spawned(), vec!["serve --port 8010"]);
+
+        core.stop_instance("svc-a").await.unwrap();
+        assert_eq!(
+            core.get_instance("svc-a").await.unwrap().status,
+            ServiceStatus::Stopped
+        );
+        assert!(!monitor.is_running(1000));
+
+        core.remove_instance("svc-a").await.unwrap();
+        assert!(core.get_instance("svc-a").await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_start_passes_merged_env() {
+        let (core, monitor) = mock_memory_core().await;
+
+        let mut template = svc_template();
+        template.start_command = "serve --port {port} --log {env.LOG_LEVEL}".to_string();
+        template.default_env = [
+            ("LOG_LEVEL".to_string(), "info".to_string()),
+            ("WORKERS".to_string(), "4".to_string()),
+        ]
+        .into();
+        core.register_template(template).await.unwrap();
+        let mut instance = svc_instance("svc-a", 8010);
+        instance.env_vars = [("LOG_LEVEL".to_string(), "debug".to_string())].into();
+        core.create_instance(instance).await.unwrap();
+
+        core.start_instance("svc-a").await.unwrap();
+        assert_eq!(monitor.spawned(), vec!["serve --port 8010 --log debug"]);
+        let env = &monitor.spawned_env()[0];
+        assert_eq!(env["LOG_LEVEL"], "debug");
+        assert_eq!(env["WORKERS"], "4");
+    }
+
+    #[tokio::test]
+    async fn test_remove_when_stop_fails() {
+        let (core, monitor) = mock_memory_core().await;
+        let mut template = svc_template();
+        template.stop_command = Some("svc-ctl stop {pid}".to_string());
+        core.register_template(template).await.unwrap();
+        core.create_instance(svc_instance("svc-a", 8010))
+            .await
+            .unwrap();
+        core.start_instance("svc-a").await.unwrap();
+        monitor.set_fail_commands(true);
+
+        // A plain removal keeps what it can't stop
+        let err = core.remove_instance("svc-a").await.unwrap_err();
+        assert_eq!(err.to_string(), "Command failed: svc-ctl stop 1000");
+        assert_eq!(
+            core.get_instance("svc-a").await.unwrap().status,
+            ServiceStatus::Running
+        );
+
+        // A forced one kills the process rather than orphaning it
+        let outcome = core.force_remove_instance("svc-a").await.unwrap();
+        assert_eq!(
+            outcome,
+            ForcedStop::Killed {
+                pid: 1000,
+                error: "Command failed: svc-ctl stop 1000".to_string()
+            }
+        );
+        assert_eq!(monitor.signals(), vec![(1000, Signal::Kill, false)]);
+        assert!(!monitor.is_running(1000));
+        assert!(core.get_instance("svc-a").await.is_none());
+
+        // With no process to kill, the service may still be running
+        let mut config = svc_instance("svc-b", 8011);
+        config.stop_command_override = Some("svc-ctl stop-all".to_string());
+        core.create_instance(config).await.unwrap();
+        core.start_instance("svc-b").await.unwrap();
+        monitor.set_running(1001, false);
+        let outcome = core.force_remove_instance("svc-b").await.unwrap();
+        assert_eq!(
+            outcome,
+            ForcedStop::Failed("Command failed: svc-ctl stop-all".to_string())
+        );
+        assert!(core.get_instance("svc-b").await.is_none());
+
+        let err = core.force_remove_instance("svc-b").await.unwrap_err();
+        assert_eq!(err.to_string(), "Instance 'svc-b' not found");
+    }
+
+    #[tokio::test]
+    async fn test_stop_escalates_to_sigkill_after_grace() {
+        let (core, monitor) = mock_memory_core().await;
+        let mut template = svc_template();
+        template.stop_timeout_ms = 300;
+        core.register_template(template).await.unwrap();
+        core.create_instance(svc_instance("svc-a", 8010))
+            .await
+            .unwrap();
+
+        // A process that exits on SIGTERM is never sent SIGKILL
+        core.start_instance("svc-a").await.unwrap();
+        core.stop_instance("svc-a").await.unwrap();
+        assert_eq!(monitor.killed(), vec![1000]);
+        assert!(monitor.signals().is_empty());
+
+        monitor.set_ignore_term(true);
+        core.start_instance("svc-a").await.unwrap();
+        let started = std::time::Instant::now();
+        let stopping = tokio::spawn({
+            let core = core.clone();
+            async move { core.stop_instance("svc-a").await }
+        });
+
+        // The registry stays usable during the grace period
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert_eq!(
+            core.get_instance("svc-a").await.unwrap().status,
+            ServiceStatus::Stopping
+        );
+        let err = core.start_instance("svc-a").await.unwrap_err();
+        assert_eq!(err.to_string(), "Instance 'svc-a' is still stopping");
+
+        stopping.await.unwrap().unwrap();
+        assert!(started.elapsed() >= Duration::from_millis(300));
+        assert_eq!(monitor.signals(), vec![(1001, Signal::Kill, false)]);
+        assert_eq!(
+            core.get_instance("svc-a").await.unwrap().status,
+            ServiceStatus::Stopped
+        );
+    }
+
+    #[tokio::test]
+    async fn test_instance_command_overrides() {
+        let (core, monitor) = mock_memory_core().await;
+        core.register_template(svc_template()).await.unwrap();
+        let mut config = svc_instance("svc-a", 8010);
+        config.start_command_override = Some("serve --port {port} --verbose".to_string());
+        config.stop_command_override = Some("svc-ctl stop {pid}".to_string());
+        core.create_instance(config).await.unwrap();
+        core.create_instance(svc_instance("svc-b", 8011))
+            .await
+            .unwrap();
+
+        core.start_instance("svc-a").await.unwrap();
+        core.start_instance("svc-b").await.unwrap();
+        assert_eq!(
+            monitor.spawned(),
+            vec!["serve --port 8010 --verbose", "serve --port 8011"]
+        );
+
+        core.stop_instance("svc-a").await.unwrap();
+        core.stop_instance("svc-b").await.unwrap();
+        assert_eq!(monitor.executed(), vec!["svc-ctl stop 1000"]);
+        assert_eq!(monitor.killed(), vec![1001]);
+
+        let mut config = svc_instance("svc-c", 8012);
+        config.start_command_override = Some("serve --port {porrt}".to_string());
+        let err = core.create_instance(config).await.unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("start_command_override: unknown placeholder '{porrt}'"));
+    }
+
+    #[tokio::test]
+    async fn test_stop_command_without_pid() {
+        let (core, _) = mock_memory_core().await;
+        let mut template = svc_template();
+        template.stop_command = Some("brew services stop svc".to_string());
+        let mut instance = ServiceInstance::from_config(svc_instance("svc-a", 8010)).unwrap();
+
+        // Service-manager commands don't need a PID
+        assert_eq!(
+            core.stop_command(Some(&template), &instance).await,
+            Some("brew services stop svc".to_string())
+        );
+
+        // Commands that do are skipped until one is known
+        instance.stop_command_override = Some("svc-ctl stop {pid}".to_string());
+        assert_eq!(core.stop_command(Some(&template), &instance).await, None);
+        instance.pid = Some(42);
+        assert_eq!(
+            core.stop_command(Some(&template), &instance).await,
+            Some("svc-ctl stop 42".to_string())
+        );
+    }
+
+    #[tokio::test]
+    async fn test_stderr_tail_prefers_memory_buffer() {
+        let settings = Settings {
+            logs: Some(config::LogSettings {
+                memory_lines: 10,
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let core = UsmCore::builder()
+            .monitor(Arc::new(monitor::MockMonitor::new()))
+            .settings(settings)
+            .build()
+            .await
+            .unwrap();
+        let instance = ServiceInstance::from_config(svc_instance("svc-a", 8010)).unwrap();
+        assert!(core.stderr_tail(&instance, 2).is_empty());
+
+        let buffer = core.logs.buffer_for("svc-a").unwrap();
+        buffer.push(logs::LogStream::Stderr, "first");
+        buffer.push(logs::LogStream::Stdout, "ignored");
+        buffer.push(logs::LogStream::Stderr, "second");
+        buffer.push(logs::LogStream::Stderr, "third");
+        assert_eq!(core.stderr_tail(&instance, 2), vec!["second", "third"]);
+    }
+
+    #[tokio::test]
+    async fn test_display_name_fallback() {
+        let (core, _) = mock_memory_core().await;
+        core.register_template(svc_template()).await.unwrap();
+        let mut config = svc_instance("svc-1699999999", 8010);
+        config.display_name = Some("Staging API".to_string());
+        core.create_instance(config).await.unwrap();
+        core.create_instance(svc_instance("svc-b", 8011))
+            .await
+            .unwrap();
+
+        let named = core.get_instance("svc-1699999999").await.unwrap();
+        assert_eq!(core.display_name(&named).await, "Staging API");
+        let unnamed = core.get_instance("svc-b").await.unwrap();
+        assert_eq!(core.display_name(&unnamed).await, "Service");
+
+        let mut orphan = unnamed.clone();
+        orphan.template_id = "gone".to_string();
+        assert_eq!(core.display_name(&orphan).await, "svc-b");
+    }
+
+    #[tokio::test]
+    async fn test_upsert_instance() {
+        let (core, monitor) = mock_memory_core().await;
+        core.register_template(svc_template()).await.unwrap();
+        core.create_instance(svc_instance("svc-other", 8020))
+            .await
+            .unwrap();
+
+        let mut config = svc_instance("svc-a", 8010);
+        config.notes = Some("keep me".to_string());
+        assert_eq!(
+            core.upsert_instance(config).await.unwrap(),
+            ("svc-a".to_string(), true)
+        );
+
+        let mut config = svc_instance("svc-a", 8011);
+        config.tags = vec!["web".to_string()];
+        config
+            .env_vars
+            .insert("LOG_LEVEL".to_string(), "debug".to_string());
+        config.working_dir = Some("/srv/svc".into());
+        let mut events = core.subscribe();
+        assert_eq!(
+            core.upsert_instance(config).await.unwrap(),
+            ("svc-a".to_string(), false)
+        );
+        let instance = core.get_instance("svc-a").await.unwrap();
+        assert_eq!(instance.port, 8011);
+        assert_eq!(instance.tags, vec!["web"]);
+        assert_eq!(instance.env_vars["LOG_LEVEL"], "debug");
+        assert_eq!(instance.working_dir, Some("/srv/svc".into()));
+        assert_eq!(instance.notes.as_deref(), Some("keep me"));
+        let event = events.try_recv().unwrap();
+        assert_eq!(event.event_type(), "instance_updated");
+        assert_eq!(event.instance_id(), Some("svc-a"));
+
+        // Unspecified port keeps the current one; tags, env vars and
+        // working dir left out are cleared
+        let mut config = svc_instance("svc-a", 0);
+        config.port = None;
+        core.upsert_instance(config).await.unwrap();
+        let instance = core.get_instance("svc-a").await.unwrap();
+        assert_eq!(instance.port, 8011);
+        assert!(instance.tags.is_empty());
+        assert!(instance.env_vars.is_empty());
+        assert_eq!(instance.working_dir, None);
+
+        // A port bound outside USM is refused, as on create
+        monitor.set_port_in_use(8012, true);
+        let err = core
+            .upsert_instance(svc_instance("svc-a", 8012))
+            .await
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Port 8012 is already in use by another process"
+        );
+
+        let err = core
+            .upsert_instance(svc_instance("svc-a", 8020))
+            .await
+            .unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("already in use by instance 'svc-other'"));
+
+        let mut config = svc_instance("svc-a", 8011);
+        config.template_id = "other".to_string();
+        let err = core.upsert_instance(config).await.unwrap_err();
+        assert!(err.to_string().contains("Cannot change template"));
+
+        // Concurrent upserts of a new ID: one creates, the others update
+        let results = futures_util::future::join_all(
+            (0..4).map(|_| core.upsert_instance(svc_instance("svc-new", 8030))),
+        )
+        .await;
+        let created: Vec<bool> = results.into_iter().map(|r| r.unwrap().1).collect();
+        assert_eq!(created.iter().filter(|&&c| c).count(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_bulk_start_runs_in_parallel() {
+        let (core, monitor) = mock_memory_core().await;
+        // Each spawn waits until the other two are underway
+        monitor.set_spawn_gate(3);
+        core.register_template(svc_template()).await.unwrap();
+        for (i, id) in ["svc-a", "svc-b", "svc-c", "svc-d"].into_iter().enumerate() {
+            let mut config = svc_instance(id, 8010 + i as u16);
+            config.tags = vec!["web".to_string()];
+            core.create_instance(config).await.unwrap();
+        }
+        // svc-b is taken by something else, so its start fails
+        monitor.set_port_in_use(8011, true);
+
+        let results = core
+            .start_matching(&InstanceFilter {
+                tags: vec!["web".to_string()],
+                ..Default::default()
+            })
+            .await;
+        assert_eq!(monitor.max_concurrent_spawns(), 3);
+
+        // Results come back in instance order
+        assert_eq!(results.len(), 4);
+        assert!(results[0].is_ok() && results[2].is_ok() && results[3].is_ok());
+        assert!(results[1]
+            .as_ref()
+            .unwrap_err()
+            .to_string()
+            .contains("8011"));
+        let status = |id: &'static str| {
+            let core = &core;
+            async move { core.get_instance(id).await.unwrap().status }
+        };
+        assert_eq!(status("svc-a").await, ServiceStatus::Running);
+        assert_eq!(status("svc-b").await, ServiceStatus::Error);
+    }
+
+    #[tokio::test]
+    async fn test_bulk_start_respects_dependencies() {
+        let (core, monitor) = mock_memory_core().await;
+        monitor.set_spawn_delay(Duration::from_millis(50));
+        core.register_template(svc_template()).await.unwrap();
+        let mut api = svc_instance("svc-api", 8010);
+        api.depends_on = vec!["svc-db".to_string()];
+        core.create_instance(api).await.unwrap();
+        core.create_instance(svc_instance("svc-db", 8011))
+            .await
+            .unwrap();
+
+        let results = core.start_by_template("svc").await;
+        assert!(results.iter().all(Result::is_ok));
+        assert_eq!(
+            monitor.spawned(),
+            vec!["serve --port 8011", "serve --port 8010"]
+        );
+
+        core.stop_by_template("svc").await;
+        assert_eq!(monitor.killed(), vec![1001, 1000]);
+    }
+
+    #[tokio::test]
+    async fn test_dry_run_start_and_stop() {
+        let dir = tempfile::tempdir().unwrap();
+        let (core, monitor) = mock_memory_core().await;
+        let mut template = svc_template();
+        template.default_env = HashMap::from([("MODE".to_string(), "dev".to_string())]);
+        template.working_dir = Some(dir.path().to_path_buf());
+        core.register_template(template).await.unwrap();
+        let mut config = svc_instance("svc-a", 8010);
+        config.env_vars = HashMap::from([("MODE".to_string(), "prod".to_string())]);
+        core.create_instance(config).await.unwrap();
+
+        let plan = core.dry_run_start("svc-a").await.unwrap();
+        assert_eq!(plan.command, "serve --port 8010");
+        assert_eq!(
+            plan.env,
+            BTreeMap::from([("MODE".to_string(), "prod".to_string())])
+        );
+        assert_eq!(plan.working_dir.as_deref(), Some(dir.path()));
+        assert!(monitor.spawned().is_empty());
+        let instance = core.get_instance("svc-a").await.unwrap();
+        assert_eq!(instance.status, ServiceStatus::Stopped);
+
+        // Stopping a running instance without a stop command signals it
+        core.start_instance("svc-a").await.unwrap();
+        let plan = core.dry_run_stop("svc-a").await.unwrap();
+        assert_eq!(plan.command, None);
+        assert_eq!(plan.pid, Some(1000));
+        assert!(monitor.killed().is_empty());
+        assert!(core.is_running("svc-a").await);
+
+        assert!(core.dry_run_start("missing").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_startup_verify_from_template() {
+        let (core, _) = mock_memory_core().await;
+        let mut template = svc_template();
+        let instance = ServiceInstance::from_config(svc_instance("svc-a", 8010)).unwrap();
+
+        let (_, options) = core.prepare_start(&template, &instance).await;
+        assert_eq!(options.startup_verify, Some(Duration::from_millis(3000)));
+
+        template.startup_verify_ms = 500;
+        let (_, options) = core.prepare_start(&template, &instance).await;
+        assert_eq!(options.startup_verify, Some(Duration::from_millis(500)));
+    }
+
+    #[tokio::test]
+    async fn test_create_assigns_free_port() {
+        let (core, monitor) = mock_memory_core().await;
+        core.register_template(svc_template()).await.unwrap();
+
+        // 8000 is the template default but bound outside USM
+        monitor.set_port_in_use(8000, true);
+        for id in ["svc-a", "svc-b"] {
+            let mut config = svc_instance(id, 0);
+            config.port = None;
+            core.create_instance(config).await.unwrap();
+        }
+        assert_eq!(core.get_instance("svc-a").await.unwrap().port, 8001);
+        assert_eq!(core.get_instance("svc-b").await.unwrap().port, 8002);
+    }
+
+    #[tokio::test]
+    async fn test_reconcile_reports_drift() {
+        let (core, monitor) = mock_memory_core().await;
+        core.register_template(svc_template()).await.unwrap();
+        for (id, port) in [("svc-a", 8010), ("svc-b", 8011)] {
+            core.create_instance(svc_instance(id, port)).await.unwrap();
+            core.start_instance(id).await.unwrap();
+        }
+
+        // Killed behind USM's back: still shown as Running
+        monitor.set_running(1001, false);
+        assert_eq!(
+            core.reconcile().await,
+            vec![
+                ("svc-a".to_string(), Some(1000), true),
+                ("svc-b".to_string(), Some(1001), false),
+            ]
+        );
+        assert_eq!(
+            core.get_instance("svc-b").await.unwrap().status,
+            ServiceStatus::Running
+        );
+
+        core.stop_instance("svc-a").await.unwrap();
+        assert_eq!(
+            core.reconcile().await[0],
+            ("svc-a".to_string(), None, false)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_zombies_reported_and_reaped() {
+        let (core, monitor) = mock_memory_core().await;
+        core.register_template(svc_template()).await.unwrap();
+        for (id, port) in [("svc-a", 8010), ("svc-b", 8011)] {
+            core.create_instance(svc_instance(id, port)).await.unwrap();
+            core.start_instance(id).await.unwrap();
+        }
+        core.stop_instance("svc-b").await.unwrap();
+        monitor.set_zombie(1000, true);
+        monitor.set_zombie(1001, true);
+        assert_eq!(
+            core.zombies().await,
+            vec![
+                (1000, Some("svc-a".to_string()), true),
+                (1001, Some("svc-b".to_string()), false),
+            ]
+        );
+
+        // Only the wrapper no instance tracks anymore is reaped
+        assert_eq!(core.reap_zombies().await, vec![1001]);
+        assert_eq!(
+            core.zombies().await,
+            vec![(1000, Some("svc-a".to_string()), true)]
+        );
+        assert!(core.reap_zombies().await.is_empty());
+        assert_eq!(monitor.children(), vec![1000]);
+    }
+
+    #[tokio::test]
+    async fn test_reassign_port() {
+        let dir = tempfile::tempdir().unwrap();
+        let config_path = dir.path().join("services.toml");
+        std::fs::write(
+            &config_path,
+            r#"
+[templates.svc]
+display_name = "Service"
+default_port = 8000
+port_range = [8000, 8009]
+start_command = "serve --port {port}"
+supports_multiple = true
+
+[instances.svc-a]
+template = "svc"
+port = 8001
+
+[instances.svc-b]
+template = "svc"
+port = 8002
+"#,
+        )
+        .unwrap();
+        let monitor = Arc::new(monitor::MockMonitor::new());
+        let core = UsmCore::with_monitor(&config_path, monitor.clone())
+            .await
+            .unwrap();
+        let mut events = core.subscribe();
+
+        for (port, error) in [
+            (9000, "outside the port range"),
+            (8002, "in use by instance 'svc-b'"),
+            (8005, "in use by another process"),
+        ] {
+            monitor.set_port_in_use(8005, true);
+            let err = core.reassign_port("svc-a", port).await.unwrap_err();
+            assert!(err.to_string().contains(error), "{}", err);
+        }
+        assert!(core.reassign_port("missing", 8003).await.is_err());
+
+        // A hand edit made since load survives the save
+        let mut content = std::fs::read_to_string(&config_path).unwrap();
+        content.push_str("\n[instances.svc-c]\ntemplate = \"svc\"\nport = 8003\n");
+        std::fs::write(&config_path, content).unwrap();
+
+        assert_eq!(core.reassign_port("svc-a", 8009).await.unwrap(), 8001);
+        assert_eq!(core.get_instance("svc-a").await.unwrap().port, 8009);
+        match events.try_recv().unwrap() {
+            ServiceEvent::PortChanged {
+                instance_id,
+                old_port,
+                new_port,
+            } => assert_eq!(
+                (instance_id.as_str(), old_port, new_port),
+                ("svc-a", 8001, 8009)
+            ),
+            other => panic!("unexpected event {:?}", other),
+        }
+
+        // Persisted
+        let reloaded = UsmCore::with_monitor(&config_path, monitor).await.unwrap();
+        assert_eq!(reloaded.get_instance("svc-a").await.unwrap().port, 8009);
+        assert_eq!(reloaded.get_instance("svc-c").await.unwrap().port, 8003);
+    }
+
+    #[tokio::test]
+    async fn test_create_checks_port_range() {
+        let (core, _) = mock_memory_core().await;
+        let mut template = svc_template();
+        template.port_range = Some((8000, 8009));
+        core.register_template(template).await.unwrap();
+
+        let err = core
+            .create_instance(svc_instance("svc-a", 9000))
+            .await
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Port 9000 is outside the port range 8000-8009 of template 'svc'"
+        );
+        assert!(core.get_instance("svc-a").await.is_none());
+
+        core.create_instance(svc_instance("svc-a", 8009))
+            .await
+            .unwrap();
+        let err = core
+            .upsert_instance(svc_instance("svc-a", 8010))
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("outside the port range"));
+        assert_eq!(core.get_instance("svc-a").await.unwrap().port, 8009);
+    }
+
+    #[tokio::test]
+    async fn test_start_refuses_port_in_use() {
+        let (core, monitor) = mock_memory_core().await;
+        core.register_template(svc_template()).await.unwrap();
+        core.create_instance(svc_instance("svc-a", 8010))
+            .await
+            .unwrap();
+
+        monitor.set_port_in_use(8010, true);
+        let err = core.start_instance("svc-a").await.unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Port 8010 is already in use by another process"
+        );
+        assert!(monitor.spawned().is_empty());
+        assert_eq!(
+            core.get_instance("svc-a").await.unwrap().status,
+            ServiceStatus::Error
+        );
+
+        // Service-manager templates only warn
+        let mut managed = svc_template();
+        managed.id = "managed".to_string();
+        managed.stop_command = Some("svc-ctl stop".to_string());
+        core.register_template(managed).await.unwrap();
+        let mut instance = svc_instance("managed-a", 8011);
+        instance.template_id = "managed".to_string();
+        core.create_instance(instance).await.unwrap();
+        monitor.set_port_in_use(8011, true);
+        core.start_instance("managed-a").await.unwrap();
+
+        monitor.set_port_in_use(8010, false);
+        core.start_instance("svc-a").await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_stop_finds_process_by_port() {
+        let (core, monitor) = mock_memory_core().await;
+        core.register_template(svc_template()).await.unwrap();
+        for (id, port) in [("lost", 8030), ("stale", 8031), ("crashed", 8032)] {
+            core.create_instance(svc_instance(id, port)).await.unwrap();
+        }
+
+        // Running with no PID (lost across a restart) or a dead one
+        {
+            let mut instances = core.instances.write().await;
+            instances
+                .update_status("lost", ServiceStatus::Running, None)
+                .unwrap();
+            instances
+                .update_status("stale", ServiceStatus::Running, Some(4242))
+                .unwrap();
+            instances
+                .update_status("crashed", ServiceStatus::Error, None)
+                .unwrap();
+        }
+        for (port, pid) in [(8030, 5000), (8031, 5001), (8032, 5002)] {
+            monitor.set_running(pid, true);
+            monitor.set_port_owner(port, Some(pid));
+        }
+
+        let plan = core.dry_run_stop("lost").await.unwrap();
+        assert_eq!(plan.pid, Some(5000));
+        core.stop_instance("lost").await.unwrap();
+        assert_eq!(monitor.killed(), vec![5000]);
+        assert_eq!(
+            core.get_instance("lost").await.unwrap().status,
+            ServiceStatus::Stopped
+        );
+
+        // A tracked PID that died is trusted over the port, like a crashed
+        // instance's port, which may have been taken by something else
+        assert_eq!(core.dry_run_stop("stale").await.unwrap().pid, None);
+        core.stop_instance("stale").await.unwrap();
+        core.stop_instance("crashed").await.unwrap();
+        assert_eq!(monitor.killed(), vec![5000]);
+        assert_eq!(
+            core.get_instance("stale").await.unwrap().status,
+            ServiceStatus::Stopped
+        );
+    }
+
+    #[tokio::test]
+    async fn test_all_instance_metrics() {
+        let (core, monitor) = mock_memory_core().await;
+        core.register_template(svc_template()).await.unwrap();
+        for (id, port) in [("svc-a", 8040), ("svc-b", 8041), ("svc-c", 8042)] {
+            core.create_instance(svc_instance(id, port)).await.unwrap();
+        }
+        core.start_instance("svc-a").await.unwrap();
+        core.start_instance("svc-b").await.unwrap();
+        assert_eq!(core.all_instance_metrics().await.len(), 2);
+
+        // A process that died unnoticed has no metrics
+        monitor.set_running(1001, false);
+        let metrics = core.all_instance_metrics().await;
+        assert_eq!(metrics.keys().collect::<Vec<_>>(), vec!["svc-a"]);
+        assert_eq!(metrics["svc-a"].cpu_percent, 1.0);
+    }
+
+    #[tokio::test]
+    async fn test_reset_instance() {
+        let dir = tempfile::tempdir().unwrap();
+        let (core, monitor) = mock_core(&dir).await;
+
+        // A failed start leaves the instance in Error
+        monitor.set_fail_spawns(true);
+        assert!(core.start_instance("svc-main").await.is_err());
+        let instance = core.get_instance("svc-main").await.unwrap();
+        assert_eq!(instance.status, ServiceStatus::Error);
+        assert!(instance.last_error.is_some());
+
+        core.reset_instance("svc-main").await.unwrap();
+        let instance = core.get_instance("svc-main").await.unwrap();
+        assert_eq!(instance.status, ServiceStatus::Stopped);
+        assert_eq!(instance.pid, None);
+        assert_eq!(instance.last_error, None);
+        assert!(monitor.killed().is_empty());
+
+        // Marked Error while its process is still alive: that gets stopped
+        monitor.set_fail_spawns(false);
+        core.start_instance("svc-main").await.unwrap();
+        core.instances
+            .write()
+            .await
+            .update_status("svc-main", ServiceStatus::Error, Some(1000))
+            .unwrap();
+        core.reset_instance("svc-main").await.unwrap();
+        assert_eq!(monitor.killed(), vec![1000]);
+
+        core.start_instance("svc-main").await.unwrap();
+        let err = core.reset_instance("svc-main").await.unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Cannot reset instance 'svc-main' while it is running"
+        );
+        assert!(core.reset_instance("missing").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_start_requires_working_dir() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("not-a-dir");
+        std::fs::write(&file, "").unwrap();
+        let missing = dir.path().join("missing");
+
+        let (core, monitor) = mock_memory_core().await;
+        core.register_template(svc_template()).await.unwrap();
+        for (id, port, path) in [("svc-a", 8020, &missing), ("svc-b", 8021, &file)] {
+            let mut instance = svc_instance(id, port);
+            instance.working_dir = Some(path.clone());
+            core.create_instance(instance).await.unwrap();
+        }
+
+        let err = core.start_instance("svc-a").await.unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            format!("working_dir {} does not exist", missing.display())
+        );
+        let err = core.start_instance("svc-b").await.unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            format!("working_dir {} is not a directory", file.display())
+        );
+        assert!(monitor.spawned().is_empty());
+        assert_eq!(
+            core.get_instance("svc-a").await.unwrap().status,
+            ServiceStatus::Error
+        );
+
+        std::fs::create_dir(&missing).unwrap();
+        core.start_instance("svc-a").await.unwrap();
+    }
+
+    #[test]
+    fn test_command_program() {
+        assert_eq!(command_program("serve --port 8000"), Some("serve"));
+        assert_eq!(
+            command_program("RUST_LOG=debug PORT=1 ./bin/server -v"),
+            Some("./bin/server")
+        );
+        assert_eq!(command_program("cd app && serve"), None);
+        assert_eq!(command_program("\"$HOME/bin/serve\""), None);
+        assert_eq!(command_program("(serve)"), None);
+        assert_eq!(command_program(""), None);
+    }
+
+    #[tokio::test]
+    async fn test_runtime_state_survives_restart() {
+        let dir = tempfile::tempdir().unwrap();
+        let config_path = dir.path().join("services.toml");
+        std::fs::write(
+            &config_path,
+            r#"
+[templates.svc]
+display_name = "Svc"
+default_port = 8000
+start_command = "serve --port {port}"
+supports_multiple = true
+
+[instances.a]
+template = "svc"
+port = 8001
+
+[instances.b]
+template = "svc"
+port = 8002
+"#,
+        )
+        .unwrap();
+
+        let old_monitor = Arc::new(monitor::MockMonitor::new());
+        let core = UsmCore::with_monitor(&config_path, old_monitor.clone())
+            .await
+            .unwrap();
+        core.start_instance("a").await.unwrap();
+        core.start_instance("b").await.unwrap();
+        assert!(dir.path().join("services.state.json").exists());
+        drop(core);
+
+        // Only a's process outlived the old USM; b's PID went to a process
+        // that started after it
+        let monitor = Arc::new(monitor::MockMonitor::with_first_pid(2000));
+        let a_started = old_monitor.process_start_time(1000).unwrap();
+        monitor.set_running(1000, true);
+        monitor.set_start_time(1000, a_started);
+        monitor.set_running(1001, true);
+        monitor.set_start_time(1001, a_started + 60);
+        let core = UsmCore::with_monitor(&config_path, monitor).await.unwrap();
+        let a = core.get_instance("a").await.unwrap();
+        assert_eq!(a.status, ServiceStatus::Running);
+        assert_eq!(a.pid, Some(1000));
+        assert!(a.started_at.is_some());
+        let b = core.get_instance("b").await.unwrap();
+        assert_eq!(b.status, ServiceStatus::Stopped);
+        assert_eq!(b.pid, None);
+        assert_eq!(b.last_pid, Some(1001));
+
+        // The dead one was settled on disk too
+        let saved = state::RuntimeState::load(&dir.path().join("services.state.json")).await;
+        assert_eq!(saved.instances["b"].status, ServiceStatus::Stopped);
+    }
+
+    #[tokio::test]
+    async fn test_bulk_start_stop_tag_modes() {
+        let (core, monitor) = mock_memory_core().await;
+        core.register_template(svc_template()).await.unwrap();
+        for (id, port, tags) in [
+            ("prod-llm", 8010, vec!["production", "llm"]),
+            ("prod-api", 8011, vec!["production"]),
+            ("dev-llm", 8012, vec!["llm"]),
+        ] {
+            let mut config = svc_instance(id, port);
+            config.tags = tags.into_iter().map(str::to_string).collect();
+            core.create_instance(config).await.unwrap();
+        }
+
+        let results = core
+            .start_by_tags_matching(&["production", "llm"], TagMatch::All)
+            .await;
+        assert_eq!(results.len(), 1);
+        assert_eq!(monitor.spawned(), vec!["serve --port 8010"]);
+
+        let results = core.stop_by_tags(&["production", "llm"]).await;
+        assert_eq!(results.len(), 3);
+        assert!(core
+            .start_by_tags_matching(&[], TagMatch::All)
+            .await
+            .is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_bulk_start_stop_by_template() {
+        let (core, monitor) = mock_memory_core().await;
+        core.register_template(svc_template()).await.unwrap();
+        let mut other = svc_template();
+        other.id = "other".to_string();
+        other.start_command = "other --port {port}".to_string();
+        core.register_template(other).await.unwrap();
+
+        core.create_instance(svc_instance("svc-b", 8011))
+            .await
+            .unwrap();
+        core.create_instance(svc_instance("svc-a", 8010))
+            .await
+            .unwrap();
+        let mut config = svc_instance("other-a", 8020);
+        config.template_id = "other".to_string();
+        core.create_instance(config).await.unwrap();
+
+        let results = core.start_by_template("svc").await;
+        assert_eq!(results.len(), 2);
+        assert!(results.iter().all(Result::is_ok));
+        assert_eq!(
+            monitor.spawned(),
+            vec!["serve --port 8010", "serve --port 8011"]
+        );
+
+        assert_eq!(core.stop_by_template("svc").await.len(), 2);
+        assert_eq!(monitor.killed(), vec![1000, 1001]);
+        assert!(core.start_by_template("missing").await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_reload_config_emits_event() {
+        let dir = tempfile::tempdir().unwrap();
+        let config_path = dir.path().join("services.toml");
+        let base = r#"
+[templates.svc]
+display_name = "Service"
+default_port = 8000
+start_command = "echo start"
+supports_multiple = true
+
+[instances.first]
+template = "svc"
+port = 8001
+"#;
+        std::fs::write(&config_path, base).unwrap();
+
+        let core = UsmCore::new(&config_path).await.unwrap();
+        core.instances
+            .write()
+            .await
+            .update_status("first", ServiceStatus::Running, Some(4242))
+            .unwrap();
+
+        std::fs::write(
+            &config_path,
+            format!(
+                "{}\n[instances.second]\ntemplate = \"svc\"\nport = 8002\n",
+                base
+            ),
+        )
+        .unwrap();
+
+        let mut rx = core.subscribe();
+        core.reload_config().await.unwrap();
+
+        assert!(matches!(
+            rx.recv().await.unwrap(),
+            ServiceEvent::InstanceCreated { ref instance_id, ref template_id }
+                if instance_id == "second" && template_id == "svc"
+        ));
+        let event = rx.recv().await.unwrap();
+        assert_eq!(event.event_type(), "config_reloaded");
+
+        // New instance appears, existing runtime state survives
+        assert!(core.get_instance("second").await.is_some());
+        let first = core.get_instance("first").await.unwrap();
+        assert_eq!(first.status, ServiceStatus::Running);
+        assert_eq!(first.pid, Some(4242));
+    }
+
+    #[tokio::test]
+    async fn test_reload_config_emits_diff_events() {
+        let dir = tempfile::tempdir().unwrap();
+        let config_path = dir.path().join("services.toml");
+        let template = |id: &str| {
+            format!(
+                "[templates.{}]\ndisplay_name = \"{}\"\ndefault_port = 8000\nstart_command = \"echo start\"\nsupports_multiple = true\n",
+                id, id
+            )
+        };
+        let instance = |id: &str, template: &str, port: u16| {
+            format!(
+                "[instances.{}]\ntemplate = \"{}\"\nport = {}\n",
+                id, template, port
+            )
+        };
+        std::fs::write(
+            &config_path,
+            [
+                template("kept"),
+                template("old"),
+                instance("stays", "kept", 8001),
+                instance("gone", "old", 8002),
+            ]
+            .join("\n"),
+        )
+        .unwrap();
+        let core = UsmCore::new(&config_path).await.unwrap();
+
+        std::fs::write(
+            &config_path,
+            [
+                template("kept"),
+                template("new"),
+                instance("stays", "kept", 8001),
+                instance("fresh", "new", 8003),
+            ]
+            .join("\n"),
+        )
+        .unwrap();
+        let mut rx = core.subscribe();
+        core.reload_config().await.unwrap();
+
+        let mut events = Vec::new();
+        while let Ok(event) = rx.try_recv() {
+            events.push(serde_json::to_value(&event).unwrap());
+        }
+        assert_eq!(
+            events,
+            vec![
+                serde_json::json!({"type": "template_registered", "template_id": "new"}),
+                serde_json::json!({"type": "instance_created", "instance_id": "fresh", "template_id": "new"}),
+                serde_json::json!({"type": "instance_removed", "instance_id": "gone"}),
+                serde_json::json!({"type": "template_removed", "template_id": "old"}),
+                serde_json::json!({"type": "config_reloaded"}),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_reload_keeps_instances_in_use() {
+        let dir = tempfile::tempdir().unwrap();
+        let (core, monitor) = mock_core(&dir).await;
+        {
+            let mut instances = core.instances.write().await;
+            for (id, port) in [("settled", 8010), ("stopping", 8011), ("crashed", 8012)] {
+                instances
+                    .add(ServiceInstance::from_config(svc_instance(id, port)).unwrap())
+                    .unwrap();
+            }
+            instances
+                .update_status("svc-main", ServiceStatus::Starting, None)
+                .unwrap();
+            instances
+                .update_status("stopping", ServiceStatus::Stopping, Some(5000))
+                .unwrap();
+            // Marked crashed, but its process is still around
+            instances
+                .update_status("crashed", ServiceStatus::Error, Some(5001))
+                .unwrap();
+            instances
+                .update_status("settled", ServiceStatus::Error, Some(5002))
+                .unwrap();
+        }
+        monitor.set_running(5000, true);
+        monitor.set_running(5001, true);
+
+        let config_path = dir.path().join("services.toml");
+        let content = std::fs::read_to_string(&config_path).unwrap();
+        let templates_only = &content[..content.find("[instances").unwrap()];
+        std::fs::write(&config_path, templates_only).unwrap();
+        core.reload_config().await.unwrap();
+
+        let mut kept: Vec<String> = core
+            .list_instances(None)
+            .await
+            .into_iter()
+            .map(|i| i.id)
+            .collect();
+        kept.sort();
+        assert_eq!(kept, vec!["crashed", "stopping", "svc-main"]);
+
+        // Dropped by the next reload once they have settled
+        monitor.set_running(5001, false);
+        core.instances
+            .write()
+            .await
+            .update_status("svc-main", ServiceStatus::Stopped, None)
+            .unwrap();
+        core.reload_config().await.unwrap();
+        let kept: Vec<String> = core
+            .list_instances(None)
+            .await
+            .into_iter()
+            .map(|i| i.id)
+            .collect();
+        assert_eq!(kept, vec!["stopping"]);
+    }
+
+    fn clone_config(instance_id: &str) -> InstanceConfig {
+        InstanceConfig {
+            instance_id: instance_id.to_string(),
+            group: None,
+            template_id: String::new(),
+            port: None,
+            working_dir: None,
+            config_path: None,
+            version: None,
+            git_branch: None,
+            tags: vec![],
+            auto_start: false,
+            env_vars: Default::default(),
+            display_name: None,
+            notes: None,
+            labels: Default::default(),
+            metadata: Default::default(),
+            depends_on: Vec::new(),
+            schedule: None,
+            restart: None,
+            start_command_override: None,
+            stop_command_override: None,
+            cpu_limit_percent: None,
+            memory_limit_mb: None,
+            limit_action: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_clone_picks_free_port_and_inherits() {
+        let dir = tempfile::tempdir().unwrap();
+        let config_path = dir.path().join("services.toml");
+        std::fs::write(
+            &config_path,
+            r#"
+[templates.svc]
+display_name = "Service"
+default_port = 8000
+port_range = [8000, 8010]
+start_command = "echo start"
+supports_multiple = true
+
+[instances.first]
+template = "svc"
+port = 8000
+tags = ["dev"]
+
+[instances.first.env_vars]
+LOG_LEVEL = "debug"
+"#,
+        )
+        .unwrap();
+
+        let core = UsmCore::new(&config_path).await.unwrap();
+
+        core.clone_instance("first", clone_config("second"), true)
+            .await
+            .unwrap();
+        let second = core.get_instance("second").await.unwrap();
+        assert_eq!(second.template_id, "svc");
+        assert_eq!(second.port, 8001);
+        assert_eq!(second.tags, vec!["dev"]);
+        assert_eq!(second.env_vars.get("LOG_LEVEL").unwrap(), "debug");
+
+        core.clone_instance("first", clone_config("third"), false)
+            .await
+            .unwrap();
+        let third = core.get_instance("third").await.unwrap();
+        assert_eq!(third.port, 8002);
+        assert!(third.tags.is_empty());
+        assert!(third.env_vars.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_export_import_round_trip() {
+        let (source, _) = mock_memory_core().await;
+        source.register_template(svc_template()).await.unwrap();
+        let mut config = svc_instance("svc-a", 8010);
+        config.notes = Some("primary".to_string());
+        config.labels = HashMap::from([("owner".to_string(), "infra".to_string())]);
+        source.create_instance(config).await.unwrap();
+        source
+            .create_instance(svc_instance("svc-b", 8011))
+            .await
+            .unwrap();
+        source.start_instance("svc-a").await.unwrap();
+        let created_at = source.get_instance("svc-a").await.unwrap().created_at;
+
+        let json = serde_json::to_string(&source.export().await).unwrap();
+
+        let dir = tempfile::tempdir().unwrap();
+        let config_path = dir.path().join("services.toml");
+        std::fs::write(&config_path, "").unwrap();
+        let target = UsmCore::new(&config_path).await.unwrap();
+        let report = target
+            .import(serde_json::from_str(&json).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(report.templates_added, vec!["svc"]);
+        assert_eq!(report.instances_added, vec!["svc-a", "svc-b"]);
+        assert!(report.conflicts.is_empty());
+
+        // Persisted with metadata, and not running
+        let reloaded = UsmCore::new(&config_path).await.unwrap();
+        let a = reloaded.get_instance("svc-a").await.unwrap();
+        assert_eq!(a.status, ServiceStatus::Stopped);
+        assert_eq!(a.pid, None);
+        assert_eq!(a.notes.as_deref(), Some("primary"));
+        assert_eq!(a.labels["owner"], "infra");
+        assert_eq!(a.created_at, created_at);
+
+        // Importing again only reports conflicts
+        let report = reloaded
+            .import(serde_json::from_str(&json).unwrap())
+            .await
+            .unwrap();
+        assert!(report.templates_added.is_empty() && report.instances_added.is_empty());
+        assert_eq!(
+            report.conflicts,
+            vec![
+                "Template 'svc' already exists",
+                "Instance 'svc-a' already exists",
+                "Instance 'svc-b' already exists",
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_max_instances_caps_template() {
+        let dir = tempfile::tempdir().unwrap();
+        let config_path = dir.path().join("services.toml");
+        std::fs::write(
+            &config_path,
+            r#"
+[templates.svc]
+display_name = "Service"
+default_port = 8000
+start_command = "serve --port {port}"
+supports_multiple = true
+max_instances = 2
+"#,
+        )
+        .unwrap();
+
+        let core = UsmCore::new(&config_path).await.unwrap();
+        core.create_instance(svc_instance("svc-a", 8000))
+            .await
+            .unwrap();
+        core.create_instance(svc_instance("svc-b", 8001))
+            .await
+            .unwrap();
+        let err = core
+            .create_instance(svc_instance("svc-c", 8002))
+            .await
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Template 'svc' instance limit reached: 2 of max_instances = 2 exist"
+        );
+        assert!(core.get_instance("svc-c").await.is_none());
+
+        core.remove_instance("svc-a").await.unwrap();
+        core.create_instance(svc_instance("svc-c", 8002))
+            .await
+            .unwrap();
+
+        // The cap survives a reload from the saved config
+        let core = UsmCore::new(&config_path).await.unwrap();
+        assert_eq!(
+            core.get_template("svc").await.unwrap().max_instances,
+            Some(2)
+        );
+        assert!(core
+            .create_instance(svc_instance("svc-d", 8003))
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_clone_rejects_single_instance_template() {
+        let dir = tempfile::tempdir().unwrap();
+        let config_path = dir.path().join("services.toml");
+        std::fs::write(
+            &config_path,
+            r#"
+[templates.db]
+display_name = "Database"
+default_port = 5432
+start_command = "echo start"
+supports_multiple = false
+
+[instances.main]
+template = "db"
+port = 5432
+"#,
+        )
+        .unwrap();
+
+        let core = UsmCore::new(&config_path).await.unwrap();
+        let err = core
+            .clone_instance("main", clone_config("copy"), true)
+            .await
+            .unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("does not support multiple instances"));
+        assert!(core.get_instance("copy").await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_autostart_outcomes() {
+        let dir = tempfile::tempdir().unwrap();
+        let config_path = dir.path().join("services.toml");
+        std::fs::write(
+            &config_path,
+            r#"
+[templates.sleeper]
+display_name = "Sleeper"
+default_port = 18960
+start_command = "sleep 30"
+supports_multiple = true
+
+[instances.a-started]
+template = "sleeper"
+port = 18960
+auto_start = true
+
+[instances.b-disabled]
+template = "sleeper"
+port = 18961
+
+[instances.c-running]
+template = "sleeper"
+port = 18962
+auto_start = true
+
+[instances.d-failed]
+template = "sleeper"
+port = 18963
+auto_start = true
+working_dir = "/nonexistent/usm-autostart-test"
+"#,
+        )
+        .unwrap();
+
+        let core = UsmCore::new(&config_path).await.unwrap();
+        core.instances
+            .write()
+            .await
+            .update_status("c-running", ServiceStatus::Running, Some(4242))
+            .unwrap();
+
+        let outcomes = core.start_autostart_instances().await;
+        let ids: Vec<&str> = outcomes.iter().map(|(id, _)| id.as_str()).collect();
+        assert_eq!(
+            ids,
+            vec!["a-started", "b-disabled", "c-running", "d-failed"]
+        );
+
+        assert!(matches!(outcomes[0].1, AutoStartOutcome::Started(pid) if pid > 0));
+        assert_eq!(
+            outcomes[1].1,
+            AutoStartOutcome::Skipped(SkipReason::Disabled)
+        );
+        assert_eq!(
+            outcomes[2].1,
+            AutoStartOutcome::Skipped(SkipReason::AlreadyRunning)
+        );
+        assert!(matches!(
+            &outcomes[3].1,
+            AutoStartOutcome::Failed(e) if e.ends_with("does not exist")
+        ));
+
+        core.stop_instance("a-started").await.unwrap();
+    }
+
+    const DEPENDENCY_CONFIG: &str = r#"
+[templates.slow-db]
+display_name = "Slow DB"
+default_port = 18970
+start_command = "sleep 1 && touch @DIR@/ready && sleep 30"
+health_command = "test -f @DIR@/ready"
+
+[templates.broken-db]
+display_name = "Broken DB"
+default_port = 18971
+start_command = "sleep 30"
+health_command = "false"
+
+[templates.app]
+display_name = "App"
+default_port = 18972
+start_command = "sleep 30"
+supports_multiple = true
+
+[instances.slow-db]
+template = "slow-db"
+
+[instances.broken-db]
+template = "broken-db"
+
+[instances.app-slow]
+template = "app"
+port = 18972
+depends_on = ["slow-db"]
+
+[instances.app-broken]
+template = "app"
+port = 18973
+depends_on = ["broken-db"]
+"#;
+
+    async fn dependency_core(dir: &tempfile::TempDir) -> UsmCore {
+        let config_path = dir.path().join("services.toml");
+        let config = DEPENDENCY_CONFIG.replace("@DIR@", &dir.path().display().to_string());
+        std::fs::write(&config_path, config).unwrap();
+        UsmCore::new(&config_path).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_dependency_slow_to_become_healthy() {
+        let dir = tempfile::tempdir().unwrap();
+        let core = dependency_core(&dir).await;
+
+        core.start_with_dependencies("app-slow", Duration::from_secs(10))
+            .await
+            .unwrap();
 
-        // Inherit template from source
-        new_config.template_id = source.template_id;
+        // The dependency was healthy before the dependent started
+        assert!(dir.path().join("ready").exists());
+        let app = core.get_instance("app-slow").await.unwrap();
+        assert_eq!(app.status, ServiceStatus::Running);
 
-        self.create_instance(new_config).await
+        core.stop_instance("app-slow").await.unwrap();
+        core.stop_instance("slow-db").await.unwrap();
     }
 
-    // =========================================================================
-    // BULK OPERATIONS
-    // =========================================================================
+    #[tokio::test]
+    async fn test_health_start_period() {
+        let dir = tempfile::tempdir().unwrap();
+        let config_path = dir.path().join("services.toml");
+        let config = format!(
+            r#"
+[templates.warmup]
+display_name = "Warmup"
+default_port = 18974
+start_command = "sleep 2 && touch {dir}/ready && sleep 30"
+health_command = "test -f {dir}/ready"
+health_start_period_ms = 5000
 
-    /// Start all instances matching the given tags
-    pub async fn start_by_tags(&self, tags: &[&str]) -> Vec<Result<()>> {
-        let instances = self.instances.read().await;
-        let matching: Vec<_> = instances
-            .list()
-            .into_iter()
-            .filter(|i| tags.iter().any(|t| i.tags.contains(&t.to_string())))
-            .map(|i| i.id)
-            .collect();
-        drop(instances);
+[templates.plain]
+display_name = "Plain"
+default_port = 18975
+start_command = "sleep 30"
+health_command = "test -f {dir}/ready"
 
-        let mut results = Vec::new();
-        for id in matching {
-            results.push(self.start_instance(&id).await);
+[instances.warmup]
+template = "warmup"
+
+[instances.plain]
+template = "plain"
+"#,
+            dir = dir.path().display()
+        );
+        std::fs::write(&config_path, config).unwrap();
+        let core = UsmCore::new(&config_path).await.unwrap();
+
+        // Unhealthy for its first 2 seconds: a short wait keeps polling
+        // through the start period instead of failing
+        core.start_instance("warmup").await.unwrap();
+        core.wait_for_healthy("warmup", Duration::from_millis(300))
+            .await
+            .unwrap();
+        assert!(dir.path().join("ready").exists());
+
+        // Without a start period the timeout applies as is
+        std::fs::remove_file(dir.path().join("ready")).unwrap();
+        core.start_instance("plain").await.unwrap();
+        let err = core
+            .wait_for_healthy("plain", Duration::from_millis(300))
+            .await
+            .unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("did not become healthy within 300ms"));
+
+        core.stop_instance("warmup").await.unwrap();
+        core.stop_instance("plain").await.unwrap();
+    }
+
+    /// Wait for a test service to write a PID to `path`
+    async fn read_pid_file(path: &std::path::Path) -> u32 {
+        tokio::time::timeout(Duration::from_secs(5), async {
+            loop {
+                if let Some(pid) = std::fs::read_to_string(path)
+                    .ok()
+                    .and_then(|s| s.trim().parse().ok())
+                {
+                    return pid;
+                }
+                tokio::time::sleep(Duration::from_millis(50)).await;
+            }
+        })
+        .await
+        .expect("service never wrote its PID")
+    }
+
+    /// Wait until `exited` holds for `pid`, failing after 5 seconds
+    async fn wait_exited(exited: impl Fn(u32) -> bool, pid: u32) {
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while !exited(pid) {
+                tokio::time::sleep(Duration::from_millis(50)).await;
+            }
+        })
+        .await
+        .expect("process still alive");
+    }
+
+    #[tokio::test]
+    async fn test_stop_kills_service_behind_exited_wrapper() {
+        let dir = tempfile::tempdir().unwrap();
+        let config_path = dir.path().join("services.toml");
+        let config = format!(
+            r#"
+[templates.quiet]
+display_name = "Quiet"
+default_port = 18976
+start_command = "sleep 30 & echo $! > {dir}/child.pid; wait"
+
+[instances.quiet]
+template = "quiet"
+"#,
+            dir = dir.path().display()
+        );
+        std::fs::write(&config_path, config).unwrap();
+        let core = UsmCore::new(&config_path).await.unwrap();
+
+        // The tracked PID is the spawn wrapper, which exits right away; the
+        // service doesn't listen on its port, so only its group gives it away
+        core.start_instance("quiet").await.unwrap();
+        let child = read_pid_file(&dir.path().join("child.pid")).await;
+        let pid = core.get_instance("quiet").await.unwrap().pid.unwrap();
+        wait_exited(|pid| core.monitor.has_exited(pid), pid).await;
+        assert!(!core.monitor.service_exited(pid));
+        assert_eq!(core.dry_run_stop("quiet").await.unwrap().pid, Some(pid));
+
+        core.stop_instance("quiet").await.unwrap();
+        wait_exited(|pid| core.monitor.has_exited(pid), child).await;
+        assert!(core.monitor.service_exited(pid));
+    }
+
+    #[tokio::test]
+    async fn test_stop_kills_service_ignoring_sigterm() {
+        let dir = tempfile::tempdir().unwrap();
+        let config_path = dir.path().join("services.toml");
+        let config = format!(
+            r#"
+[templates.stubborn]
+display_name = "Stubborn"
+default_port = 18977
+start_command = "trap '' TERM; sleep 30 & echo $! > {dir}/child.pid; wait"
+stop_timeout_ms = 500
+
+[instances.stubborn]
+template = "stubborn"
+"#,
+            dir = dir.path().display()
+        );
+        std::fs::write(&config_path, config).unwrap();
+        let core = UsmCore::new(&config_path).await.unwrap();
+
+        core.start_instance("stubborn").await.unwrap();
+        let child = read_pid_file(&dir.path().join("child.pid")).await;
+        let pid = core.get_instance("stubborn").await.unwrap().pid.unwrap();
+        wait_exited(|pid| core.monitor.has_exited(pid), pid).await;
+
+        // SIGTERM is ignored, so the group only goes after the grace period
+        let started = std::time::Instant::now();
+        core.stop_instance("stubborn").await.unwrap();
+        assert!(started.elapsed() >= Duration::from_millis(500));
+        wait_exited(|pid| core.monitor.has_exited(pid), child).await;
+        assert!(core.monitor.service_exited(pid));
+    }
+
+    #[tokio::test]
+    async fn test_dependency_never_healthy() {
+        let dir = tempfile::tempdir().unwrap();
+        let core = dependency_core(&dir).await;
+
+        let err = core
+            .start_with_dependencies("app-broken", Duration::from_millis(600))
+            .await
+            .unwrap_err()
+            .to_string();
+        assert!(
+            err.contains("Dependency 'broken-db' failed to become healthy"),
+            "{}",
+            err
+        );
+
+        // The dependent was never started
+        let app = core.get_instance("app-broken").await.unwrap();
+        assert_eq!(app.status, ServiceStatus::Stopped);
+
+        core.stop_instance("broken-db").await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_instance_and_running_limits() {
+        let mut templates = TemplateRegistry::new();
+        templates.register(svc_template()).unwrap();
+        let settings = Settings {
+            limits: Some(config::LimitSettings {
+                max_instances: Some(3),
+                max_running: Some(1),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let core = UsmCore::builder()
+            .monitor(Arc::new(monitor::MockMonitor::new()))
+            .templates(templates)
+            .settings(settings)
+            .build()
+            .await
+            .unwrap();
+
+        // Below the cap, creating and cloning work; at it, both are refused
+        core.create_instance(svc_instance("a", 8001)).await.unwrap();
+        core.create_instance(svc_instance("b", 8002)).await.unwrap();
+        core.clone_instance("a", svc_instance("c", 8003), false)
+            .await
+            .unwrap();
+        let err = core
+            .create_instance(svc_instance("d", 8004))
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("max_instances = 3"));
+        assert!(core
+            .clone_instance("a", svc_instance("e", 8005), false)
+            .await
+            .is_err());
+
+        // One may run; restarting a running instance isn't a new start
+        core.start_instance("a").await.unwrap();
+        let err = core.start_instance("b").await.unwrap_err();
+        assert!(err.to_string().contains("max_running = 1"));
+        core.restart_instance("a").await.unwrap();
+
+        core.stop_instance("a").await.unwrap();
+        core.start_instance("b").await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_run_schedules_starts_and_stops() {
+        use chrono::TimeZone;
+        let at = |h, m| chrono::Utc.with_ymd_and_hms(2024, 1, 8, h, m, 0).unwrap();
+
+        let mut templates = TemplateRegistry::new();
+        templates.register(svc_template()).unwrap();
+        let mut config = svc_instance("nightly", 8001);
+        config.schedule = Some(schedule::ScheduleConfig {
+            start: Some("0 2 * * *".to_string()),
+            stop: Some("30 2 * * *".to_string()),
+            timezone: None,
+        });
+        let mut instances = InstanceRegistry::new();
+        instances
+            .add(ServiceInstance::from_config(config).unwrap())
+            .unwrap();
+        let monitor = Arc::new(monitor::MockMonitor::new());
+        let core = UsmCore::builder()
+            .monitor(monitor.clone())
+            .templates(templates)
+            .instances(instances)
+            .build()
+            .await
+            .unwrap();
+        let mut events = core.subscribe();
+
+        let mut scheduler = core.new_scheduler().await;
+        assert!(core
+            .run_schedules(&mut scheduler, at(1, 59))
+            .await
+            .is_empty());
+        assert!(!core.is_running("nightly").await);
+
+        let fired = core.run_schedules(&mut scheduler, at(2, 0)).await;
+        assert_eq!(fired, vec![("nightly".to_string(), ScheduledAction::Start)]);
+        assert!(core.is_running("nightly").await);
+
+        let fired = core.run_schedules(&mut scheduler, at(2, 30)).await;
+        assert_eq!(fired, vec![("nightly".to_string(), ScheduledAction::Stop)]);
+        assert!(!core.is_running("nightly").await);
+        assert_eq!(monitor.killed(), vec![1000]);
+
+        let mut triggered = Vec::new();
+        while let Ok(event) = events.try_recv() {
+            if let ServiceEvent::ScheduleTriggered { action, error, .. } = event {
+                assert!(error.is_none());
+                triggered.push(action);
+            }
         }
-        results
+        assert_eq!(
+            triggered,
+            vec![ScheduledAction::Start, ScheduledAction::Stop]
+        );
     }
 
-    /// Stop all instances matching the given tags
-    pub async fn stop_by_tags(&self, tags: &[&str]) -> Vec<Result<()>> {
-        let instances = self.instances.read().await;
-        let matching: Vec<_> = instances
-            .list()
-            .into_iter()
-            .filter(|i| tags.iter().any(|t| i.tags.contains(&t.to_string())))
-            .map(|i| i.id)
-            .collect();
-        drop(instances);
+    #[tokio::test]
+    async fn test_enforce_limits() {
+        let (core, _) = mock_memory_core().await;
+        core.register_template(svc_template()).await.unwrap();
+        // The mock reports 64 MB and 1% CPU for every process
+        let limited = |id: &str, port, memory_limit_mb, limit_action| {
+            let mut config = svc_instance(id, port);
+            config.memory_limit_mb = Some(memory_limit_mb);
+            config.limit_action = limit_action;
+            config
+        };
+        for config in [
+            limited("svc-warn", 8010, 32, None),
+            limited("svc-stop", 8011, 32, Some(LimitAction::Stop)),
+            limited("svc-restart", 8012, 32, Some(LimitAction::Restart)),
+            limited("svc-ok", 8013, 128, Some(LimitAction::Stop)),
+        ] {
+            let id = core.create_instance(config).await.unwrap();
+            core.start_instance(&id).await.unwrap();
+        }
+        let mut events = core.subscribe();
+        let mut supervisor = Supervisor::new();
 
-        let mut results = Vec::new();
-        for id in matching {
-            results.push(self.stop_instance(&id).await);
+        for _ in 1..LIMIT_SAMPLES {
+            assert!(core.enforce_limits(&mut supervisor).await.is_empty());
         }
-        results
+        // The restart (with its 2s pause) happens in the background
+        let started = std::time::Instant::now();
+        assert_eq!(
+            core.enforce_limits(&mut supervisor).await,
+            vec!["svc-restart", "svc-stop", "svc-warn"]
+        );
+        assert!(started.elapsed() < Duration::from_secs(1));
+
+        let instance = |id: &'static str| {
+            let core = core.clone();
+            async move { core.get_instance(id).await.unwrap() }
+        };
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while instance("svc-restart").await.pid != Some(1004)
+                || instance("svc-stop").await.status != ServiceStatus::Stopped
+            {
+                tokio::time::sleep(Duration::from_millis(50)).await;
+            }
+        })
+        .await
+        .unwrap();
+        assert_eq!(instance("svc-warn").await.status, ServiceStatus::Running);
+        assert_eq!(instance("svc-stop").await.status, ServiceStatus::Stopped);
+        assert_eq!(instance("svc-ok").await.status, ServiceStatus::Running);
+        let restarted = instance("svc-restart").await;
+        assert_eq!(restarted.status, ServiceStatus::Running);
+        assert_eq!(restarted.pid, Some(1004));
+
+        let mut errors = Vec::new();
+        while let Ok(event) = events.try_recv() {
+            if let ServiceEvent::Error {
+                instance_id: Some(id),
+                message,
+            } = event
+            {
+                errors.push((id, message));
+            }
+        }
+        assert_eq!(errors.len(), 3);
+        assert!(errors.iter().any(|(id, message)| id == "svc-stop"
+            && message.contains("memory 64 MB > 32 MB")
+            && message.ends_with("action: stop")));
+
+        // Warned once per streak; the restarted instance counts from scratch
+        assert!(core.enforce_limits(&mut supervisor).await.is_empty());
     }
 
-    // =========================================================================
-    // EVENTS & METRICS
-    // =========================================================================
+    #[tokio::test]
+    async fn test_supervise_restarts_with_backoff() {
+        let (core, monitor) = mock_memory_core().await;
+        core.register_template(svc_template()).await.unwrap();
+        let mut config = svc_instance("svc-a", 8010);
+        config.restart = Some(toml::from_str("policy = \"on-failure\"\nmax_retries = 1").unwrap());
+        core.create_instance(config).await.unwrap();
+        core.create_instance(svc_instance("svc-b", 8011))
+            .await
+            .unwrap();
+        core.start_instance("svc-a").await.unwrap();
+        core.start_instance("svc-b").await.unwrap();
+        let mut events = core.subscribe();
 
-    /// Subscribe to service events
-    pub fn subscribe(&self) -> broadcast::Receiver<ServiceEvent> {
-        self.event_bus.subscribe()
+        let t0 = chrono::Utc::now();
+        let secs = |n| t0 + chrono::Duration::seconds(n);
+        let status = |id: &'static str| {
+            let core = core.clone();
+            async move { core.get_instance(id).await.unwrap().status }
+        };
+        let mut supervisor = Supervisor::new();
+
+        // Both die; only the one with a policy is noticed
+        monitor.set_running(1000, false);
+        monitor.set_running(1001, false);
+        assert!(core.supervise(&mut supervisor, t0).await.is_empty());
+        assert_eq!(status("svc-a").await, ServiceStatus::Error);
+        assert_eq!(status("svc-b").await, ServiceStatus::Running);
+
+        // Restarted once the 1s backoff has passed
+        assert!(core.supervise(&mut supervisor, t0).await.is_empty());
+        assert_eq!(
+            core.supervise(&mut supervisor, secs(1)).await,
+            vec!["svc-a"]
+        );
+        let instance = core.get_instance("svc-a").await.unwrap();
+        assert_eq!(instance.status, ServiceStatus::Running);
+        assert_eq!(instance.pid, Some(1002));
+
+        // Dies again: retries are exhausted
+        monitor.set_running(1002, false);
+        core.supervise(&mut supervisor, secs(2)).await;
+        assert_eq!(status("svc-a").await, ServiceStatus::Error);
+        assert!(core.supervise(&mut supervisor, secs(60)).await.is_empty());
+
+        let mut transitions = Vec::new();
+        let mut gave_up = false;
+        while let Ok(event) = events.try_recv() {
+            match event {
+                ServiceEvent::StatusChanged { status, .. } => transitions.push(status),
+                ServiceEvent::Error { instance_id, .. } => {
+                    gave_up = instance_id.as_deref() == Some("svc-a");
+                },
+                _ => {},
+            }
+        }
+        assert_eq!(
+            transitions,
+            vec![
+                ServiceStatus::Error,
+                ServiceStatus::Starting,
+                ServiceStatus::Running,
+                ServiceStatus::Error
+            ]
+        );
+        assert!(gave_up);
+
+        // Stopping a crashed instance settles it
+        core.stop_instance("svc-a").await.unwrap();
+        assert_eq!(status("svc-a").await, ServiceStatus::Stopped);
     }
 
-    /// Get system-wide metrics
-    pub fn get_system_metrics(&self) -> metrics::SystemMetrics {
-        self.monitor.get_system_metrics()
+    #[tokio::test]
+    async fn test_publish_metrics_skips_unchanged() {
+        let dir = tempfile::tempdir().unwrap();
+        let (core, _monitor) = mock_core(&dir).await;
+        let mut events = core.subscribe();
+        let mut last = HashMap::new();
+
+        assert_eq!(core.publish_metrics(&mut last).await, 0);
+
+        core.start_instance("svc-main").await.unwrap();
+        assert_eq!(core.publish_metrics(&mut last).await, 1);
+        assert_eq!(core.publish_metrics(&mut last).await, 0);
+
+        let mut published = Vec::new();
+        while let Ok(event) = events.try_recv() {
+            if let ServiceEvent::MetricsUpdated {
+                instance_id,
+                cpu_percent,
+                memory_mb,
+            } = event
+            {
+                published.push((instance_id, cpu_percent, memory_mb));
+            }
+        }
+        assert_eq!(published, vec![("svc-main".to_string(), 1.0, 64)]);
+
+        // Stopped instances are forgotten, so a new run publishes again
+        core.stop_instance("svc-main").await.unwrap();
+        assert_eq!(core.publish_metrics(&mut last).await, 0);
+        assert!(last.is_empty());
+        core.start_instance("svc-main").await.unwrap();
+        assert_eq!(core.publish_metrics(&mut last).await, 1);
     }
 
-    /// Get metrics for a specific instance
-    pub async fn get_instance_metrics(&self, id: &str) -> Option<metrics::InstanceMetrics> {
-        let instances = self.instances.read().await;
-        let instance = instances.get(id)?;
-        instance
-            .pid
-            .and_then(|pid| self.monitor.get_process_metrics(pid))
+    #[tokio::test]
+    async fn test_metrics_history_recorded_and_dropped() {
+        let dir = tempfile::tempdir().unwrap();
+        let (core, _monitor) = mock_core(&dir).await;
+        let mut last = HashMap::new();
+
+        core.start_instance("svc-main").await.unwrap();
+        core.publish_metrics(&mut last).await;
+        core.publish_metrics(&mut last).await;
+
+        // Unchanged samples aren't re-published but still make the history
+        let history = core.instance_metrics_history("svc-main").await.unwrap();
+        assert_eq!(history.len(), 2);
+        assert_eq!(history[0].cpu_percent, 1.0);
+        assert_eq!(history[0].memory_bytes, 64 * 1024 * 1024);
+        assert!(history[0].timestamp <= history[1].timestamp);
+        assert_eq!(core.system_metrics_history().len(), 2);
+
+        core.remove_instance("svc-main").await.unwrap();
+        assert!(core.metrics_history.instance("svc-main").is_empty());
+        assert!(core.instance_metrics_history("svc-main").await.is_err());
     }
-}
 
-#[cfg(test)]
-mod tests {
-    #[allow(unused_imports)]
-    use super::*;
+    #[tokio::test]
+    async fn test_shutdown_policies() {
+        use config::ShutdownPolicy;
+
+        let (core, monitor) = mock_memory_core().await;
+        core.register_template(svc_template()).await.unwrap();
+        let mut scratch = svc_instance("svc-scratch", 8010);
+        scratch.tags = vec![config::EPHEMERAL_TAG.to_string()];
+        core.create_instance(scratch).await.unwrap();
+        core.create_instance(svc_instance("svc-main", 8011))
+            .await
+            .unwrap();
+        core.start_instance("svc-scratch").await.unwrap();
+        core.start_instance("svc-main").await.unwrap();
+
+        assert!(core.shutdown(ShutdownPolicy::Leave).await.is_empty());
+
+        let stopped = core.shutdown(ShutdownPolicy::StopEphemeral).await;
+        assert_eq!(stopped.len(), 1);
+        assert_eq!(stopped[0].0, "svc-scratch");
+        assert!(core.is_running("svc-main").await);
+
+        let stopped = core.shutdown(ShutdownPolicy::StopAll).await;
+        assert_eq!(stopped.len(), 1);
+        assert_eq!(stopped[0].0, "svc-main");
+        assert!(!core.is_running("svc-main").await);
+        assert_eq!(monitor.killed(), vec![1000, 1001]);
+    }
+
+    async fn mock_core(dir: &tempfile::TempDir) -> (UsmCore, Arc<monitor::MockMonitor>) {
+        let config_path = dir.path().join("services.toml");
+        std::fs::write(
+            &config_path,
+            r#"
+[templates.svc]
+display_name = "Service"
+default_port = 8000
+start_command = "serve --port {port}"
+
+[instances.svc-main]
+template = "svc"
+port = 8001
+"#,
+        )
+        .unwrap();
+
+        let monitor = Arc::new(monitor::MockMonitor::new());
+        let core = UsmCore::with_monitor(&config_path, monitor.clone())
+            .await
+            .unwrap();
+        (core, monitor)
+    }
 
     #[tokio::test]
-    async fn test_usm_core_creation() {
-        // Test will be implemented with mock config
+    async fn test_old_runs_output_removed() {
+        let dir = tempfile::tempdir().unwrap();
+        let (core, monitor) = mock_core(&dir).await;
+
+        // The last run's output stays readable after it stops
+        core.start_instance("svc-main").await.unwrap();
+        core.stop_instance("svc-main").await.unwrap();
+        assert!(monitor.removed_logs().is_empty());
+
+        // Replaced by the next run's
+        core.start_instance("svc-main").await.unwrap();
+        assert_eq!(monitor.removed_logs(), vec![1000]);
+        core.restart_instance("svc-main").await.unwrap();
+        assert_eq!(monitor.removed_logs(), vec![1000, 1001]);
+
+        // Gone with the instance
+        core.remove_instance("svc-main").await.unwrap();
+        assert_eq!(monitor.removed_logs(), vec![1000, 1001, 1002]);
+    }
+
+    #[tokio::test]
+    async fn test_lifecycle_with_mock_monitor() {
+        let dir = tempfile::tempdir().unwrap();
+        let (core, monitor) = mock_core(&dir).await;
+
+        // Start
+        core.start_instance("svc-main").await.unwrap();
+        let instance = core.get_instance("svc-main").await.unwrap();
+        assert_eq!(instance.status, ServiceStatus::Running);
+        assert_eq!(instance.pid, Some(1000));
+        assert_eq!(monitor.spawned(), vec!["serve --port 8001"]);
+
+        // Signal while running
+        core.signal_instance("svc-main", "USR1", false)
+            .await
+            .unwrap();
+        assert_eq!(monitor.signals(), vec![(1000, Signal::Usr1, false)]);
+
+        // Crash, then restart (the dead process isn't signalled)
+        monitor.set_running(1000, false);
+        core.restart_instance("svc-main").await.unwrap();
+        assert!(monitor.killed().is_empty());
+        let instance = core.get_instance("svc-main").await.unwrap();
+        assert_eq!(instance.pid, Some(1001));
+        assert!(monitor.is_running(1001));
+
+        // Stop
+        core.stop_instance("svc-main").await.unwrap();
+        assert_eq!(monitor.killed(), vec![1001]);
+        let instance = core.get_instance("svc-main").await.unwrap();
+        assert_eq!(instance.status, ServiceStatus::Stopped);
+        assert_eq!(instance.pid, None);
+    }
+
+    #[tokio::test]
+    async fn test_reload_instance() {
+        let dir = tempfile::tempdir().unwrap();
+        let config_path = dir.path().join("services.toml");
+        std::fs::write(
+            &config_path,
+            r#"
+[templates.svc]
+display_name = "Service"
+default_port = 8000
+start_command = "serve --port {port}"
+
+[templates.custom]
+display_name = "Custom reload"
+default_port = 8100
+start_command = "serve --port {port}"
+reload_signal = "SIGUSR2"
+
+[instances.svc-main]
+template = "svc"
+
+[instances.custom-main]
+template = "custom"
+"#,
+        )
+        .unwrap();
+        let monitor = Arc::new(monitor::MockMonitor::new());
+        let core = UsmCore::with_monitor(&config_path, monitor.clone())
+            .await
+            .unwrap();
+
+        let err = core.reload_instance("svc-main").await.unwrap_err();
+        assert!(err.to_string().ends_with("is not running"), "{}", err);
+        assert!(core.reload_instance("missing").await.is_err());
+
+        // SIGHUP unless the template picks another signal
+        core.start_instance("svc-main").await.unwrap();
+        core.start_instance("custom-main").await.unwrap();
+        let svc_pid = core.get_instance("svc-main").await.unwrap().pid.unwrap();
+        let custom_pid = core.get_instance("custom-main").await.unwrap().pid.unwrap();
+        assert_eq!(core.reload_instance("svc-main").await.unwrap(), Signal::Hup);
+        assert_eq!(
+            core.reload_instance("custom-main").await.unwrap(),
+            Signal::Usr2
+        );
+        assert_eq!(
+            monitor.signals(),
+            vec![
+                (svc_pid, Signal::Hup, false),
+                (custom_pid, Signal::Usr2, false)
+            ]
+        );
+        assert!(core.is_running("svc-main").await);
+    }
+
+    #[tokio::test]
+    async fn test_config_hot_reload() {
+        let dir = tempfile::tempdir().unwrap();
+        let (core, _monitor) = mock_core(&dir).await;
+        let mut events = core.subscribe();
+        core.start_instance("svc-main").await.unwrap();
+
+        let reloaded = |events: &mut broadcast::Receiver<ServiceEvent>| {
+            let mut events = events.resubscribe();
+            async move {
+                tokio::time::timeout(Duration::from_secs(5), async {
+                    loop {
+                        if let Ok(ServiceEvent::ConfigReloaded) = events.recv().await {
+                            return;
+                        }
+                    }
+                })
+                .await
+                .is_ok()
+            }
+        };
+
+        // USM's own save doesn't trigger a reload
+        let wait = reloaded(&mut events);
+        core.patch_instance_env(
+            "svc-main",
+            HashMap::from([("LOG_LEVEL".to_string(), Some("debug".to_string()))]),
+        )
+        .await
+        .unwrap();
+        let wait = tokio::time::timeout(Duration::from_secs(1), wait).await;
+        assert!(!matches!(wait, Ok(true)));
+
+        // An external edit does
+        let config_path = dir.path().join("services.toml");
+        let mut content = std::fs::read_to_string(&config_path).unwrap();
+        content.push_str(
+            r#"
+[templates.other]
+display_name = "Other"
+default_port = 9000
+start_command = "other"
+
+[instances.svc-c]
+template = "other"
+port = 8003
+"#,
+        );
+        let wait = reloaded(&mut events);
+        std::fs::write(&config_path, content).unwrap();
+        assert!(wait.await);
+
+        assert_eq!(core.get_instance("svc-c").await.unwrap().port, 8003);
+        // Running instances keep running across the reload
+        assert!(core.is_running("svc-main").await);
+    }
+
+    #[tokio::test]
+    async fn test_docker_instance_uses_compose() {
+        let (core, monitor) = mock_memory_core().await;
+        let mut template = svc_template();
+        template.is_docker = true;
+        core.register_template(template).await.unwrap();
+        let mut config = svc_instance("svc-db", 5432);
+        config.config_path = Some("/srv/db/compose.yml".into());
+        config.working_dir = Some("/srv/db".into());
+        core.create_instance(config).await.unwrap();
+
+        core.start_instance("svc-db").await.unwrap();
+        let instance = core.get_instance("svc-db").await.unwrap();
+        assert_eq!(instance.status, ServiceStatus::Running);
+        assert_eq!(instance.pid, None);
+        assert!(monitor.spawned().is_empty());
+
+        core.stop_instance("svc-db").await.unwrap();
+        assert_eq!(
+            monitor.executed(),
+            vec![
+                "docker compose -f '/srv/db/compose.yml' --project-directory '/srv/db' \
+                 -p svc-db up -d",
+                "docker compose -f '/srv/db/compose.yml' --project-directory '/srv/db' \
+                 -p svc-db down",
+            ]
+        );
+        assert!(monitor.killed().is_empty());
+
+        // Health comes from the containers when there's no explicit check
+        let template = core.get_template("svc").await.unwrap();
+        assert!(matches!(
+            HealthCheck::for_instance(&template, &instance),
+            Some(HealthCheck::Compose(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_supervise_notices_stopped_compose_stack() {
+        let (core, monitor) = mock_memory_core().await;
+        let mut template = svc_template();
+        template.is_docker = true;
+        core.register_template(template).await.unwrap();
+        let mut config = svc_instance("svc-db", 5432);
+        config.restart = Some(toml::from_str("policy = \"on-failure\"").unwrap());
+        core.create_instance(config).await.unwrap();
+        core.start_instance("svc-db").await.unwrap();
+
+        let container = |state: &str| {
+            docker::parse_ps(&format!(r#"{{"Name":"svc-db-db-1","State":"{state}"}}"#)).unwrap()
+        };
+        let t0 = chrono::Utc::now();
+        let mut supervisor = Supervisor::new();
+        let status =
+            |core: UsmCore| async move { core.get_instance("svc-db").await.unwrap().status };
+
+        // Docker unreachable, then the stack up: nothing to do
+        core.supervise(&mut supervisor, t0).await;
+        monitor.set_compose_containers("svc-db", container("running"));
+        core.supervise(&mut supervisor, t0).await;
+        assert_eq!(status(core.clone()).await, ServiceStatus::Running);
+
+        // The stack went down behind USM's back
+        monitor.set_compose_containers("svc-db", container("exited"));
+        core.supervise(&mut supervisor, t0).await;
+        assert_eq!(status(core.clone()).await, ServiceStatus::Error);
+
+        // Restarted with `docker compose up` after the backoff
+        monitor.set_compose_containers("svc-db", container("running"));
+        assert_eq!(
+            core.supervise(&mut supervisor, t0 + chrono::Duration::seconds(1))
+                .await,
+            vec!["svc-db"]
+        );
+        assert_eq!(status(core.clone()).await, ServiceStatus::Running);
+        assert_eq!(monitor.executed().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_resource_usage_only_for_running() {
+        let dir = tempfile::tempdir().unwrap();
+        let (core, _monitor) = mock_core(&dir).await;
+
+        let stopped = core.get_instance("svc-main").await.unwrap();
+        assert_eq!(core.resource_usage(&stopped).await, None);
+
+        core.start_instance("svc-main").await.unwrap();
+        let running = core.get_instance("svc-main").await.unwrap();
+        // MockMonitor finds nothing by port, so this exercises the PID fallback
+        assert_eq!(
+            core.resource_usage(&running).await,
+            Some((1.0, 64 * 1024 * 1024))
+        );
+
+        assert!(core.get_instance_metrics("svc-main").await.is_some());
+        core.stop_instance("svc-main").await.unwrap();
+        assert!(core.get_instance_metrics("svc-main").await.is_none());
+        assert!(core.get_instance_metrics("missing").await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_failed_spawn_with_mock_monitor() {
+        let dir = tempfile::tempdir().unwrap();
+        let (core, monitor) = mock_core(&dir).await;
+
+        monitor.set_fail_spawns(true);
+        assert!(core.start_instance("svc-main").await.is_err());
+
+        let instance = core.get_instance("svc-main").await.unwrap();
+        assert_eq!(instance.status, ServiceStatus::Error);
+        assert_eq!(instance.pid, None);
+        assert_eq!(
+            instance.last_error.as_deref(),
+            Some("Mock spawn failure for 'serve --port 8001'")
+        );
+        assert_eq!(instance.last_exit_code, None);
+
+        // A successful start clears the failure
+        monitor.set_fail_spawns(false);
+        core.start_instance("svc-main").await.unwrap();
+        let instance = core.get_instance("svc-main").await.unwrap();
+        assert_eq!(instance.last_error, None);
+    }
+
+    #[tokio::test]
+    async fn test_subscribe_instance() {
+        let (core, _) = mock_memory_core().await;
+        core.register_template(svc_template()).await.unwrap();
+        let mut events = core.subscribe_instance("svc-a");
+
+        core.create_instance(svc_instance("svc-a", 8010))
+            .await
+            .unwrap();
+        core.create_instance(svc_instance("svc-b", 8011))
+            .await
+            .unwrap();
+        core.start_instance("svc-b").await.unwrap();
+        core.start_instance("svc-a").await.unwrap();
+
+        let mut seen = Vec::new();
+        while let Ok(event) = events.try_recv() {
+            assert_eq!(event.instance_id(), Some("svc-a"));
+            seen.push(event.event_type());
+        }
+        assert_eq!(
+            seen,
+            vec!["instance_created", "status_changed", "status_changed"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_transitional_statuses_are_broadcast() {
+        let dir = tempfile::tempdir().unwrap();
+        let (core, monitor) = mock_core(&dir).await;
+        let mut events = core.subscribe();
+        let mut transitions = || {
+            let mut seen = Vec::new();
+            while let Ok(event) = events.try_recv() {
+                if let ServiceEvent::StatusChanged { status, .. } = event {
+                    seen.push(status);
+                }
+            }
+            seen
+        };
+
+        core.start_instance("svc-main").await.unwrap();
+        assert_eq!(
+            transitions(),
+            vec![ServiceStatus::Starting, ServiceStatus::Running]
+        );
+
+        core.stop_instance("svc-main").await.unwrap();
+        assert_eq!(
+            transitions(),
+            vec![ServiceStatus::Stopping, ServiceStatus::Stopped]
+        );
+
+        monitor.set_fail_spawns(true);
+        assert!(core.start_instance("svc-main").await.is_err());
+        assert_eq!(
+            transitions(),
+            vec![ServiceStatus::Starting, ServiceStatus::Error]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_unhealthy_instances() {
+        let (core, monitor) = mock_memory_core().await;
+        core.register_template(svc_template()).await.unwrap();
+        let restart = || Some(toml::from_str("policy = \"on-failure\"\nmax_retries = 0").unwrap());
+        let mut config = svc_instance("svc-auto", 8010);
+        config.auto_start = true;
+        config.restart = restart();
+        core.create_instance(config).await.unwrap();
+        let mut config = svc_instance("svc-critical", 8011);
+        config.tags = vec!["critical".to_string()];
+        config.restart = restart();
+        core.create_instance(config).await.unwrap();
+        let mut config = svc_instance("svc-extra", 8012);
+        config.restart = restart();
+        core.create_instance(config).await.unwrap();
+        for id in ["svc-auto", "svc-critical", "svc-extra"] {
+            core.start_instance(id).await.unwrap();
+        }
+        assert!(core.unhealthy_instances().await.is_empty());
+
+        // A crashed instance outside the core set doesn't count
+        monitor.set_running(1002, false);
+        core.supervise(&mut Supervisor::new(), chrono::Utc::now())
+            .await;
+        assert!(core.unhealthy_instances().await.is_empty());
+
+        monitor.set_running(1000, false);
+        monitor.set_running(1001, false);
+        core.supervise(&mut Supervisor::new(), chrono::Utc::now())
+            .await;
+        assert_eq!(
+            core.unhealthy_instances().await,
+            vec!["svc-auto", "svc-critical"]
+        );
+
+        core.stop_instance("svc-auto").await.unwrap();
+        assert_eq!(core.unhealthy_instances().await, vec!["svc-critical"]);
+    }
+
+    #[tokio::test]
+    async fn test_crash_records_exit_code() {
+        let (core, monitor) = mock_memory_core().await;
+        core.register_template(svc_template()).await.unwrap();
+        let mut config = svc_instance("svc-a", 8010);
+        config.restart = Some(toml::from_str("policy = \"on-failure\"\nmax_retries = 0").unwrap());
+        core.create_instance(config).await.unwrap();
+        core.start_instance("svc-a").await.unwrap();
+
+        monitor.set_running(1000, false);
+        monitor.set_exit_code(1000, 1);
+        core.supervise(&mut Supervisor::new(), chrono::Utc::now())
+            .await;
+
+        let instance = core.get_instance("svc-a").await.unwrap();
+        assert_eq!(instance.status, ServiceStatus::Error);
+        assert_eq!(instance.last_exit_code, Some(1));
+        assert_eq!(instance.last_error.as_deref(), Some("Exited with code 1"));
+
+        // Exit details come out of the REST payload with the instance
+        let json = serde_json::to_value(&instance).unwrap();
+        assert_eq!(json["last_exit_code"], 1);
+
+        // The recorded exit code was consumed
+        assert_eq!(monitor.exit_code(1000), None);
+
+        // So is one a stopped run left, and the last run's on removal
+        core.start_instance("svc-a").await.unwrap();
+        monitor.set_exit_code(1001, 143);
+        core.stop_instance("svc-a").await.unwrap();
+        assert_eq!(monitor.exit_code(1001), None);
+        monitor.set_exit_code(1001, 143);
+        core.remove_instance("svc-a").await.unwrap();
+        assert_eq!(monitor.exit_code(1001), None);
     }
 }
@@ -0,0 +1,521 @@
+//! In-memory capture of recent process output
+//!
+//! When enabled, the spawn path tees each line a service writes to
+//! stdout/stderr into a bounded per-instance ring buffer, so the most recent
+//! output can be served without reading log files.
+//!
+//! Independently of that, process monitors capture each spawned process's
+//! output to files (see `monitor::log_paths`, or `monitor::instance_log_paths`
+//! with `[logs] dir` set), which `tail_lines` reads back and `OutputFollower`
+//! follows as they grow.
+
+use std::collections::{HashMap, VecDeque};
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, RwLock};
+
+use serde::{Deserialize, Serialize};
+
+/// Which output stream a line came from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogStream {
+    Stdout,
+    Stderr,
+}
+
+/// A single captured line of output
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LogLine {
+    pub stream: LogStream,
+    pub line: String,
+}
+
+/// Bounded ring buffer of recent output lines
+///
+/// Cheap to clone; clones share the same underlying buffer.
+#[derive(Debug, Clone)]
+pub struct LogBuffer {
+    capacity: usize,
+    lines: Arc<Mutex<VecDeque<LogLine>>>,
+}
+
+impl LogBuffer {
+    /// Create a buffer that keeps at most `capacity` lines
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            lines: Arc::new(Mutex::new(VecDeque::with_capacity(capacity))),
+        }
+    }
+
+    /// Append a line, evicting the oldest one if the buffer is full
+    pub fn push(&self, stream: LogStream, line: impl Into<String>) {
+        if self.capacity == 0 {
+            return;
+        }
+        if let Ok(mut lines) = self.lines.lock() {
+            if lines.len() == self.capacity {
+                lines.pop_front();
+            }
+            lines.push_back(LogLine {
+                stream,
+                line: line.into(),
+            });
+        }
+    }
+
+    /// Snapshot of the buffered lines, oldest first
+    pub fn lines(&self) -> Vec<LogLine> {
+        self.lines
+            .lock()
+            .map(|lines| lines.iter().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    /// Maximum number of lines kept
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+}
+
+/// Per-instance log buffers
+///
+/// A store created with a capacity of 0 is disabled and never allocates
+/// buffers, which bounds memory for users that don't opt in.
+#[derive(Debug, Default)]
+pub struct LogStore {
+    capacity: usize,
+    buffers: RwLock<HashMap<String, LogBuffer>>,
+}
+
+impl LogStore {
+    /// Create a store keeping `capacity` lines per instance (0 disables capture)
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            buffers: RwLock::new(HashMap::new()),
+        }
+    }
+
+    /// Whether in-memory capture is enabled
+    pub fn is_enabled(&self) -> bool {
+        self.capacity > 0
+    }
+
+    /// Get (or create) the buffer for an instance
+    ///
+    /// Returns None when capture is disabled.
+    pub fn buffer_for(&self, instance_id: &str) -> Option<LogBuffer> {
+        if !self.is_enabled() {
+            return None;
+        }
+        let mut buffers = self.buffers.write().ok()?;
+        Some(
+            buffers
+                .entry(instance_id.to_string())
+                .or_insert_with(|| LogBuffer::new(self.capacity))
+                .clone(),
+        )
+    }
+
+    /// Recent lines for an instance, if any have been captured
+    pub fn recent(&self, instance_id: &str) -> Option<Vec<LogLine>> {
+        let buffers = self.buffers.read().ok()?;
+        buffers.get(instance_id).map(LogBuffer::lines)
+    }
+
+    /// Forget the buffer for an instance
+    pub fn remove(&self, instance_id: &str) {
+        if let Ok(mut buffers) = self.buffers.write() {
+            buffers.remove(instance_id);
+        }
+    }
+}
+
+/// The last lines a process wrote to its captured output files
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CapturedLogs {
+    pub stdout: Vec<String>,
+    pub stderr: Vec<String>,
+}
+
+/// The last `n` lines of a file
+///
+/// Reads backwards from the end in chunks, so only the tail of a large log is
+/// read. A missing file has no lines.
+pub fn tail_lines(path: &Path, n: usize) -> std::io::Result<Vec<String>> {
+    const CHUNK: u64 = 8192;
+
+    let mut file = match File::open(path) {
+        Ok(file) => file,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e),
+    };
+    if n == 0 {
+        return Ok(Vec::new());
+    }
+
+    let len = file.metadata()?.len();
+    let mut start = len;
+    let mut tail = Vec::new();
+    // Stop once the tail holds n complete lines: n newlines before the end
+    while start > 0 {
+        let read_from = start.saturating_sub(CHUNK);
+        let mut chunk = vec![0; (start - read_from) as usize];
+        file.seek(SeekFrom::Start(read_from))?;
+        file.read_exact(&mut chunk)?;
+        chunk.extend_from_slice(&tail);
+        tail = chunk;
+        start = read_from;
+
+        let body = tail.strip_suffix(b"\n").unwrap_or(&tail);
+        if body.iter().filter(|&&b| b == b'\n').count() >= n {
+            break;
+        }
+    }
+
+    let text = String::from_utf8_lossy(&tail);
+    let lines: Vec<&str> = text.lines().collect();
+    let skip = lines.len().saturating_sub(n);
+    Ok(lines[skip..].iter().map(|l| l.to_string()).collect())
+}
+
+/// The last `lines` lines of each of a process's `(stdout, stderr)` files
+///
+/// Fails if neither file exists.
+pub fn read_captured(
+    (stdout, stderr): &(PathBuf, PathBuf),
+    lines: usize,
+) -> anyhow::Result<CapturedLogs> {
+    if !stdout.exists() && !stderr.exists() {
+        anyhow::bail!("No captured output in {}", stdout.display());
+    }
+    Ok(CapturedLogs {
+        stdout: tail_lines(stdout, lines)?,
+        stderr: tail_lines(stderr, lines)?,
+    })
+}
+
+/// Most bytes one `FileFollower::read_new` call reads; anything beyond is
+/// left for the following calls
+pub const FOLLOW_READ_LIMIT: u64 = 1024 * 1024;
+
+/// Follows a growing file by polling its size, like `tail -f`
+///
+/// A file that doesn't exist yet has no lines; one that shrank (was
+/// truncated or replaced) is read again from the start. A line is returned
+/// once its newline has been written, or in pieces of `FOLLOW_READ_LIMIT`
+/// bytes if it is longer than that.
+#[derive(Debug)]
+pub struct FileFollower {
+    path: PathBuf,
+    offset: u64,
+    partial: Vec<u8>,
+}
+
+impl FileFollower {
+    /// Follow `path` from its current end
+    pub fn from_end(path: impl Into<PathBuf>) -> Self {
+        let path = path.into();
+        let offset = std::fs::metadata(&path).map(|m| m.len()).unwrap_or(0);
+        Self {
+            path,
+            offset,
+            partial: Vec::new(),
+        }
+    }
+
+    /// Follow `path` from its first line
+    pub fn from_start(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            offset: 0,
+            partial: Vec::new(),
+        }
+    }
+
+    /// Complete lines written since the last call, reading at most
+    /// `FOLLOW_READ_LIMIT` bytes
+    pub fn read_new(&mut self) -> std::io::Result<Vec<String>> {
+        let mut file = match File::open(&self.path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e),
+        };
+        let len = file.metadata()?.len();
+        if len < self.offset {
+            self.offset = 0;
+            self.partial.clear();
+        }
+        if len == self.offset {
+            return Ok(Vec::new());
+        }
+
+        file.seek(SeekFrom::Start(self.offset))?;
+        let mut chunk = Vec::new();
+        file.take((len - self.offset).min(FOLLOW_READ_LIMIT))
+            .read_to_end(&mut chunk)?;
+        self.offset += chunk.len() as u64;
+        self.partial.extend_from_slice(&chunk);
+
+        let Some(last_newline) = self.partial.iter().rposition(|&b| b == b'\n') else {
+            if self.partial.len() as u64 >= FOLLOW_READ_LIMIT {
+                let piece = std::mem::take(&mut self.partial);
+                return Ok(vec![String::from_utf8_lossy(&piece).into_owned()]);
+            }
+            return Ok(Vec::new());
+        };
+        let rest = self.partial.split_off(last_newline + 1);
+        let complete = std::mem::replace(&mut self.partial, rest);
+        Ok(String::from_utf8_lossy(&complete)
+            .lines()
+            .map(str::to_string)
+            .collect())
+    }
+}
+
+/// Follows both captured output files of one process
+#[derive(Debug)]
+pub struct OutputFollower {
+    pid: u32,
+    stdout: FileFollower,
+    stderr: FileFollower,
+}
+
+impl OutputFollower {
+    /// Follow the files at `(stdout, stderr)`, skipping what they already
+    /// hold unless `from_start`
+    pub fn new(pid: u32, (stdout, stderr): (PathBuf, PathBuf), from_start: bool) -> Self {
+        let follow = if from_start {
+            FileFollower::from_start
+        } else {
+            FileFollower::from_end
+        };
+        Self {
+            pid,
+            stdout: follow(stdout),
+            stderr: follow(stderr),
+        }
+    }
+
+    /// Process whose output is followed
+    pub fn pid(&self) -> u32 {
+        self.pid
+    }
+
+    /// Lines written since the last call, stdout's before stderr's
+    pub fn read_new(&mut self) -> std::io::Result<Vec<LogLine>> {
+        let stdout = self.stdout.read_new()?;
+        let stderr = self.stderr.read_new()?;
+        let tag = |stream| move |line| LogLine { stream, line };
+        Ok(stdout
+            .into_iter()
+            .map(tag(LogStream::Stdout))
+            .chain(stderr.into_iter().map(tag(LogStream::Stderr)))
+            .collect())
+    }
+}
+
+/// Copy lines from a child's output pipe into an optional file and a buffer
+///
+/// Runs on a dedicated thread until the pipe closes (i.e. the process exits).
+pub(crate) fn tee_lines<R>(
+    reader: R,
+    stream: LogStream,
+    file: Option<std::fs::File>,
+    buffer: LogBuffer,
+) where
+    R: Read + Send + 'static,
+{
+    std::thread::spawn(move || {
+        let mut file = file;
+        for line in BufReader::new(reader).lines() {
+            let Ok(line) = line else { break };
+            if let Some(f) = file.as_mut() {
+                let _ = writeln!(f, "{}", line);
+            }
+            buffer.push(stream, line);
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_buffer_keeps_last_n_lines() {
+        let buffer = LogBuffer::new(3);
+        for i in 0..10 {
+            buffer.push(LogStream::Stdout, format!("line {}", i));
+        }
+
+        let lines: Vec<String> = buffer.lines().into_iter().map(|l| l.line).collect();
+        assert_eq!(lines, vec!["line 7", "line 8", "line 9"]);
+    }
+
+    #[test]
+    fn test_disabled_store() {
+        let store = LogStore::new(0);
+        assert!(!store.is_enabled());
+        assert!(store.buffer_for("test").is_none());
+        assert!(store.recent("test").is_none());
+    }
+
+    #[test]
+    fn test_store_shares_buffers() {
+        let store = LogStore::new(5);
+        let buffer = store.buffer_for("test").unwrap();
+        buffer.push(LogStream::Stderr, "boom");
+
+        let recent = store.recent("test").unwrap();
+        assert_eq!(recent.len(), 1);
+        assert_eq!(recent[0].stream, LogStream::Stderr);
+        assert_eq!(recent[0].line, "boom");
+
+        store.remove("test");
+        assert!(store.recent("test").is_none());
+    }
+
+    #[test]
+    fn test_tail_lines() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("out.log");
+        let content: String = (0..5000).map(|i| format!("line {}\n", i)).collect();
+        std::fs::write(&path, content).unwrap();
+
+        assert_eq!(
+            tail_lines(&path, 3).unwrap(),
+            vec!["line 4997", "line 4998", "line 4999"]
+        );
+        // Spans several chunks
+        let many = tail_lines(&path, 2000).unwrap();
+        assert_eq!(many.len(), 2000);
+        assert_eq!(many[0], "line 3000");
+        assert_eq!(tail_lines(&path, 10_000).unwrap().len(), 5000);
+        assert!(tail_lines(&path, 0).unwrap().is_empty());
+
+        std::fs::write(&path, "no newline at end").unwrap();
+        assert_eq!(tail_lines(&path, 5).unwrap(), vec!["no newline at end"]);
+        assert!(tail_lines(&dir.path().join("missing.log"), 5)
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn test_file_follower() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("out.log");
+        let append = |text: &str| {
+            let mut file = std::fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&path)
+                .unwrap();
+            file.write_all(text.as_bytes()).unwrap();
+        };
+
+        // Not created yet
+        let mut from_start = FileFollower::from_start(&path);
+        assert!(from_start.read_new().unwrap().is_empty());
+
+        append("old 1\nold 2\n");
+        let mut from_end = FileFollower::from_end(&path);
+        assert!(from_end.read_new().unwrap().is_empty());
+        assert_eq!(from_start.read_new().unwrap(), vec!["old 1", "old 2"]);
+
+        // Lines are only returned once complete
+        append("new 1\nhalf");
+        assert_eq!(from_end.read_new().unwrap(), vec!["new 1"]);
+        assert!(from_end.read_new().unwrap().is_empty());
+        append(" a line\n");
+        assert_eq!(from_end.read_new().unwrap(), vec!["half a line"]);
+
+        // Truncated: start over
+        std::fs::write(&path, "fresh\n").unwrap();
+        assert_eq!(from_end.read_new().unwrap(), vec!["fresh"]);
+    }
+
+    #[test]
+    fn test_file_follower_reads_in_bounded_chunks() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("out.log");
+        let line = "x".repeat(99);
+        let count = 15_000; // 1.5 MB, more than one read's worth
+        std::fs::write(&path, format!("{}\n", line).repeat(count)).unwrap();
+
+        let mut follower = FileFollower::from_start(&path);
+        let first = follower.read_new().unwrap();
+        assert!(!first.is_empty() && first.len() < count);
+        assert_eq!(follower.offset, FOLLOW_READ_LIMIT);
+
+        let mut lines = first;
+        lines.extend(follower.read_new().unwrap());
+        assert_eq!(lines.len(), count);
+        assert!(lines.iter().all(|l| *l == line));
+        assert!(follower.read_new().unwrap().is_empty());
+
+        // A line longer than a read comes out in pieces
+        std::fs::write(&path, "y".repeat(FOLLOW_READ_LIMIT as usize + 10) + "\n").unwrap();
+        let mut follower = FileFollower::from_start(&path);
+        assert_eq!(
+            follower.read_new().unwrap()[0].len(),
+            FOLLOW_READ_LIMIT as usize
+        );
+        assert_eq!(follower.read_new().unwrap(), vec!["y".repeat(10)]);
+    }
+
+    #[test]
+    fn test_output_follower_tags_streams() {
+        let dir = tempfile::tempdir().unwrap();
+        let paths = (dir.path().join("out.log"), dir.path().join("err.log"));
+        std::fs::write(&paths.0, "before\n").unwrap();
+        let mut follower = OutputFollower::new(7, paths.clone(), false);
+        assert_eq!(follower.pid(), 7);
+
+        std::fs::write(&paths.0, "before\nout\n").unwrap();
+        std::fs::write(&paths.1, "err\n").unwrap();
+        let lines: Vec<(LogStream, String)> = follower
+            .read_new()
+            .unwrap()
+            .into_iter()
+            .map(|l| (l.stream, l.line))
+            .collect();
+        assert_eq!(
+            lines,
+            vec![
+                (LogStream::Stdout, "out".to_string()),
+                (LogStream::Stderr, "err".to_string())
+            ]
+        );
+    }
+
+    #[test]
+    fn test_tee_lines_from_pipe() {
+        let buffer = LogBuffer::new(10);
+        let mut child = std::process::Command::new("/bin/sh")
+            .args(["-c", "echo one; echo two"])
+            .stdout(std::process::Stdio::piped())
+            .spawn()
+            .unwrap();
+        tee_lines(
+            child.stdout.take().unwrap(),
+            LogStream::Stdout,
+            None,
+            buffer.clone(),
+        );
+        child.wait().unwrap();
+
+        // The reader thread finishes shortly after the pipe closes
+        for _ in 0..50 {
+            if buffer.lines().len() == 2 {
+                break;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(10));
+        }
+        let lines: Vec<String> = buffer.lines().into_iter().map(|l| l.line).collect();
+        assert_eq!(lines, vec!["one", "two"]);
+    }
+}
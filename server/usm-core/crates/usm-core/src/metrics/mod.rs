@@ -1,5 +1,9 @@
 //! Resource metrics collection
 
+use std::collections::{HashMap, VecDeque};
+use std::sync::Mutex;
+
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use sysinfo::LoadAvg;
 
@@ -61,11 +65,39 @@ pub struct SystemMetrics {
     /// Memory usage percentage
     pub memory_percent: f64,
 
-    /// Load average (1, 5, 15 minutes)
-    #[serde(skip)]
+    /// Load average (1, 5, 15 minutes), serialized as `[one, five, fifteen]`
+    #[serde(with = "load_average_array", default)]
     pub load_average: LoadAvg,
 }
 
+/// The system load average, or zeros where the platform doesn't report one
+pub fn load_average() -> LoadAvg {
+    let load = sysinfo::System::load_average();
+    if [load.one, load.five, load.fifteen]
+        .iter()
+        .all(|v| v.is_finite() && *v >= 0.0)
+    {
+        load
+    } else {
+        LoadAvg::default()
+    }
+}
+
+/// (De)serialize a `LoadAvg` as a `[f64; 3]`
+mod load_average_array {
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+    use sysinfo::LoadAvg;
+
+    pub fn serialize<S: Serializer>(load: &LoadAvg, serializer: S) -> Result<S::Ok, S::Error> {
+        [load.one, load.five, load.fifteen].serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<LoadAvg, D::Error> {
+        let [one, five, fifteen] = <[f64; 3]>::deserialize(deserializer)?;
+        Ok(LoadAvg { one, five, fifteen })
+    }
+}
+
 impl SystemMetrics {
     /// Get total memory in gigabytes
     pub fn memory_total_gb(&self) -> f64 {
@@ -100,11 +132,7 @@ impl Default for SystemMetrics {
             memory_total_bytes: 0,
             memory_used_bytes: 0,
             memory_percent: 0.0,
-            load_average: LoadAvg {
-                one: 0.0,
-                five: 0.0,
-                fifteen: 0.0,
-            },
+            load_average: LoadAvg::default(),
         }
     }
 }
@@ -131,10 +159,311 @@ pub struct MetricsSummary {
     pub total_instance_memory: u64,
 }
 
+/// An instance as it appears in the Prometheus output
+#[derive(Debug, Clone)]
+pub struct PrometheusInstance {
+    pub instance_id: String,
+    pub template_id: String,
+    pub up: bool,
+    /// Usage, if the instance is running and could be measured
+    pub metrics: Option<InstanceMetrics>,
+}
+
+/// Reads one gauge's value off an instance (None: no sample)
+type InstanceValue = fn(&PrometheusInstance) -> Option<f64>;
+
+/// Render metrics in the Prometheus text exposition format
+pub fn prometheus_text(system: &SystemMetrics, instances: &[PrometheusInstance]) -> String {
+    let mut out = String::new();
+
+    let system_gauges = [
+        (
+            "usm_system_cpu_percent",
+            "System-wide CPU usage percentage",
+            system.cpu_percent,
+        ),
+        (
+            "usm_system_memory_used_bytes",
+            "Used system memory in bytes",
+            system.memory_used_bytes as f64,
+        ),
+        (
+            "usm_system_memory_total_bytes",
+            "Total system memory in bytes",
+            system.memory_total_bytes as f64,
+        ),
+        (
+            "usm_system_load1",
+            "1 minute load average",
+            system.load_average.one,
+        ),
+        (
+            "usm_system_load5",
+            "5 minute load average",
+            system.load_average.five,
+        ),
+        (
+            "usm_system_load15",
+            "15 minute load average",
+            system.load_average.fifteen,
+        ),
+    ];
+    for (name, help, value) in system_gauges {
+        write_header(&mut out, name, help);
+        out.push_str(&format!("{} {}\n", name, value));
+    }
+
+    let instance_gauges: [(&str, &str, InstanceValue); 3] = [
+        (
+            "usm_instance_up",
+            "Whether the instance is running (1) or not (0)",
+            |i| Some(if i.up { 1.0 } else { 0.0 }),
+        ),
+        (
+            "usm_instance_cpu_percent",
+            "Instance CPU usage percentage",
+            |i| i.metrics.as_ref().map(|m| m.cpu_percent),
+        ),
+        (
+            "usm_instance_memory_bytes",
+            "Instance memory usage in bytes",
+            |i| i.metrics.as_ref().map(|m| m.memory_bytes as f64),
+        ),
+    ];
+    for (name, help, value) in instance_gauges {
+        write_header(&mut out, name, help);
+        for instance in instances {
+            if let Some(value) = value(instance) {
+                out.push_str(&format!(
+                    "{}{{instance=\"{}\",template=\"{}\"}} {}\n",
+                    name,
+                    escape_label(&instance.instance_id),
+                    escape_label(&instance.template_id),
+                    value
+                ));
+            }
+        }
+    }
+
+    out
+}
+
+fn write_header(out: &mut String, name: &str, help: &str) {
+    out.push_str(&format!(
+        "# HELP {} {}\n# TYPE {} gauge\n",
+        name, help, name
+    ));
+}
+
+/// Escape a label value (backslash, double quote and newline)
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// One timestamped usage sample, for sparklines
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MetricsSample {
+    pub timestamp: DateTime<Utc>,
+    pub cpu_percent: f64,
+    pub memory_bytes: u64,
+}
+
+/// Recent samples per instance and for the whole system
+///
+/// Each series is a ring buffer of at most `capacity` samples, so memory is
+/// bounded by the number of instances. Fed by the metrics publisher.
+#[derive(Debug)]
+pub struct MetricsHistory {
+    capacity: usize,
+    system: Mutex<VecDeque<MetricsSample>>,
+    instances: Mutex<HashMap<String, VecDeque<MetricsSample>>>,
+}
+
+impl MetricsHistory {
+    /// Samples kept per series: 25 minutes at the default 5s interval
+    pub const DEFAULT_CAPACITY: usize = 300;
+
+    /// Create a history keeping `capacity` samples per series
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            system: Mutex::new(VecDeque::new()),
+            instances: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Append a system-wide sample
+    pub fn record_system(&self, sample: MetricsSample) {
+        if let Ok(mut system) = self.system.lock() {
+            push_bounded(&mut system, sample, self.capacity);
+        }
+    }
+
+    /// Append a sample for an instance
+    pub fn record_instance(&self, instance_id: &str, sample: MetricsSample) {
+        if let Ok(mut instances) = self.instances.lock() {
+            let series = instances.entry(instance_id.to_string()).or_default();
+            push_bounded(series, sample, self.capacity);
+        }
+    }
+
+    /// System-wide samples, oldest first
+    pub fn system(&self) -> Vec<MetricsSample> {
+        self.system
+            .lock()
+            .map(|system| system.iter().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    /// An instance's samples, oldest first (empty if none were recorded)
+    pub fn instance(&self, instance_id: &str) -> Vec<MetricsSample> {
+        self.instances
+            .lock()
+            .ok()
+            .and_then(|instances| {
+                instances
+                    .get(instance_id)
+                    .map(|series| series.iter().cloned().collect())
+            })
+            .unwrap_or_default()
+    }
+
+    /// Drop an instance's history
+    pub fn remove(&self, instance_id: &str) {
+        if let Ok(mut instances) = self.instances.lock() {
+            instances.remove(instance_id);
+        }
+    }
+
+    /// Keep only the history of instances for which `keep` returns true
+    pub fn retain(&self, keep: impl Fn(&str) -> bool) {
+        if let Ok(mut instances) = self.instances.lock() {
+            instances.retain(|id, _| keep(id));
+        }
+    }
+}
+
+impl Default for MetricsHistory {
+    fn default() -> Self {
+        Self::new(Self::DEFAULT_CAPACITY)
+    }
+}
+
+fn push_bounded(series: &mut VecDeque<MetricsSample>, sample: MetricsSample, capacity: usize) {
+    if capacity == 0 {
+        return;
+    }
+    while series.len() >= capacity {
+        series.pop_front();
+    }
+    series.push_back(sample);
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_load_average_serialization() {
+        let system = SystemMetrics {
+            load_average: LoadAvg {
+                one: 1.5,
+                five: 0.75,
+                fifteen: 0.25,
+            },
+            ..Default::default()
+        };
+        let json = serde_json::to_value(&system).unwrap();
+        assert_eq!(json["load_average"], serde_json::json!([1.5, 0.75, 0.25]));
+
+        let parsed: SystemMetrics = serde_json::from_value(json).unwrap();
+        assert_eq!(parsed.load_average_tuple(), (1.5, 0.75, 0.25));
+
+        // Older payloads without the field still parse
+        let parsed: SystemMetrics = serde_json::from_str(
+            r#"{"cpu_percent": 0, "memory_total_bytes": 0, "memory_used_bytes": 0, "memory_percent": 0}"#,
+        )
+        .unwrap();
+        assert_eq!(parsed.load_average_tuple(), (0.0, 0.0, 0.0));
+
+        let load = load_average();
+        assert!([load.one, load.five, load.fifteen]
+            .iter()
+            .all(|v| v.is_finite() && *v >= 0.0));
+    }
+
+    #[test]
+    fn test_prometheus_text() {
+        let system = SystemMetrics {
+            cpu_percent: 12.5,
+            memory_total_bytes: 1000,
+            memory_used_bytes: 400,
+            ..Default::default()
+        };
+        let instances = [
+            PrometheusInstance {
+                instance_id: "api".to_string(),
+                template_id: "svc".to_string(),
+                up: true,
+                metrics: Some(InstanceMetrics {
+                    cpu_percent: 2.5,
+                    memory_bytes: 2048,
+                    memory_percent: 0.1,
+                    threads: 1,
+                    open_files: 0,
+                    uptime_seconds: 0,
+                }),
+            },
+            PrometheusInstance {
+                instance_id: "odd\"id".to_string(),
+                template_id: "svc".to_string(),
+                up: false,
+                metrics: None,
+            },
+        ];
+
+        let text = prometheus_text(&system, &instances);
+        assert!(text.contains("# TYPE usm_system_cpu_percent gauge\nusm_system_cpu_percent 12.5\n"));
+        assert!(text.contains("usm_system_memory_used_bytes 400\n"));
+        assert!(text.contains("usm_instance_up{instance=\"api\",template=\"svc\"} 1\n"));
+        assert!(text.contains("usm_instance_up{instance=\"odd\\\"id\",template=\"svc\"} 0\n"));
+        assert!(text.contains("usm_instance_cpu_percent{instance=\"api\",template=\"svc\"} 2.5\n"));
+        assert!(
+            text.contains("usm_instance_memory_bytes{instance=\"api\",template=\"svc\"} 2048\n")
+        );
+        // Stopped instances have no usage samples
+        assert!(!text.contains("usm_instance_cpu_percent{instance=\"odd"));
+    }
+
+    #[test]
+    fn test_metrics_history_is_bounded() {
+        let history = MetricsHistory::new(3);
+        let sample = |cpu: f64| MetricsSample {
+            timestamp: Utc::now(),
+            cpu_percent: cpu,
+            memory_bytes: 0,
+        };
+        for cpu in 0..5 {
+            history.record_instance("api", sample(cpu as f64));
+            history.record_system(sample(cpu as f64));
+        }
+        history.record_instance("worker", sample(9.0));
+
+        let cpu = |samples: Vec<MetricsSample>| -> Vec<f64> {
+            samples.iter().map(|s| s.cpu_percent).collect()
+        };
+        assert_eq!(cpu(history.instance("api")), vec![2.0, 3.0, 4.0]);
+        assert_eq!(cpu(history.system()), vec![2.0, 3.0, 4.0]);
+
+        history.remove("api");
+        assert!(history.instance("api").is_empty());
+        history.retain(|id| id != "worker");
+        assert!(history.instance("worker").is_empty());
+    }
+
     #[test]
     fn test_instance_metrics() {
         let metrics = InstanceMetrics {
@@ -1,9 +1,20 @@
 //! Process monitor trait - abstraction over platform-specific implementations
 
-use std::path::Path;
+use std::collections::HashMap;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{LockResult, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
 
 use anyhow::Result;
+use sysinfo::{Pid, System};
+use tracing::{debug, debug_span, warn};
 
+use super::Signal;
+use crate::docker::{ComposeProject, ContainerState};
+use crate::logs::{self, CapturedLogs, LogBuffer};
 use crate::metrics::{InstanceMetrics, SystemMetrics};
 
 /// Information about a process
@@ -14,6 +25,39 @@ pub struct ProcessInfo {
     pub cpu_percent: f64,
     pub memory_bytes: u64,
     pub threads: u32,
+    /// Protocol of the socket the process was found by (port lookups only)
+    pub protocol: Option<Protocol>,
+}
+
+/// Transport protocol of a bound port
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Protocol {
+    Tcp,
+    Udp,
+}
+
+/// Options for spawning a service process
+#[derive(Debug, Clone, Default)]
+pub struct SpawnOptions {
+    /// Directory to run the command in
+    pub working_dir: Option<PathBuf>,
+
+    /// Port the service listens on (used for fallback PID detection)
+    pub port: Option<u16>,
+
+    /// Buffer to tee stdout/stderr lines into, if in-memory capture is enabled
+    pub log_buffer: Option<LogBuffer>,
+
+    /// Files to capture stdout and stderr to, replacing their contents,
+    /// instead of the per-PID files at `log_paths`
+    pub log_files: Option<(PathBuf, PathBuf)>,
+
+    /// Environment variables set on top of USM's own environment
+    pub env: HashMap<String, String>,
+
+    /// Longest wait for the process to be confirmed running, for monitors
+    /// that verify spawns (their own default if None)
+    pub startup_verify: Option<Duration>,
 }
 
 /// Trait for platform-specific process monitoring
@@ -23,12 +67,46 @@ pub struct ProcessInfo {
 pub trait ProcessMonitor: Send + Sync {
     /// Find a process by the port it's listening on
     ///
-    /// Returns the process info if found, None otherwise.
+    /// Returns the process info if found, None otherwise. Implementations
+    /// that can see UDP sockets try them when no TCP listener matches, and
+    /// report which protocol did in `ProcessInfo::protocol`.
     fn find_by_port(&self, port: u16) -> Option<ProcessInfo>;
 
+    /// Find a process bound to a port over one protocol
+    ///
+    /// The default only knows TCP listeners, via `find_by_port`.
+    fn find_by_port_proto(&self, port: u16, protocol: Protocol) -> Option<ProcessInfo> {
+        match protocol {
+            Protocol::Tcp => self
+                .find_by_port(port)
+                .filter(|info| info.protocol != Some(Protocol::Udp)),
+            Protocol::Udp => None,
+        }
+    }
+
+    /// Whether any process is listening on a TCP port
+    ///
+    /// Unlike `find_by_port` this doesn't need to see the owning process, so
+    /// it also notices listeners owned by other users. The default falls back
+    /// to `find_by_port`.
+    fn is_port_in_use(&self, port: u16) -> bool {
+        self.find_by_port(port).is_some()
+    }
+
     /// Get metrics for a specific process by PID
     fn get_process_metrics(&self, pid: u32) -> Option<InstanceMetrics>;
 
+    /// Metrics for several processes at once, keyed by PID
+    ///
+    /// PIDs that aren't running are left out. The default asks
+    /// `get_process_metrics` for each one; backends that refresh their
+    /// process table per call override it to refresh only once.
+    fn get_processes_metrics(&self, pids: &[u32]) -> HashMap<u32, InstanceMetrics> {
+        pids.iter()
+            .filter_map(|&pid| Some((pid, self.get_process_metrics(pid)?)))
+            .collect()
+    }
+
     /// Get system-wide metrics (CPU, memory, etc.)
     fn get_system_metrics(&self) -> SystemMetrics;
 
@@ -51,15 +129,616 @@ pub trait ProcessMonitor: Send + Sync {
         self.start_process(command, working_dir)
     }
 
+    /// Start a process with the full set of spawn options
+    ///
+    /// Backends that support output capture override this; the default
+    /// ignores the log buffer.
+    fn spawn(&self, command: &str, options: &SpawnOptions) -> Result<u32> {
+        self.start_process_with_port(command, options.working_dir.as_deref(), options.port)
+    }
+
     /// Kill a process by PID
+    ///
+    /// Backends that spawn services in their own process group (see
+    /// `process_group`) kill the whole group, so children the service forked
+    /// don't outlive it.
     fn kill_process(&self, pid: u32) -> Result<()>;
 
+    /// Process group this monitor spawned `pid` in, while `pid` is still in it
+    ///
+    /// The default tracks no groups.
+    fn process_group(&self, _pid: u32) -> Option<u32> {
+        None
+    }
+
+    /// Send a signal to a process, or to its whole process group
+    ///
+    /// The default implementation uses `/bin/kill`, which is available on
+    /// every supported Unix platform.
+    fn signal_process(&self, pid: u32, signal: Signal, group: bool) -> Result<()> {
+        let target = if group {
+            // Look up the process group so children spawned by the service
+            // receive the signal too
+            let pgid = current_pgid(pid)
+                .ok_or_else(|| anyhow::anyhow!("Could not determine process group of {}", pid))?;
+            format!("-{}", pgid)
+        } else {
+            pid.to_string()
+        };
+
+        debug!(pid = pid, signal = %signal, group = group, "Sending signal");
+
+        let status = Command::new("/bin/kill")
+            .args(["-s", signal.name(), "--", &target])
+            .status()?;
+
+        if !status.success() {
+            anyhow::bail!("Failed to send {} to {}", signal, target);
+        }
+
+        Ok(())
+    }
+
     /// Execute a command (for custom stop commands)
     fn execute_command(&self, command: &str) -> Result<()>;
 
+    /// Current state of a Docker instance's containers (`docker compose ps`)
+    fn compose_containers(&self, project: &ComposeProject) -> Result<Vec<ContainerState>> {
+        project.containers()
+    }
+
     /// Check if a process is still running
     fn is_running(&self, pid: u32) -> bool;
 
+    /// Whether a process is gone for good, e.g. after being signalled
+    ///
+    /// Unlike `is_running`, backends count exited-but-unreaped (zombie)
+    /// processes as gone, since signals can't affect them anymore.
+    fn has_exited(&self, pid: u32) -> bool {
+        !self.is_running(pid)
+    }
+
+    /// Whether the service spawned as `pid` is gone, with all it forked
+    ///
+    /// `pid` is the spawn wrapper, which leads the service's process group
+    /// but exits (lingering as a zombie) as soon as the service is running.
+    /// So while the group is known (see `process_group`), the service lives
+    /// as long as any member of it does. The default checks `pid` alone with
+    /// `has_exited`.
+    fn service_exited(&self, pid: u32) -> bool {
+        match self.process_group(pid).and_then(group_alive) {
+            Some(alive) => !alive,
+            None => self.has_exited(pid),
+        }
+    }
+
+    /// When a running process started, in seconds since the Unix epoch
+    ///
+    /// Together with the PID this identifies a process: a recycled PID
+    /// belongs to one that started later. None if the process isn't running
+    /// or the backend can't tell.
+    fn process_start_time(&self, _pid: u32) -> Option<u64> {
+        None
+    }
+
+    /// Whether a process has exited but not been reaped by its parent
+    /// (state `Z`, shown as `<defunct>` by ps)
+    ///
+    /// The default knows no zombies.
+    fn is_zombie(&self, _pid: u32) -> bool {
+        false
+    }
+
+    /// PIDs of spawn wrappers this monitor started and hasn't reaped yet
+    fn children(&self) -> Vec<u32> {
+        Vec::new()
+    }
+
+    /// Reap spawn wrappers that have exited, except those in `keep`
+    ///
+    /// Returns the PIDs reaped. Callers pass the PIDs instances still track:
+    /// where the wrapper's PID is the one returned by `spawn`, reaping it
+    /// would let the PID be reused while USM still watches it.
+    fn reap_children(&self, _keep: &[u32]) -> Vec<u32> {
+        Vec::new()
+    }
+
     /// Get a list of all processes matching a pattern
     fn find_by_name(&self, pattern: &str) -> Vec<ProcessInfo>;
+
+    /// Exit code of a spawned process that has exited, if it was recorded
+    ///
+    /// Spawn wrappers write it to `exit_status_path(pid)` when the service
+    /// exits; None while it's still running or when nothing was recorded
+    /// (e.g. the process was killed along with its wrapper).
+    fn exit_code(&self, pid: u32) -> Option<i32> {
+        std::fs::read_to_string(exit_status_path(pid))
+            .ok()
+            .and_then(|code| code.trim().parse().ok())
+    }
+
+    /// Delete the exit code recorded for a spawned process, once it has been
+    /// read or nothing will read it anymore
+    fn remove_exit_status(&self, pid: u32) {
+        let _ = std::fs::remove_file(exit_status_path(pid));
+    }
+
+    /// The last `lines` lines of a spawned process's captured stdout/stderr
+    ///
+    /// Reads the files at `log_paths(pid)`, which stay around after the
+    /// process exits.
+    fn read_logs(&self, pid: u32, lines: usize) -> Result<CapturedLogs> {
+        let paths = log_paths(pid);
+        if !paths.0.exists() && !paths.1.exists() {
+            anyhow::bail!("No captured output for process {}", pid);
+        }
+        logs::read_captured(&paths, lines)
+    }
+
+    /// Delete the output captured for a spawned process at `log_paths(pid)`
+    ///
+    /// For once nothing shows it anymore: its instance has started again
+    /// or was removed.
+    fn remove_logs(&self, pid: u32) {
+        let (stdout, stderr) = log_paths(pid);
+        for path in [stdout, stderr] {
+            if let Err(e) = std::fs::remove_file(&path) {
+                if e.kind() != std::io::ErrorKind::NotFound {
+                    debug!("Could not remove {}: {}", path.display(), e);
+                }
+            }
+        }
+    }
+}
+
+/// Files a spawned process's stdout and stderr are captured to
+pub fn log_paths(pid: u32) -> (PathBuf, PathBuf) {
+    let dir = std::env::temp_dir();
+    (
+        dir.join(format!("usm-{}-stdout.log", pid)),
+        dir.join(format!("usm-{}-stderr.log", pid)),
+    )
+}
+
+/// Files an instance's stdout and stderr are captured to when a log
+/// directory is configured
+///
+/// `{dir}/{instance_id}.stdout.log` and `.stderr.log`; instances in a group
+/// get a subdirectory per group.
+pub fn instance_log_paths(dir: &Path, instance_id: &str) -> (PathBuf, PathBuf) {
+    (
+        dir.join(format!("{}.stdout.log", instance_id)),
+        dir.join(format!("{}.stderr.log", instance_id)),
+    )
+}
+
+/// A temp file name no other spawn, in this or another USM process, uses
+pub(crate) fn unique_temp_path(suffix: &str) -> PathBuf {
+    static NEXT: AtomicU64 = AtomicU64::new(0);
+    let n = NEXT.fetch_add(1, Ordering::Relaxed);
+    std::env::temp_dir().join(format!("usm-{}-{}-{}", std::process::id(), n, suffix))
+}
+
+/// File a spawn wrapper writes the service's exit code to
+///
+/// `pid` is usually a number, but can be a shell parameter (`$!`, `$$`) for
+/// use inside the wrapper script itself.
+pub fn exit_status_path(pid: impl std::fmt::Display) -> PathBuf {
+    std::env::temp_dir().join(format!("usm-{}-exit", pid))
+}
+
+/// A spawned process that died before its start could be verified
+///
+/// Returned (through `anyhow`) by backends that verify spawns, so callers
+/// can downcast it to record the exit code.
+#[derive(Debug, Clone)]
+pub struct StartupFailed {
+    pub pid: u32,
+    pub exit_code: Option<i32>,
+    /// File the process's stderr was captured to, if it ran at all
+    pub stderr_log: Option<PathBuf>,
+}
+
+impl std::fmt::Display for StartupFailed {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.pid == 0 {
+            // Neither the wrapper's PID file nor the port turned it up
+            write!(f, "Process started but was never found running")?;
+        } else {
+            write!(f, "Process {} started but immediately died", self.pid)?;
+        }
+        if let Some(code) = self.exit_code {
+            write!(f, " with exit code {}", code)?;
+        }
+        if let Some(path) = &self.stderr_log {
+            write!(f, " (output in {})", path.display())?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for StartupFailed {}
+
+/// Output capture files for a process that's about to be spawned
+///
+/// Without fixed `log_files`, the PID isn't known until after the spawn, so
+/// output goes to uniquely named files first, which `claim` then renames to
+/// `log_paths(pid)`. Renaming doesn't disturb the process's open handles.
+pub(crate) struct CaptureFiles {
+    stdout: PathBuf,
+    stderr: PathBuf,
+    /// The files are the instance's own (`SpawnOptions::log_files`)
+    fixed: bool,
+}
+
+impl CaptureFiles {
+    /// Create the files, returning handles to write output to
+    pub(crate) fn create(log_files: Option<&(PathBuf, PathBuf)>) -> Result<(Self, File, File)> {
+        let files = match log_files {
+            Some((stdout, stderr)) => {
+                for parent in [stdout.parent(), stderr.parent()].into_iter().flatten() {
+                    std::fs::create_dir_all(parent)?;
+                }
+                Self {
+                    stdout: stdout.clone(),
+                    stderr: stderr.clone(),
+                    fixed: true,
+                }
+            },
+            None => Self {
+                stdout: unique_temp_path("pending-stdout.log"),
+                stderr: unique_temp_path("pending-stderr.log"),
+                fixed: false,
+            },
+        };
+        let stdout = File::create(&files.stdout)?;
+        let stderr = File::create(&files.stderr)?;
+        Ok((files, stdout, stderr))
+    }
+
+    /// Where stderr ends up once the files are claimed for `pid`
+    ///
+    /// For `StartupFailed`, which only backends that verify spawns return.
+    #[cfg_attr(not(target_os = "macos"), allow(dead_code))]
+    pub(crate) fn stderr_path(&self, pid: u32) -> PathBuf {
+        if self.fixed {
+            self.stderr.clone()
+        } else {
+            log_paths(pid).1
+        }
+    }
+
+    /// Move the files to `log_paths(pid)`, replacing those of an earlier
+    /// process with the same PID; fixed files stay where they are
+    pub(crate) fn claim(self, pid: u32) {
+        if self.fixed {
+            return;
+        }
+        let (stdout, stderr) = log_paths(pid);
+        for (from, to) in [(&self.stdout, stdout), (&self.stderr, stderr)] {
+            if let Err(e) = std::fs::rename(from, &to) {
+                warn!(
+                    pid = pid,
+                    "Could not move captured output to {}: {}",
+                    to.display(),
+                    e
+                );
+            }
+        }
+    }
+
+    /// Delete the temp files, for spawns that produced no process
+    pub(crate) fn discard(self) {
+        if self.fixed {
+            return;
+        }
+        let _ = std::fs::remove_file(&self.stdout);
+        let _ = std::fs::remove_file(&self.stderr);
+    }
+}
+
+/// Spawn wrappers kept around so they can be reaped once they exit
+///
+/// Dropping a `Child` doesn't wait for it, so without this every exited
+/// wrapper stays a zombie until USM exits.
+#[derive(Debug, Default)]
+pub(crate) struct SpawnedChildren(Mutex<Vec<Child>>);
+
+impl SpawnedChildren {
+    pub(crate) fn push(&self, child: Child) {
+        self.0.lock().unwrap().push(child);
+    }
+
+    pub(crate) fn pids(&self) -> Vec<u32> {
+        self.0.lock().unwrap().iter().map(Child::id).collect()
+    }
+
+    /// Wait on every exited child not in `keep`, returning their PIDs
+    ///
+    /// No instance tracks a reaped wrapper's PID anymore, so whatever exit
+    /// code its service recorded is deleted along with it.
+    pub(crate) fn reap(&self, keep: &[u32]) -> Vec<u32> {
+        let mut reaped = Vec::new();
+        self.0.lock().unwrap().retain_mut(|child| {
+            if keep.contains(&child.id()) {
+                return true;
+            }
+            match child.try_wait() {
+                Ok(None) => true,
+                Ok(Some(_)) | Err(_) => {
+                    let _ = std::fs::remove_file(exit_status_path(child.id()));
+                    reaped.push(child.id());
+                    false
+                },
+            }
+        });
+        reaped
+    }
+}
+
+/// Process groups spawned services were started in
+///
+/// Spawn wrappers lead a new process group, which the service and whatever
+/// it forks (e.g. the file watcher of a dev server) inherit. Maps the PID
+/// `spawn` returned to that group.
+#[derive(Debug, Default)]
+pub(crate) struct ProcessGroups(Mutex<HashMap<u32, u32>>);
+
+impl ProcessGroups {
+    pub(crate) fn insert(&self, pid: u32, pgid: u32) {
+        self.0.lock().unwrap().insert(pid, pgid);
+    }
+
+    /// The group `pid` was spawned in, if the process is still a member
+    ///
+    /// Checked against the process table, so a recycled PID is never
+    /// mistaken for the service that used to have it.
+    pub(crate) fn get(&self, pid: u32) -> Option<u32> {
+        let pgid = *self.0.lock().unwrap().get(&pid)?;
+        (current_pgid(pid) == Some(pgid)).then_some(pgid)
+    }
+
+    /// Forget the groups of every PID not in `keep`
+    pub(crate) fn retain(&self, keep: &[u32]) {
+        self.0.lock().unwrap().retain(|pid, _| keep.contains(pid));
+    }
+}
+
+/// How often monitors refresh their process table by default
+pub const DEFAULT_REFRESH_INTERVAL: Duration = Duration::from_millis(500);
+
+/// sysinfo's process table, refreshed at most once per interval
+///
+/// A full refresh reads every process on the host, and the metrics loop,
+/// the dashboard and every status check all ask for one. Lookups of a PID
+/// the cached table doesn't have yet (a service spawned since the last
+/// refresh) re-read just that process, so they're never missed.
+pub(crate) struct CachedSystem {
+    system: Mutex<System>,
+    interval: Duration,
+    refreshed: Mutex<Option<Instant>>,
+}
+
+impl CachedSystem {
+    pub(crate) fn new(interval: Duration) -> Self {
+        Self {
+            system: Mutex::new(System::new_all()),
+            interval,
+            refreshed: Mutex::new(Some(Instant::now())),
+        }
+    }
+
+    /// The process table, refreshed first if it's older than the interval
+    pub(crate) fn lock(&self) -> LockResult<MutexGuard<'_, System>> {
+        self.lock_with(&[])
+    }
+
+    /// Like `lock`, also reading any of `pids` the table doesn't have
+    pub(crate) fn lock_with(&self, pids: &[u32]) -> LockResult<MutexGuard<'_, System>> {
+        let mut system = self.system.lock()?;
+        let mut refreshed = self.refreshed.lock().unwrap();
+        if refreshed.map_or(true, |at| at.elapsed() >= self.interval) {
+            system.refresh_all();
+            *refreshed = Some(Instant::now());
+        }
+        for &pid in pids {
+            if system.process(Pid::from_u32(pid)).is_none() {
+                system.refresh_process(Pid::from_u32(pid));
+            }
+        }
+        Ok(system)
+    }
+
+    /// The process table with just `pid` re-read, skipping the full refresh
+    ///
+    /// None if the process is gone (its stale entry can linger in the
+    /// table) or the lock is poisoned.
+    pub(crate) fn lock_process(&self, pid: u32) -> Option<MutexGuard<'_, System>> {
+        let mut system = self.system.lock().ok()?;
+        system.refresh_process(Pid::from_u32(pid)).then_some(system)
+    }
+
+    /// Whether a process exists, read now rather than from the cache
+    ///
+    /// None if the table's lock is poisoned.
+    pub(crate) fn is_alive(&self, pid: u32) -> Option<bool> {
+        let mut system = self.system.lock().ok()?;
+        Some(system.refresh_process(Pid::from_u32(pid)))
+    }
+
+    /// Make the next `lock` refresh the whole table, e.g. after a spawn or
+    /// kill changed it
+    pub(crate) fn invalidate(&self) {
+        *self.refreshed.lock().unwrap() = None;
+    }
+}
+
+/// A process's current process group, from ps
+pub(crate) fn current_pgid(pid: u32) -> Option<u32> {
+    let output = Command::new("ps")
+        .args(["-o", "pgid=", "-p", &pid.to_string()])
+        .output()
+        .ok()?;
+    String::from_utf8_lossy(&output.stdout).trim().parse().ok()
+}
+
+/// Whether process group `pgid` has a member that isn't a zombie, from ps
+///
+/// None if ps couldn't be run.
+pub(crate) fn group_alive(pgid: u32) -> Option<bool> {
+    let output = Command::new("ps")
+        .args(["-A", "-o", "pgid=,stat="])
+        .output()
+        .ok()?;
+    let alive = String::from_utf8_lossy(&output.stdout).lines().any(|line| {
+        let mut fields = line.split_whitespace();
+        fields.next().and_then(|g| g.parse().ok()) == Some(pgid)
+            && fields.next().is_some_and(|stat| !stat.starts_with('Z'))
+    });
+    Some(alive)
+}
+
+/// SIGTERM a process, or its whole group when `pgid` is known, falling back
+/// to SIGKILL if the signal can't be delivered
+pub(crate) fn kill_process_or_group(pid: u32, pgid: Option<u32>) -> Result<()> {
+    let target = match pgid {
+        Some(pgid) => format!("-{}", pgid),
+        None => pid.to_string(),
+    };
+    debug!(pid = pid, pgid = ?pgid, "Killing process");
+
+    // First try SIGTERM
+    let status = Command::new("/bin/kill")
+        .args(["-TERM", "--", &target])
+        .status()?;
+
+    if !status.success() {
+        warn!(pid = pid, "SIGTERM failed, trying SIGKILL");
+        Command::new("/bin/kill")
+            .args(["-KILL", "--", &target])
+            .status()?;
+    }
+
+    Ok(())
+}
+
+/// Run one phase of a process spawn inside a `spawn_phase` span
+///
+/// Emits a debug event with the phase's wall-clock duration, so `--verbose`
+/// output breaks a slow start down into e.g. `verify_sleep elapsed_ms=3000`.
+pub(crate) fn timed_phase<T>(phase: &'static str, f: impl FnOnce() -> T) -> T {
+    let span = debug_span!("spawn_phase", phase);
+    let _enter = span.enter();
+    let start = Instant::now();
+    let result = f();
+    debug!(
+        phase,
+        elapsed_ms = start.elapsed().as_millis() as u64,
+        "Spawn phase finished"
+    );
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use std::sync::{Arc, Mutex};
+
+    /// Writer that collects formatted tracing output in memory
+    #[derive(Clone, Default)]
+    struct CaptureWriter(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for CaptureWriter {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_spawned_children_reap() {
+        let children = SpawnedChildren::default();
+        let sleeper = Command::new("sleep").arg("30").spawn().unwrap();
+        let sleeper_pid = sleeper.id();
+        let kept = Command::new("true").spawn().unwrap();
+        let kept_pid = kept.id();
+        let quick = Command::new("true").spawn().unwrap();
+        let quick_pid = quick.id();
+        for child in [sleeper, kept, quick] {
+            children.push(child);
+        }
+        std::thread::sleep(std::time::Duration::from_millis(200));
+
+        // Running children and kept ones stay, exited ones are reaped
+        assert_eq!(children.reap(&[kept_pid]), vec![quick_pid]);
+        assert_eq!(children.pids(), vec![sleeper_pid, kept_pid]);
+
+        Command::new("/bin/kill")
+            .arg(sleeper_pid.to_string())
+            .status()
+            .unwrap();
+        std::thread::sleep(std::time::Duration::from_millis(200));
+        assert_eq!(children.reap(&[]), vec![sleeper_pid, kept_pid]);
+        assert!(children.pids().is_empty());
+    }
+
+    #[test]
+    fn test_startup_failed_message() {
+        let died = StartupFailed {
+            pid: 4242,
+            exit_code: Some(1),
+            stderr_log: Some(PathBuf::from("/tmp/usm-4242.err")),
+        };
+        assert_eq!(
+            died.to_string(),
+            "Process 4242 started but immediately died with exit code 1 (output in /tmp/usm-4242.err)"
+        );
+
+        let unseen = StartupFailed {
+            pid: 0,
+            exit_code: None,
+            stderr_log: None,
+        };
+        assert_eq!(
+            unseen.to_string(),
+            "Process started but was never found running"
+        );
+    }
+
+    #[test]
+    fn test_timed_phase_reports_duration() {
+        let writer = CaptureWriter::default();
+        let make_writer = {
+            let writer = writer.clone();
+            move || writer.clone()
+        };
+        let subscriber = tracing_subscriber::fmt()
+            .with_max_level(tracing::Level::DEBUG)
+            .with_ansi(false)
+            .with_writer(make_writer)
+            .finish();
+
+        let value = tracing::subscriber::with_default(subscriber, || {
+            timed_phase("verify_sleep", || {
+                std::thread::sleep(std::time::Duration::from_millis(20));
+                42
+            })
+        });
+        assert_eq!(value, 42);
+
+        let output = String::from_utf8(writer.0.lock().unwrap().clone()).unwrap();
+        assert!(output.contains("spawn_phase{phase=\"verify_sleep\"}"));
+        let elapsed: u64 = output
+            .split("elapsed_ms=")
+            .nth(1)
+            .and_then(|rest| rest.split_whitespace().next())
+            .and_then(|ms| ms.parse().ok())
+            .expect("elapsed_ms field missing");
+        assert!(elapsed >= 20);
+    }
 }
@@ -2,50 +2,49 @@
 //!
 //! This module is only compiled on Linux targets.
 
+use std::collections::HashMap;
+use std::os::unix::process::CommandExt;
 use std::path::Path;
 use std::process::Command;
+use std::time::Duration;
 
 use anyhow::Result;
 use sysinfo::{Pid, System};
-use tracing::{debug, trace, warn};
-
-use super::backend::{ProcessInfo, ProcessMonitor};
+use tracing::{debug, instrument, trace};
+
+use super::backend::{
+    exit_status_path, kill_process_or_group, timed_phase, CachedSystem, CaptureFiles,
+    ProcessGroups, ProcessInfo, ProcessMonitor, Protocol, SpawnOptions, SpawnedChildren,
+    DEFAULT_REFRESH_INTERVAL,
+};
+use crate::logs::{tee_lines, LogStream};
 use crate::metrics::{InstanceMetrics, SystemMetrics};
 
 /// Linux-specific process monitor using procfs and sysinfo
 pub struct LinuxMonitor {
-    system: std::sync::Mutex<System>,
+    system: CachedSystem,
+    children: SpawnedChildren,
+    groups: ProcessGroups,
 }
 
 impl LinuxMonitor {
     /// Create a new Linux process monitor
     pub fn new() -> Self {
-        Self {
-            system: std::sync::Mutex::new(System::new_all()),
-        }
+        Self::with_refresh_interval(DEFAULT_REFRESH_INTERVAL)
     }
 
-    /// Refresh system information
-    fn refresh(&self) {
-        if let Ok(mut system) = self.system.lock() {
-            system.refresh_all();
+    /// Monitor that refreshes its process table at most once per `interval`
+    pub fn with_refresh_interval(interval: Duration) -> Self {
+        Self {
+            system: CachedSystem::new(interval),
+            children: SpawnedChildren::default(),
+            groups: ProcessGroups::default(),
         }
     }
 
-    /// Find PID listening on a port using /proc/net/tcp
-    /// Falls back to ss command if procfs parsing fails
-    fn find_pid_by_port(&self, port: u16) -> Option<u32> {
-        // Try using ss command (more reliable on Linux)
-        let output = Command::new("ss")
-            .args(["-tlnp", &format!("sport = :{}", port)])
-            .output()
-            .ok()?;
-
-        if !output.status.success() {
-            return None;
-        }
-
-        let stdout = String::from_utf8_lossy(&output.stdout);
+    /// Find the PID listening on a TCP port, or bound to a UDP one, using ss
+    fn find_pid_by_port(&self, port: u16, protocol: Protocol) -> Option<u32> {
+        let stdout = ss_listeners(port, protocol)?;
         // Parse ss output to extract PID
         for line in stdout.lines().skip(1) {
             if let Some(pid_info) = line.split("pid=").nth(1) {
@@ -60,18 +59,65 @@ impl LinuxMonitor {
     }
 }
 
+/// `ss` output for TCP listeners or bound UDP sockets on a port (header
+/// line first)
+///
+/// None if `ss` isn't available.
+fn ss_listeners(port: u16, protocol: Protocol) -> Option<String> {
+    let flags = match protocol {
+        Protocol::Tcp => "-tlnp",
+        Protocol::Udp => "-ulnp",
+    };
+    let output = Command::new("ss")
+        .args([flags, &format!("sport = :{}", port)])
+        .output()
+        .ok()?;
+
+    if !output.status.success() {
+        return None;
+    }
+    Some(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// Whether /proc/net/tcp{,6} lists a listener on a port
+fn proc_net_listening(port: u16) -> bool {
+    let entries = procfs::net::tcp()
+        .into_iter()
+        .chain(procfs::net::tcp6())
+        .flatten();
+    entries
+        .into_iter()
+        .any(|e| e.state == procfs::net::TcpState::Listen && e.local_address.port() == port)
+}
+
 impl Default for LinuxMonitor {
     fn default() -> Self {
         Self::new()
     }
 }
 
+/// Metrics of one process from an already refreshed process table
+fn process_metrics(system: &System, pid: u32) -> Option<InstanceMetrics> {
+    let process = system.process(Pid::from_u32(pid))?;
+    Some(InstanceMetrics {
+        cpu_percent: process.cpu_usage() as f64,
+        memory_bytes: process.memory(),
+        memory_percent: (process.memory() as f64 / system.total_memory() as f64) * 100.0,
+        threads: thread_count(pid),
+        open_files: open_file_count(pid),
+        uptime_seconds: process.run_time(),
+    })
+}
+
 impl ProcessMonitor for LinuxMonitor {
     fn find_by_port(&self, port: u16) -> Option<ProcessInfo> {
-        let pid = self.find_pid_by_port(port)?;
-        self.refresh();
+        self.find_by_port_proto(port, Protocol::Tcp)
+            .or_else(|| self.find_by_port_proto(port, Protocol::Udp))
+    }
 
-        let system = self.system.lock().ok()?;
+    fn find_by_port_proto(&self, port: u16, protocol: Protocol) -> Option<ProcessInfo> {
+        let pid = self.find_pid_by_port(port, protocol)?;
+        let system = self.system.lock_process(pid)?;
         let process = system.process(Pid::from_u32(pid))?;
 
         Some(ProcessInfo {
@@ -79,13 +125,21 @@ impl ProcessMonitor for LinuxMonitor {
             name: process.name().to_string(),
             cpu_percent: process.cpu_usage() as f64,
             memory_bytes: process.memory(),
-            threads: 0, // sysinfo doesn't expose thread count directly
+            threads: thread_count(pid),
+            protocol: Some(protocol),
         })
     }
 
-    fn find_by_name(&self, pattern: &str) -> Vec<ProcessInfo> {
-        self.refresh();
+    /// Any listener in `ss` output (the PID column is only filled in for our
+    /// own processes, so don't rely on it); /proc/net/tcp without `ss`
+    fn is_port_in_use(&self, port: u16) -> bool {
+        match ss_listeners(port, Protocol::Tcp) {
+            Some(stdout) => stdout.lines().skip(1).any(|line| !line.trim().is_empty()),
+            None => proc_net_listening(port),
+        }
+    }
 
+    fn find_by_name(&self, pattern: &str) -> Vec<ProcessInfo> {
         let system = self.system.lock().unwrap();
 
         system
@@ -102,30 +156,32 @@ impl ProcessMonitor for LinuxMonitor {
                 name: process.name().to_string(),
                 cpu_percent: process.cpu_usage() as f64,
                 memory_bytes: process.memory(),
-                threads: 0,
+                threads: thread_count(pid.as_u32()),
+                protocol: None,
             })
             .collect()
     }
 
     fn get_process_metrics(&self, pid: u32) -> Option<InstanceMetrics> {
-        self.refresh();
+        let system = self.system.lock_process(pid)?;
+        process_metrics(&system, pid)
+    }
 
-        let system = self.system.lock().ok()?;
-        let process = system.process(Pid::from_u32(pid))?;
+    fn process_start_time(&self, pid: u32) -> Option<u64> {
+        let system = self.system.lock_process(pid)?;
+        Some(system.process(Pid::from_u32(pid))?.start_time())
+    }
 
-        Some(InstanceMetrics {
-            cpu_percent: process.cpu_usage() as f64,
-            memory_bytes: process.memory(),
-            memory_percent: (process.memory() as f64 / system.total_memory() as f64) * 100.0,
-            threads: 0,
-            open_files: 0,
-            uptime_seconds: process.run_time(),
-        })
+    fn get_processes_metrics(&self, pids: &[u32]) -> HashMap<u32, InstanceMetrics> {
+        let Ok(system) = self.system.lock_with(pids) else {
+            return HashMap::new();
+        };
+        pids.iter()
+            .filter_map(|&pid| Some((pid, process_metrics(&system, pid)?)))
+            .collect()
     }
 
     fn get_system_metrics(&self) -> SystemMetrics {
-        self.refresh();
-
         let system = self.system.lock().unwrap();
 
         SystemMetrics {
@@ -133,47 +189,92 @@ impl ProcessMonitor for LinuxMonitor {
             memory_total_bytes: system.total_memory(),
             memory_used_bytes: system.used_memory(),
             memory_percent: (system.used_memory() as f64 / system.total_memory() as f64) * 100.0,
-            load_average: System::load_average(),
+            load_average: crate::metrics::load_average(),
         }
     }
 
     fn start_process(&self, command: &str, working_dir: Option<&Path>) -> Result<u32> {
-        debug!(command = %command, working_dir = ?working_dir, "Starting process");
+        self.spawn(
+            command,
+            &SpawnOptions {
+                working_dir: working_dir.map(Path::to_path_buf),
+                ..Default::default()
+            },
+        )
+    }
 
-        let mut cmd = Command::new("/bin/bash");
-        cmd.args(["-c", &format!("{} &", command)]);
+    #[instrument(skip(self, options), fields(port = ?options.port))]
+    fn spawn(&self, command: &str, options: &SpawnOptions) -> Result<u32> {
+        debug!(command = %command, working_dir = ?options.working_dir, "Starting process");
 
-        if let Some(dir) = working_dir {
+        // The background subshell records the service's exit code, replacing
+        // any left over from an earlier process with the same PID. `$$` is
+        // the wrapper's PID, i.e. the one returned below.
+        let exit_file = exit_status_path("$$");
+        let mut cmd = Command::new("/bin/bash");
+        cmd.args([
+            "-c",
+            &format!(
+                "rm -f \"{0}\"; ( ( {1}\n); echo $? > \"{0}\" ) &",
+                exit_file.display(),
+                command
+            ),
+        ]);
+
+        if let Some(dir) = &options.working_dir {
             cmd.current_dir(dir);
         }
+        cmd.envs(&options.env);
+
+        // Lead a new process group (the wrapper's PID), so stopping the
+        // service also stops whatever it forked
+        cmd.process_group(0);
+
+        // Capture stdout/stderr to files (see log_paths, or the instance's
+        // own log_files), teeing them into
+        // the in-memory buffer when that's enabled
+        let (capture, stdout_log, stderr_log) = CaptureFiles::create(options.log_files.as_ref())?;
+        if options.log_buffer.is_some() {
+            cmd.stdout(std::process::Stdio::piped());
+            cmd.stderr(std::process::Stdio::piped());
+        } else {
+            cmd.stdout(std::process::Stdio::from(stdout_log.try_clone()?));
+            cmd.stderr(std::process::Stdio::from(stderr_log.try_clone()?));
+        }
 
-        // Detach from our process group
-        cmd.stdout(std::process::Stdio::null());
-        cmd.stderr(std::process::Stdio::null());
-
-        let child = cmd.spawn()?;
+        let mut child = match timed_phase("wrapper_spawn", || cmd.spawn()) {
+            Ok(child) => child,
+            Err(e) => {
+                capture.discard();
+                return Err(e.into());
+            },
+        };
         let pid = child.id();
+        capture.claim(pid);
+        self.groups.insert(pid, pid);
+
+        if let Some(buffer) = &options.log_buffer {
+            if let Some(stdout) = child.stdout.take() {
+                tee_lines(stdout, LogStream::Stdout, Some(stdout_log), buffer.clone());
+            }
+            if let Some(stderr) = child.stderr.take() {
+                tee_lines(stderr, LogStream::Stderr, Some(stderr_log), buffer.clone());
+            }
+        }
 
+        self.children.push(child);
         trace!(pid = pid, "Process started");
         Ok(pid)
     }
 
     fn kill_process(&self, pid: u32) -> Result<()> {
-        debug!(pid = pid, "Killing process");
-
-        // First try SIGTERM
-        let status = Command::new("/bin/kill")
-            .args(["-TERM", &pid.to_string()])
-            .status()?;
-
-        if !status.success() {
-            warn!(pid = pid, "SIGTERM failed, trying SIGKILL");
-            Command::new("/bin/kill")
-                .args(["-KILL", &pid.to_string()])
-                .status()?;
-        }
+        let result = kill_process_or_group(pid, self.groups.get(pid));
+        self.system.invalidate();
+        result
+    }
 
-        Ok(())
+    fn process_group(&self, pid: u32) -> Option<u32> {
+        self.groups.get(pid)
     }
 
     fn execute_command(&self, command: &str) -> Result<()> {
@@ -189,15 +290,57 @@ impl ProcessMonitor for LinuxMonitor {
     }
 
     fn is_running(&self, pid: u32) -> bool {
-        self.refresh();
-
-        if let Ok(system) = self.system.lock() {
-            system.process(Pid::from_u32(pid)).is_some()
-        } else {
+        // Always read fresh: callers poll this right after starting or
+        // stopping a process
+        self.system.is_alive(pid).unwrap_or_else(|| {
             // Fallback: check if /proc/{pid} exists
             std::path::Path::new(&format!("/proc/{}", pid)).exists()
+        })
+    }
+
+    fn has_exited(&self, pid: u32) -> bool {
+        // Read the state straight from /proc: cheaper than a full refresh,
+        // and the spawn wrapper stays a zombie until it's reaped
+        match procfs::process::Process::new(pid as i32).and_then(|p| p.stat()) {
+            Ok(stat) => stat.state == 'Z',
+            Err(_) => true,
         }
     }
+
+    fn is_zombie(&self, pid: u32) -> bool {
+        procfs::process::Process::new(pid as i32)
+            .and_then(|p| p.stat())
+            .is_ok_and(|stat| stat.state == 'Z')
+    }
+
+    fn children(&self) -> Vec<u32> {
+        self.children.pids()
+    }
+
+    fn reap_children(&self, keep: &[u32]) -> Vec<u32> {
+        // The wrapper exits as soon as it has backgrounded the service, but
+        // its PID is the one instances track, so only reap those no
+        // instance holds anymore
+        self.groups.retain(keep);
+        self.children.reap(keep)
+    }
+}
+
+/// Number of threads in a process, from /proc/{pid}/stat (0 if unavailable)
+fn thread_count(pid: u32) -> u32 {
+    procfs::process::Process::new(pid as i32)
+        .and_then(|p| p.stat())
+        .map(|stat| stat.num_threads.max(0) as u32)
+        .unwrap_or(0)
+}
+
+/// Number of open file descriptors, from /proc/{pid}/fd
+///
+/// 0 if the process exited mid-read or the directory isn't readable.
+fn open_file_count(pid: u32) -> u32 {
+    std::fs::read_dir(format!("/proc/{}/fd", pid))
+        .map(|entries| entries.filter(|e| e.is_ok()).count() as u32)
+        .unwrap_or(0)
 }
 
 #[cfg(test)]
@@ -209,6 +352,103 @@ mod tests {
         let _monitor = LinuxMonitor::new();
     }
 
+    #[test]
+    fn test_cached_table_sees_new_and_exited_processes() {
+        let monitor = LinuxMonitor::with_refresh_interval(Duration::from_secs(60));
+        monitor.get_system_metrics();
+
+        // Spawned after the table was cached
+        let mut child = Command::new("sleep").arg("30").spawn().unwrap();
+        let pid = child.id();
+        assert!(monitor.is_running(pid));
+        assert!(monitor.get_process_metrics(pid).is_some());
+
+        child.kill().unwrap();
+        child.wait().unwrap();
+        assert!(!monitor.is_running(pid));
+        // Re-read on its own, not served from the stale table
+        assert!(monitor.get_process_metrics(pid).is_none());
+    }
+
+    #[test]
+    fn test_has_exited_counts_zombies() {
+        let monitor = LinuxMonitor::new();
+        let mut child = Command::new("true").spawn().unwrap();
+        let pid = child.id();
+
+        // Not reaped yet: still listed as a process, but it has exited
+        std::thread::sleep(std::time::Duration::from_millis(200));
+        assert!(monitor.has_exited(pid));
+
+        child.wait().unwrap();
+        assert!(monitor.has_exited(pid));
+
+        let mut sleeper = Command::new("sleep").arg("30").spawn().unwrap();
+        assert!(!monitor.has_exited(sleeper.id()));
+        sleeper.kill().unwrap();
+        sleeper.wait().unwrap();
+    }
+
+    #[test]
+    fn test_spawn_wrapper_zombie_reaped() {
+        let monitor = LinuxMonitor::new();
+        let pid = monitor.spawn("true", &SpawnOptions::default()).unwrap();
+        std::thread::sleep(std::time::Duration::from_millis(300));
+
+        // The wrapper has exited but is held until no instance tracks it
+        assert!(monitor.is_zombie(pid));
+        assert_eq!(monitor.children(), vec![pid]);
+        assert!(monitor.reap_children(&[pid]).is_empty());
+        assert!(monitor.is_zombie(pid));
+        assert!(exit_status_path(pid).exists());
+
+        assert_eq!(monitor.reap_children(&[]), vec![pid]);
+        assert!(!monitor.is_zombie(pid));
+        assert!(monitor.children().is_empty());
+        assert!(!exit_status_path(pid).exists());
+        let (stdout, stderr) = super::super::log_paths(pid);
+        monitor.remove_logs(pid);
+        assert!(!stdout.exists() && !stderr.exists());
+    }
+
+    #[test]
+    fn test_kill_process_kills_forked_children() {
+        let dir = tempfile::tempdir().unwrap();
+        let child_pid_file = dir.path().join("child.pid");
+        let monitor = LinuxMonitor::new();
+        let command = format!("sleep 30 & echo $! > '{}'; wait", child_pid_file.display());
+        let pid = monitor.spawn(&command, &SpawnOptions::default()).unwrap();
+
+        let deadline = std::time::Instant::now() + std::time::Duration::from_secs(5);
+        let child_pid: u32 = loop {
+            if let Some(child) = std::fs::read_to_string(&child_pid_file)
+                .ok()
+                .and_then(|s| s.trim().parse().ok())
+            {
+                break child;
+            }
+            assert!(std::time::Instant::now() < deadline, "child never started");
+            std::thread::sleep(std::time::Duration::from_millis(20));
+        };
+        assert_eq!(monitor.process_group(pid), Some(pid));
+        assert!(!monitor.has_exited(child_pid));
+
+        monitor.kill_process(pid).unwrap();
+        let deadline = std::time::Instant::now() + std::time::Duration::from_secs(5);
+        while !monitor.has_exited(child_pid) {
+            assert!(
+                std::time::Instant::now() < deadline,
+                "forked child survived"
+            );
+            std::thread::sleep(std::time::Duration::from_millis(20));
+        }
+
+        // Untracked PIDs have no group
+        assert_eq!(monitor.process_group(std::process::id()), None);
+        monitor.reap_children(&[]);
+        assert_eq!(monitor.process_group(pid), None);
+    }
+
     #[test]
     fn test_system_metrics() {
         let monitor = LinuxMonitor::new();
@@ -218,4 +458,193 @@ mod tests {
         assert!(metrics.memory_total_bytes > 0);
         assert!(metrics.memory_percent >= 0.0 && metrics.memory_percent <= 100.0);
     }
+
+    #[test]
+    fn test_thread_count() {
+        // Park a few extra threads in this (test) process
+        let (tx, rx) = std::sync::mpsc::channel::<()>();
+        let rx = std::sync::Arc::new(std::sync::Mutex::new(rx));
+        let workers: Vec<_> = (0..3)
+            .map(|_| {
+                let rx = rx.clone();
+                std::thread::spawn(move || {
+                    let _ = rx.lock().unwrap().recv();
+                })
+            })
+            .collect();
+
+        let monitor = LinuxMonitor::new();
+        let metrics = monitor.get_process_metrics(std::process::id()).unwrap();
+        assert!(metrics.threads > 3, "threads = {}", metrics.threads);
+
+        drop(tx);
+        for worker in workers {
+            worker.join().unwrap();
+        }
+    }
+
+    #[test]
+    fn test_open_file_count() {
+        // A child holding stdin/stdout/stderr plus three extra descriptors
+        let mut child = Command::new("/bin/sh")
+            .args(["-c", "exec 3</dev/null 4</dev/null 5</dev/null; sleep 30"])
+            .stdin(std::process::Stdio::null())
+            .stdout(std::process::Stdio::null())
+            .stderr(std::process::Stdio::null())
+            .spawn()
+            .unwrap();
+        std::thread::sleep(std::time::Duration::from_millis(300));
+
+        let monitor = LinuxMonitor::new();
+        let metrics = monitor.get_process_metrics(child.id()).unwrap();
+        assert!(
+            metrics.open_files >= 6,
+            "open_files = {}",
+            metrics.open_files
+        );
+
+        child.kill().unwrap();
+        child.wait().unwrap();
+
+        // Gone processes degrade to 0 instead of erroring
+        assert_eq!(open_file_count(u32::MAX), 0);
+    }
+
+    #[test]
+    fn test_spawn_captures_output() {
+        let monitor = LinuxMonitor::new();
+        let pid = monitor
+            .spawn("echo hello; echo oops >&2", &SpawnOptions::default())
+            .unwrap();
+
+        let deadline = std::time::Instant::now() + std::time::Duration::from_secs(5);
+        let logs = loop {
+            let logs = monitor.read_logs(pid, 10).unwrap();
+            if !logs.stdout.is_empty() && !logs.stderr.is_empty() {
+                break logs;
+            }
+            assert!(std::time::Instant::now() < deadline, "no output captured");
+            std::thread::sleep(std::time::Duration::from_millis(20));
+        };
+        assert_eq!(logs.stdout, vec!["hello"]);
+        assert_eq!(logs.stderr, vec!["oops"]);
+
+        let (stdout, stderr) = super::super::log_paths(pid);
+        let _ = std::fs::remove_file(stdout);
+        let _ = std::fs::remove_file(stderr);
+    }
+
+    #[test]
+    fn test_spawn_captures_to_log_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let paths = super::super::instance_log_paths(dir.path(), "group/api");
+        std::fs::create_dir_all(dir.path().join("group")).unwrap();
+        std::fs::write(&paths.0, "previous run\n").unwrap();
+
+        let monitor = LinuxMonitor::new();
+        let options = SpawnOptions {
+            log_files: Some(paths.clone()),
+            ..Default::default()
+        };
+        let pid = monitor
+            .spawn("echo hello; echo oops >&2", &options)
+            .unwrap();
+
+        let deadline = std::time::Instant::now() + std::time::Duration::from_secs(5);
+        let logs = loop {
+            let logs = crate::logs::read_captured(&paths, 10).unwrap();
+            if !logs.stdout.is_empty() && !logs.stderr.is_empty() {
+                break logs;
+            }
+            assert!(std::time::Instant::now() < deadline, "no output captured");
+            std::thread::sleep(std::time::Duration::from_millis(20));
+        };
+        // Each run replaces the last one's output
+        assert_eq!(logs.stdout, vec!["hello"]);
+        assert_eq!(logs.stderr, vec!["oops"]);
+        assert!(paths.0.ends_with("group/api.stdout.log"));
+        assert!(!super::super::log_paths(pid).0.exists());
+    }
+
+    #[test]
+    fn test_is_port_in_use() {
+        let monitor = LinuxMonitor::new();
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        assert!(monitor.is_port_in_use(port));
+        assert!(proc_net_listening(port));
+
+        drop(listener);
+        assert!(!monitor.is_port_in_use(port));
+    }
+
+    #[test]
+    fn test_find_by_port_udp() {
+        let monitor = LinuxMonitor::new();
+        let socket = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+        let port = socket.local_addr().unwrap().port();
+
+        let info = monitor.find_by_port(port).unwrap();
+        assert_eq!(info.pid, std::process::id());
+        assert_eq!(info.protocol, Some(Protocol::Udp));
+        assert!(monitor.find_by_port_proto(port, Protocol::Tcp).is_none());
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let info = monitor.find_by_port_proto(port, Protocol::Tcp).unwrap();
+        assert_eq!(info.protocol, Some(Protocol::Tcp));
+    }
+
+    #[test]
+    fn test_spawn_passes_env() {
+        let monitor = LinuxMonitor::new();
+        let options = SpawnOptions {
+            env: [("USM_TEST_GREETING".to_string(), "hi there".to_string())].into(),
+            ..Default::default()
+        };
+        let pid = monitor
+            .spawn("echo \"$USM_TEST_GREETING\"", &options)
+            .unwrap();
+
+        let deadline = std::time::Instant::now() + std::time::Duration::from_secs(5);
+        let logs = loop {
+            let logs = monitor.read_logs(pid, 10).unwrap();
+            if !logs.stdout.is_empty() {
+                break logs;
+            }
+            assert!(std::time::Instant::now() < deadline, "no output captured");
+            std::thread::sleep(std::time::Duration::from_millis(20));
+        };
+        assert_eq!(logs.stdout, vec!["hi there"]);
+
+        monitor.remove_logs(pid);
+        let (stdout, stderr) = super::super::log_paths(pid);
+        assert!(!stdout.exists() && !stderr.exists());
+        assert!(monitor.read_logs(pid, 10).is_err());
+    }
+
+    #[test]
+    fn test_spawn_records_exit_code() {
+        let monitor = LinuxMonitor::new();
+        let pid = monitor
+            .spawn("echo starting; exit 3", &SpawnOptions::default())
+            .unwrap();
+
+        let deadline = std::time::Instant::now() + std::time::Duration::from_secs(5);
+        let code = loop {
+            if let Some(code) = monitor.exit_code(pid) {
+                break code;
+            }
+            assert!(
+                std::time::Instant::now() < deadline,
+                "no exit code recorded"
+            );
+            std::thread::sleep(std::time::Duration::from_millis(20));
+        };
+        assert_eq!(code, 3);
+
+        monitor.remove_exit_status(pid);
+        assert_eq!(monitor.exit_code(pid), None);
+        monitor.remove_logs(pid);
+    }
 }
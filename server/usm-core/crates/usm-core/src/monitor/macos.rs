@@ -1,41 +1,83 @@
 //! macOS process monitoring using libproc
 
+use std::collections::HashMap;
+use std::os::unix::process::CommandExt;
 use std::path::Path;
 use std::process::Command;
+use std::time::{Duration, Instant};
 
 use anyhow::Result;
-use sysinfo::{Pid, System};
-use tracing::{debug, info, trace, warn};
-
-use super::backend::{ProcessInfo, ProcessMonitor};
+use libproc::bsd_info::BSDInfo;
+use libproc::file_info::ListFDs;
+use libproc::proc_pid::{listpidinfo, pidinfo};
+use libproc::task_info::TaskInfo;
+use sysinfo::{Pid, ProcessStatus, System};
+use tracing::{debug, info, instrument, trace, warn};
+
+use super::backend::{
+    exit_status_path, kill_process_or_group, timed_phase, unique_temp_path, CachedSystem,
+    CaptureFiles, ProcessGroups, ProcessInfo, ProcessMonitor, Protocol, SpawnOptions,
+    SpawnedChildren, StartupFailed, DEFAULT_REFRESH_INTERVAL,
+};
+use crate::logs::{tee_lines, LogStream};
 use crate::metrics::{InstanceMetrics, SystemMetrics};
+use crate::service::ServiceTemplate;
+
+/// Longest wait for the wrapper shell to write the service's PID file
+const PID_FILE_TIMEOUT: Duration = Duration::from_millis(200);
+
+/// How often a spawned process is checked while verifying it started
+const VERIFY_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Startup verification budget when `SpawnOptions` doesn't set one
+const DEFAULT_STARTUP_VERIFY_MS: u64 = ServiceTemplate::DEFAULT_STARTUP_VERIFY_MS as u64;
+
+/// The PID the wrapper shell wrote to `path`, once it's there
+///
+/// The file is removed once read; `echo` ends it with a newline, so one
+/// without is still being written and reads as None.
+fn read_pid_file(path: &Path) -> Option<u32> {
+    let contents = std::fs::read_to_string(path)
+        .ok()
+        .filter(|c| c.ends_with('\n'))?;
+    let _ = std::fs::remove_file(path);
+    contents
+        .trim()
+        .parse()
+        .map_err(|e| warn!("Failed to parse PID file {}: {}", path.display(), e))
+        .ok()
+}
 
 /// macOS process monitor using libproc and sysinfo
 pub struct MacOSMonitor {
-    system: std::sync::Mutex<System>,
+    system: CachedSystem,
+    children: SpawnedChildren,
+    groups: ProcessGroups,
 }
 
 impl MacOSMonitor {
     pub fn new() -> Self {
-        Self {
-            system: std::sync::Mutex::new(System::new_all()),
-        }
+        Self::with_refresh_interval(DEFAULT_REFRESH_INTERVAL)
     }
 
-    /// Refresh system information
-    fn refresh(&self) {
-        if let Ok(mut system) = self.system.lock() {
-            system.refresh_all();
+    /// Monitor that refreshes its process table at most once per `interval`
+    pub fn with_refresh_interval(interval: Duration) -> Self {
+        Self {
+            system: CachedSystem::new(interval),
+            children: SpawnedChildren::default(),
+            groups: ProcessGroups::default(),
         }
     }
 
-    /// Find PID listening on a port using lsof
+    /// Find PID listening on a port (or bound to it, for UDP) using lsof
     /// TODO: Replace with direct libproc calls for better performance
-    fn find_pid_by_port(&self, port: u16) -> Option<u32> {
-        let output = Command::new("/usr/sbin/lsof")
-            .args(["-i", &format!(":{}", port), "-sTCP:LISTEN", "-t"])
-            .output()
-            .ok()?;
+    fn find_pid_by_port(&self, port: u16, protocol: Protocol) -> Option<u32> {
+        let mut cmd = Command::new("/usr/sbin/lsof");
+        match protocol {
+            Protocol::Tcp => cmd.args([&format!("-iTCP:{}", port), "-sTCP:LISTEN", "-t"]),
+            Protocol::Udp => cmd.args([&format!("-iUDP:{}", port), "-t"]),
+        };
+        let output = cmd.output().ok()?;
 
         if !output.status.success() {
             return None;
@@ -49,18 +91,47 @@ impl MacOSMonitor {
     }
 }
 
+/// Whether something is listening on a port, by trying to bind it
+///
+/// lsof only lists our own processes when not run as root, so a listener
+/// owned by another user (e.g. a system daemon) is only caught this way.
+fn bind_fails(port: u16) -> bool {
+    ["0.0.0.0", "127.0.0.1"].iter().any(|host| {
+        matches!(
+            std::net::TcpListener::bind((*host, port)),
+            Err(e) if e.kind() == std::io::ErrorKind::AddrInUse
+        )
+    })
+}
+
 impl Default for MacOSMonitor {
     fn default() -> Self {
         Self::new()
     }
 }
 
+/// Metrics of one process from an already refreshed process table
+fn process_metrics(system: &System, pid: u32) -> Option<InstanceMetrics> {
+    let process = system.process(Pid::from_u32(pid))?;
+    Some(InstanceMetrics {
+        cpu_percent: process.cpu_usage() as f64,
+        memory_bytes: process.memory(),
+        memory_percent: (process.memory() as f64 / system.total_memory() as f64) * 100.0,
+        threads: thread_count(pid),
+        open_files: open_file_count(pid),
+        uptime_seconds: process.run_time(),
+    })
+}
+
 impl ProcessMonitor for MacOSMonitor {
     fn find_by_port(&self, port: u16) -> Option<ProcessInfo> {
-        let pid = self.find_pid_by_port(port)?;
-        self.refresh();
+        self.find_by_port_proto(port, Protocol::Tcp)
+            .or_else(|| self.find_by_port_proto(port, Protocol::Udp))
+    }
 
-        let system = self.system.lock().ok()?;
+    fn find_by_port_proto(&self, port: u16, protocol: Protocol) -> Option<ProcessInfo> {
+        let pid = self.find_pid_by_port(port, protocol)?;
+        let system = self.system.lock_process(pid)?;
         let process = system.process(Pid::from_u32(pid))?;
 
         Some(ProcessInfo {
@@ -68,29 +139,31 @@ impl ProcessMonitor for MacOSMonitor {
             name: process.name().to_string(),
             cpu_percent: process.cpu_usage() as f64,
             memory_bytes: process.memory(),
-            threads: 0, // sysinfo doesn't expose thread count directly
+            threads: thread_count(pid),
+            protocol: Some(protocol),
         })
     }
 
     fn get_process_metrics(&self, pid: u32) -> Option<InstanceMetrics> {
-        self.refresh();
+        let system = self.system.lock_process(pid)?;
+        process_metrics(&system, pid)
+    }
 
-        let system = self.system.lock().ok()?;
-        let process = system.process(Pid::from_u32(pid))?;
+    fn process_start_time(&self, pid: u32) -> Option<u64> {
+        let system = self.system.lock_process(pid)?;
+        Some(system.process(Pid::from_u32(pid))?.start_time())
+    }
 
-        Some(InstanceMetrics {
-            cpu_percent: process.cpu_usage() as f64,
-            memory_bytes: process.memory(),
-            memory_percent: (process.memory() as f64 / system.total_memory() as f64) * 100.0,
-            threads: 0,
-            open_files: 0,
-            uptime_seconds: process.run_time(),
-        })
+    fn get_processes_metrics(&self, pids: &[u32]) -> HashMap<u32, InstanceMetrics> {
+        let Ok(system) = self.system.lock_with(pids) else {
+            return HashMap::new();
+        };
+        pids.iter()
+            .filter_map(|&pid| Some((pid, process_metrics(&system, pid)?)))
+            .collect()
     }
 
     fn get_system_metrics(&self) -> SystemMetrics {
-        self.refresh();
-
         let system = self.system.lock().unwrap();
 
         SystemMetrics {
@@ -98,7 +171,7 @@ impl ProcessMonitor for MacOSMonitor {
             memory_total_bytes: system.total_memory(),
             memory_used_bytes: system.used_memory(),
             memory_percent: (system.used_memory() as f64 / system.total_memory() as f64) * 100.0,
-            load_average: System::load_average(),
+            load_average: crate::metrics::load_average(),
         }
     }
 
@@ -117,24 +190,48 @@ impl ProcessMonitor for MacOSMonitor {
         working_dir: Option<&Path>,
         port: Option<u16>,
     ) -> Result<u32> {
+        self.spawn(
+            command,
+            &SpawnOptions {
+                working_dir: working_dir.map(Path::to_path_buf),
+                port,
+                ..Default::default()
+            },
+        )
+    }
+
+    #[instrument(skip(self, options), fields(port = ?options.port))]
+    fn spawn(&self, command: &str, options: &SpawnOptions) -> Result<u32> {
+        let working_dir = options.working_dir.as_deref();
+        let port = options.port;
         debug!(command = %command, working_dir = ?working_dir, port = ?port, "Starting process");
 
-        // Create temp file to capture the actual service PID
-        let pid_file = std::env::temp_dir().join(format!("usm-{}.pid", std::process::id()));
+        // Temp file to capture the actual service PID, unique per spawn so
+        // concurrent starts (and other USM processes) don't read each other's
+        let pid_file = unique_temp_path("service.pid");
 
         // Wrapper script that:
         // 1. Starts the service in background
         // 2. Captures its PID and writes to file
         // 3. Waits so the shell doesn't exit immediately
+        // 4. Records the service's exit code (see exit_status_path), after
+        //    removing any left over from an earlier process with that PID
+        let exit_file = exit_status_path("$p");
         let wrapper = format!(
-            r#"{{ {} }} & echo $! > "{}" && wait"#,
+            r#"{{ {} }} & p=$!; rm -f "{}"; echo $p > "{}" && wait $p; echo $? > "{}""#,
             command,
-            pid_file.display()
+            exit_file.display(),
+            pid_file.display(),
+            exit_file.display()
         );
 
         let mut cmd = Command::new("/bin/zsh");
         cmd.args(["-c", &wrapper]);
 
+        // The wrapper leads a new process group that the backgrounded
+        // service and its children inherit, so they can be stopped together
+        cmd.process_group(0);
+
         if let Some(dir) = working_dir {
             cmd.current_dir(dir);
         }
@@ -147,95 +244,133 @@ impl ProcessMonitor for MacOSMonitor {
         );
         cmd.env("PATH", &path);
 
-        // Capture stdout/stderr to temp files for debugging
-        let stdout_file =
-            std::env::temp_dir().join(format!("usm-{}-stdout.log", std::process::id()));
-        let stderr_file =
-            std::env::temp_dir().join(format!("usm-{}-stderr.log", std::process::id()));
-        cmd.stdout(std::process::Stdio::from(std::fs::File::create(
-            &stdout_file,
-        )?));
-        cmd.stderr(std::process::Stdio::from(std::fs::File::create(
-            &stderr_file,
-        )?));
+        // Template/instance env last, so it can override PATH too
+        cmd.envs(&options.env);
 
-        // Spawn the wrapper (it will wait in background)
-        let mut _child = cmd.spawn()?;
-
-        // Give shell time to write PID file (200ms should be plenty)
-        std::thread::sleep(std::time::Duration::from_millis(200));
-
-        // Read the actual service PID from file
-        let pid = if pid_file.exists() {
-            match std::fs::read_to_string(&pid_file) {
-                Ok(contents) => {
-                    let _ = std::fs::remove_file(&pid_file); // Clean up
-                    match contents.trim().parse::<u32>() {
-                        Ok(p) => p,
-                        Err(e) => {
-                            warn!("Failed to parse PID: {}", e);
-                            0 // Will trigger fallback
-                        },
-                    }
-                },
-                Err(e) => {
-                    warn!("Failed to read PID file: {}", e);
-                    0 // Will trigger fallback
-                },
-            }
+        // Capture stdout/stderr to files (see log_paths, or the instance's
+        // own log_files) for debugging
+        let (capture, stdout_log, stderr_log) = CaptureFiles::create(options.log_files.as_ref())?;
+
+        // When in-memory capture is enabled, pipe output and tee it into both
+        if options.log_buffer.is_some() {
+            cmd.stdout(std::process::Stdio::piped());
+            cmd.stderr(std::process::Stdio::piped());
         } else {
-            warn!("PID file not created after 200ms");
-            0 // Will trigger fallback
-        };
+            cmd.stdout(std::process::Stdio::from(stdout_log.try_clone()?));
+            cmd.stderr(std::process::Stdio::from(stderr_log.try_clone()?));
+        }
 
-        // Verify process is actually running
-        // Wait 3 seconds to allow services like pnpm/node to fully initialize
-        // (measured at ~921ms for pnpm dev, so 3s provides safe margin)
-        std::thread::sleep(std::time::Duration::from_millis(3000));
+        // Spawn the wrapper (it will wait in background)
+        let mut child = match timed_phase("wrapper_spawn", || cmd.spawn()) {
+            Ok(child) => child,
+            Err(e) => {
+                capture.discard();
+                return Err(e.into());
+            },
+        };
 
-        if pid > 0 && self.is_running(pid) {
-            trace!(pid = pid, "Process started and verified running");
-            return Ok(pid);
+        if let Some(buffer) = &options.log_buffer {
+            if let Some(stdout) = child.stdout.take() {
+                tee_lines(stdout, LogStream::Stdout, Some(stdout_log), buffer.clone());
+            }
+            if let Some(stderr) = child.stderr.take() {
+                tee_lines(stderr, LogStream::Stderr, Some(stderr_log), buffer.clone());
+            }
         }
-
-        // Fallback: If we have a port and the PID didn't work, try to find by port
-        // This handles brew services, systemd, and already-running services
-        if let Some(port) = port {
-            debug!(
-                port = port,
-                "PID tracking failed, trying to find process by port"
-            );
-            if let Some(pid) = self.find_pid_by_port(port) {
-                if self.is_running(pid) {
+        // The wrapper waits for the service, then lingers as a zombie until
+        // reaped (see reap_children)
+        let pgid = child.id();
+        self.children.push(child);
+
+        let mut pid = timed_phase("pid_file_read", || {
+            // The shell writes the PID file right after forking
+            let deadline = Instant::now() + PID_FILE_TIMEOUT;
+            loop {
+                if let Some(pid) = read_pid_file(&pid_file) {
+                    return pid;
+                }
+                if Instant::now() >= deadline {
+                    debug!(
+                        "PID file not written after {}ms, still watching it and the port",
+                        PID_FILE_TIMEOUT.as_millis()
+                    );
+                    return 0;
+                }
+                std::thread::sleep(Duration::from_millis(10));
+            }
+        });
+
+        // Verify the process is running, polling until the template's
+        // startup_verify_ms runs out. A slow wrapper may still write the PID
+        // file, and a service that's still binding may only show up on its
+        // port later, so both keep being retried. Finding the service by port
+        // also handles brew services, systemd, and already-running services.
+        let verify = options
+            .startup_verify
+            .unwrap_or(Duration::from_millis(DEFAULT_STARTUP_VERIFY_MS));
+        let deadline = Instant::now() + verify;
+        let found = timed_phase("verify_poll", || loop {
+            std::thread::sleep(VERIFY_POLL_INTERVAL.min(verify));
+            if pid == 0 {
+                pid = read_pid_file(&pid_file).unwrap_or(0);
+            }
+            if pid > 0 && self.is_running(pid) {
+                trace!(pid = pid, "Process started and verified running");
+                return Some(pid);
+            }
+            if let Some(port) = port {
+                let by_port = self
+                    .find_pid_by_port(port, Protocol::Tcp)
+                    .filter(|&pid| self.is_running(pid));
+                if let Some(found) = by_port {
                     info!(
-                        pid = pid,
+                        pid = found,
                         port = port,
                         "Found already-running service by port"
                     );
-                    return Ok(pid);
+                    return Some(found);
                 }
             }
+            if Instant::now() >= deadline {
+                return None;
+            }
+        });
+        let _ = std::fs::remove_file(&pid_file);
+        if let Some(pid) = found {
+            capture.claim(pid);
+            // A service found by port may not be ours; `groups.get` only
+            // reports the group while the process is actually in it
+            self.groups.insert(pid, pgid);
+            return Ok(pid);
         }
 
-        anyhow::bail!("Process {} started but immediately died", pid)
+        // Keep the output of the dead process: it usually says why it died
+        let stderr_log = (pid > 0).then(|| capture.stderr_path(pid));
+        if pid > 0 {
+            capture.claim(pid);
+        } else {
+            capture.discard();
+        }
+        let exit_code = (pid > 0).then(|| self.exit_code(pid)).flatten();
+        if exit_code.is_some() {
+            self.remove_exit_status(pid);
+        }
+        Err(StartupFailed {
+            pid,
+            exit_code,
+            stderr_log,
+        }
+        .into())
     }
 
     fn kill_process(&self, pid: u32) -> Result<()> {
-        debug!(pid = pid, "Killing process");
-
-        // First try SIGTERM
-        let status = Command::new("/bin/kill")
-            .args(["-TERM", &pid.to_string()])
-            .status()?;
-
-        if !status.success() {
-            warn!(pid = pid, "SIGTERM failed, trying SIGKILL");
-            Command::new("/bin/kill")
-                .args(["-KILL", &pid.to_string()])
-                .status()?;
-        }
+        let result = kill_process_or_group(pid, self.groups.get(pid));
+        self.system.invalidate();
+        result
+    }
 
-        Ok(())
+    fn process_group(&self, pid: u32) -> Option<u32> {
+        self.groups.get(pid)
     }
 
     fn execute_command(&self, command: &str) -> Result<()> {
@@ -251,18 +386,36 @@ impl ProcessMonitor for MacOSMonitor {
     }
 
     fn is_running(&self, pid: u32) -> bool {
-        self.refresh();
+        // Always read fresh: callers poll this right after starting or
+        // stopping a process
+        self.system.is_alive(pid).unwrap_or(false)
+    }
 
-        if let Ok(system) = self.system.lock() {
-            system.process(Pid::from_u32(pid)).is_some()
-        } else {
-            false
-        }
+    fn is_port_in_use(&self, port: u16) -> bool {
+        self.find_pid_by_port(port, Protocol::Tcp).is_some() || bind_fails(port)
     }
 
-    fn find_by_name(&self, pattern: &str) -> Vec<ProcessInfo> {
-        self.refresh();
+    fn is_zombie(&self, pid: u32) -> bool {
+        let mut system = self.system.lock().unwrap();
+        // The cached status can predate the exit
+        system.refresh_process(Pid::from_u32(pid));
+        system
+            .process(Pid::from_u32(pid))
+            .is_some_and(|process| process.status() == ProcessStatus::Zombie)
+    }
+
+    fn children(&self) -> Vec<u32> {
+        self.children.pids()
+    }
+
+    fn reap_children(&self, keep: &[u32]) -> Vec<u32> {
+        // Instances track the service's PID, not the wrapper's, so `keep`
+        // rarely matters here
+        self.groups.retain(keep);
+        self.children.reap(keep)
+    }
 
+    fn find_by_name(&self, pattern: &str) -> Vec<ProcessInfo> {
         let system = self.system.lock().unwrap();
 
         system
@@ -279,12 +432,31 @@ impl ProcessMonitor for MacOSMonitor {
                 name: process.name().to_string(),
                 cpu_percent: process.cpu_usage() as f64,
                 memory_bytes: process.memory(),
-                threads: 0,
+                threads: thread_count(pid.as_u32()),
+                protocol: None,
             })
             .collect()
     }
 }
 
+/// Number of threads in a process, from proc_pidinfo (0 if unavailable)
+fn thread_count(pid: u32) -> u32 {
+    pidinfo::<TaskInfo>(pid as i32, 0)
+        .map(|info| info.pti_threadnum.max(0) as u32)
+        .unwrap_or(0)
+}
+
+/// Number of open file descriptors, via proc_pidinfo(PROC_PIDLISTFDS)
+///
+/// 0 if the process exited mid-read or can't be inspected.
+fn open_file_count(pid: u32) -> u32 {
+    let pid = pid as i32;
+    pidinfo::<BSDInfo>(pid, 0)
+        .and_then(|info| listpidinfo::<ListFDs>(pid, info.pbi_nfiles as usize))
+        .map(|fds| fds.len() as u32)
+        .unwrap_or(0)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -310,4 +482,55 @@ mod tests {
         // Don't fail if not found since this is environment-dependent
         let _ = results; // Acknowledge we're not asserting on the results
     }
+
+    #[test]
+    fn test_thread_count() {
+        // Park a few extra threads in this (test) process
+        let (tx, rx) = std::sync::mpsc::channel::<()>();
+        let rx = std::sync::Arc::new(std::sync::Mutex::new(rx));
+        let workers: Vec<_> = (0..3)
+            .map(|_| {
+                let rx = rx.clone();
+                std::thread::spawn(move || {
+                    let _ = rx.lock().unwrap().recv();
+                })
+            })
+            .collect();
+
+        let monitor = MacOSMonitor::new();
+        let metrics = monitor.get_process_metrics(std::process::id()).unwrap();
+        assert!(metrics.threads > 3, "threads = {}", metrics.threads);
+
+        drop(tx);
+        for worker in workers {
+            worker.join().unwrap();
+        }
+    }
+
+    #[test]
+    fn test_open_file_count() {
+        // A child holding stdin/stdout/stderr plus three extra descriptors
+        let mut child = Command::new("/bin/sh")
+            .args(["-c", "exec 3</dev/null 4</dev/null 5</dev/null; sleep 30"])
+            .stdin(std::process::Stdio::null())
+            .stdout(std::process::Stdio::null())
+            .stderr(std::process::Stdio::null())
+            .spawn()
+            .unwrap();
+        std::thread::sleep(std::time::Duration::from_millis(300));
+
+        let monitor = MacOSMonitor::new();
+        let metrics = monitor.get_process_metrics(child.id()).unwrap();
+        assert!(
+            metrics.open_files >= 6,
+            "open_files = {}",
+            metrics.open_files
+        );
+
+        child.kill().unwrap();
+        child.wait().unwrap();
+
+        // Gone processes degrade to 0 instead of erroring
+        assert_eq!(open_file_count(u32::MAX), 0);
+    }
 }
@@ -0,0 +1,421 @@
+//! In-memory process monitor for tests
+//!
+//! Never touches real processes: spawns hand out fake PIDs, and every call
+//! that would affect a process is recorded so tests can assert on it. Tests
+//! drive the lifecycle by flipping `is_running` (e.g. to simulate a crash)
+//! and by making spawns fail.
+
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Condvar, Mutex};
+use std::time::Duration;
+
+use anyhow::Result;
+use sysinfo::LoadAvg;
+
+use super::backend::{ProcessInfo, ProcessMonitor, SpawnOptions};
+use super::Signal;
+use crate::docker::{ComposeProject, ContainerState};
+use crate::metrics::{InstanceMetrics, SystemMetrics};
+
+/// How long a gated spawn waits for the others before going ahead alone
+const SPAWN_GATE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Scriptable fake `ProcessMonitor`
+#[derive(Debug)]
+pub struct MockMonitor {
+    next_pid: AtomicU32,
+    fail_spawns: AtomicBool,
+    fail_commands: AtomicBool,
+    ignore_term: AtomicBool,
+    spawn_delay_ms: AtomicU64,
+    spawn_gate: AtomicUsize,
+    /// Spawns in progress now, and the most there ever were at once
+    spawns_in_flight: Mutex<(usize, usize)>,
+    spawn_arrived: Condvar,
+    running: Mutex<HashSet<u32>>,
+    zombies: Mutex<HashSet<u32>>,
+    children: Mutex<Vec<u32>>,
+    exit_codes: Mutex<HashMap<u32, i32>>,
+    start_times: Mutex<HashMap<u32, u64>>,
+    compose: Mutex<HashMap<String, Vec<ContainerState>>>,
+    ports_in_use: Mutex<HashSet<u16>>,
+    port_owners: Mutex<HashMap<u16, u32>>,
+    spawned: Mutex<Vec<String>>,
+    spawned_env: Mutex<Vec<HashMap<String, String>>>,
+    killed: Mutex<Vec<u32>>,
+    signals: Mutex<Vec<(u32, Signal, bool)>>,
+    executed: Mutex<Vec<String>>,
+    removed_logs: Mutex<Vec<u32>>,
+}
+
+impl MockMonitor {
+    /// Create a monitor whose first spawned process gets PID 1000
+    pub fn new() -> Self {
+        Self::with_first_pid(1000)
+    }
+
+    /// Create a monitor that hands out PIDs starting at `pid`
+    pub fn with_first_pid(pid: u32) -> Self {
+        Self {
+            next_pid: AtomicU32::new(pid),
+            fail_spawns: AtomicBool::new(false),
+            fail_commands: AtomicBool::new(false),
+            ignore_term: AtomicBool::new(false),
+            spawn_delay_ms: AtomicU64::new(0),
+            spawn_gate: AtomicUsize::new(0),
+            spawns_in_flight: Mutex::new((0, 0)),
+            spawn_arrived: Condvar::new(),
+            running: Mutex::new(HashSet::new()),
+            zombies: Mutex::new(HashSet::new()),
+            children: Mutex::new(Vec::new()),
+            exit_codes: Mutex::new(HashMap::new()),
+            start_times: Mutex::new(HashMap::new()),
+            compose: Mutex::new(HashMap::new()),
+            ports_in_use: Mutex::new(HashSet::new()),
+            port_owners: Mutex::new(HashMap::new()),
+            spawned: Mutex::new(Vec::new()),
+            spawned_env: Mutex::new(Vec::new()),
+            killed: Mutex::new(Vec::new()),
+            signals: Mutex::new(Vec::new()),
+            executed: Mutex::new(Vec::new()),
+            removed_logs: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Make subsequent spawns fail (or succeed again)
+    pub fn set_fail_spawns(&self, fail: bool) {
+        self.fail_spawns.store(fail, Ordering::SeqCst);
+    }
+
+    /// Make execute_command fail (after recording the command)
+    pub fn set_fail_commands(&self, fail: bool) {
+        self.fail_commands.store(fail, Ordering::SeqCst);
+    }
+
+    /// Make processes survive kill_process (SIGTERM), so only SIGKILL ends them
+    pub fn set_ignore_term(&self, ignore: bool) {
+        self.ignore_term.store(ignore, Ordering::SeqCst);
+    }
+
+    /// Make spawn block for `delay`, like real monitors verifying the process
+    pub fn set_spawn_delay(&self, delay: Duration) {
+        self.spawn_delay_ms
+            .store(delay.as_millis() as u64, Ordering::SeqCst);
+    }
+
+    /// Hold each spawn until `count` spawns are in progress at once
+    ///
+    /// Only overlapping spawns get through together; serialized ones each
+    /// wait `SPAWN_GATE_TIMEOUT` and then go ahead alone, which
+    /// `max_concurrent_spawns` shows.
+    pub fn set_spawn_gate(&self, count: usize) {
+        self.spawn_gate.store(count, Ordering::SeqCst);
+    }
+
+    /// Most spawns that were ever in progress at the same time
+    pub fn max_concurrent_spawns(&self) -> usize {
+        self.spawns_in_flight.lock().unwrap().1
+    }
+
+    /// Mark a PID as running or not, e.g. to simulate a crash
+    pub fn set_running(&self, pid: u32, running: bool) {
+        let mut set = self.running.lock().unwrap();
+        if running {
+            set.insert(pid);
+        } else {
+            set.remove(&pid);
+        }
+    }
+
+    /// Mark a PID as a zombie (exited, not yet reaped) or not
+    pub fn set_zombie(&self, pid: u32, zombie: bool) {
+        let mut set = self.zombies.lock().unwrap();
+        if zombie {
+            set.insert(pid);
+        } else {
+            set.remove(&pid);
+        }
+    }
+
+    /// Record the exit code a PID reports once it has exited
+    pub fn set_exit_code(&self, pid: u32, code: i32) {
+        self.exit_codes.lock().unwrap().insert(pid, code);
+    }
+
+    /// Record when the process with a PID started, in seconds since the
+    /// Unix epoch (spawns record the time they happened)
+    pub fn set_start_time(&self, pid: u32, start_time: u64) {
+        self.start_times.lock().unwrap().insert(pid, start_time);
+    }
+
+    /// Set the containers `docker compose ps` reports for a project;
+    /// projects never set fail as if docker couldn't be reached
+    pub fn set_compose_containers(&self, project: &str, containers: Vec<ContainerState>) {
+        self.compose
+            .lock()
+            .unwrap()
+            .insert(project.to_string(), containers);
+    }
+
+    /// Mark a port as bound by some process outside USM (or free it)
+    pub fn set_port_in_use(&self, port: u16, in_use: bool) {
+        let mut set = self.ports_in_use.lock().unwrap();
+        if in_use {
+            set.insert(port);
+        } else {
+            set.remove(&port);
+        }
+    }
+
+    /// Make `find_by_port` report `pid` as listening on `port` while it's
+    /// running (or no one, with None)
+    pub fn set_port_owner(&self, port: u16, pid: Option<u32>) {
+        let mut owners = self.port_owners.lock().unwrap();
+        match pid {
+            Some(pid) => owners.insert(port, pid),
+            None => owners.remove(&port),
+        };
+    }
+
+    /// Commands passed to spawn, in order
+    pub fn spawned(&self) -> Vec<String> {
+        self.spawned.lock().unwrap().clone()
+    }
+
+    /// Environments passed to spawn, in the same order as `spawned`
+    pub fn spawned_env(&self) -> Vec<HashMap<String, String>> {
+        self.spawned_env.lock().unwrap().clone()
+    }
+
+    /// PIDs passed to kill_process, in order
+    pub fn killed(&self) -> Vec<u32> {
+        self.killed.lock().unwrap().clone()
+    }
+
+    /// Signals sent as (pid, signal, group), in order
+    pub fn signals(&self) -> Vec<(u32, Signal, bool)> {
+        self.signals.lock().unwrap().clone()
+    }
+
+    /// Commands passed to execute_command, in order
+    pub fn executed(&self) -> Vec<String> {
+        self.executed.lock().unwrap().clone()
+    }
+
+    /// PIDs whose captured output was removed, in order
+    pub fn removed_logs(&self) -> Vec<u32> {
+        self.removed_logs.lock().unwrap().clone()
+    }
+}
+
+impl Default for MockMonitor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ProcessMonitor for MockMonitor {
+    fn find_by_port(&self, port: u16) -> Option<ProcessInfo> {
+        let pid = *self.port_owners.lock().unwrap().get(&port)?;
+        self.is_running(pid).then(|| ProcessInfo {
+            pid,
+            name: "mock".to_string(),
+            cpu_percent: 0.0,
+            memory_bytes: 0,
+            threads: 1,
+            protocol: Some(super::Protocol::Tcp),
+        })
+    }
+
+    fn is_port_in_use(&self, port: u16) -> bool {
+        self.ports_in_use.lock().unwrap().contains(&port)
+    }
+
+    fn get_process_metrics(&self, pid: u32) -> Option<InstanceMetrics> {
+        self.is_running(pid).then_some(InstanceMetrics {
+            cpu_percent: 1.0,
+            memory_bytes: 64 * 1024 * 1024,
+            memory_percent: 1.0,
+            threads: 1,
+            open_files: 0,
+            uptime_seconds: 0,
+        })
+    }
+
+    fn get_system_metrics(&self) -> SystemMetrics {
+        SystemMetrics {
+            cpu_percent: 0.0,
+            memory_total_bytes: 8 * 1024 * 1024 * 1024,
+            memory_used_bytes: 0,
+            memory_percent: 0.0,
+            load_average: LoadAvg {
+                one: 0.0,
+                five: 0.0,
+                fifteen: 0.0,
+            },
+        }
+    }
+
+    fn start_process(&self, command: &str, working_dir: Option<&Path>) -> Result<u32> {
+        self.spawn(
+            command,
+            &SpawnOptions {
+                working_dir: working_dir.map(Path::to_path_buf),
+                ..Default::default()
+            },
+        )
+    }
+
+    fn spawn(&self, command: &str, options: &SpawnOptions) -> Result<u32> {
+        self.spawned.lock().unwrap().push(command.to_string());
+        self.spawned_env.lock().unwrap().push(options.env.clone());
+        // Wait at the gate, if one is set, for the other spawns
+        let gate = self.spawn_gate.load(Ordering::SeqCst);
+        let mut in_flight = self.spawns_in_flight.lock().unwrap();
+        in_flight.0 += 1;
+        in_flight.1 = in_flight.1.max(in_flight.0);
+        self.spawn_arrived.notify_all();
+        let (in_flight, _) = self
+            .spawn_arrived
+            .wait_timeout_while(in_flight, SPAWN_GATE_TIMEOUT, |(_, max)| *max < gate)
+            .unwrap();
+        drop(in_flight);
+        std::thread::sleep(Duration::from_millis(
+            self.spawn_delay_ms.load(Ordering::SeqCst),
+        ));
+        self.spawns_in_flight.lock().unwrap().0 -= 1;
+        if self.fail_spawns.load(Ordering::SeqCst) {
+            anyhow::bail!("Mock spawn failure for '{}'", command);
+        }
+
+        let pid = self.next_pid.fetch_add(1, Ordering::SeqCst);
+        self.set_running(pid, true);
+        self.set_start_time(pid, chrono::Utc::now().timestamp() as u64);
+        self.children.lock().unwrap().push(pid);
+        Ok(pid)
+    }
+
+    fn kill_process(&self, pid: u32) -> Result<()> {
+        self.killed.lock().unwrap().push(pid);
+        if !self.ignore_term.load(Ordering::SeqCst) {
+            self.set_running(pid, false);
+        }
+        Ok(())
+    }
+
+    fn signal_process(&self, pid: u32, signal: Signal, group: bool) -> Result<()> {
+        if !self.is_running(pid) {
+            anyhow::bail!("No such process {}", pid);
+        }
+        self.signals.lock().unwrap().push((pid, signal, group));
+        if signal == Signal::Kill {
+            self.set_running(pid, false);
+        }
+        Ok(())
+    }
+
+    fn execute_command(&self, command: &str) -> Result<()> {
+        self.executed.lock().unwrap().push(command.to_string());
+        if self.fail_commands.load(Ordering::SeqCst) {
+            anyhow::bail!("Command failed: {}", command);
+        }
+        Ok(())
+    }
+
+    fn compose_containers(&self, project: &ComposeProject) -> Result<Vec<ContainerState>> {
+        self.compose
+            .lock()
+            .unwrap()
+            .get(&project.name)
+            .cloned()
+            .ok_or_else(|| anyhow::anyhow!("Cannot connect to the Docker daemon"))
+    }
+
+    fn is_running(&self, pid: u32) -> bool {
+        self.running.lock().unwrap().contains(&pid)
+    }
+
+    fn process_start_time(&self, pid: u32) -> Option<u64> {
+        if !self.is_running(pid) {
+            return None;
+        }
+        self.start_times.lock().unwrap().get(&pid).copied()
+    }
+
+    fn is_zombie(&self, pid: u32) -> bool {
+        self.zombies.lock().unwrap().contains(&pid)
+    }
+
+    fn children(&self) -> Vec<u32> {
+        self.children.lock().unwrap().clone()
+    }
+
+    fn reap_children(&self, keep: &[u32]) -> Vec<u32> {
+        let mut zombies = self.zombies.lock().unwrap();
+        let mut reaped = Vec::new();
+        self.children.lock().unwrap().retain(|pid| {
+            if keep.contains(pid) || !zombies.remove(pid) {
+                return true;
+            }
+            reaped.push(*pid);
+            false
+        });
+        for pid in &reaped {
+            self.set_running(*pid, false);
+        }
+        reaped
+    }
+
+    fn find_by_name(&self, _pattern: &str) -> Vec<ProcessInfo> {
+        Vec::new()
+    }
+
+    fn exit_code(&self, pid: u32) -> Option<i32> {
+        if self.is_running(pid) {
+            return None;
+        }
+        self.exit_codes.lock().unwrap().get(&pid).copied()
+    }
+
+    fn remove_exit_status(&self, pid: u32) {
+        self.exit_codes.lock().unwrap().remove(&pid);
+    }
+
+    fn remove_logs(&self, pid: u32) {
+        self.removed_logs.lock().unwrap().push(pid);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mock_spawn_and_kill() {
+        let monitor = MockMonitor::with_first_pid(42);
+
+        let pid = monitor
+            .spawn("serve --port 8000", &SpawnOptions::default())
+            .unwrap();
+        assert_eq!(pid, 42);
+        assert!(monitor.is_running(pid));
+        assert!(monitor.get_process_metrics(pid).is_some());
+
+        monitor.kill_process(pid).unwrap();
+        assert!(!monitor.is_running(pid));
+        assert_eq!(monitor.killed(), vec![42]);
+        assert_eq!(monitor.spawned(), vec!["serve --port 8000"]);
+    }
+
+    #[test]
+    fn test_mock_spawn_failure() {
+        let monitor = MockMonitor::new();
+        monitor.set_fail_spawns(true);
+        assert!(monitor.spawn("serve", &SpawnOptions::default()).is_err());
+
+        monitor.set_fail_spawns(false);
+        assert!(monitor.spawn("serve", &SpawnOptions::default()).is_ok());
+    }
+}
@@ -1,6 +1,9 @@
 //! Process monitoring with platform-specific backends
 
 mod backend;
+#[cfg(any(test, feature = "test-util"))]
+mod mock;
+mod signal;
 
 #[cfg(target_os = "macos")]
 mod macos;
@@ -8,20 +11,33 @@ mod macos;
 #[cfg(target_os = "linux")]
 mod linux;
 
-pub use backend::ProcessMonitor;
+pub use backend::{
+    exit_status_path, instance_log_paths, log_paths, ProcessInfo, ProcessMonitor, Protocol,
+    SpawnOptions, StartupFailed, DEFAULT_REFRESH_INTERVAL,
+};
+#[cfg(any(test, feature = "test-util"))]
+pub use mock::MockMonitor;
+pub use signal::Signal;
 
 use std::sync::Arc;
+use std::time::Duration;
 
 /// Create the appropriate process monitor for the current platform
 pub fn create_monitor() -> Arc<dyn ProcessMonitor> {
+    create_monitor_with_refresh(DEFAULT_REFRESH_INTERVAL)
+}
+
+/// Like `create_monitor`, refreshing the process table at most once per
+/// `interval` (see `[monitor] refresh_interval_ms`)
+pub fn create_monitor_with_refresh(interval: Duration) -> Arc<dyn ProcessMonitor> {
     #[cfg(target_os = "macos")]
     {
-        Arc::new(macos::MacOSMonitor::new())
+        Arc::new(macos::MacOSMonitor::with_refresh_interval(interval))
     }
 
     #[cfg(target_os = "linux")]
     {
-        Arc::new(linux::LinuxMonitor::new())
+        Arc::new(linux::LinuxMonitor::with_refresh_interval(interval))
     }
 
     #[cfg(not(any(target_os = "macos", target_os = "linux")))]
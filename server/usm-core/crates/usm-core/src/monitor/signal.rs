@@ -0,0 +1,134 @@
+//! Unix signal names understood by the process monitor
+
+use std::fmt;
+use std::str::FromStr;
+
+/// A Unix signal that can be sent to a managed process
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Signal {
+    Hup,
+    Int,
+    Quit,
+    Kill,
+    Usr1,
+    Usr2,
+    Term,
+    Cont,
+    Stop,
+    Tstp,
+    Winch,
+    Alrm,
+}
+
+impl Signal {
+    /// All supported signals
+    pub const ALL: [Signal; 12] = [
+        Signal::Hup,
+        Signal::Int,
+        Signal::Quit,
+        Signal::Kill,
+        Signal::Usr1,
+        Signal::Usr2,
+        Signal::Term,
+        Signal::Cont,
+        Signal::Stop,
+        Signal::Tstp,
+        Signal::Winch,
+        Signal::Alrm,
+    ];
+
+    /// Signal name without the `SIG` prefix, as accepted by `kill -s`
+    pub fn name(&self) -> &'static str {
+        match self {
+            Signal::Hup => "HUP",
+            Signal::Int => "INT",
+            Signal::Quit => "QUIT",
+            Signal::Kill => "KILL",
+            Signal::Usr1 => "USR1",
+            Signal::Usr2 => "USR2",
+            Signal::Term => "TERM",
+            Signal::Cont => "CONT",
+            Signal::Stop => "STOP",
+            Signal::Tstp => "TSTP",
+            Signal::Winch => "WINCH",
+            Signal::Alrm => "ALRM",
+        }
+    }
+}
+
+impl fmt::Display for Signal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "SIG{}", self.name())
+    }
+}
+
+impl FromStr for Signal {
+    type Err = anyhow::Error;
+
+    /// Parse a signal name such as `SIGUSR1`, `USR1` or `usr1`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let upper = s.trim().to_ascii_uppercase();
+        let name = upper.strip_prefix("SIG").unwrap_or(&upper);
+        Signal::ALL
+            .into_iter()
+            .find(|signal| signal.name() == name)
+            .ok_or_else(|| anyhow::anyhow!("Unknown signal '{}'", s))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::monitor::create_monitor;
+
+    #[test]
+    fn test_parse_signal_names() {
+        assert_eq!("SIGUSR1".parse::<Signal>().unwrap(), Signal::Usr1);
+        assert_eq!("USR2".parse::<Signal>().unwrap(), Signal::Usr2);
+        assert_eq!("sighup".parse::<Signal>().unwrap(), Signal::Hup);
+        assert!("SIGFOO".parse::<Signal>().is_err());
+        assert!("".parse::<Signal>().is_err());
+    }
+
+    #[test]
+    fn test_signal_display_roundtrip() {
+        for signal in Signal::ALL {
+            assert_eq!(signal.to_string().parse::<Signal>().unwrap(), signal);
+        }
+    }
+
+    #[test]
+    fn test_signal_trapping_process() {
+        let dir = tempfile::tempdir().unwrap();
+        let marker = dir.path().join("got-usr1");
+        let script = format!(
+            r#"trap 'touch "{}"' USR1; while :; do sleep 0.1; done"#,
+            marker.display()
+        );
+        let mut child = std::process::Command::new("/bin/sh")
+            .args(["-c", &script])
+            .spawn()
+            .unwrap();
+
+        // Give the shell a moment to install its trap
+        std::thread::sleep(std::time::Duration::from_millis(200));
+
+        let monitor = create_monitor();
+        monitor
+            .signal_process(child.id(), Signal::Usr1, false)
+            .unwrap();
+
+        let mut received = false;
+        for _ in 0..50 {
+            if marker.exists() {
+                received = true;
+                break;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(20));
+        }
+
+        let _ = child.kill();
+        let _ = child.wait();
+        assert!(received, "Process did not receive SIGUSR1");
+    }
+}
@@ -0,0 +1,318 @@
+//! Cron-style start/stop scheduling for instances
+//!
+//! An instance can carry a `schedule` with `start` and/or `stop` cron
+//! expressions. The scheduler is evaluated on a one-minute tick: each tick
+//! looks at the window since the previous one and reports the latest action
+//! that fell inside it.
+//!
+//! Expressions use the standard 5-field form (`min hour dom month dow`);
+//! the 6/7-field form with seconds (and years) is accepted too.
+//!
+//! When USM starts, scheduled times that passed while it was down are
+//! either ignored (`missed = "skip"`, the default) or the most recent one
+//! is applied (`missed = "catch_up"`), bringing each instance into the state
+//! its schedule says it should be in.
+
+use std::str::FromStr;
+
+use anyhow::Result;
+use chrono::{DateTime, Duration, Utc};
+use chrono_tz::Tz;
+use serde::{Deserialize, Serialize};
+
+/// Schedule attached to an instance (`[instances.<id>.schedule]`)
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ScheduleConfig {
+    /// Cron expression for starting the instance
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub start: Option<String>,
+
+    /// Cron expression for stopping the instance
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stop: Option<String>,
+
+    /// IANA timezone the expressions are evaluated in, e.g. "Europe/Berlin"
+    /// (defaults to `[scheduler] timezone`, then UTC)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timezone: Option<String>,
+}
+
+impl ScheduleConfig {
+    /// Check that the expressions and timezone parse
+    pub fn validate(&self) -> Result<()> {
+        CompiledSchedule::compile(self, Tz::UTC).map(|_| ())
+    }
+}
+
+/// What a schedule asks for
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ScheduledAction {
+    Start,
+    Stop,
+}
+
+impl std::fmt::Display for ScheduledAction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ScheduledAction::Start => write!(f, "start"),
+            ScheduledAction::Stop => write!(f, "stop"),
+        }
+    }
+}
+
+/// How to treat scheduled times that passed while USM was down
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MissedPolicy {
+    /// Only act on times after USM started
+    #[default]
+    Skip,
+    /// On startup, apply the most recent action that was missed
+    CatchUp,
+}
+
+/// Parse a cron expression, accepting the 5-field form without seconds
+pub fn parse_cron(expr: &str) -> Result<cron::Schedule> {
+    let expr = expr.trim();
+    let full = if expr.split_whitespace().count() == 5 {
+        format!("0 {}", expr)
+    } else {
+        expr.to_string()
+    };
+    cron::Schedule::from_str(&full)
+        .map_err(|e| anyhow::anyhow!("Invalid cron expression '{}': {}", expr, e))
+}
+
+/// Parse an IANA timezone name
+pub fn parse_timezone(name: &str) -> Result<Tz> {
+    Tz::from_str(name).map_err(|e| anyhow::anyhow!("Invalid timezone '{}': {}", name, e))
+}
+
+/// A schedule with its expressions parsed, ready to evaluate
+#[derive(Debug, Clone)]
+pub struct CompiledSchedule {
+    start: Option<cron::Schedule>,
+    stop: Option<cron::Schedule>,
+    timezone: Tz,
+}
+
+impl CompiledSchedule {
+    /// Parse a schedule, using `default_tz` if it doesn't name a timezone
+    pub fn compile(config: &ScheduleConfig, default_tz: Tz) -> Result<Self> {
+        Ok(Self {
+            start: config.start.as_deref().map(parse_cron).transpose()?,
+            stop: config.stop.as_deref().map(parse_cron).transpose()?,
+            timezone: match config.timezone.as_deref() {
+                Some(name) => parse_timezone(name)?,
+                None => default_tz,
+            },
+        })
+    }
+
+    /// The latest action scheduled at or before `at`, with its time
+    ///
+    /// If start and stop fall on the same time, stop wins.
+    pub fn latest_at_or_before(
+        &self,
+        at: DateTime<Utc>,
+    ) -> Option<(DateTime<Utc>, ScheduledAction)> {
+        let latest = |schedule: &Option<cron::Schedule>| {
+            let after = (at + Duration::seconds(1)).with_timezone(&self.timezone);
+            schedule
+                .as_ref()
+                .and_then(|s| s.after(&after).next_back())
+                .map(|t| t.with_timezone(&Utc))
+        };
+
+        match (latest(&self.start), latest(&self.stop)) {
+            (Some(start), Some(stop)) if start > stop => Some((start, ScheduledAction::Start)),
+            (_, Some(stop)) => Some((stop, ScheduledAction::Stop)),
+            (Some(start), None) => Some((start, ScheduledAction::Start)),
+            (None, None) => None,
+        }
+    }
+
+    /// The latest action scheduled in the window `(since, until]`
+    pub fn due_between(
+        &self,
+        since: DateTime<Utc>,
+        until: DateTime<Utc>,
+    ) -> Option<ScheduledAction> {
+        self.latest_at_or_before(until)
+            .filter(|(time, _)| *time > since)
+            .map(|(_, action)| action)
+    }
+}
+
+/// Tracks tick times and decides which scheduled actions are due
+///
+/// Time is always passed in, so callers (and tests) control the clock.
+#[derive(Debug, Clone)]
+pub struct Scheduler {
+    policy: MissedPolicy,
+    last_tick: Option<DateTime<Utc>>,
+}
+
+impl Scheduler {
+    /// Create a scheduler that hasn't ticked yet
+    pub fn new(policy: MissedPolicy) -> Self {
+        Self {
+            policy,
+            last_tick: None,
+        }
+    }
+
+    /// Actions due at `now`, in the order the schedules were given
+    ///
+    /// The first tick only returns actions under `MissedPolicy::CatchUp`.
+    pub fn tick<'a>(
+        &mut self,
+        schedules: impl IntoIterator<Item = (&'a str, &'a CompiledSchedule)>,
+        now: DateTime<Utc>,
+    ) -> Vec<(String, ScheduledAction)> {
+        let last_tick = self.last_tick.replace(now);
+
+        schedules
+            .into_iter()
+            .filter_map(|(id, schedule)| {
+                let action = match last_tick {
+                    Some(since) => schedule.due_between(since, now),
+                    None if self.policy == MissedPolicy::CatchUp => {
+                        schedule.latest_at_or_before(now).map(|(_, action)| action)
+                    },
+                    None => None,
+                };
+                action.map(|action| (id.to_string(), action))
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn utc(y: i32, mo: u32, d: u32, h: u32, mi: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(y, mo, d, h, mi, 0).unwrap()
+    }
+
+    fn business_hours(timezone: Option<&str>) -> CompiledSchedule {
+        CompiledSchedule::compile(
+            &ScheduleConfig {
+                start: Some("0 9 * * Mon-Fri".to_string()),
+                stop: Some("0 17 * * Mon-Fri".to_string()),
+                timezone: timezone.map(str::to_string),
+            },
+            Tz::UTC,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_parse_cron() {
+        assert!(parse_cron("0 9 * * Mon-Fri").is_ok());
+        assert!(parse_cron("30 0 9 * * *").is_ok()); // with seconds
+        assert!(parse_cron("not a cron").is_err());
+        assert!(parse_cron("61 * * * *").is_err());
+        assert!(parse_timezone("Europe/Berlin").is_ok());
+        assert!(parse_timezone("Mars/Olympus").is_err());
+
+        let bad = ScheduleConfig {
+            stop: Some("every day".to_string()),
+            ..Default::default()
+        };
+        assert!(bad
+            .validate()
+            .unwrap_err()
+            .to_string()
+            .contains("every day"));
+    }
+
+    #[test]
+    fn test_due_between() {
+        // 2024-01-08 is a Monday
+        let schedule = business_hours(None);
+        assert_eq!(
+            schedule.due_between(utc(2024, 1, 8, 8, 59), utc(2024, 1, 8, 9, 0)),
+            Some(ScheduledAction::Start)
+        );
+        assert_eq!(
+            schedule.due_between(utc(2024, 1, 8, 9, 0), utc(2024, 1, 8, 9, 1)),
+            None
+        );
+        assert_eq!(
+            schedule.due_between(utc(2024, 1, 8, 16, 59), utc(2024, 1, 8, 17, 0)),
+            Some(ScheduledAction::Stop)
+        );
+        // Saturday: nothing
+        assert_eq!(
+            schedule.due_between(utc(2024, 1, 13, 8, 59), utc(2024, 1, 13, 9, 0)),
+            None
+        );
+        // A window spanning both reports the later one
+        assert_eq!(
+            schedule.due_between(utc(2024, 1, 8, 8, 0), utc(2024, 1, 8, 18, 0)),
+            Some(ScheduledAction::Stop)
+        );
+    }
+
+    #[test]
+    fn test_timezone() {
+        // 09:00 in Berlin is 08:00 UTC in winter
+        let schedule = business_hours(Some("Europe/Berlin"));
+        assert_eq!(
+            schedule.due_between(utc(2024, 1, 8, 7, 59), utc(2024, 1, 8, 8, 0)),
+            Some(ScheduledAction::Start)
+        );
+        assert_eq!(
+            schedule.due_between(utc(2024, 1, 8, 8, 59), utc(2024, 1, 8, 9, 0)),
+            None
+        );
+    }
+
+    #[test]
+    fn test_scheduler_ticks() {
+        let schedule = business_hours(None);
+        let mut scheduler = Scheduler::new(MissedPolicy::Skip);
+
+        // First tick at 10:00: the 09:00 start was missed and is skipped
+        assert!(scheduler
+            .tick([("svc", &schedule)], utc(2024, 1, 8, 10, 0))
+            .is_empty());
+        assert!(scheduler
+            .tick([("svc", &schedule)], utc(2024, 1, 8, 16, 59))
+            .is_empty());
+        assert_eq!(
+            scheduler.tick([("svc", &schedule)], utc(2024, 1, 8, 17, 0)),
+            vec![("svc".to_string(), ScheduledAction::Stop)]
+        );
+        assert!(scheduler
+            .tick([("svc", &schedule)], utc(2024, 1, 8, 17, 1))
+            .is_empty());
+    }
+
+    #[test]
+    fn test_scheduler_catch_up() {
+        let schedule = business_hours(None);
+
+        // Down across Monday 09:00: catch up by starting
+        let mut scheduler = Scheduler::new(MissedPolicy::CatchUp);
+        assert_eq!(
+            scheduler.tick([("svc", &schedule)], utc(2024, 1, 8, 10, 0)),
+            vec![("svc".to_string(), ScheduledAction::Start)]
+        );
+        // Only on the first tick
+        assert!(scheduler
+            .tick([("svc", &schedule)], utc(2024, 1, 8, 10, 1))
+            .is_empty());
+
+        // Down over the weekend: the last action was Friday's stop
+        let mut scheduler = Scheduler::new(MissedPolicy::CatchUp);
+        assert_eq!(
+            scheduler.tick([("svc", &schedule)], utc(2024, 1, 13, 12, 0)),
+            vec![("svc".to_string(), ScheduledAction::Stop)]
+        );
+    }
+}
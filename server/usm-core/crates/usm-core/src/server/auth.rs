@@ -0,0 +1,101 @@
+//! Bearer token authentication for the HTTP API
+//!
+//! When a token is configured, every `/api/*` route, `/metrics` and the
+//! `/ws` endpoints require `Authorization: Bearer <token>`. Only the health
+//! check and the static dashboard stay public, as do CORS preflight requests
+//! (browsers never send credentials with those).
+
+use std::sync::Arc;
+
+use axum::{
+    extract::{Request, State},
+    http::{header, Method, StatusCode},
+    middleware::{self, Next},
+    response::{IntoResponse, Response},
+    Json, Router,
+};
+
+/// Paths that never require the token
+const PUBLIC_PATHS: &[&str] = &["/api/health"];
+
+/// Wrap `app` so protected routes require `token`
+///
+/// Leaving this layer off (no token configured) keeps the API open, which
+/// is the default for local use.
+pub fn require_token(app: Router, token: &str) -> Router {
+    let token: Arc<str> = Arc::from(token);
+    app.layer(middleware::from_fn_with_state(token, check_token))
+}
+
+async fn check_token(State(token): State<Arc<str>>, request: Request, next: Next) -> Response {
+    if !is_protected(request.method(), request.uri().path()) {
+        return next.run(request).await;
+    }
+
+    let presented = request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "));
+
+    match presented {
+        Some(presented) if constant_time_eq(presented.trim().as_bytes(), token.as_bytes()) => {
+            next.run(request).await
+        },
+        _ => (
+            StatusCode::UNAUTHORIZED,
+            [(header::WWW_AUTHENTICATE, "Bearer")],
+            Json(serde_json::json!({ "error": "Missing or invalid bearer token" })),
+        )
+            .into_response(),
+    }
+}
+
+/// Whether a request must carry the token
+fn is_protected(method: &Method, path: &str) -> bool {
+    if method == Method::OPTIONS || PUBLIC_PATHS.contains(&path) {
+        return false;
+    }
+    path == "/metrics"
+        || path == "/ws"
+        || path.starts_with("/ws/")
+        || path == "/api"
+        || path.starts_with("/api/")
+}
+
+/// Compare two byte strings without returning early on the first mismatch,
+/// so response timing doesn't reveal how much of a guess was right
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_protected() {
+        assert!(is_protected(&Method::GET, "/api/instances"));
+        assert!(is_protected(&Method::POST, "/api/instances/x/start"));
+        assert!(is_protected(&Method::GET, "/ws"));
+        assert!(is_protected(&Method::GET, "/ws/instances/x/logs"));
+        assert!(!is_protected(&Method::GET, "/api/health"));
+        assert!(is_protected(&Method::GET, "/api/openapi.json"));
+        assert!(is_protected(&Method::GET, "/api/version"));
+        assert!(is_protected(&Method::GET, "/metrics"));
+        assert!(!is_protected(&Method::OPTIONS, "/api/instances"));
+        assert!(!is_protected(&Method::GET, "/index.html"));
+        assert!(!is_protected(&Method::GET, "/apidocs"));
+    }
+
+    #[test]
+    fn test_constant_time_eq() {
+        assert!(constant_time_eq(b"secret", b"secret"));
+        assert!(!constant_time_eq(b"secret", b"secreT"));
+        assert!(!constant_time_eq(b"secret", b"secret2"));
+        assert!(!constant_time_eq(b"", b"secret"));
+    }
+}
@@ -1,102 +1,355 @@
 //! HTTP/WebSocket server for real-time service management
 
-use std::sync::Arc;
+use std::collections::{HashMap, HashSet};
+use std::future::IntoFuture;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+mod auth;
+mod openapi;
+
+pub use auth::require_token;
 
 use anyhow::Result;
 use axum::{
     extract::{Path, Query, State, WebSocketUpgrade},
     http::StatusCode,
     response::{IntoResponse, Json},
-    routing::{get, post},
+    routing::{get, post, put},
     Router,
 };
 use serde::Deserialize;
-use tokio::sync::RwLock;
+use tokio::sync::{broadcast, Notify};
 use tower_http::cors::CorsLayer;
-use tracing::{info, instrument};
+use tower_http::services::ServeDir;
+use tracing::{debug, info, instrument, warn};
 
-use crate::events::EventBus;
-use crate::monitor::ProcessMonitor;
+use crate::events::{InstanceEvents, ServiceEvent};
+use crate::logs::{LogLine, OutputFollower};
 use crate::service::{
-    InstanceConfig, InstanceRegistry, ServiceStatus, ServiceTemplate, TemplateRegistry,
+    InstanceConfig, InstanceFilter, InstanceUpdate, ServiceCategory, ServiceInstance,
+    ServiceStatus, ServiceTemplate, TagMatch,
 };
+use crate::UsmCore;
 
 /// Shared application state
 #[derive(Clone)]
 pub struct AppState {
-    pub templates: Arc<RwLock<TemplateRegistry>>,
-    pub instances: Arc<RwLock<InstanceRegistry>>,
-    pub monitor: Arc<dyn ProcessMonitor>,
-    pub event_bus: Arc<EventBus>,
+    pub core: Arc<UsmCore>,
+    /// Instances with a start, stop or restart in progress
+    pub in_flight: InFlight,
+}
+
+impl AppState {
+    pub fn new(core: Arc<UsmCore>) -> Self {
+        Self {
+            core,
+            in_flight: InFlight::default(),
+        }
+    }
+}
+
+/// Ids of instances with a lifecycle operation in progress
+///
+/// Start, stop and restart requests claim their instance for as long as
+/// they run, so a client retrying in a loop gets 429 instead of launching
+/// (or racing) a second operation on the same instance.
+#[derive(Clone, Default)]
+pub struct InFlight(Arc<Mutex<HashSet<String>>>);
+
+impl InFlight {
+    /// Claim `id` until the returned guard is dropped
+    pub fn begin(&self, id: &str) -> Result<InFlightGuard, (StatusCode, String)> {
+        let mut ids = self.0.lock().unwrap();
+        if !ids.insert(id.to_string()) {
+            return Err((
+                StatusCode::TOO_MANY_REQUESTS,
+                format!("Instance '{}' is busy with another operation", id),
+            ));
+        }
+        Ok(InFlightGuard {
+            ids: self.0.clone(),
+            id: id.to_string(),
+        })
+    }
+
+    /// Whether an operation on `id` is in progress
+    pub fn contains(&self, id: &str) -> bool {
+        self.0.lock().unwrap().contains(id)
+    }
+}
+
+/// Releases an instance claimed with `InFlight::begin`
+pub struct InFlightGuard {
+    ids: Arc<Mutex<HashSet<String>>>,
+    id: String,
+}
+
+impl Drop for InFlightGuard {
+    fn drop(&mut self) {
+        self.ids.lock().unwrap().remove(&self.id);
+    }
 }
 
-/// Run the HTTP/WebSocket server
+/// How long open connections (e.g. WebSockets) may delay shutdown
+const SHUTDOWN_GRACE: Duration = Duration::from_secs(10);
+
+/// Run the HTTP/WebSocket server on `addr` until SIGINT or SIGTERM
+///
+/// If `static_dir` is set, files in it are served at `/` as a fallback for
+/// any path not matched by the API or WebSocket routes. On a signal the
+/// server stops accepting connections and waits up to `SHUTDOWN_GRACE` for
+/// open ones to finish before returning. With an `auth_token` the API and
+/// WebSocket routes require it as a bearer token (see `require_token`).
 #[instrument(skip_all)]
 pub async fn run_server(
-    port: u16,
-    templates: Arc<RwLock<TemplateRegistry>>,
-    instances: Arc<RwLock<InstanceRegistry>>,
-    monitor: Arc<dyn ProcessMonitor>,
-    event_bus: Arc<EventBus>,
+    addr: SocketAddr,
+    core: Arc<UsmCore>,
+    static_dir: Option<PathBuf>,
+    auth_token: Option<String>,
 ) -> Result<()> {
-    let state = AppState {
-        templates,
-        instances,
-        monitor,
-        event_bus,
+    if let Some(ref dir) = static_dir {
+        info!(static_dir = %dir.display(), "Serving static dashboard");
+    }
+    let mut app = build_router(core, static_dir);
+    if let Some(ref token) = auth_token {
+        info!("API requires a bearer token");
+        app = require_token(app, token);
+    }
+
+    let listener = bind(addr).await?;
+    info!(addr = %addr, "USM Core server listening");
+
+    let stop = Arc::new(Notify::new());
+    let server = axum::serve(listener, app).with_graceful_shutdown({
+        let stop = stop.clone();
+        async move { stop.notified().await }
+    });
+    let server = server.into_future();
+    tokio::pin!(server);
+
+    tokio::select! {
+        result = &mut server => return Ok(result?),
+        _ = shutdown_signal() => {},
+    }
+
+    info!("Shutting down server");
+    stop.notify_one();
+    match tokio::time::timeout(SHUTDOWN_GRACE, server).await {
+        Ok(result) => result?,
+        Err(_) => warn!(
+            "Connections still open after {}s, shutting down anyway",
+            SHUTDOWN_GRACE.as_secs()
+        ),
+    }
+    Ok(())
+}
+
+/// Bind the listening socket, explaining the common failures
+async fn bind(addr: SocketAddr) -> Result<tokio::net::TcpListener> {
+    tokio::net::TcpListener::bind(addr)
+        .await
+        .map_err(|e| match e.kind() {
+            std::io::ErrorKind::AddrInUse => anyhow::anyhow!(
+                "Cannot listen on {}: port {} is already in use (is another USM server running?)",
+                addr,
+                addr.port()
+            ),
+            std::io::ErrorKind::AddrNotAvailable => anyhow::anyhow!(
+                "Cannot listen on {}: {} is not an address of this machine",
+                addr,
+                addr.ip()
+            ),
+            std::io::ErrorKind::PermissionDenied => anyhow::anyhow!(
+                "Cannot listen on {}: permission denied (ports below 1024 need root)",
+                addr
+            ),
+            _ => anyhow::anyhow!("Cannot listen on {}: {}", addr, e),
+        })
+}
+
+/// Resolve on the first SIGINT (Ctrl-C) or SIGTERM
+async fn shutdown_signal() {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut terminate = match signal(SignalKind::terminate()) {
+        Ok(terminate) => Some(terminate),
+        Err(e) => {
+            warn!("Cannot listen for SIGTERM: {}", e);
+            None
+        },
+    };
+    let sigterm = async {
+        match terminate.as_mut() {
+            Some(terminate) => {
+                terminate.recv().await;
+            },
+            None => std::future::pending().await,
+        }
     };
 
-    let app = Router::new()
+    let sigint = async {
+        if let Err(e) = tokio::signal::ctrl_c().await {
+            warn!("Cannot listen for SIGINT: {}", e);
+            std::future::pending::<()>().await;
+        }
+    };
+
+    tokio::select! {
+        _ = sigint => info!("Received SIGINT"),
+        _ = sigterm => info!("Received SIGTERM"),
+    }
+}
+
+/// Build the application router
+pub fn build_router(core: Arc<UsmCore>, static_dir: Option<PathBuf>) -> Router {
+    let state = AppState::new(core);
+
+    let mut app = Router::new()
         // Health check
         .route("/api/health", get(health_check))
+        .route("/api/openapi.json", get(openapi))
+        .route("/api/version", get(version))
         // Templates
         .route("/api/templates", get(list_templates))
-        .route("/api/templates/:id", get(get_template))
+        .route(
+            "/api/templates/:id",
+            get(get_template).delete(delete_template),
+        )
         .route("/api/templates", post(create_template))
         // Instances
         .route("/api/instances", get(list_instances))
-        .route("/api/instances/:id", get(get_instance))
+        .route(
+            "/api/instances/:id",
+            get(get_instance)
+                .patch(update_instance)
+                .delete(delete_instance),
+        )
         .route("/api/instances", post(create_instance))
         .route("/api/instances/:id/start", post(start_instance))
         .route("/api/instances/:id/stop", post(stop_instance))
         .route("/api/instances/:id/restart", post(restart_instance))
+        .route("/api/instances/:id/reset", post(reset_instance))
+        .route("/api/instances/:id/clone", post(clone_instance))
+        .route("/api/instances/:id/signal", post(signal_instance))
+        .route("/api/instances/:id/reload", post(reload_instance))
+        .route("/api/instances/:id/port", put(set_instance_port))
+        .route("/api/instances/:id/logs", get(get_instance_logs))
+        .route(
+            "/api/instances/:id/metrics/history",
+            get(get_instance_metrics_history),
+        )
+        .route(
+            "/api/instances/:id/env",
+            get(get_instance_env).patch(patch_instance_env),
+        )
         // Metrics
         .route("/api/metrics", get(get_metrics))
+        .route("/api/metrics/instances", get(get_all_instance_metrics))
+        .route("/api/metrics/history", get(get_metrics_history))
+        .route("/metrics", get(prometheus_metrics))
+        // Diagnostics
+        .route("/api/diagnostics/reconcile", get(reconcile))
+        .route("/api/diagnostics/zombies", get(zombies))
         // WebSocket
         .route("/ws", get(websocket_handler))
+        .route("/ws/instances/:id", get(instance_events_websocket))
+        .route("/ws/instances/:id/logs", get(instance_logs_websocket));
+
+    // Static dashboard (explicit routes above take precedence)
+    if let Some(dir) = static_dir {
+        app = app.fallback_service(ServeDir::new(dir));
+    }
+
+    app
         // CORS
         .layer(CorsLayer::permissive())
-        .with_state(state);
-
-    let listener = tokio::net::TcpListener::bind(format!("0.0.0.0:{}", port)).await?;
-    info!(port = port, "USM Core server listening");
+        .with_state(state)
+}
 
-    axum::serve(listener, app).await?;
-    Ok(())
+/// Map an error from a core operation to an HTTP status
+fn core_error(e: anyhow::Error) -> (StatusCode, String) {
+    let message = e.to_string();
+    let status = if message.contains("not found") {
+        StatusCode::NOT_FOUND
+    } else if message.starts_with("Invalid") {
+        StatusCode::BAD_REQUEST
+    } else if message.ends_with("is not running") {
+        StatusCode::CONFLICT
+    } else {
+        StatusCode::INTERNAL_SERVER_ERROR
+    };
+    (status, message)
 }
 
 // === Health Check ===
 
-async fn health_check() -> Json<serde_json::Value> {
-    Json(serde_json::json!({
-        "status": "ok",
-        "service": "USM Core",
-        "version": env!("CARGO_PKG_VERSION")
-    }))
+/// 503 while an auto_start or critical-tagged instance is in Error, so load
+/// balancers and uptime monitors can take the node out of rotation
+async fn health_check(State(state): State<AppState>) -> (StatusCode, Json<serde_json::Value>) {
+    let event_bus = &state.core.event_bus;
+    let unhealthy = state.core.unhealthy_instances().await;
+    let (code, status) = if unhealthy.is_empty() {
+        (StatusCode::OK, "ok")
+    } else {
+        (StatusCode::SERVICE_UNAVAILABLE, "degraded")
+    };
+    (
+        code,
+        Json(serde_json::json!({
+            "status": status,
+            "unhealthy": unhealthy,
+            "service": "USM Core",
+            "version": crate::VERSION,
+            "ws_subscribers": event_bus.subscriber_count(),
+            "event_capacity": event_bus.capacity(),
+            "event_lagged": event_bus.lag_count()
+        })),
+    )
+}
+
+/// Version, git commit and build time of the running server
+async fn version() -> Json<crate::BuildInfo> {
+    Json(crate::build_info())
+}
+
+/// OpenAPI document describing these routes
+async fn openapi() -> Json<serde_json::Value> {
+    Json(openapi::document())
 }
 
 // === Templates ===
 
-async fn list_templates(State(state): State<AppState>) -> Json<Vec<ServiceTemplate>> {
-    let templates = state.templates.read().await;
-    Json(templates.list())
+#[derive(Debug, Deserialize)]
+struct TemplateQuery {
+    category: Option<String>,
+}
+
+async fn list_templates(
+    State(state): State<AppState>,
+    Query(query): Query<TemplateQuery>,
+) -> Result<Json<Vec<ServiceTemplate>>, (StatusCode, String)> {
+    let category = query
+        .category
+        .as_deref()
+        .map(str::parse::<ServiceCategory>)
+        .transpose()
+        .map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?;
+
+    let templates = state.core.templates.read().await;
+    Ok(Json(match category {
+        Some(category) => templates.list_by_category(category),
+        None => templates.list(),
+    }))
 }
 
 async fn get_template(
     State(state): State<AppState>,
     Path(id): Path<String>,
 ) -> Result<Json<ServiceTemplate>, StatusCode> {
-    let templates = state.templates.read().await;
+    let templates = state.core.templates.read().await;
     templates.get(&id).map(Json).ok_or(StatusCode::NOT_FOUND)
 }
 
@@ -104,20 +357,59 @@ async fn create_template(
     State(state): State<AppState>,
     Json(template): Json<ServiceTemplate>,
 ) -> Result<Json<ServiceTemplate>, (StatusCode, String)> {
-    let mut templates = state.templates.write().await;
-    templates
-        .register(template.clone())
-        .map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?;
+    state
+        .core
+        .register_template(template.clone())
+        .await
+        .map_err(|e| {
+            let message = e.to_string();
+            if message.starts_with("Template '") {
+                (StatusCode::BAD_REQUEST, message)
+            } else {
+                core_error(e)
+            }
+        })?;
+
+    info!(template_id = %template.id, "Template registered via HTTP API");
+
     Ok(Json(template))
 }
 
+/// Remove a template; 409 while instances still use it
+async fn delete_template(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    state.core.remove_template(&id).await.map_err(|e| {
+        if e.to_string().starts_with("Cannot remove template") {
+            (StatusCode::CONFLICT, e.to_string())
+        } else {
+            core_error(e)
+        }
+    })?;
+
+    info!(template_id = %id, "Template removed via HTTP API");
+
+    Ok(Json(serde_json::json!({
+        "status": "ok",
+        "message": format!("Removed template {}", id),
+        "template_id": id
+    })))
+}
+
 // === Instances ===
 
 #[derive(Debug, Deserialize)]
 struct InstanceQuery {
+    group: Option<String>,
     template: Option<String>,
+    /// Comma-separated tags
     tag: Option<String>,
+    /// How several tags combine: "any" (default) or "all"
+    tag_mode: Option<String>,
     status: Option<String>,
+    /// Text the id, template id or a tag must contain (any case)
+    q: Option<String>,
 }
 
 /// Helper to insert CPU and memory metrics into a JSON object
@@ -136,70 +428,80 @@ fn insert_metrics(
 async fn list_instances(
     State(state): State<AppState>,
     Query(query): Query<InstanceQuery>,
-) -> Json<serde_json::Value> {
-    // Snapshot instance data while holding the lock, then release it
-    let (list, counts, total) = {
-        let instances = state.instances.read().await;
-        let mut list = instances.list();
-
-        // Filter by template
-        if let Some(ref template) = query.template {
-            list.retain(|i| &i.template_id == template);
-        }
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    let status = query
+        .status
+        .as_deref()
+        .map(str::parse::<ServiceStatus>)
+        .transpose()
+        .map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?;
 
-        // Filter by tag
-        if let Some(ref tag) = query.tag {
-            list.retain(|i| i.has_tag(tag));
-        }
+    let tag_match = query
+        .tag_mode
+        .as_deref()
+        .map(str::parse::<TagMatch>)
+        .transpose()
+        .map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?
+        .unwrap_or_default();
+
+    let filter = InstanceFilter {
+        group: query.group,
+        template: query.template,
+        tags: query
+            .tag
+            .iter()
+            .flat_map(|t| t.split(','))
+            .map(str::trim)
+            .filter(|t| !t.is_empty())
+            .map(str::to_string)
+            .collect(),
+        tag_match,
+        status,
+    };
 
-        // Filter by status
-        if let Some(ref status) = query.status {
-            let status = match status.as_str() {
-                "running" => Some(ServiceStatus::Running),
-                "stopped" => Some(ServiceStatus::Stopped),
-                "error" => Some(ServiceStatus::Error),
-                _ => None,
-            };
-            if let Some(s) = status {
-                list.retain(|i| i.status == s);
-            }
-        }
+    let instances = state.core.instances.read().await;
+    let mut list = instances.query(&filter);
+    if let Some(q) = &query.q {
+        let found: HashSet<String> = instances.search(q).into_iter().map(|i| i.id).collect();
+        list.retain(|i| found.contains(&i.id));
+    }
+    Ok(instance_list_response(&state, instances, list).await)
+}
 
-        let counts = instances.status_counts();
-        let total = instances.len();
-        (list, counts, total)
-    }; // Lock released here
+/// The `list_instances` body for `list`, with status counts over all of
+/// `instances`
+///
+/// Takes the registry lock guard so it can release it before querying
+/// metrics.
+async fn instance_list_response(
+    state: &AppState,
+    instances: tokio::sync::RwLockReadGuard<'_, crate::service::InstanceRegistry>,
+    list: Vec<crate::service::ServiceInstance>,
+) -> Json<serde_json::Value> {
+    let counts = instances.status_counts();
+    let total = instances.len();
+    drop(instances); // Lock released here
 
     // Build instances with metrics (monitor calls are outside the lock)
-    let instances_with_metrics: Vec<serde_json::Value> = list
-        .iter()
-        .map(|instance| {
-            let mut json = match serde_json::to_value(instance) {
-                Ok(v) => v,
-                Err(e) => {
-                    tracing::warn!("Failed to serialize instance {}: {}", instance.id, e);
-                    serde_json::json!({})
-                },
-            };
-            // Add metrics for running instances - try by port first (more reliable), then by PID
-            if instance.status == ServiceStatus::Running {
-                // Try to find process by port (most reliable for child processes)
-                if let Some(info) = state.monitor.find_by_port(instance.port) {
-                    if let Some(obj) = json.as_object_mut() {
-                        insert_metrics(obj, info.cpu_percent, info.memory_bytes);
-                    }
-                } else if let Some(pid) = instance.pid {
-                    // Fallback to stored PID
-                    if let Some(metrics) = state.monitor.get_process_metrics(pid) {
-                        if let Some(obj) = json.as_object_mut() {
-                            insert_metrics(obj, metrics.cpu_percent, metrics.memory_bytes);
-                        }
-                    }
-                }
+    let mut instances_with_metrics: Vec<serde_json::Value> = Vec::with_capacity(list.len());
+    for instance in &list {
+        let mut json = match serde_json::to_value(instance) {
+            Ok(v) => v,
+            Err(e) => {
+                tracing::warn!("Failed to serialize instance {}: {}", instance.id, e);
+                serde_json::json!({})
+            },
+        };
+        if let Some(obj) = json.as_object_mut() {
+            let name = state.core.display_name(instance).await;
+            obj.insert("name".to_string(), serde_json::json!(name));
+            // Add metrics for running instances
+            if let Some((cpu, memory_bytes)) = state.core.resource_usage(instance).await {
+                insert_metrics(obj, cpu, memory_bytes);
             }
-            json
-        })
-        .collect();
+        }
+        instances_with_metrics.push(json);
+    }
 
     Json(serde_json::json!({
         "instances": instances_with_metrics,
@@ -214,62 +516,218 @@ async fn get_instance(
     State(state): State<AppState>,
     Path(id): Path<String>,
 ) -> Result<Json<serde_json::Value>, StatusCode> {
-    let instances = state.instances.read().await;
-    let instance = instances.get(&id).ok_or(StatusCode::NOT_FOUND)?;
+    let instance = state
+        .core
+        .get_instance(&id)
+        .await
+        .ok_or(StatusCode::NOT_FOUND)?;
 
     // Get metrics if running
     let metrics = instance
         .pid
-        .and_then(|pid| state.monitor.get_process_metrics(pid));
+        .and_then(|pid| state.core.monitor.get_process_metrics(pid));
+
+    let mut json =
+        serde_json::to_value(&instance).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    json["name"] = serde_json::json!(state.core.display_name(&instance).await);
 
     Ok(Json(serde_json::json!({
-        "instance": instance,
+        "instance": json,
         "metrics": metrics
     })))
 }
 
+/// Edit env vars, tags, labels, metadata, working dir, version or git
+/// branch of an instance
+async fn update_instance(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    Json(update): Json<InstanceUpdate>,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    let requires_restart = update.requires_restart();
+    let instance = state
+        .core
+        .update_instance(&id, update)
+        .await
+        .map_err(core_error)?;
+
+    info!(instance_id = %id, "Instance updated via HTTP API");
+
+    Ok(Json(serde_json::json!({
+        "status": "ok",
+        "instance": instance,
+        "restart_required": requires_restart && instance.status == ServiceStatus::Running
+    })))
+}
+
+#[derive(Debug, Deserialize)]
+struct CreateQuery {
+    /// Update the instance in place if it already exists
+    #[serde(default)]
+    upsert: bool,
+}
+
+/// Create an instance
+///
+/// With `upsert=true` an existing instance of the same template is
+/// updated instead of rejected (see `UsmCore::upsert_instance`).
 async fn create_instance(
     State(state): State<AppState>,
+    Query(query): Query<CreateQuery>,
     Json(config): Json<InstanceConfig>,
 ) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
-    // Verify template exists
-    let templates = state.templates.read().await;
-    let template = templates.get(&config.template_id).ok_or((
-        StatusCode::BAD_REQUEST,
-        format!("Template '{}' not found", config.template_id),
-    ))?;
+    if query.upsert {
+        return upsert_instance(&state, config).await;
+    }
 
-    // Determine port
-    let port = config.port.unwrap_or(template.default_port);
-    drop(templates);
+    let instance_id = state
+        .core
+        .create_instance(config)
+        .await
+        .map_err(create_error)?;
+    let port = state.core.get_instance(&instance_id).await.map(|i| i.port);
 
-    // Create instance
-    let mut config = config;
-    config.port = Some(port);
+    info!(instance_id = %instance_id, "Instance created via HTTP API");
 
-    let instance = crate::service::ServiceInstance::from_config(config.clone())
-        .map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?;
+    Ok(Json(serde_json::json!({
+        "status": "ok",
+        "instance_id": instance_id,
+        "port": port
+    })))
+}
+
+/// Status for a failed create or upsert: 409 for conflicts with existing
+/// instances or limits, 400 for an invalid config
+fn create_error(e: anyhow::Error) -> (StatusCode, String) {
+    let message = e.to_string();
+    if message.starts_with("Cannot change template")
+        || message.contains("already in use")
+        || message.contains("already exists")
+        || message.contains("does not support multiple instances")
+        || message.contains("limit reached")
+        || message.starts_with("No free port")
+    {
+        (StatusCode::CONFLICT, message)
+    } else if message.starts_with("Template '")
+        || message.starts_with("Instance '")
+        || message.contains("cannot be empty")
+        || message.contains("outside the port range")
+    {
+        (StatusCode::BAD_REQUEST, message)
+    } else {
+        core_error(e)
+    }
+}
 
-    let instance_id = instance.id.clone();
+async fn upsert_instance(
+    state: &AppState,
+    config: InstanceConfig,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    let (instance_id, created) = state
+        .core
+        .upsert_instance(config)
+        .await
+        .map_err(create_error)?;
+    let port = state.core.get_instance(&instance_id).await.map(|i| i.port);
 
-    let mut instances = state.instances.write().await;
-    instances
-        .add(instance)
-        .map_err(|e| (StatusCode::CONFLICT, e.to_string()))?;
+    info!(instance_id = %instance_id, created, "Instance upserted via HTTP API");
 
     Ok(Json(serde_json::json!({
         "status": "ok",
         "instance_id": instance_id,
-        "port": port
+        "port": port,
+        "created": created
     })))
 }
 
-async fn start_instance(
+#[derive(Debug, Deserialize)]
+struct DeleteQuery {
+    #[serde(default)]
+    force: bool,
+}
+
+/// Remove an instance, stopping it first if it's running
+///
+/// If stopping fails the instance is kept, unless `force=true`: then its
+/// process gets SIGKILL and `stop` reports what happened to it.
+async fn delete_instance(
     State(state): State<AppState>,
     Path(id): Path<String>,
+    Query(query): Query<DeleteQuery>,
 ) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
-    let mut instances = state.instances.write().await;
-    let instance = instances.get_mut(&id).ok_or((
+    if state.core.get_instance(&id).await.is_none() {
+        return Err((
+            StatusCode::NOT_FOUND,
+            format!("Instance '{}' not found", id),
+        ));
+    }
+    let stop = if query.force {
+        let outcome = state
+            .core
+            .force_remove_instance(&id)
+            .await
+            .map_err(core_error)?;
+        Some(outcome.to_string())
+    } else {
+        state.core.remove_instance(&id).await.map_err(core_error)?;
+        None
+    };
+
+    info!(instance_id = %id, force = query.force, "Instance removed via HTTP API");
+
+    let mut body = serde_json::json!({
+        "status": "ok",
+        "message": format!("Removed instance {}", id),
+        "instance_id": id
+    });
+    if let Some(stop) = stop {
+        body["stop"] = stop.into();
+    }
+    Ok(Json(body))
+}
+
+/// Lines of stderr included when a start fails
+const START_FAILURE_STDERR_LINES: usize = 20;
+
+/// A failed start: a status and message, plus JSON details when launching failed
+#[derive(Debug)]
+struct StartError {
+    code: StatusCode,
+    message: String,
+    details: Option<serde_json::Value>,
+}
+
+impl From<(StatusCode, String)> for StartError {
+    fn from((code, message): (StatusCode, String)) -> Self {
+        Self {
+            code,
+            message,
+            details: None,
+        }
+    }
+}
+
+impl IntoResponse for StartError {
+    fn into_response(self) -> axum::response::Response {
+        match self.details {
+            Some(details) => (self.code, Json(details)).into_response(),
+            None => (self.code, self.message).into_response(),
+        }
+    }
+}
+
+/// Start an instance
+///
+/// Responds with the resolved `command` and `working_dir`. When launching
+/// fails the 500 body is JSON too, adding the `error` and the instance's
+/// most recent captured `stderr` lines. The instance is `Starting` while
+/// the launch is verified (see `UsmCore::start_instance`).
+async fn start_instance(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> Result<Json<serde_json::Value>, StartError> {
+    let _busy = state.in_flight.begin(&id)?;
+    let instance = state.core.get_instance(&id).await.ok_or((
         StatusCode::NOT_FOUND,
         format!("Instance '{}' not found", id),
     ))?;
@@ -283,111 +741,108 @@ async fn start_instance(
         })));
     }
 
+    // Starting, stopping and limit errors are conflicts
+    let (instance, previous) =
+        state
+            .core
+            .begin_start(&id)
+            .await
+            .map_err(|e| match core_error(e) {
+                (StatusCode::NOT_FOUND, message) => (StatusCode::NOT_FOUND, message),
+                (_, message) => (StatusCode::CONFLICT, message),
+            })?;
+
     // Get template for start command
-    let templates = state.templates.read().await;
-    let template = templates.get(&instance.template_id).ok_or((
-        StatusCode::BAD_REQUEST,
-        format!("Template '{}' not found", instance.template_id),
-    ))?;
+    let Some(template) = state.core.get_template(&instance.template_id).await else {
+        let message = format!("Template '{}' not found", instance.template_id);
+        let _ = state
+            .core
+            .finish_start(&id, previous, Err(anyhow::anyhow!(message.clone())))
+            .await;
+        return Err(StartError::from((StatusCode::BAD_REQUEST, message)));
+    };
 
     // Build and execute start command
-    let command = template.build_start_command(instance);
-    let pid = state
-        .monitor
-        .start_process_with_port(
-            &command,
-            instance.working_dir.as_deref(),
-            Some(instance.port),
-        )
-        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
-
-    // Update instance state
-    instance.status = ServiceStatus::Running;
-    instance.pid = Some(pid);
-    instance.started_at = Some(chrono::Utc::now());
-
-    // Broadcast event
-    state
-        .event_bus
-        .send(crate::events::ServiceEvent::StatusChanged {
-            instance_id: id.clone(),
-            status: ServiceStatus::Running,
-            pid: Some(pid),
-        });
+    let (command, options) = state.core.prepare_start(&template, &instance).await;
+    let result = state
+        .core
+        .launch_prepared(&template, &instance, &command, &options)
+        .await;
+    let stderr = result.is_err().then(|| {
+        state
+            .core
+            .stderr_tail(&instance, START_FAILURE_STDERR_LINES)
+    });
+    let pid = match state.core.finish_start(&id, previous, result).await {
+        Ok(pid) => pid,
+        Err(e) => {
+            return Err(StartError {
+                code: StatusCode::INTERNAL_SERVER_ERROR,
+                message: e.to_string(),
+                details: Some(serde_json::json!({
+                    "status": "error",
+                    "message": format!("Failed to start instance {}", id),
+                    "error": e.to_string(),
+                    "command": command,
+                    "working_dir": options.working_dir,
+                    "stderr": stderr.unwrap_or_default()
+                })),
+            });
+        },
+    };
 
-    info!(instance_id = %id, pid = %pid, "Instance started via HTTP API");
+    info!(instance_id = %id, pid = ?pid, "Instance started via HTTP API");
 
     Ok(Json(serde_json::json!({
         "status": "ok",
         "message": format!("Started instance {}", id),
-        "pid": pid
+        "pid": pid,
+        "command": command,
+        "working_dir": options.working_dir
     })))
 }
 
+/// Stop an instance
+///
+/// `command` is the stop command that was run, or null if the process was
+/// signalled.
 async fn stop_instance(
     State(state): State<AppState>,
     Path(id): Path<String>,
 ) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
-    let mut instances = state.instances.write().await;
-    let instance = instances.get_mut(&id).ok_or((
+    let _busy = state.in_flight.begin(&id)?;
+    let instance = state.core.get_instance(&id).await.ok_or((
         StatusCode::NOT_FOUND,
         format!("Instance '{}' not found", id),
     ))?;
 
-    // Check if already stopped
-    if instance.status != ServiceStatus::Running {
+    // Check if already stopped (crashed instances are settled as Stopped)
+    if !matches!(
+        instance.status,
+        ServiceStatus::Running | ServiceStatus::Error
+    ) {
         return Ok(Json(serde_json::json!({
             "status": "ok",
             "message": format!("Instance {} is already stopped", id)
         })));
     }
 
-    // Get template for optional custom stop command
-    let templates = state.templates.read().await;
-    let template = templates.get(&instance.template_id);
-
-    // Stop the process
-    if let Some(pid) = instance.pid {
-        if let Some(tmpl) = template {
-            if let Some(stop_cmd) = &tmpl.stop_command {
-                let cmd = stop_cmd.replace("{pid}", &pid.to_string());
-                state
-                    .monitor
-                    .execute_command(&cmd)
-                    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
-            } else {
-                state
-                    .monitor
-                    .kill_process(pid)
-                    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
-            }
-        } else {
-            state
-                .monitor
-                .kill_process(pid)
-                .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
-        }
-    }
-
-    // Update instance state
-    instance.status = ServiceStatus::Stopped;
-    instance.pid = None;
-    instance.started_at = None;
-
-    // Broadcast event
-    state
-        .event_bus
-        .send(crate::events::ServiceEvent::StatusChanged {
-            instance_id: id.clone(),
-            status: ServiceStatus::Stopped,
-            pid: None,
-        });
+    // Report the command for the process `stop_instance` will target, not
+    // the possibly exited one the instance records
+    let template = state.core.get_template(&instance.template_id).await;
+    let mut instance = instance;
+    instance.pid = state
+        .core
+        .stop_pid(template.as_ref(), &instance, instance.status);
+    let command = state.core.stop_command(template.as_ref(), &instance).await;
+    state.core.stop_instance(&id).await.map_err(core_error)?;
 
     info!(instance_id = %id, "Instance stopped via HTTP API");
 
     Ok(Json(serde_json::json!({
         "status": "ok",
-        "message": format!("Stopped instance {}", id)
+        "message": format!("Stopped instance {}", id),
+        "command": command
     })))
 }
 
@@ -395,103 +850,481 @@ async fn restart_instance(
     State(state): State<AppState>,
     Path(id): Path<String>,
 ) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
-    // Stop first
-    let mut instances = state.instances.write().await;
-    let instance = instances.get_mut(&id).ok_or((
-        StatusCode::NOT_FOUND,
-        format!("Instance '{}' not found", id),
-    ))?;
+    let _busy = state.in_flight.begin(&id)?;
+    state.core.restart_instance(&id).await.map_err(core_error)?;
 
-    // Get template
-    let templates = state.templates.read().await;
-    let template = templates.get(&instance.template_id).ok_or((
-        StatusCode::BAD_REQUEST,
-        format!("Template '{}' not found", instance.template_id),
-    ))?;
+    let pid = state.core.get_instance(&id).await.and_then(|i| i.pid);
+    info!(instance_id = %id, pid = ?pid, "Instance restarted via HTTP API");
 
-    // Stop if running
-    if instance.status == ServiceStatus::Running {
-        if let Some(pid) = instance.pid {
-            if let Some(stop_cmd) = &template.stop_command {
-                let cmd = stop_cmd.replace("{pid}", &pid.to_string());
-                let _ = state.monitor.execute_command(&cmd);
-            } else {
-                let _ = state.monitor.kill_process(pid);
-            }
+    Ok(Json(serde_json::json!({
+        "status": "ok",
+        "message": format!("Restarted instance {}", id),
+        "pid": pid
+    })))
+}
+
+/// Clear an instance's error state (see `UsmCore::reset_instance`)
+async fn reset_instance(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    let _busy = state.in_flight.begin(&id)?;
+    state.core.reset_instance(&id).await.map_err(|e| {
+        let message = e.to_string();
+        if message.starts_with("Cannot reset") {
+            (StatusCode::CONFLICT, message)
+        } else {
+            core_error(e)
         }
-        instance.status = ServiceStatus::Stopped;
-        instance.pid = None;
-    }
+    })?;
 
-    // Brief delay before restart
-    drop(templates);
-    drop(instances);
-    tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
+    info!(instance_id = %id, "Instance reset via HTTP API");
 
-    // Start again
-    let mut instances = state.instances.write().await;
-    let instance = instances.get_mut(&id).ok_or((
-        StatusCode::NOT_FOUND,
-        format!("Instance '{}' not found after stop", id),
-    ))?;
+    Ok(Json(serde_json::json!({
+        "status": "ok",
+        "message": format!("Reset instance {}", id)
+    })))
+}
 
-    let templates = state.templates.read().await;
-    let template = templates.get(&instance.template_id).ok_or((
-        StatusCode::BAD_REQUEST,
-        format!("Template '{}' not found", instance.template_id),
-    ))?;
+#[derive(Debug, Deserialize)]
+struct SignalRequest {
+    signal: String,
+    #[serde(default)]
+    group: bool,
+}
 
-    let command = template.build_start_command(instance);
+async fn signal_instance(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    Json(request): Json<SignalRequest>,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
     let pid = state
-        .monitor
-        .start_process_with_port(
-            &command,
-            instance.working_dir.as_deref(),
-            Some(instance.port),
-        )
-        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+        .core
+        .signal_instance(&id, &request.signal, request.group)
+        .await
+        .map_err(|e| {
+            let message = e.to_string();
+            if message.starts_with("Unknown signal") {
+                (StatusCode::BAD_REQUEST, message)
+            } else {
+                core_error(e)
+            }
+        })?;
 
-    instance.status = ServiceStatus::Running;
-    instance.pid = Some(pid);
-    instance.started_at = Some(chrono::Utc::now());
+    info!(instance_id = %id, pid = %pid, signal = %request.signal, "Signal sent via HTTP API");
 
-    state
-        .event_bus
-        .send(crate::events::ServiceEvent::StatusChanged {
-            instance_id: id.clone(),
-            status: ServiceStatus::Running,
-            pid: Some(pid),
-        });
+    Ok(Json(serde_json::json!({
+        "status": "ok",
+        "message": format!("Sent {} to instance {}", request.signal, id),
+        "pid": pid
+    })))
+}
 
-    info!(instance_id = %id, pid = %pid, "Instance restarted via HTTP API");
+/// Send an instance its template's reload signal (SIGHUP by default)
+async fn reload_instance(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    let signal = state.core.reload_instance(&id).await.map_err(core_error)?;
+    let pid = state.core.get_instance(&id).await.and_then(|i| i.pid);
+
+    info!(instance_id = %id, signal = %signal, "Instance reloaded via HTTP API");
 
     Ok(Json(serde_json::json!({
         "status": "ok",
-        "message": format!("Restarted instance {}", id),
+        "message": format!("Sent {} to instance {}", signal, id),
+        "signal": signal.to_string(),
         "pid": pid
     })))
 }
 
-// === Metrics ===
+#[derive(Debug, Deserialize)]
+struct LogQuery {
+    source: Option<String>,
+    lines: Option<usize>,
+}
 
-async fn get_metrics(State(state): State<AppState>) -> Json<serde_json::Value> {
-    let system = state.monitor.get_system_metrics();
-    let instances = state.instances.read().await;
-    let counts = instances.status_counts();
+/// Recent output of an instance
+///
+/// `source=memory` serves the in-memory buffer (`[logs] memory_lines`),
+/// `source=file` the last `lines` (default 100) of each stream from the
+/// captured output files. Without `source`, memory is used if enabled.
+async fn get_instance_logs(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    Query(query): Query<LogQuery>,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    const DEFAULT_LINES: usize = 100;
 
-    Json(serde_json::json!({
-        "system": {
-            "cpu_percent": system.cpu_percent,
+    if state.core.instances.read().await.get(&id).is_none() {
+        return Err((
+            StatusCode::NOT_FOUND,
+            format!("Instance '{}' not found", id),
+        ));
+    }
+
+    let default_source = if state.core.logs.is_enabled() {
+        "memory"
+    } else {
+        "file"
+    };
+    match query.source.as_deref().unwrap_or(default_source) {
+        "memory" => {
+            if !state.core.logs.is_enabled() {
+                return Err((
+                    StatusCode::BAD_REQUEST,
+                    "In-memory log capture is disabled (set [logs] memory_lines)".to_string(),
+                ));
+            }
+            let mut lines = state.core.logs.recent(&id).unwrap_or_default();
+            if let Some(n) = query.lines {
+                lines.drain(..lines.len().saturating_sub(n));
+            }
+            Ok(Json(serde_json::json!({
+                "instance_id": id,
+                "source": "memory",
+                "lines": lines
+            })))
+        },
+        "file" => {
+            let lines = query.lines.unwrap_or(DEFAULT_LINES);
+            let (pid, logs) = state
+                .core
+                .captured_logs(&id, lines)
+                .await
+                .map_err(|e| (StatusCode::NOT_FOUND, e.to_string()))?;
+            Ok(Json(serde_json::json!({
+                "instance_id": id,
+                "source": "file",
+                "pid": pid,
+                "stdout": logs.stdout,
+                "stderr": logs.stderr
+            })))
+        },
+        other => Err((
+            StatusCode::BAD_REQUEST,
+            format!("Unsupported log source '{}'", other),
+        )),
+    }
+}
+
+/// Body of `POST /api/instances/:id/clone`; the template comes from the source
+#[derive(Debug, Deserialize)]
+struct CloneRequest {
+    instance_id: String,
+    /// The source's group if not specified
+    #[serde(default)]
+    group: Option<String>,
+    #[serde(default)]
+    port: Option<u16>,
+    #[serde(default)]
+    working_dir: Option<std::path::PathBuf>,
+    #[serde(default)]
+    version: Option<String>,
+    #[serde(default)]
+    tags: Vec<String>,
+    /// Don't copy tags and environment from the source
+    #[serde(default)]
+    no_inherit: bool,
+}
+
+async fn clone_instance(
+    State(state): State<AppState>,
+    Path(source_id): Path<String>,
+    Json(request): Json<CloneRequest>,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    if request.instance_id.is_empty() {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "Instance ID cannot be empty".to_string(),
+        ));
+    }
+
+    let config = InstanceConfig {
+        instance_id: request.instance_id,
+        group: request.group,
+        template_id: String::new(), // inherited from the source
+        port: request.port,
+        working_dir: request.working_dir,
+        config_path: None,
+        version: request.version,
+        git_branch: None,
+        tags: request.tags,
+        auto_start: false,
+        env_vars: Default::default(),
+        display_name: None,
+        notes: None,
+        labels: Default::default(),
+        metadata: Default::default(),
+        depends_on: Vec::new(),
+        schedule: None,
+        restart: None,
+        start_command_override: None,
+        stop_command_override: None,
+        cpu_limit_percent: None,
+        memory_limit_mb: None,
+        limit_action: None,
+    };
+
+    let instance_id = state
+        .core
+        .clone_instance(&source_id, config, !request.no_inherit)
+        .await
+        .map_err(|e| {
+            let message = e.to_string();
+            if message.contains("does not support multiple instances")
+                || message.contains("already")
+                || message.contains("limit reached")
+                || message.starts_with("No free port")
+            {
+                (StatusCode::CONFLICT, message)
+            } else {
+                core_error(e)
+            }
+        })?;
+
+    let instance = state.core.get_instance(&instance_id).await;
+    info!(source_id = %source_id, instance_id = %instance_id, "Instance cloned via HTTP API");
+
+    Ok(Json(serde_json::json!({
+        "status": "ok",
+        "instance_id": instance_id,
+        "port": instance.map(|i| i.port)
+    })))
+}
+
+/// Effective environment: template `default_env` overlaid by instance `env_vars`
+async fn get_instance_env(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    let env = state.core.instance_env(&id).await.map_err(core_error)?;
+    Ok(Json(serde_json::json!({
+        "instance_id": id,
+        "env": env
+    })))
+}
+
+/// Set or unset instance env vars: `{"KEY": "value", "OTHER": null}`
+///
+/// Takes effect on the instance's next start.
+async fn patch_instance_env(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    Json(changes): Json<HashMap<String, Option<String>>>,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    let env = state
+        .core
+        .patch_instance_env(&id, changes)
+        .await
+        .map_err(core_error)?;
+    let running = state
+        .core
+        .get_instance(&id)
+        .await
+        .is_some_and(|i| i.status == ServiceStatus::Running);
+
+    info!(instance_id = %id, "Instance environment updated via HTTP API");
+
+    Ok(Json(serde_json::json!({
+        "status": "ok",
+        "instance_id": id,
+        "env": env,
+        "restart_required": running
+    })))
+}
+
+#[derive(Debug, Deserialize)]
+struct PortRequest {
+    port: u16,
+}
+
+/// Move an instance to another port (see `UsmCore::reassign_port`)
+async fn set_instance_port(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    Json(request): Json<PortRequest>,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    let old_port =
+        state
+            .core
+            .reassign_port(&id, request.port)
+            .await
+            .map_err(|e| match core_error(e) {
+                (_, message) if message.contains("already in use") => {
+                    (StatusCode::CONFLICT, message)
+                },
+                (_, message) if message.contains("outside the port range") => {
+                    (StatusCode::BAD_REQUEST, message)
+                },
+                error => error,
+            })?;
+    let running = state
+        .core
+        .get_instance(&id)
+        .await
+        .is_some_and(|i| i.status == ServiceStatus::Running);
+
+    info!(instance_id = %id, old_port, port = request.port, "Instance port changed via HTTP API");
+
+    Ok(Json(serde_json::json!({
+        "status": "ok",
+        "instance_id": id,
+        "old_port": old_port,
+        "port": request.port,
+        "restart_required": running && old_port != request.port
+    })))
+}
+
+// === Metrics ===
+
+async fn get_metrics(State(state): State<AppState>) -> Json<serde_json::Value> {
+    let system = state.core.monitor.get_system_metrics();
+    let (counts, total) = {
+        let instances = state.core.instances.read().await;
+        (instances.status_counts(), instances.len())
+    };
+
+    // Per-instance metrics for running instances (monitor calls outside the lock)
+    let instance_metrics = state.core.all_instance_metrics().await;
+
+    Json(serde_json::json!({
+        "system": {
+            "cpu_percent": system.cpu_percent,
             "memory_used_gb": system.memory_used_gb(),
             "memory_total_gb": system.memory_total_gb(),
-            "memory_percent": system.memory_percent
+            "memory_percent": system.memory_percent,
+            "load_average": system.load_average_tuple()
         },
         "instances": {
             "running": counts.get(&ServiceStatus::Running).unwrap_or(&0),
             "stopped": counts.get(&ServiceStatus::Stopped).unwrap_or(&0),
             "error": counts.get(&ServiceStatus::Error).unwrap_or(&0),
-            "total": instances.len()
-        }
+            "total": total
+        },
+        "instance_metrics": instance_metrics
+    }))
+}
+
+/// Metrics of every running instance, keyed by instance ID
+async fn get_all_instance_metrics(
+    State(state): State<AppState>,
+) -> Json<HashMap<String, crate::metrics::InstanceMetrics>> {
+    Json(state.core.all_instance_metrics().await)
+}
+
+/// Metrics in the Prometheus text format, for scraping
+async fn prometheus_metrics(State(state): State<AppState>) -> impl IntoResponse {
+    let system = state.core.monitor.get_system_metrics();
+    let instances = state.core.instances.read().await.list();
+
+    // One pass over the process table for every running instance
+    let mut measured = state.core.all_instance_metrics().await;
+    let mut rows: Vec<_> = instances
+        .into_iter()
+        .map(|instance| {
+            let up = instance.status == ServiceStatus::Running;
+            let metrics = measured.remove(&instance.id).filter(|_| up);
+            crate::metrics::PrometheusInstance {
+                instance_id: instance.id,
+                template_id: instance.template_id,
+                up,
+                metrics,
+            }
+        })
+        .collect();
+    rows.sort_by(|a, b| a.instance_id.cmp(&b.instance_id));
+
+    (
+        [(
+            axum::http::header::CONTENT_TYPE,
+            "text/plain; version=0.0.4; charset=utf-8",
+        )],
+        crate::metrics::prometheus_text(&system, &rows),
+    )
+}
+
+/// Recent system samples, oldest first, for sparklines
+async fn get_metrics_history(State(state): State<AppState>) -> Json<serde_json::Value> {
+    Json(serde_json::json!({
+        "samples": state.core.system_metrics_history()
+    }))
+}
+
+/// Recent samples for one instance, oldest first
+async fn get_instance_metrics_history(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    let samples = state
+        .core
+        .instance_metrics_history(&id)
+        .await
+        .map_err(core_error)?;
+    Ok(Json(serde_json::json!({
+        "instance_id": id,
+        "samples": samples
+    })))
+}
+
+// === Diagnostics ===
+
+/// Each instance's stored PID and whether it is alive
+///
+/// `drift` marks instances shown as Running whose process is gone.
+async fn reconcile(State(state): State<AppState>) -> Json<serde_json::Value> {
+    let statuses: HashMap<String, ServiceStatus> = state
+        .core
+        .list_instances(None)
+        .await
+        .into_iter()
+        .map(|i| (i.id, i.status))
+        .collect();
+
+    let mut drifted = 0;
+    let instances: Vec<serde_json::Value> = state
+        .core
+        .reconcile()
+        .await
+        .into_iter()
+        .map(|(id, pid, alive)| {
+            let status = statuses.get(&id).copied().unwrap_or_default();
+            let drift = status == ServiceStatus::Running && pid.is_some() && !alive;
+            drifted += usize::from(drift);
+            serde_json::json!({
+                "instance_id": id,
+                "status": status,
+                "pid": pid,
+                "alive": alive,
+                "drift": drift
+            })
+        })
+        .collect();
+
+    Json(serde_json::json!({
+        "instances": instances,
+        "drifted": drifted
+    }))
+}
+
+async fn zombies(State(state): State<AppState>) -> Json<serde_json::Value> {
+    let zombies: Vec<serde_json::Value> = state
+        .core
+        .zombies()
+        .await
+        .into_iter()
+        .map(|(pid, instance_id, tracked)| {
+            serde_json::json!({
+                "pid": pid,
+                "instance_id": instance_id,
+                "tracked": tracked
+            })
+        })
+        .collect();
+
+    Json(serde_json::json!({
+        "total": zombies.len(),
+        "zombies": zombies
     }))
 }
 
@@ -508,7 +1341,7 @@ async fn handle_websocket(mut socket: axum::extract::ws::WebSocket, state: AppSt
     use axum::extract::ws::Message;
 
     // Send initial state
-    let instances = state.instances.read().await;
+    let instances = state.core.instances.read().await;
     let initial = serde_json::json!({
         "type": "connected",
         "instances": instances.list()
@@ -524,25 +1357,45 @@ async fn handle_websocket(mut socket: axum::extract::ws::WebSocket, state: AppSt
     }
 
     // Subscribe to events
-    let mut rx = state.event_bus.subscribe();
+    let mut rx = state.core.event_bus.subscribe();
+    let mut heartbeat = Heartbeat::new(&state.core).await;
 
     loop {
         tokio::select! {
             // Forward events to WebSocket
-            Ok(event) = rx.recv() => {
-                let json = serde_json::to_string(&event).unwrap_or_default();
-                if socket.send(Message::Text(json)).await.is_err() {
+            event = rx.recv() => match event {
+                Ok(event) => {
+                    let json = serde_json::to_string(&event).unwrap_or_default();
+                    if socket.send(Message::Text(json)).await.is_err() {
+                        break;
+                    }
+                }
+                Err(broadcast::error::RecvError::Lagged(missed)) => {
+                    state.core.event_bus.record_lag();
+                    warn!(missed, "WebSocket client fell behind, events dropped");
+                }
+                Err(broadcast::error::RecvError::Closed) => break,
+            },
+            beat = heartbeat.tick() => {
+                if !beat.send(&mut socket).await {
                     break;
                 }
             }
-            // Handle incoming messages (ping/pong)
+            // Handle incoming messages (ping/pong and commands)
             Some(msg) = socket.recv() => {
+                heartbeat.seen();
                 match msg {
                     Ok(Message::Ping(data)) => {
                         if socket.send(Message::Pong(data)).await.is_err() {
                             break;
                         }
                     }
+                    Ok(Message::Text(text)) => {
+                        let reply = handle_ws_command(&state, &text).await;
+                        if socket.send(Message::Text(reply.to_string())).await.is_err() {
+                            break;
+                        }
+                    }
                     Ok(Message::Close(_)) | Err(_) => break,
                     _ => {}
                 }
@@ -550,3 +1403,1654 @@ async fn handle_websocket(mut socket: axum::extract::ws::WebSocket, state: AppSt
         }
     }
 }
+
+/// Stream one instance's events, including its metrics updates
+///
+/// Starts with a `{"type": "connected", "instance": ...}` frame and closes
+/// after forwarding the instance's removal.
+async fn instance_events_websocket(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    ws: WebSocketUpgrade,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    // Subscribe first, so nothing between the snapshot and the stream is lost
+    let events = state.core.subscribe_instance(&id);
+    let instance = state.core.get_instance(&id).await.ok_or((
+        StatusCode::NOT_FOUND,
+        format!("Instance '{}' not found", id),
+    ))?;
+    Ok(ws.on_upgrade(move |socket| stream_instance_events(socket, state, instance, events)))
+}
+
+async fn stream_instance_events(
+    mut socket: axum::extract::ws::WebSocket,
+    state: AppState,
+    instance: ServiceInstance,
+    mut events: InstanceEvents,
+) {
+    use axum::extract::ws::Message;
+
+    let initial = serde_json::json!({
+        "type": "connected",
+        "instance": instance
+    });
+    if socket
+        .send(Message::Text(initial.to_string()))
+        .await
+        .is_err()
+    {
+        return;
+    }
+
+    let mut heartbeat = Heartbeat::new(&state.core).await;
+    loop {
+        tokio::select! {
+            event = events.recv() => match event {
+                Ok(event) => {
+                    let removed = matches!(event, ServiceEvent::InstanceRemoved { .. });
+                    let json = serde_json::to_string(&event).unwrap_or_default();
+                    if socket.send(Message::Text(json)).await.is_err() || removed {
+                        break;
+                    }
+                }
+                Err(broadcast::error::RecvError::Lagged(missed)) => {
+                    state.core.event_bus.record_lag();
+                    warn!(
+                        instance_id = %events.instance_id(),
+                        missed,
+                        "WebSocket client fell behind, events dropped"
+                    );
+                }
+                Err(broadcast::error::RecvError::Closed) => break,
+            },
+            beat = heartbeat.tick() => {
+                if !beat.send(&mut socket).await {
+                    return;
+                }
+            }
+            Some(msg) = socket.recv() => match msg {
+                Ok(Message::Ping(data)) => {
+                    heartbeat.seen();
+                    if socket.send(Message::Pong(data)).await.is_err() {
+                        break;
+                    }
+                }
+                Ok(Message::Close(_)) | Err(_) => break,
+                _ => heartbeat.seen(),
+            }
+        }
+    }
+    let _ = socket.send(Message::Close(None)).await;
+}
+
+/// Server-side pings that find WebSocket clients which went away without
+/// closing (e.g. their network dropped)
+///
+/// Without them such a socket lingers as an event subscriber forever. Any
+/// frame from the client counts as an answer to the last ping.
+struct Heartbeat {
+    interval: Option<Duration>,
+    timeout: Duration,
+    next_ping: tokio::time::Instant,
+    pong_deadline: Option<tokio::time::Instant>,
+}
+
+/// What a `Heartbeat` tick asks the socket loop to do
+enum Beat {
+    Ping,
+    TimedOut,
+}
+
+impl Heartbeat {
+    /// Heartbeat per `[server] ws_ping_interval_ms` / `ws_pong_timeout_ms`
+    async fn new(core: &UsmCore) -> Self {
+        let settings = core.settings.read().await;
+        let server = settings.server.clone().unwrap_or_default();
+        let interval = server.ws_ping_interval();
+        Self {
+            interval,
+            timeout: server.ws_pong_timeout(),
+            next_ping: tokio::time::Instant::now() + interval.unwrap_or_default(),
+            pong_deadline: None,
+        }
+    }
+
+    /// Wait until a ping is due or the client ran out of time to answer
+    ///
+    /// Never completes when heartbeats are disabled. Cancel-safe, so it can
+    /// sit in a `select!` next to the socket.
+    async fn tick(&mut self) -> Beat {
+        let Some(interval) = self.interval else {
+            return std::future::pending().await;
+        };
+        if let Some(deadline) = self.pong_deadline {
+            tokio::time::sleep_until(deadline).await;
+            return Beat::TimedOut;
+        }
+        tokio::time::sleep_until(self.next_ping).await;
+        let now = tokio::time::Instant::now();
+        self.next_ping = now + interval;
+        self.pong_deadline = Some(now + self.timeout);
+        Beat::Ping
+    }
+
+    /// Record a frame from the client
+    fn seen(&mut self) {
+        self.pong_deadline = None;
+    }
+}
+
+impl Beat {
+    /// Ping the client, or close the socket on a timeout
+    ///
+    /// Returns whether the socket is still usable.
+    async fn send(self, socket: &mut axum::extract::ws::WebSocket) -> bool {
+        use axum::extract::ws::Message;
+
+        match self {
+            Beat::Ping => socket.send(Message::Ping(Vec::new())).await.is_ok(),
+            Beat::TimedOut => {
+                debug!("WebSocket client stopped answering pings, closing");
+                let _ = socket.send(Message::Close(None)).await;
+                false
+            },
+        }
+    }
+}
+
+/// How often a log stream checks the captured output files for new lines
+const LOG_POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Stream an instance's new output lines, like `tail -f`
+///
+/// Each line is sent as a `{"stream": "stdout", "line": "..."}` frame. The
+/// stream follows the instance across restarts and closes when the instance
+/// is removed.
+async fn instance_logs_websocket(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    ws: WebSocketUpgrade,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    let instance = state.core.get_instance(&id).await.ok_or((
+        StatusCode::NOT_FOUND,
+        format!("Instance '{}' not found", id),
+    ))?;
+    // Join the current run at its end; later runs are sent from the start
+    let follower = instance
+        .last_pid
+        .map(|pid| OutputFollower::new(pid, state.core.log_paths(&id, pid), false));
+    Ok(ws.on_upgrade(move |socket| stream_instance_logs(socket, state, id, follower)))
+}
+
+async fn stream_instance_logs(
+    mut socket: axum::extract::ws::WebSocket,
+    state: AppState,
+    id: String,
+    mut follower: Option<OutputFollower>,
+) {
+    use axum::extract::ws::Message;
+
+    let mut interval = tokio::time::interval(LOG_POLL_INTERVAL);
+    interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    let mut heartbeat = Heartbeat::new(&state.core).await;
+    loop {
+        tokio::select! {
+            _ = interval.tick() => {
+                let Some(lines) = poll_instance_logs(&state.core, &id, &mut follower).await else {
+                    break;
+                };
+                for line in lines {
+                    let json = serde_json::to_string(&line).unwrap_or_default();
+                    if socket.send(Message::Text(json)).await.is_err() {
+                        return;
+                    }
+                }
+            }
+            beat = heartbeat.tick() => {
+                if !beat.send(&mut socket).await {
+                    return;
+                }
+            }
+            msg = socket.recv() => match msg {
+                Some(Ok(Message::Ping(data))) => {
+                    heartbeat.seen();
+                    if socket.send(Message::Pong(data)).await.is_err() {
+                        return;
+                    }
+                }
+                Some(Ok(Message::Close(_)) | Err(_)) | None => return,
+                _ => heartbeat.seen(),
+            }
+        }
+    }
+    // The instance was removed
+    let _ = socket.send(Message::Close(None)).await;
+}
+
+/// Output lines an instance wrote since the last poll
+///
+/// `follower` follows the run seen last; once the instance has started
+/// again, the new run's files are followed from their start. Returns None
+/// when the instance no longer exists.
+async fn poll_instance_logs(
+    core: &UsmCore,
+    id: &str,
+    follower: &mut Option<OutputFollower>,
+) -> Option<Vec<LogLine>> {
+    let instance = core.get_instance(id).await?;
+    let Some(pid) = instance.last_pid else {
+        return Some(Vec::new());
+    };
+    let follower = match follower {
+        Some(f) if f.pid() == pid => f,
+        _ => follower.insert(OutputFollower::new(pid, core.log_paths(id, pid), true)),
+    };
+    Some(follower.read_new().unwrap_or_else(|e| {
+        warn!(instance_id = %id, pid, "Cannot read captured output: {}", e);
+        Vec::new()
+    }))
+}
+
+/// A command sent by a WebSocket client
+#[derive(Debug, Deserialize)]
+struct WsCommand {
+    action: String,
+    #[serde(default)]
+    instance_id: Option<String>,
+    /// Echoed back in the reply so clients can match it to the request
+    #[serde(default)]
+    request_id: Option<serde_json::Value>,
+}
+
+/// Run a WebSocket command and build its reply frame
+///
+/// Supports `start`, `stop` and `restart`, with the same behavior as the
+/// corresponding HTTP endpoints. Replies are `command_result` frames carrying
+/// the endpoint's response, or `command_error` frames with the HTTP status
+/// the endpoint would have returned; bad commands never close the socket.
+async fn handle_ws_command(state: &AppState, text: &str) -> serde_json::Value {
+    let command: WsCommand = match serde_json::from_str(text) {
+        Ok(command) => command,
+        Err(e) => {
+            return serde_json::json!({
+                "type": "command_error",
+                "code": StatusCode::BAD_REQUEST.as_u16(),
+                "message": format!("Invalid command: {}", e),
+            })
+        },
+    };
+
+    let result = match (command.action.as_str(), command.instance_id.clone()) {
+        ("start" | "stop" | "restart", None) => Err(StartError::from((
+            StatusCode::BAD_REQUEST,
+            format!("Action '{}' requires instance_id", command.action),
+        ))),
+        ("start", Some(id)) => start_instance(State(state.clone()), Path(id)).await,
+        ("stop", Some(id)) => stop_instance(State(state.clone()), Path(id))
+            .await
+            .map_err(StartError::from),
+        ("restart", Some(id)) => restart_instance(State(state.clone()), Path(id))
+            .await
+            .map_err(StartError::from),
+        (action, _) => Err(StartError::from((
+            StatusCode::BAD_REQUEST,
+            format!("Unknown action '{}'", action),
+        ))),
+    };
+
+    match result {
+        Ok(Json(result)) => serde_json::json!({
+            "type": "command_result",
+            "action": command.action,
+            "instance_id": command.instance_id,
+            "request_id": command.request_id,
+            "result": result,
+        }),
+        Err(error) => {
+            let mut frame = serde_json::json!({
+                "type": "command_error",
+                "action": command.action,
+                "instance_id": command.instance_id,
+                "request_id": command.request_id,
+                "code": error.code.as_u16(),
+                "message": error.message,
+            });
+            if let Some(details) = error.details {
+                frame["details"] = details;
+            }
+            frame
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::Body;
+    use axum::http::Request;
+    use tower::ServiceExt;
+
+    const TEST_CONFIG: &str = r#"
+[templates.sleeper]
+display_name = "Sleeper"
+default_port = 18950
+start_command = "sleep 30"
+
+[instances.sleeper-1]
+template = "sleeper"
+port = 18950
+"#;
+
+    /// A core on `config` (saved as `services.toml` in `dir`) whose
+    /// processes are only pretended
+    async fn mock_core(
+        dir: &tempfile::TempDir,
+        config: &str,
+    ) -> (Arc<UsmCore>, Arc<crate::monitor::MockMonitor>) {
+        let config_path = dir.path().join("services.toml");
+        std::fs::write(&config_path, config).unwrap();
+        let monitor = Arc::new(crate::monitor::MockMonitor::new());
+        let core = UsmCore::with_monitor(&config_path, monitor.clone())
+            .await
+            .unwrap();
+        (Arc::new(core), monitor)
+    }
+
+    async fn get_body(app: Router, uri: &str) -> (StatusCode, String) {
+        let response = app
+            .oneshot(Request::get(uri).body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        let status = response.status();
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        (status, String::from_utf8_lossy(&bytes).into_owned())
+    }
+
+    #[tokio::test]
+    async fn test_search_instances() {
+        let dir = tempfile::tempdir().unwrap();
+        let (core, _) = mock_core(&dir, TEST_CONFIG).await;
+        let app = build_router(core.clone(), None);
+
+        let (status, body) = get_body(app.clone(), "/api/instances?q=SLEEP").await;
+        assert_eq!(status, StatusCode::OK);
+        let json: serde_json::Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(json["instances"][0]["id"], "sleeper-1");
+        assert_eq!(
+            json["instances"][0]["display_name"],
+            serde_json::Value::Null
+        );
+        assert_eq!(json["instances"][0]["name"], "Sleeper");
+        assert_eq!(json["total"], 1);
+
+        let (_, body) = get_body(app.clone(), "/api/instances?q=nomatch").await;
+        let json: serde_json::Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(json["instances"], serde_json::json!([]));
+
+        // Combines with the other filters
+        let (_, body) = get_body(app.clone(), "/api/instances?q=sleep&status=running").await;
+        let json: serde_json::Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(json["instances"], serde_json::json!([]));
+
+        // An instance may be called "search"
+        core.remove_instance("sleeper-1").await.unwrap();
+        let config = serde_json::json!({"instance_id": "search", "template_id": "sleeper"});
+        core.create_instance(serde_json::from_value(config).unwrap())
+            .await
+            .unwrap();
+        let (status, body) = get_body(app, "/api/instances/search").await;
+        assert_eq!(status, StatusCode::OK);
+        let json: serde_json::Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(json["instance"]["id"], "search");
+        assert_eq!(json["instance"]["name"], "Sleeper");
+    }
+
+    #[tokio::test]
+    async fn test_bind_errors_are_explained() {
+        let taken = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let err = bind(taken.local_addr().unwrap()).await.unwrap_err();
+        assert!(err.to_string().contains("is already in use"), "{}", err);
+
+        let foreign: SocketAddr = "192.0.2.1:0".parse().unwrap();
+        let err = bind(foreign).await.unwrap_err();
+        assert!(
+            err.to_string()
+                .contains("is not an address of this machine"),
+            "{}",
+            err
+        );
+    }
+
+    #[tokio::test]
+    async fn test_auth_token_required() {
+        let dir = tempfile::tempdir().unwrap();
+        let app = require_token(
+            build_router(mock_core(&dir, TEST_CONFIG).await.0, None),
+            "s3cret",
+        );
+
+        let (status, _) = get_body(app.clone(), "/api/instances").await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+        let (status, _) = get_body(app.clone(), "/ws").await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+        // Only the health check stays public
+        let (status, _) = get_body(app.clone(), "/api/health").await;
+        assert_eq!(status, StatusCode::OK);
+        for path in ["/api/openapi.json", "/api/version", "/metrics"] {
+            let (status, _) = get_body(app.clone(), path).await;
+            assert_eq!(status, StatusCode::UNAUTHORIZED, "{}", path);
+        }
+
+        let authorized = |path: &str, token: &str| {
+            Request::get(path)
+                .header("authorization", format!("Bearer {}", token))
+                .body(Body::empty())
+                .unwrap()
+        };
+        let response = app
+            .clone()
+            .oneshot(authorized("/api/instances", "wrong"))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        let response = app
+            .clone()
+            .oneshot(authorized("/api/instances", "s3cret"))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let response = app.oneshot(authorized("/metrics", "s3cret")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_static_dir_served_alongside_api() {
+        let dir = tempfile::tempdir().unwrap();
+        let static_dir = dir.path().join("static");
+        std::fs::create_dir(&static_dir).unwrap();
+        std::fs::write(static_dir.join("index.html"), "<h1>dashboard</h1>").unwrap();
+        let app = build_router(mock_core(&dir, TEST_CONFIG).await.0, Some(static_dir));
+
+        let (status, body) = get_body(app.clone(), "/index.html").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body, "<h1>dashboard</h1>");
+
+        let (status, body) = get_body(app.clone(), "/").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body, "<h1>dashboard</h1>");
+
+        let (status, body) = get_body(app, "/api/health").await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(body.contains("\"status\":\"ok\""));
+        assert!(body.contains("\"ws_subscribers\":0"));
+        assert!(body.contains("\"event_capacity\":"));
+        assert!(body.contains("\"event_lagged\":0"));
+    }
+
+    #[tokio::test]
+    async fn test_version() {
+        let dir = tempfile::tempdir().unwrap();
+        let (core, _) = mock_core(&dir, TEST_CONFIG).await;
+
+        let (status, body) = get_body(build_router(core, None), "/api/version").await;
+        assert_eq!(status, StatusCode::OK);
+        let json: serde_json::Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(json["version"], env!("CARGO_PKG_VERSION"));
+        assert!(!json["git_commit"].as_str().unwrap().is_empty());
+        let built = json["build_timestamp"].as_str().unwrap();
+        assert!(chrono::DateTime::parse_from_rfc3339(built).is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_all_instance_metrics() {
+        let dir = tempfile::tempdir().unwrap();
+        let (core, _) = mock_core(&dir, TEST_CONFIG).await;
+        let app = build_router(core.clone(), None);
+
+        let (status, body) = get_body(app.clone(), "/api/metrics/instances").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body, "{}");
+
+        core.start_instance("sleeper-1").await.unwrap();
+        let (_, body) = get_body(app, "/api/metrics/instances").await;
+        let json: serde_json::Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(json["sleeper-1"]["cpu_percent"], 1.0);
+    }
+
+    #[tokio::test]
+    async fn test_reset_instance() {
+        let dir = tempfile::tempdir().unwrap();
+        let (core, _) = mock_core(&dir, TEST_CONFIG).await;
+        let app = build_router(core.clone(), None);
+        let reset = |id: &str| {
+            let app = app.clone();
+            let request = Request::post(format!("/api/instances/{}/reset", id))
+                .body(Body::empty())
+                .unwrap();
+            async move { app.oneshot(request).await.unwrap().status() }
+        };
+
+        core.instances
+            .write()
+            .await
+            .update_status("sleeper-1", ServiceStatus::Error, None)
+            .unwrap();
+        assert_eq!(reset("sleeper-1").await, StatusCode::OK);
+        assert_eq!(
+            core.get_instance("sleeper-1").await.unwrap().status,
+            ServiceStatus::Stopped
+        );
+
+        core.start_instance("sleeper-1").await.unwrap();
+        assert_eq!(reset("sleeper-1").await, StatusCode::CONFLICT);
+        assert_eq!(reset("missing").await, StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_health_degraded() {
+        let dir = tempfile::tempdir().unwrap();
+        let (core, _) = mock_core(&dir, &format!("{}tags = [\"critical\"]\n", TEST_CONFIG)).await;
+        let app = build_router(core.clone(), None);
+
+        core.instances
+            .write()
+            .await
+            .get_mut("sleeper-1")
+            .unwrap()
+            .status = ServiceStatus::Error;
+        let (status, body) = get_body(app.clone(), "/api/health").await;
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+        let json: serde_json::Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(json["status"], "degraded");
+        assert_eq!(json["unhealthy"], serde_json::json!(["sleeper-1"]));
+
+        core.stop_instance("sleeper-1").await.unwrap();
+        let (status, body) = get_body(app, "/api/health").await;
+        assert_eq!(status, StatusCode::OK);
+        let json: serde_json::Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(json["status"], "ok");
+        assert_eq!(json["unhealthy"], serde_json::json!([]));
+    }
+
+    #[tokio::test]
+    async fn test_list_templates_by_category() {
+        let dir = tempfile::tempdir().unwrap();
+        let (core, _) = mock_core(&dir, &format!(
+                "{}\n[templates.postgres]\ndisplay_name = \"Postgres\"\ndefault_port = 5432\nstart_command = \"postgres\"\ncategory = \"database\"\n",
+                TEST_CONFIG
+            )).await;
+        let app = build_router(core, None);
+        let ids = |body: &str| {
+            let json: serde_json::Value = serde_json::from_str(body).unwrap();
+            let mut ids: Vec<String> = json
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|t| t["id"].as_str().unwrap().to_string())
+                .collect();
+            ids.sort();
+            ids
+        };
+
+        let (_, body) = get_body(app.clone(), "/api/templates").await;
+        assert_eq!(ids(&body), vec!["postgres", "sleeper"]);
+        let (_, body) = get_body(app.clone(), "/api/templates?category=database").await;
+        assert_eq!(ids(&body), vec!["postgres"]);
+        let (_, body) = get_body(app.clone(), "/api/templates?category=custom").await;
+        assert!(ids(&body).is_empty());
+
+        let (status, body) = get_body(app, "/api/templates?category=cache").await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body, "Unknown category 'cache'");
+    }
+
+    #[tokio::test]
+    async fn test_list_instances_tag_mode() {
+        let dir = tempfile::tempdir().unwrap();
+        let (core, _) = mock_core(&dir, &format!(
+                "{}tags = [\"production\", \"llm\"]\n\n[instances.sleeper-2]\ntemplate = \"sleeper\"\nport = 18951\ntags = [\"production\"]\n",
+                TEST_CONFIG
+            )).await;
+        let app = build_router(core, None);
+        let ids = |body: &str| {
+            let json: serde_json::Value = serde_json::from_str(body).unwrap();
+            let mut ids: Vec<String> = json["instances"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|i| i["id"].as_str().unwrap().to_string())
+                .collect();
+            ids.sort();
+            ids
+        };
+
+        let (_, body) = get_body(app.clone(), "/api/instances?tag=production,llm").await;
+        assert_eq!(ids(&body), vec!["sleeper-1", "sleeper-2"]);
+
+        let (_, body) = get_body(
+            app.clone(),
+            "/api/instances?tag=production,llm&tag_mode=all",
+        )
+        .await;
+        assert_eq!(ids(&body), vec!["sleeper-1"]);
+
+        let (status, _) = get_body(app, "/api/instances?tag=llm&tag_mode=some").await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_grouped_instances() {
+        let dir = tempfile::tempdir().unwrap();
+        let (core, _) = mock_core(&dir, &format!(
+                "{}\n[instances.sleeper-2]\ntemplate = \"sleeper\"\ngroup = \"projectA\"\nport = 18951\ndisplay_name = \"Sleeper A\"\n",
+                TEST_CONFIG
+            )).await;
+        let app = build_router(core, None);
+
+        let (_, body) = get_body(app.clone(), "/api/instances?group=projectA").await;
+        let json: serde_json::Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(json["instances"][0]["id"], "projectA/sleeper-2");
+        assert_eq!(json["instances"][0]["group"], "projectA");
+        assert_eq!(json["instances"][0]["name"], "Sleeper A");
+        assert_eq!(json["instances"].as_array().unwrap().len(), 1);
+
+        // The id's '/' is percent-encoded in paths
+        let (status, body) = get_body(app, "/api/instances/projectA%2Fsleeper-2").await;
+        assert_eq!(status, StatusCode::OK);
+        let json: serde_json::Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(json["instance"]["id"], "projectA/sleeper-2");
+        assert_eq!(json["instance"]["name"], "Sleeper A");
+    }
+
+    #[tokio::test]
+    async fn test_no_static_dir_by_default() {
+        let dir = tempfile::tempdir().unwrap();
+        let app = build_router(mock_core(&dir, TEST_CONFIG).await.0, None);
+        let (status, _) = get_body(app, "/index.html").await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+    }
+
+    // Paused time skips restart's pause between stop and start
+    #[tokio::test(start_paused = true)]
+    async fn test_restart_via_http() {
+        let dir = tempfile::tempdir().unwrap();
+        let (core, monitor) = mock_core(&dir, TEST_CONFIG).await;
+        let app = build_router(core.clone(), None);
+
+        let response = app
+            .clone()
+            .oneshot(
+                Request::post("/api/instances/sleeper-1/restart")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let instance = core.get_instance("sleeper-1").await.unwrap();
+        assert_eq!(instance.status, ServiceStatus::Running);
+        assert_eq!(instance.pid, Some(1000));
+        assert_eq!(monitor.spawned(), vec!["sleep 30"]);
+
+        let response = app
+            .oneshot(
+                Request::post("/api/instances/missing/restart")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_metrics_history() {
+        let dir = tempfile::tempdir().unwrap();
+        let (core, _) = mock_core(&dir, TEST_CONFIG).await;
+        let app = build_router(core.clone(), None);
+
+        core.start_instance("sleeper-1").await.unwrap();
+        core.publish_metrics(&mut Default::default()).await;
+
+        let (status, body) =
+            get_body(app.clone(), "/api/instances/sleeper-1/metrics/history").await;
+        assert_eq!(status, StatusCode::OK);
+        let json: serde_json::Value = serde_json::from_str(&body).unwrap();
+        let samples = json["samples"].as_array().unwrap();
+        assert_eq!(samples.len(), 1);
+        assert!(samples[0]["timestamp"].is_string());
+        assert!(samples[0]["cpu_percent"].is_number());
+        assert!(samples[0]["memory_bytes"].is_number());
+
+        let (status, body) = get_body(app.clone(), "/api/metrics/history").await;
+        assert_eq!(status, StatusCode::OK);
+        let json: serde_json::Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(json["samples"].as_array().unwrap().len(), 1);
+
+        let (status, _) = get_body(app, "/api/instances/missing/metrics/history").await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_prometheus_metrics() {
+        let dir = tempfile::tempdir().unwrap();
+        let (core, _) = mock_core(&dir, TEST_CONFIG).await;
+        let app = build_router(core.clone(), None);
+
+        let (status, body) = get_body(app.clone(), "/metrics").await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(body.contains("usm_instance_up{instance=\"sleeper-1\",template=\"sleeper\"} 0\n"));
+        assert!(body.contains("# TYPE usm_system_cpu_percent gauge"));
+
+        core.start_instance("sleeper-1").await.unwrap();
+        let response = app
+            .oneshot(Request::get("/metrics").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert!(response.headers()["content-type"]
+            .to_str()
+            .unwrap()
+            .starts_with("text/plain; version=0.0.4"));
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body = String::from_utf8_lossy(&bytes);
+        assert!(body.contains("usm_instance_up{instance=\"sleeper-1\",template=\"sleeper\"} 1\n"));
+        assert!(body.contains(
+            "usm_instance_memory_bytes{instance=\"sleeper-1\",template=\"sleeper\"} 67108864\n"
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_start_over_running_limit_is_conflict() {
+        let dir = tempfile::tempdir().unwrap();
+        let (core, _) = mock_core(&dir, &format!(
+                "{}\n[instances.sleeper-2]\ntemplate = \"sleeper\"\nport = 18951\n\n[limits]\nmax_running = 1\n",
+                TEST_CONFIG
+            )).await;
+        let app = build_router(core, None);
+
+        let post = |uri: &str| Request::post(uri).body(Body::empty()).unwrap();
+        let response = app
+            .clone()
+            .oneshot(post("/api/instances/sleeper-1/start"))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let response = app
+            .oneshot(post("/api/instances/sleeper-2/start"))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::CONFLICT);
+    }
+
+    #[tokio::test]
+    async fn test_delete_instance() {
+        let dir = tempfile::tempdir().unwrap();
+        let config_path = dir.path().join("services.toml");
+        let (core, monitor) = mock_core(&dir, TEST_CONFIG).await;
+        let mut events = core.event_bus.subscribe();
+        let app = build_router(core.clone(), None);
+        let delete = |uri: &str| Request::delete(uri).body(Body::empty()).unwrap();
+
+        core.start_instance("sleeper-1").await.unwrap();
+        let response = app
+            .clone()
+            .oneshot(delete("/api/instances/sleeper-1"))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(json["instance_id"], "sleeper-1");
+
+        // Stopped first, removed, persisted and announced
+        assert_eq!(monitor.killed(), vec![1000]);
+        assert!(core.get_instance("sleeper-1").await.is_none());
+        let saved = std::fs::read_to_string(&config_path).unwrap();
+        assert!(!saved.contains("sleeper-1"));
+        let mut removed = false;
+        while let Ok(event) = events.try_recv() {
+            removed |= matches!(
+                event,
+                crate::events::ServiceEvent::InstanceRemoved { ref instance_id }
+                    if instance_id == "sleeper-1"
+            );
+        }
+        assert!(removed);
+
+        let response = app
+            .oneshot(delete("/api/instances/sleeper-1?force=true"))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_start_stop_report_commands() {
+        let dir = tempfile::tempdir().unwrap();
+        let (core, monitor) = mock_core(
+            &dir,
+            &format!(
+                "{}working_dir = \"{}\"\nstop_command_override = \"kill -INT {{pid}}\"\n",
+                TEST_CONFIG,
+                dir.path().display()
+            ),
+        )
+        .await;
+        let app = build_router(core, None);
+        let post = |uri: &str| Request::post(uri).body(Body::empty()).unwrap();
+        let send = |request: Request<Body>| {
+            let app = app.clone();
+            async move {
+                let response = app.oneshot(request).await.unwrap();
+                let status = response.status();
+                let bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+                    .await
+                    .unwrap();
+                let json: serde_json::Value = serde_json::from_slice(&bytes).unwrap();
+                (status, json)
+            }
+        };
+
+        monitor.set_fail_spawns(true);
+        let (status, json) = send(post("/api/instances/sleeper-1/start")).await;
+        assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR);
+        assert_eq!(json["status"], "error");
+        assert_eq!(json["command"], "sleep 30");
+        assert_eq!(json["working_dir"], dir.path().display().to_string());
+        assert!(json["error"].as_str().is_some_and(|e| !e.is_empty()));
+        assert!(json["stderr"].is_array());
+
+        monitor.set_fail_spawns(false);
+        let (status, json) = send(post("/api/instances/sleeper-1/start")).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(json["pid"], 1000);
+        assert_eq!(json["command"], "sleep 30");
+        assert_eq!(json["working_dir"], dir.path().display().to_string());
+
+        let (status, json) = send(post("/api/instances/sleeper-1/stop")).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(json["command"], "kill -INT 1000");
+        assert_eq!(monitor.executed(), vec!["kill -INT 1000"]);
+
+        // The process is gone: nothing is run, and nothing is reported
+        let (status, _) = send(post("/api/instances/sleeper-1/start")).await;
+        assert_eq!(status, StatusCode::OK);
+        monitor.set_running(1001, false);
+        let (status, json) = send(post("/api/instances/sleeper-1/stop")).await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(json["command"].is_null());
+        assert_eq!(monitor.executed(), vec!["kill -INT 1000"]);
+    }
+
+    #[tokio::test]
+    async fn test_delete_template() {
+        let dir = tempfile::tempdir().unwrap();
+        let config_path = dir.path().join("services.toml");
+        let (core, _) = mock_core(&dir, &format!(
+                "{}\n[templates.unused]\ndisplay_name = \"Unused\"\ndefault_port = 18960\nstart_command = \"true\"\n",
+                TEST_CONFIG
+            )).await;
+        let mut events = core.event_bus.subscribe();
+        let app = build_router(core.clone(), None);
+        let delete = |uri: &str| Request::delete(uri).body(Body::empty()).unwrap();
+
+        let response = app
+            .clone()
+            .oneshot(delete("/api/templates/sleeper"))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::CONFLICT);
+        assert!(core.get_template("sleeper").await.is_some());
+
+        let response = app
+            .clone()
+            .oneshot(delete("/api/templates/unused"))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(core.get_template("unused").await.is_none());
+        let saved = std::fs::read_to_string(&config_path).unwrap();
+        assert!(!saved.contains("[templates.unused]"));
+        assert!(matches!(
+            events.try_recv().unwrap(),
+            crate::events::ServiceEvent::TemplateRemoved { ref template_id } if template_id == "unused"
+        ));
+
+        let response = app.oneshot(delete("/api/templates/unused")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_create_instance_upsert() {
+        let dir = tempfile::tempdir().unwrap();
+        let config_path = dir.path().join("services.toml");
+        let (core, _) = mock_core(&dir, &format!(
+                "{}\n[templates.multi]\ndisplay_name = \"Multi\"\ndefault_port = 18956\nstart_command = \"sleep 30\"\nsupports_multiple = true\n",
+                TEST_CONFIG
+            )).await;
+        let app = build_router(core.clone(), None);
+        let send = |uri: &str, body: &str| {
+            let request = Request::post(uri)
+                .header("content-type", "application/json")
+                .body(Body::from(body.to_string()))
+                .unwrap();
+            let app = app.clone();
+            async move {
+                let response = app.oneshot(request).await.unwrap();
+                let status = response.status();
+                let bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+                    .await
+                    .unwrap();
+                (status, String::from_utf8(bytes.to_vec()).unwrap())
+            }
+        };
+        let body = r#"{"instance_id": "sleeper-1", "template_id": "sleeper", "port": 18955, "tags": ["nightly"]}"#;
+
+        let (status, _) = send("/api/instances", body).await;
+        assert_eq!(status, StatusCode::CONFLICT);
+
+        let (status, text) = send("/api/instances?upsert=true", body).await;
+        assert_eq!(status, StatusCode::OK);
+        let json: serde_json::Value = serde_json::from_str(&text).unwrap();
+        assert_eq!(json["created"], false);
+        assert_eq!(json["port"], 18955);
+        let instance = core.get_instance("sleeper-1").await.unwrap();
+        assert_eq!(instance.tags, vec!["nightly"]);
+        assert!(std::fs::read_to_string(&config_path)
+            .unwrap()
+            .contains("18955"));
+
+        let (status, text) = send(
+            "/api/instances?upsert=true",
+            r#"{"instance_id": "multi-1", "template_id": "multi"}"#,
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(text.contains("\"created\":true"));
+
+        let (status, text) = send(
+            "/api/instances?upsert=true",
+            r#"{"instance_id": "sleeper-1", "template_id": "other"}"#,
+        )
+        .await;
+        assert_eq!(status, StatusCode::CONFLICT);
+        assert!(text.starts_with("Cannot change template"));
+    }
+
+    #[tokio::test]
+    async fn test_create_via_http_persists_and_announces() {
+        let dir = tempfile::tempdir().unwrap();
+        let config_path = dir.path().join("services.toml");
+        let (core, _) = mock_core(&dir, TEST_CONFIG).await;
+        let mut events = core.subscribe();
+        let app = build_router(core, None);
+        let post = |uri: &str, body: &str| {
+            let request = Request::post(uri)
+                .header("content-type", "application/json")
+                .body(Body::from(body.to_string()))
+                .unwrap();
+            let app = app.clone();
+            async move { app.oneshot(request).await.unwrap().status() }
+        };
+
+        let template = r#"{"id": "extra", "display_name": "Extra", "default_port": 18970,
+            "start_command": "sleep 30", "supports_multiple": true}"#;
+        assert_eq!(post("/api/templates", template).await, StatusCode::OK);
+        assert_eq!(
+            post("/api/templates", template).await,
+            StatusCode::BAD_REQUEST
+        );
+        let instance = r#"{"instance_id": "extra-1", "template_id": "extra"}"#;
+        assert_eq!(post("/api/instances", instance).await, StatusCode::OK);
+        assert_eq!(post("/api/instances", instance).await, StatusCode::CONFLICT);
+
+        let event = events.recv().await.unwrap();
+        assert_eq!(event.event_type(), "template_registered");
+        let event = events.recv().await.unwrap();
+        assert_eq!(event.event_type(), "instance_created");
+        assert_eq!(event.instance_id(), Some("extra-1"));
+
+        let reloaded = UsmCore::new(&config_path).await.unwrap();
+        assert!(reloaded.get_template("extra").await.is_some());
+        assert_eq!(reloaded.get_instance("extra-1").await.unwrap().port, 18970);
+    }
+
+    #[tokio::test]
+    async fn test_reload_via_http() {
+        let dir = tempfile::tempdir().unwrap();
+        let (core, monitor) = mock_core(&dir, TEST_CONFIG).await;
+        let app = build_router(core.clone(), None);
+        let reload = |id: &str| {
+            Request::post(format!("/api/instances/{}/reload", id))
+                .body(Body::empty())
+                .unwrap()
+        };
+
+        let response = app.clone().oneshot(reload("sleeper-1")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::CONFLICT);
+        let response = app.clone().oneshot(reload("missing")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+        core.start_instance("sleeper-1").await.unwrap();
+        let response = app.oneshot(reload("sleeper-1")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(json["signal"], "SIGHUP");
+        assert_eq!(json["pid"], 1000);
+        assert_eq!(
+            monitor.signals(),
+            vec![(1000, crate::monitor::Signal::Hup, false)]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_signal_via_http() {
+        let dir = tempfile::tempdir().unwrap();
+        let (core, monitor) = mock_core(&dir, TEST_CONFIG).await;
+        let app = build_router(core.clone(), None);
+        let signal = |id: &str, body: &'static str| {
+            let request = Request::post(format!("/api/instances/{}/signal", id))
+                .header("content-type", "application/json")
+                .body(Body::from(body))
+                .unwrap();
+            let app = app.clone();
+            async move { app.oneshot(request).await.unwrap().status() }
+        };
+
+        let usr1 = r#"{"signal": "USR1"}"#;
+        assert_eq!(signal("sleeper-1", usr1).await, StatusCode::CONFLICT);
+        assert_eq!(signal("missing", usr1).await, StatusCode::NOT_FOUND);
+        core.start_instance("sleeper-1").await.unwrap();
+        assert_eq!(
+            signal("sleeper-1", r#"{"signal": "BOGUS"}"#).await,
+            StatusCode::BAD_REQUEST
+        );
+        assert_eq!(
+            signal("sleeper-1", r#"{"signal": "USR1", "group": true}"#).await,
+            StatusCode::OK
+        );
+        assert_eq!(
+            monitor.signals(),
+            vec![(1000, crate::monitor::Signal::Usr1, true)]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_set_instance_port() {
+        let dir = tempfile::tempdir().unwrap();
+        let (core, _) = mock_core(
+            &dir,
+            &format!(
+                "{}\n[instances.sleeper-2]\ntemplate = \"sleeper\"\nport = 18951\n",
+                TEST_CONFIG
+            ),
+        )
+        .await;
+        let app = build_router(core.clone(), None);
+        let set_port = |id: &str, port: u16| {
+            Request::put(format!("/api/instances/{}/port", id))
+                .header("content-type", "application/json")
+                .body(Body::from(format!(r#"{{"port": {}}}"#, port)))
+                .unwrap()
+        };
+
+        let response = app
+            .clone()
+            .oneshot(set_port("sleeper-1", 18951))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::CONFLICT);
+        let response = app
+            .clone()
+            .oneshot(set_port("missing", 18952))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+        let response = app.oneshot(set_port("sleeper-1", 18952)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(json["old_port"], 18950);
+        assert_eq!(json["port"], 18952);
+        assert_eq!(json["restart_required"], false);
+        assert_eq!(core.get_instance("sleeper-1").await.unwrap().port, 18952);
+    }
+
+    #[tokio::test]
+    async fn test_reconcile_endpoint() {
+        let dir = tempfile::tempdir().unwrap();
+        let (core, monitor) = mock_core(&dir, TEST_CONFIG).await;
+        core.start_instance("sleeper-1").await.unwrap();
+        let app = build_router(core, None);
+
+        let (status, body) = get_body(app.clone(), "/api/diagnostics/reconcile").await;
+        assert_eq!(status, StatusCode::OK);
+        let json: serde_json::Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(json["drifted"], 0);
+        assert_eq!(json["instances"][0]["pid"], 1000);
+        assert_eq!(json["instances"][0]["alive"], true);
+
+        monitor.set_running(1000, false);
+        let (_, body) = get_body(app, "/api/diagnostics/reconcile").await;
+        let json: serde_json::Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(json["drifted"], 1);
+        let entry = &json["instances"][0];
+        assert_eq!(entry["instance_id"], "sleeper-1");
+        assert_eq!(entry["status"], "running");
+        assert_eq!(entry["alive"], false);
+        assert_eq!(entry["drift"], true);
+    }
+
+    #[tokio::test]
+    async fn test_zombies_endpoint() {
+        let dir = tempfile::tempdir().unwrap();
+        let (core, monitor) = mock_core(&dir, TEST_CONFIG).await;
+        core.start_instance("sleeper-1").await.unwrap();
+        let app = build_router(core.clone(), None);
+
+        let (status, body) = get_body(app.clone(), "/api/diagnostics/zombies").await;
+        assert_eq!(status, StatusCode::OK);
+        let json: serde_json::Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(json["total"], 0);
+
+        core.stop_instance("sleeper-1").await.unwrap();
+        monitor.set_zombie(1000, true);
+        let (_, body) = get_body(app.clone(), "/api/diagnostics/zombies").await;
+        let json: serde_json::Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(json["total"], 1);
+        let entry = &json["zombies"][0];
+        assert_eq!(entry["pid"], 1000);
+        assert_eq!(entry["instance_id"], "sleeper-1");
+        assert_eq!(entry["tracked"], false);
+
+        core.reap_zombies().await;
+        let (_, body) = get_body(app, "/api/diagnostics/zombies").await;
+        let json: serde_json::Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(json["total"], 0);
+    }
+
+    #[tokio::test]
+    async fn test_create_instance_port_out_of_range() {
+        let dir = tempfile::tempdir().unwrap();
+        let (core, _) = mock_core(&dir, "[templates.svc]\ndisplay_name = \"Service\"\ndefault_port = 18960\nport_range = [18960, 18964]\nstart_command = \"serve\"\nsupports_multiple = true\n").await;
+        let app = build_router(core.clone(), None);
+
+        for uri in ["/api/instances", "/api/instances?upsert=true"] {
+            let request = Request::post(uri)
+                .header("content-type", "application/json")
+                .body(Body::from(
+                    r#"{"instance_id": "svc-1", "template_id": "svc", "port": 18970}"#,
+                ))
+                .unwrap();
+            let response = app.clone().oneshot(request).await.unwrap();
+            assert_eq!(response.status(), StatusCode::BAD_REQUEST, "{}", uri);
+            let bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+                .await
+                .unwrap();
+            assert_eq!(
+                String::from_utf8_lossy(&bytes),
+                "Port 18970 is outside the port range 18960-18964 of template 'svc'"
+            );
+        }
+        assert!(core.get_instance("svc-1").await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_clone_instance() {
+        let dir = tempfile::tempdir().unwrap();
+        let (core, _) = mock_core(
+            &dir,
+            r#"
+[templates.svc]
+display_name = "Service"
+default_port = 18970
+port_range = [18970, 18979]
+start_command = "serve"
+supports_multiple = true
+
+[templates.single]
+display_name = "Single"
+default_port = 18980
+start_command = "serve"
+
+[instances.svc-1]
+template = "svc"
+port = 18970
+working_dir = "/srv/svc"
+version = "1.0"
+tags = ["dev"]
+
+[instances.single-1]
+template = "single"
+port = 18980
+"#,
+        )
+        .await;
+        let app = build_router(core.clone(), None);
+        let clone = |uri: &str, body: &str| {
+            Request::post(uri)
+                .header("content-type", "application/json")
+                .body(Body::from(body.to_string()))
+                .unwrap()
+        };
+
+        let response = app
+            .clone()
+            .oneshot(clone(
+                "/api/instances/svc-1/clone",
+                r#"{"instance_id": "svc-2", "version": "2.0", "tags": ["canary"]}"#,
+            ))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let cloned = core.get_instance("svc-2").await.unwrap();
+        assert_eq!(cloned.template_id, "svc");
+        assert_eq!(cloned.port, 18971);
+        assert_eq!(
+            cloned.working_dir.as_deref(),
+            Some(std::path::Path::new("/srv/svc"))
+        );
+        assert_eq!(cloned.version.as_deref(), Some("2.0"));
+        assert_eq!(cloned.tags, vec!["canary", "dev"]);
+
+        let response = app
+            .clone()
+            .oneshot(clone(
+                "/api/instances/svc-1/clone",
+                r#"{"instance_id": "svc-3", "port": 18975, "no_inherit": true}"#,
+            ))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let cloned = core.get_instance("svc-3").await.unwrap();
+        assert_eq!(cloned.port, 18975);
+        assert!(cloned.tags.is_empty());
+
+        // Same id again, single-instance template, missing source
+        for (uri, status) in [
+            ("/api/instances/svc-1/clone", StatusCode::CONFLICT),
+            ("/api/instances/single-1/clone", StatusCode::CONFLICT),
+            ("/api/instances/missing/clone", StatusCode::NOT_FOUND),
+        ] {
+            let response = app
+                .clone()
+                .oneshot(clone(uri, r#"{"instance_id": "svc-3"}"#))
+                .await
+                .unwrap();
+            assert_eq!(response.status(), status, "{}", uri);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_busy_instance_rejected() {
+        let dir = tempfile::tempdir().unwrap();
+        let (core, monitor) = mock_core(&dir, TEST_CONFIG).await;
+        let state = AppState::new(core);
+
+        let busy = state.in_flight.begin("sleeper-1").unwrap();
+        assert!(state.in_flight.begin("sleeper-1").is_err());
+        for action in ["start", "stop", "restart"] {
+            let reply = handle_ws_command(
+                &state,
+                &format!(r#"{{"action":"{}","instance_id":"sleeper-1"}}"#, action),
+            )
+            .await;
+            assert_eq!(reply["code"], 429, "{}", action);
+        }
+        assert!(monitor.spawned().is_empty());
+
+        drop(busy);
+        assert!(!state.in_flight.contains("sleeper-1"));
+        let reply =
+            handle_ws_command(&state, r#"{"action":"start","instance_id":"sleeper-1"}"#).await;
+        assert_eq!(reply["type"], "command_result");
+        assert!(!state.in_flight.contains("sleeper-1"));
+    }
+
+    #[tokio::test]
+    async fn test_websocket_commands() {
+        let dir = tempfile::tempdir().unwrap();
+        let (core, _) = mock_core(&dir, TEST_CONFIG).await;
+        let state = AppState::new(core.clone());
+
+        let reply = handle_ws_command(
+            &state,
+            r#"{"action":"start","instance_id":"sleeper-1","request_id":7}"#,
+        )
+        .await;
+        assert_eq!(reply["type"], "command_result");
+        assert_eq!(reply["request_id"], 7);
+        assert_eq!(reply["result"]["pid"], 1000);
+        assert_eq!(
+            core.get_instance("sleeper-1").await.unwrap().status,
+            ServiceStatus::Running
+        );
+
+        let reply =
+            handle_ws_command(&state, r#"{"action":"stop","instance_id":"sleeper-1"}"#).await;
+        assert_eq!(reply["type"], "command_result");
+        assert_eq!(reply["action"], "stop");
+
+        let reply = handle_ws_command(&state, r#"{"action":"start","instance_id":"nope"}"#).await;
+        assert_eq!(reply["type"], "command_error");
+        assert_eq!(reply["code"], 404);
+
+        let reply =
+            handle_ws_command(&state, r#"{"action":"explode","instance_id":"sleeper-1"}"#).await;
+        assert_eq!(reply["type"], "command_error");
+        assert_eq!(reply["message"], "Unknown action 'explode'");
+
+        let reply = handle_ws_command(&state, r#"{"action":"start"}"#).await;
+        assert_eq!(reply["code"], 400);
+
+        let reply = handle_ws_command(&state, "not json").await;
+        assert_eq!(reply["type"], "command_error");
+        assert!(reply["message"]
+            .as_str()
+            .unwrap()
+            .starts_with("Invalid command"));
+    }
+
+    #[tokio::test]
+    async fn test_websocket_heartbeat_drops_dead_clients() {
+        use futures_util::StreamExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let (core, _) = mock_core(
+            &dir,
+            &format!(
+                "{}\n[server]\nws_ping_interval_ms = 100\nws_pong_timeout_ms = 100\n",
+                TEST_CONFIG
+            ),
+        )
+        .await;
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("ws://{}/ws", listener.local_addr().unwrap());
+        let app = build_router(core.clone(), None);
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        let baseline = core.event_bus.subscriber_count();
+
+        // Reads the greeting, then never again, so it never answers a ping
+        let (mut dead, _) = tokio_tungstenite::connect_async(&url).await.unwrap();
+        dead.next().await.unwrap().unwrap();
+        // Keeps reading, which answers pings
+        let (mut live, _) = tokio_tungstenite::connect_async(&url).await.unwrap();
+        live.next().await.unwrap().unwrap();
+        let reader = tokio::spawn(async move { while let Some(Ok(_)) = live.next().await {} });
+
+        tokio::time::sleep(Duration::from_millis(700)).await;
+        assert_eq!(core.event_bus.subscriber_count(), baseline + 1);
+        assert!(!reader.is_finished());
+        reader.abort();
+    }
+
+    #[tokio::test]
+    async fn test_file_logs_survive_stop() {
+        let dir = tempfile::tempdir().unwrap();
+        let config_path = dir.path().join("services.toml");
+        std::fs::write(&config_path, TEST_CONFIG).unwrap();
+        // A PID no real process has, so the capture files are ours alone
+        let pid = u32::MAX - 7;
+        let monitor = Arc::new(crate::monitor::MockMonitor::with_first_pid(pid));
+        let core = Arc::new(UsmCore::with_monitor(&config_path, monitor).await.unwrap());
+        let app = build_router(core.clone(), None);
+
+        let (status, _) = get_body(app.clone(), "/api/instances/sleeper-1/logs").await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+
+        let (stdout, stderr) = crate::monitor::log_paths(pid);
+        std::fs::write(&stdout, "booting\nlistening\n").unwrap();
+        std::fs::write(&stderr, "warning: deprecated flag\npanic: port in use\n").unwrap();
+        core.start_instance("sleeper-1").await.unwrap();
+        core.stop_instance("sleeper-1").await.unwrap();
+
+        let (status, body) = get_body(app.clone(), "/api/instances/sleeper-1/logs?lines=1").await;
+        let _ = std::fs::remove_file(&stdout);
+        let _ = std::fs::remove_file(&stderr);
+        assert_eq!(status, StatusCode::OK);
+        let json: serde_json::Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(json["source"], "file");
+        assert_eq!(json["pid"], pid);
+        assert_eq!(json["stdout"], serde_json::json!(["listening"]));
+        assert_eq!(json["stderr"], serde_json::json!(["panic: port in use"]));
+
+        let (status, _) = get_body(app, "/api/instances/sleeper-1/logs?source=memory").await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_file_logs_in_log_dir() {
+        let dir = tempfile::tempdir().unwrap();
+        let (core, _) = mock_core(
+            &dir,
+            &format!(
+                "{}\n[logs]\ndir = \"{}\"\n",
+                TEST_CONFIG,
+                dir.path().join("logs").display()
+            ),
+        )
+        .await;
+        let app = build_router(core.clone(), None);
+
+        core.start_instance("sleeper-1").await.unwrap();
+        let pid = core
+            .get_instance("sleeper-1")
+            .await
+            .unwrap()
+            .last_pid
+            .unwrap();
+        let (stdout, stderr) = core.log_paths("sleeper-1", pid);
+        assert_eq!(stdout, dir.path().join("logs/sleeper-1.stdout.log"));
+        assert_eq!(stderr, dir.path().join("logs/sleeper-1.stderr.log"));
+
+        std::fs::create_dir_all(dir.path().join("logs")).unwrap();
+        std::fs::write(&stdout, "booting\nlistening\n").unwrap();
+        std::fs::write(&stderr, "").unwrap();
+        let (status, body) = get_body(app, "/api/instances/sleeper-1/logs?lines=1").await;
+        assert_eq!(status, StatusCode::OK);
+        let json: serde_json::Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(json["stdout"], serde_json::json!(["listening"]));
+        assert_eq!(json["stderr"], serde_json::json!([]));
+    }
+
+    #[tokio::test]
+    async fn test_env_get_and_patch() {
+        let dir = tempfile::tempdir().unwrap();
+        let config_path = dir.path().join("services.toml");
+        let (core, _) = mock_core(
+            &dir,
+            r#"
+[templates.svc]
+display_name = "Service"
+default_port = 8000
+start_command = "serve"
+
+[templates.svc.default_env]
+LOG_LEVEL = "info"
+WORKERS = "4"
+
+[instances.svc-1]
+template = "svc"
+port = 8001
+
+[instances.svc-1.env_vars]
+LOG_LEVEL = "debug"
+"#,
+        )
+        .await;
+        let app = build_router(core.clone(), None);
+
+        let (status, body) = get_body(app.clone(), "/api/instances/svc-1/env").await;
+        assert_eq!(status, StatusCode::OK);
+        let json: serde_json::Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(json["env"]["LOG_LEVEL"]["value"], "debug");
+        assert_eq!(json["env"]["LOG_LEVEL"]["source"], "instance");
+        assert_eq!(json["env"]["LOG_LEVEL"]["overrides_template"], true);
+        assert_eq!(json["env"]["WORKERS"]["source"], "template");
+
+        // Unsetting LOG_LEVEL reveals the template default again
+        let patch = |body: &str| {
+            Request::patch("/api/instances/svc-1/env")
+                .header("content-type", "application/json")
+                .body(Body::from(body.to_string()))
+                .unwrap()
+        };
+        let response = app
+            .clone()
+            .oneshot(patch(r#"{"LOG_LEVEL": null, "REGION": "eu"}"#))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let env = core.instance_env("svc-1").await.unwrap();
+        assert_eq!(env["LOG_LEVEL"].value, "info");
+        assert_eq!(env["LOG_LEVEL"].source, crate::service::EnvSource::Template);
+        assert_eq!(env["REGION"].value, "eu");
+
+        // Persisted to the config file
+        let reloaded = UsmCore::new(&config_path).await.unwrap();
+        let instance = reloaded.get_instance("svc-1").await.unwrap();
+        assert_eq!(instance.env_vars.get("REGION").unwrap(), "eu");
+        assert!(!instance.env_vars.contains_key("LOG_LEVEL"));
+
+        let response = app.clone().oneshot(patch(r#"{"A=B": "x"}"#)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        let (status, _) = get_body(app, "/api/instances/missing/env").await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_patch_instance() {
+        let dir = tempfile::tempdir().unwrap();
+        let config_path = dir.path().join("services.toml");
+        let (core, _) = mock_core(&dir, TEST_CONFIG).await;
+        let mut events = core.subscribe();
+        let app = build_router(core.clone(), None);
+        let patch = |body: &str| {
+            Request::patch("/api/instances/sleeper-1")
+                .header("content-type", "application/json")
+                .body(Body::from(body.to_string()))
+                .unwrap()
+        };
+        let send = |body: &'static str| {
+            let app = app.clone();
+            async move {
+                let response = app.oneshot(patch(body)).await.unwrap();
+                let status = response.status();
+                let bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+                    .await
+                    .unwrap();
+                (status, String::from_utf8_lossy(&bytes).into_owned())
+            }
+        };
+
+        let (status, body) =
+            send(r#"{"tags": ["core"], "version": "1.2", "labels": {"ticket": "OPS-7"}}"#).await;
+        assert_eq!(status, StatusCode::OK);
+        let json: serde_json::Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(json["instance"]["tags"], serde_json::json!(["core"]));
+        assert_eq!(json["instance"]["labels"]["ticket"], "OPS-7");
+        assert!(json["instance"]["env_vars"].get("ticket").is_none());
+        assert_eq!(json["instance"]["version"], "1.2");
+        assert_eq!(json["restart_required"], false);
+        let event = events.recv().await.unwrap();
+        assert_eq!(event.event_type(), "instance_updated");
+        assert_eq!(event.instance_id(), Some("sleeper-1"));
+
+        // Env changes on a running instance need a restart
+        core.instances
+            .write()
+            .await
+            .update_status("sleeper-1", ServiceStatus::Running, Some(1))
+            .unwrap();
+        let (status, body) = send(r#"{"env_vars": {"REGION": "eu"}}"#).await;
+        assert_eq!(status, StatusCode::OK);
+        let json: serde_json::Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(json["instance"]["env_vars"]["REGION"], "eu");
+        assert_eq!(json["instance"]["tags"], serde_json::json!(["core"]));
+        assert_eq!(json["restart_required"], true);
+
+        // Persisted to the config file
+        let reloaded = UsmCore::new(&config_path).await.unwrap();
+        let instance = reloaded.get_instance("sleeper-1").await.unwrap();
+        assert_eq!(instance.tags, vec!["core"]);
+        assert_eq!(instance.version.as_deref(), Some("1.2"));
+        assert_eq!(instance.env_vars.get("REGION").unwrap(), "eu");
+        assert_eq!(instance.labels.get("ticket").unwrap(), "OPS-7");
+
+        let (status, _) = send(r#"{"env_vars": {"": "x"}}"#).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        let response = app
+            .clone()
+            .oneshot(
+                Request::patch("/api/instances/missing")
+                    .header("content-type", "application/json")
+                    .body(Body::from("{}"))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_patch_instance_metadata_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let config_path = dir.path().join("services.toml");
+        let (core, _) = mock_core(&dir, TEST_CONFIG).await;
+        let app = build_router(core, None);
+        let send = |body: &'static str| {
+            let app = app.clone();
+            async move {
+                let request = Request::patch("/api/instances/sleeper-1")
+                    .header("content-type", "application/json")
+                    .body(Body::from(body))
+                    .unwrap();
+                let response = app.oneshot(request).await.unwrap();
+                assert_eq!(response.status(), StatusCode::OK);
+                let bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+                    .await
+                    .unwrap();
+                serde_json::from_slice::<serde_json::Value>(&bytes).unwrap()
+            }
+        };
+
+        send(r#"{"metadata": {"owner": "infra", "deploy_sha": "abc123"}}"#).await;
+        // Merged: keys left out stay, null removes one
+        let json = send(r#"{"metadata": {"deploy_sha": null, "ticket": "OPS-9"}}"#).await;
+        assert_eq!(
+            json["instance"]["metadata"],
+            serde_json::json!({"owner": "infra", "ticket": "OPS-9"})
+        );
+        assert!(json["instance"]["env_vars"].get("owner").is_none());
+
+        // Saved as its own table and read back
+        let content = std::fs::read_to_string(&config_path).unwrap();
+        assert!(content.contains("[instances.sleeper-1.metadata]"));
+        let reloaded = UsmCore::new(&config_path).await.unwrap();
+        let instance = reloaded.get_instance("sleeper-1").await.unwrap();
+        assert_eq!(
+            instance.metadata,
+            HashMap::from([
+                ("owner".to_string(), "infra".to_string()),
+                ("ticket".to_string(), "OPS-9".to_string()),
+            ])
+        );
+        assert!(instance.labels.is_empty());
+    }
+}
@@ -0,0 +1,781 @@
+//! OpenAPI 3 document for the REST API, served at `/api/openapi.json`
+//!
+//! Written by hand next to the handlers rather than generated, so it costs
+//! no dependencies. Keep it in step with `build_router` when adding a route
+//! or changing a response body; a test checks that every route is listed.
+
+use serde_json::{json, Value};
+
+/// The OpenAPI document
+pub fn document() -> Value {
+    json!({
+        "openapi": "3.0.3",
+        "info": {
+            "title": "USM Core API",
+            "description": "Manage service templates and instances. Errors are plain-text messages unless noted.",
+            "version": crate::VERSION
+        },
+        "servers": [{ "url": "/" }],
+        "security": [{ "bearer": [] }],
+        "paths": paths(),
+        "components": {
+            "securitySchemes": {
+                "bearer": {
+                    "type": "http",
+                    "scheme": "bearer",
+                    "description": "Required when the server runs with an auth token"
+                }
+            },
+            "schemas": schemas()
+        }
+    })
+}
+
+fn paths() -> Value {
+    json!({
+        "/api/health": {
+            "get": public(op("Server health", None, ok(obj(json!({
+                "status": string(),
+                "unhealthy": array(string()),
+                "service": string(),
+                "version": string(),
+                "ws_subscribers": integer(),
+                "event_capacity": integer(),
+                "event_lagged": integer()
+            })))).with_error("503", "An auto_start or critical instance is in error"))
+        },
+        "/api/openapi.json": {
+            "get": op("This document", None, ok(json!({ "type": "object" })))
+        },
+        "/api/version": {
+            "get": op("Server version and build", None, ok(required_fields(obj(json!({
+                "version": string(),
+                "git_commit": string(),
+                "build_timestamp": string()
+            })), &["version", "git_commit", "build_timestamp"])))
+        },
+        "/api/templates": {
+            "get": with_params(
+                op("List templates", None, ok(array(schema("ServiceTemplate"))))
+                    .with_error("400", "Unknown category"),
+                vec![query(
+                    "category",
+                    enumeration(&["core", "development", "database", "infrastructure", "custom"]),
+                    "Only templates in this category"
+                )]
+            ),
+            "post": with_body(
+                op("Register a template", None, ok(schema("ServiceTemplate")))
+                    .with_error("400", "Invalid template"),
+                schema("ServiceTemplate")
+            )
+        },
+        "/api/templates/{id}": {
+            "get": with_params(
+                op("Get a template", None, ok(schema("ServiceTemplate"))).with_error("404", "Unknown template"),
+                vec![path_id()]
+            ),
+            "delete": with_params(
+                op("Remove a template", None, ok(schema("Removed")))
+                    .with_error("404", "Unknown template")
+                    .with_error("409", "Template is in use or extended by another"),
+                vec![path_id()]
+            )
+        },
+        "/api/instances": {
+            "get": with_params(
+                op("List instances, with metrics for running ones", None, ok(schema("InstanceList")))
+                    .with_error("400", "Invalid status or tag_mode"),
+                vec![
+                    query("group", string(), "Only instances in this group"),
+                    query("template", string(), "Only instances of this template"),
+                    query("tag", string(), "Comma-separated tags"),
+                    query("tag_mode", enumeration(&["any", "all"]), "How several tags combine (default any)"),
+                    query("status", schema("ServiceStatus"), "Only instances with this status"),
+                    query("q", string(), "Only instances whose id, template or tags contain this (any case)"),
+                ]
+            ),
+            "post": with_body(
+                with_params(
+                    op("Create an instance", None, ok(obj(json!({
+                        "status": string(),
+                        "instance_id": string(),
+                        "port": integer(),
+                        "created": { "type": "boolean", "description": "Only with upsert=true" }
+                    }))))
+                    .with_error("400", "Invalid config, unknown template or port outside the template's range")
+                    .with_error("409", "Instance or port already in use, or a limit was reached"),
+                    vec![query("upsert", boolean(), "Update the instance in place if it exists: its port (if given), tags, env_vars and working_dir are replaced, so ones left out are cleared")]
+                ),
+                schema("InstanceConfig")
+            )
+        },
+        "/api/instances/{id}": {
+            "get": with_params(
+                op("Get an instance", None, ok(obj(json!({
+                    "instance": schema("NamedInstance"),
+                    "metrics": nullable(schema("InstanceMetrics"))
+                })))).with_error("404", "Unknown instance"),
+                vec![path_id()]
+            ),
+            "patch": with_body(
+                with_params(
+                    op(
+                        "Edit env vars, tags, labels, metadata, working dir, version or git branch",
+                        Some("Fields left out keep their value; env vars, labels and metadata are merged, null unsets a key. A running instance picks up env and working dir changes on restart."),
+                        ok(obj(json!({
+                            "status": string(),
+                            "instance": schema("ServiceInstance"),
+                            "restart_required": boolean()
+                        })))
+                    )
+                    .with_error("400", "Invalid environment variable, label or metadata key")
+                    .with_error("404", "Unknown instance"),
+                    vec![path_id()]
+                ),
+                schema("InstanceUpdate")
+            ),
+            "delete": with_params(
+                op("Remove an instance, stopping it first", None, ok(schema("Removed")))
+                    .with_error("404", "Unknown instance"),
+                vec![path_id(), query("force", boolean(), "Remove even if stopping fails, SIGKILLing the process; `stop` says what happened to it")]
+            )
+        },
+        "/api/instances/{id}/start": {
+            "post": with_params(
+                op("Start an instance", None, ok(obj(json!({
+                    "status": string(),
+                    "message": string(),
+                    "pid": nullable(integer()),
+                    "command": string(),
+                    "working_dir": nullable(string())
+                }))))
+                .with_error("404", "Unknown instance")
+                .with_error("409", "Already starting or stopping, or a limit was reached")
+                .with_error("429", "Another operation on the instance is in progress")
+                .with_json_error("500", "Launching failed", obj(json!({
+                    "status": string(),
+                    "message": string(),
+                    "error": string(),
+                    "command": string(),
+                    "working_dir": nullable(string()),
+                    "stderr": array(string())
+                }))),
+                vec![path_id()]
+            )
+        },
+        "/api/instances/{id}/stop": {
+            "post": with_params(
+                op("Stop an instance", None, ok(obj(json!({
+                    "status": string(),
+                    "message": string(),
+                    "command": { "type": "string", "nullable": true, "description": "Stop command run, or null if signalled" }
+                }))))
+                .with_error("404", "Unknown instance")
+                .with_error("429", "Another operation on the instance is in progress"),
+                vec![path_id()]
+            )
+        },
+        "/api/instances/{id}/restart": {
+            "post": with_params(
+                op("Restart an instance", None, ok(obj(json!({
+                    "status": string(),
+                    "message": string(),
+                    "pid": nullable(integer())
+                }))))
+                .with_error("404", "Unknown instance")
+                .with_error("429", "Another operation on the instance is in progress"),
+                vec![path_id()]
+            )
+        },
+        "/api/instances/{id}/reset": {
+            "post": with_params(
+                op(
+                    "Clear an instance's error state",
+                    Some("Stops whatever is left of a failed instance and leaves it stopped, forgetting its last exit code and error. Stopped instances are only cleared."),
+                    ok(obj(json!({
+                        "status": string(),
+                        "message": string()
+                    })))
+                )
+                .with_error("404", "Unknown instance")
+                .with_error("409", "Instance is running, starting or stopping")
+                .with_error("429", "Another operation on the instance is in progress"),
+                vec![path_id()]
+            )
+        },
+        "/api/instances/{id}/clone": {
+            "post": with_body(
+                with_params(
+                    op("Create an instance from another one's template and settings", None, ok(obj(json!({
+                        "status": string(),
+                        "instance_id": string(),
+                        "port": integer()
+                    }))))
+                    .with_error("404", "Unknown source instance")
+                    .with_error("409", "Template is single-instance, id or port taken, or a limit was reached"),
+                    vec![path_id()]
+                ),
+                obj(json!({
+                    "instance_id": string(),
+                    "group": { "type": "string", "description": "The source's group if not specified" },
+                    "port": integer(),
+                    "working_dir": string(),
+                    "version": string(),
+                    "tags": array(string()),
+                    "no_inherit": boolean()
+                }))
+            )
+        },
+        "/api/instances/{id}/reload": {
+            "post": with_params(
+                op("Send a running instance its template's reload signal (SIGHUP by default)", None, ok(obj(json!({
+                    "status": string(),
+                    "message": string(),
+                    "signal": string(),
+                    "pid": integer()
+                }))))
+                .with_error("404", "Unknown instance")
+                .with_error("409", "Instance is not running"),
+                vec![path_id()]
+            )
+        },
+        "/api/instances/{id}/signal": {
+            "post": with_body(
+                with_params(
+                    op("Send a signal to a running instance", None, ok(obj(json!({
+                        "status": string(),
+                        "message": string(),
+                        "pid": integer()
+                    }))))
+                    .with_error("400", "Unknown signal")
+                    .with_error("404", "Unknown instance")
+                    .with_error("409", "Instance is not running"),
+                    vec![path_id()]
+                ),
+                obj(json!({
+                    "signal": { "type": "string", "example": "HUP" },
+                    "group": { "type": "boolean", "description": "Signal the whole process group" }
+                }))
+            )
+        },
+        "/api/instances/{id}/logs": {
+            "get": with_params(
+                op("Recent output of an instance", None, ok(obj(json!({
+                    "instance_id": string(),
+                    "source": enumeration(&["memory", "file"]),
+                    "lines": { "type": "array", "items": schema("LogLine"), "description": "source=memory" },
+                    "pid": { "type": "integer", "description": "source=file" },
+                    "stdout": { "type": "array", "items": string(), "description": "source=file" },
+                    "stderr": { "type": "array", "items": string(), "description": "source=file" }
+                }))))
+                .with_error("400", "Unsupported source, or memory capture disabled")
+                .with_error("404", "Unknown instance, or no captured output"),
+                vec![
+                    path_id(),
+                    query("source", enumeration(&["memory", "file"]), "Memory buffer if enabled, else files"),
+                    query("lines", integer(), "Number of lines (default 100 for files)"),
+                ]
+            )
+        },
+        "/api/instances/{id}/metrics/history": {
+            "get": with_params(
+                op("Recent samples for one instance, oldest first", None, ok(obj(json!({
+                    "instance_id": string(),
+                    "samples": array(schema("MetricsSample"))
+                })))).with_error("404", "Unknown instance"),
+                vec![path_id()]
+            )
+        },
+        "/api/instances/{id}/env": {
+            "get": with_params(
+                op("Effective environment with the source of each value", None, ok(obj(json!({
+                    "instance_id": string(),
+                    "env": map(schema("EnvValue"))
+                })))).with_error("404", "Unknown instance"),
+                vec![path_id()]
+            ),
+            "patch": with_body(
+                with_params(
+                    op("Set (string) or unset (null) instance env vars", None, ok(obj(json!({
+                        "status": string(),
+                        "instance_id": string(),
+                        "env": map(schema("EnvValue")),
+                        "restart_required": boolean()
+                    })))).with_error("404", "Unknown instance"),
+                    vec![path_id()]
+                ),
+                map(nullable(string()))
+            )
+        },
+        "/api/instances/{id}/port": {
+            "put": with_body(
+                with_params(
+                    op("Move an instance to another port", None, ok(obj(json!({
+                        "status": string(),
+                        "instance_id": string(),
+                        "old_port": integer(),
+                        "port": integer(),
+                        "restart_required": boolean()
+                    }))))
+                    .with_error("400", "Port outside the template's port range")
+                    .with_error("404", "Unknown instance")
+                    .with_error("409", "Port used by another instance or process"),
+                    vec![path_id()]
+                ),
+                obj(json!({ "port": integer() }))
+            )
+        },
+        "/api/metrics": {
+            "get": op("System metrics and per-instance metrics of running instances", None, ok(obj(json!({
+                "system": obj(json!({
+                    "cpu_percent": number(),
+                    "memory_used_gb": number(),
+                    "memory_total_gb": number(),
+                    "memory_percent": number(),
+                    "load_average": {
+                        "type": "array",
+                        "items": number(),
+                        "minItems": 3,
+                        "maxItems": 3,
+                        "description": "1, 5 and 15 minute load average; zeros where unavailable"
+                    }
+                })),
+                "instances": obj(json!({
+                    "running": integer(),
+                    "stopped": integer(),
+                    "error": integer(),
+                    "total": integer()
+                })),
+                "instance_metrics": map(schema("InstanceMetrics"))
+            }))))
+        },
+        "/api/metrics/instances": {
+            "get": op(
+                "Metrics of every running instance, by instance ID",
+                Some("Measures all processes in one pass; cheaper than polling each instance."),
+                ok(map(schema("InstanceMetrics")))
+            )
+        },
+        "/api/metrics/history": {
+            "get": op("Recent system samples, oldest first", None, ok(obj(json!({
+                "samples": array(schema("MetricsSample"))
+            }))))
+        },
+        "/metrics": {
+            "get": op(
+                "Prometheus text format",
+                None,
+                json!({ "200": { "description": "OK", "content": { "text/plain": { "schema": string() } } } })
+            )
+        },
+        "/api/diagnostics/reconcile": {
+            "get": op("Stored PIDs checked against running processes", None, ok(obj(json!({
+                "instances": array(obj(json!({
+                    "instance_id": string(),
+                    "status": schema("ServiceStatus"),
+                    "pid": nullable(integer()),
+                    "alive": boolean(),
+                    "drift": boolean()
+                }))),
+                "drifted": integer()
+            }))))
+        },
+        "/api/diagnostics/zombies": {
+            "get": op("Exited but unreaped processes among spawn wrappers and instance PIDs", None, ok(obj(json!({
+                "zombies": array(obj(json!({
+                    "pid": integer(),
+                    "instance_id": nullable(string()),
+                    "tracked": boolean()
+                }))),
+                "total": integer()
+            }))))
+        },
+        "/ws/instances/{id}": {
+            "get": with_params(
+                op(
+                    "WebSocket upgrade for one instance's events",
+                    Some("Like /ws, but only forwards events about this instance, including its metrics_updated. The first frame is {\"type\": \"connected\", \"instance\": ServiceInstance}. Closes after the instance_removed event."),
+                    json!({ "101": { "description": "Switching protocols" } })
+                )
+                .with_error("404", "Unknown instance"),
+                vec![path_id()]
+            )
+        },
+        "/ws/instances/{id}/logs": {
+            "get": with_params(
+                op(
+                    "WebSocket upgrade streaming an instance's new output lines",
+                    Some("Sends each line written to the captured stdout/stderr files as a LogLine frame, following the instance across restarts. Closes when the instance is removed."),
+                    json!({
+                        "101": {
+                            "description": "Switching protocols; frames are LogLine objects",
+                            "content": { "application/json": { "schema": schema("LogLine") } }
+                        }
+                    })
+                )
+                .with_error("404", "Unknown instance"),
+                vec![path_id()]
+            )
+        },
+        "/ws": {
+            "get": op(
+                "WebSocket upgrade for live events",
+                Some("Receives service events as JSON messages. Send {\"action\": \"start\" | \"stop\" | \"restart\", \"instance_id\": ..., \"request_id\": ...} to control instances; replies are command_result or command_error messages."),
+                json!({ "101": { "description": "Switching protocols" } })
+            )
+        }
+    })
+}
+
+fn schemas() -> Value {
+    let instance_fields = json!({
+        "group": nullable(string()),
+        "port": integer(),
+        "working_dir": nullable(string()),
+        "config_path": nullable(string()),
+        "version": nullable(string()),
+        "git_branch": nullable(string()),
+        "tags": array(string()),
+        "auto_start": boolean(),
+        "env_vars": map(string()),
+        "display_name": nullable(string()),
+        "notes": nullable(string()),
+        "labels": map(string()),
+        "metadata": map(string()),
+        "depends_on": array(string()),
+        "schedule": nullable(schema("ScheduleConfig")),
+        "restart": nullable(schema("RestartPolicy")),
+        "start_command_override": nullable(string()),
+        "stop_command_override": nullable(string()),
+        "cpu_limit_percent": nullable(number()),
+        "memory_limit_mb": nullable(integer()),
+        "limit_action": nullable(enumeration(&["warn", "restart", "stop"]))
+    });
+    let with = |extra: Value| {
+        let mut fields = instance_fields.as_object().unwrap().clone();
+        fields.extend(extra.as_object().unwrap().clone());
+        Value::Object(fields)
+    };
+
+    let mut port_range = array(integer());
+    port_range["minItems"] = json!(2);
+    port_range["maxItems"] = json!(2);
+
+    json!({
+        "ServiceStatus": enumeration(&["stopped", "starting", "running", "stopping", "error", "unknown"]),
+        "ServiceTemplate": required_fields(obj(json!({
+            "id": string(),
+            "display_name": string(),
+            "description": nullable(string()),
+            "default_port": integer(),
+            "port_range": nullable(port_range),
+            "start_command": string(),
+            "stop_command": nullable(string()),
+            "health_endpoint": nullable(string()),
+            "health_command": nullable(string()),
+            "health_expect_status": nullable(integer()),
+            "health_expect_body_contains": nullable(string()),
+            "health_timeout_ms": integer(),
+            "health_start_period_ms": nullable(integer()),
+            "stop_timeout_ms": integer(),
+            "startup_verify_ms": integer(),
+            "reload_signal": nullable(string()),
+            "category": enumeration(&["core", "development", "database", "infrastructure", "custom"]),
+            "supports_multiple": boolean(),
+            "max_instances": nullable(integer()),
+            "is_docker": boolean(),
+            "restart": nullable(schema("RestartPolicy")),
+            "default_env": map(string()),
+            "working_dir": nullable(string()),
+            "extends": nullable(string())
+        })), &["id", "display_name", "default_port", "start_command"]),
+        "InstanceConfig": required_fields(obj(with(json!({
+            "instance_id": string(),
+            "template_id": string()
+        }))), &["instance_id", "template_id"]),
+        "InstanceUpdate": obj(json!({
+            "env_vars": map(nullable(string())),
+            "tags": array(string()),
+            "labels": map(nullable(string())),
+            "metadata": map(nullable(string())),
+            "working_dir": string(),
+            "version": string(),
+            "git_branch": string()
+        })),
+        "ServiceInstance": obj(with(json!({
+            "id": string(),
+            "template_id": string(),
+            "status": schema("ServiceStatus"),
+            "pid": nullable(integer()),
+            "started_at": nullable(date_time()),
+            "last_pid": nullable(integer()),
+            "last_exit_code": nullable(integer()),
+            "last_error": nullable(string()),
+            "created_at": date_time(),
+            "created_via": enumeration(&["api", "config"])
+        }))),
+        "NamedInstance": {
+            "allOf": [
+                schema("ServiceInstance"),
+                obj(json!({
+                    "name": { "type": "string", "description": "display_name, else the template's display name, else the id" }
+                }))
+            ]
+        },
+        "InstanceWithMetrics": {
+            "allOf": [
+                schema("NamedInstance"),
+                obj(json!({
+                    "cpu_percent": { "type": "number", "description": "Running instances only" },
+                    "memory_mb": { "type": "integer", "description": "Running instances only" }
+                }))
+            ]
+        },
+        "InstanceList": obj(json!({
+            "instances": array(schema("InstanceWithMetrics")),
+            "total": { "type": "integer", "description": "All instances, not just those listed" },
+            "running": integer(),
+            "stopped": integer(),
+            "error": integer()
+        })),
+        "InstanceMetrics": obj(json!({
+            "cpu_percent": number(),
+            "memory_bytes": integer(),
+            "memory_percent": number(),
+            "threads": integer(),
+            "open_files": integer(),
+            "uptime_seconds": integer()
+        })),
+        "MetricsSample": obj(json!({
+            "timestamp": date_time(),
+            "cpu_percent": number(),
+            "memory_bytes": integer()
+        })),
+        "EnvValue": obj(json!({
+            "value": string(),
+            "source": enumeration(&["template", "instance"]),
+            "overrides_template": boolean()
+        })),
+        "LogLine": obj(json!({
+            "stream": enumeration(&["stdout", "stderr"]),
+            "line": string()
+        })),
+        "RestartPolicy": obj(json!({
+            "policy": enumeration(&["no", "on-failure"]),
+            "max_retries": integer(),
+            "backoff_ms": integer(),
+            "max_backoff_ms": integer()
+        })),
+        "ScheduleConfig": obj(json!({
+            "start": { "type": "string", "description": "5-field cron expression" },
+            "stop": { "type": "string", "description": "5-field cron expression" },
+            "timezone": string()
+        })),
+        "Removed": obj(json!({
+            "status": string(),
+            "message": string(),
+            "template_id": string(),
+            "instance_id": string(),
+            "stop": string()
+        }))
+    })
+}
+
+// === Builders ===
+
+/// An operation with a summary, optional description and responses
+fn op(summary: &str, description: Option<&str>, responses: Value) -> Value {
+    let mut op = json!({ "summary": summary, "responses": responses });
+    if let Some(description) = description {
+        op["description"] = json!(description);
+    }
+    op
+}
+
+/// A 200 response with a JSON body
+fn ok(body: Value) -> Value {
+    json!({
+        "200": { "description": "OK", "content": { "application/json": { "schema": body } } }
+    })
+}
+
+trait Responses {
+    /// Add a plain-text error response
+    fn with_error(self, code: &str, description: &str) -> Self;
+    /// Add an error response with a JSON body
+    fn with_json_error(self, code: &str, description: &str, body: Value) -> Self;
+}
+
+impl Responses for Value {
+    fn with_error(mut self, code: &str, description: &str) -> Self {
+        self["responses"][code] = json!({
+            "description": description,
+            "content": { "text/plain": { "schema": string() } }
+        });
+        self
+    }
+
+    fn with_json_error(mut self, code: &str, description: &str, body: Value) -> Self {
+        self["responses"][code] = json!({
+            "description": description,
+            "content": { "application/json": { "schema": body } }
+        });
+        self
+    }
+}
+
+fn with_params(mut op: Value, params: Vec<Value>) -> Value {
+    op["parameters"] = Value::Array(params);
+    op
+}
+
+fn with_body(mut op: Value, body: Value) -> Value {
+    op["requestBody"] = json!({
+        "required": true,
+        "content": { "application/json": { "schema": body } }
+    });
+    op
+}
+
+/// Mark an operation as not needing the bearer token
+fn public(mut op: Value) -> Value {
+    op["security"] = json!([]);
+    op
+}
+
+fn path_id() -> Value {
+    json!({ "name": "id", "in": "path", "required": true, "schema": string() })
+}
+
+fn query(name: &str, schema: Value, description: &str) -> Value {
+    json!({ "name": name, "in": "query", "schema": schema, "description": description })
+}
+
+fn required_fields(mut schema: Value, fields: &[&str]) -> Value {
+    schema["required"] = json!(fields);
+    schema
+}
+
+fn schema(name: &str) -> Value {
+    json!({ "$ref": format!("#/components/schemas/{}", name) })
+}
+
+fn obj(properties: Value) -> Value {
+    json!({ "type": "object", "properties": properties })
+}
+
+fn array(items: Value) -> Value {
+    json!({ "type": "array", "items": items })
+}
+
+fn map(values: Value) -> Value {
+    json!({ "type": "object", "additionalProperties": values })
+}
+
+/// A schema that may also be null (`$ref`s are wrapped, as OpenAPI 3.0
+/// ignores siblings of `$ref`)
+fn nullable(schema: Value) -> Value {
+    if schema.get("$ref").is_some() {
+        return json!({ "allOf": [schema], "nullable": true });
+    }
+    let mut schema = schema;
+    schema["nullable"] = json!(true);
+    schema
+}
+
+fn enumeration(values: &[&str]) -> Value {
+    json!({ "type": "string", "enum": values })
+}
+
+fn string() -> Value {
+    json!({ "type": "string" })
+}
+
+fn integer() -> Value {
+    json!({ "type": "integer" })
+}
+
+fn number() -> Value {
+    json!({ "type": "number" })
+}
+
+fn boolean() -> Value {
+    json!({ "type": "boolean" })
+}
+
+fn date_time() -> Value {
+    json!({ "type": "string", "format": "date-time" })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Paths registered in `build_router`, in OpenAPI form (`:id` -> `{id}`)
+    fn router_paths() -> Vec<String> {
+        let source = include_str!("mod.rs");
+        let mut paths = Vec::new();
+        let mut rest = source;
+        while let Some(start) = rest.find(".route(") {
+            rest = &rest[start..];
+            let quote = rest.find('"').unwrap() + 1;
+            let end = quote + rest[quote..].find('"').unwrap();
+            paths.push(rest[quote..end].replace(":id", "{id}"));
+            rest = &rest[end..];
+        }
+        paths
+    }
+
+    #[test]
+    fn test_every_route_is_documented() {
+        let doc = document();
+        let documented = doc["paths"].as_object().unwrap();
+        let routes = router_paths();
+        assert!(routes.len() > 10, "{:?}", routes);
+        for path in routes {
+            assert!(documented.contains_key(&path), "{} is not documented", path);
+        }
+    }
+
+    #[test]
+    fn test_refs_resolve() {
+        let doc = document();
+        let text = doc.to_string();
+        let schemas = doc["components"]["schemas"].as_object().unwrap();
+        for part in text.split("#/components/schemas/").skip(1) {
+            let name = &part[..part.find('"').unwrap()];
+            assert!(schemas.contains_key(name), "missing schema {}", name);
+        }
+    }
+
+    #[test]
+    fn test_instance_schema_matches_serialization() {
+        let instance = crate::service::ServiceInstance::from_config(
+            serde_json::from_value(json!({ "instance_id": "a", "template_id": "t", "port": 1 }))
+                .unwrap(),
+        )
+        .unwrap();
+        let serialized = serde_json::to_value(instance).unwrap();
+        let schemas = &document()["components"]["schemas"];
+        let documented = schemas["ServiceInstance"]["properties"]
+            .as_object()
+            .unwrap();
+
+        let fields = serialized.as_object().unwrap();
+        for key in fields.keys() {
+            assert!(
+                documented.contains_key(key),
+                "ServiceInstance.{} is not documented",
+                key
+            );
+        }
+        for key in documented.keys() {
+            assert!(
+                fields.contains_key(key),
+                "ServiceInstance.{} no longer exists",
+                key
+            );
+        }
+    }
+}
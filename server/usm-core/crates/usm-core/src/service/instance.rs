@@ -7,6 +7,10 @@ use anyhow::Result;
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 
+use super::check_placeholders;
+use crate::schedule::ScheduleConfig;
+use crate::supervisor::{check_limits, LimitAction, RestartPolicy};
+
 /// Status of a service instance
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, Default)]
 #[serde(rename_all = "lowercase")]
@@ -33,16 +37,36 @@ impl std::fmt::Display for ServiceStatus {
     }
 }
 
+impl std::str::FromStr for ServiceStatus {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "stopped" => Ok(ServiceStatus::Stopped),
+            "running" => Ok(ServiceStatus::Running),
+            "starting" => Ok(ServiceStatus::Starting),
+            "stopping" => Ok(ServiceStatus::Stopping),
+            "error" => Ok(ServiceStatus::Error),
+            "unknown" => Ok(ServiceStatus::Unknown),
+            _ => anyhow::bail!("Unknown status '{}'", s),
+        }
+    }
+}
+
 /// Configuration for creating a new service instance
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct InstanceConfig {
-    /// Unique identifier for this instance
+    /// Unique identifier for this instance (within its group, if any)
     pub instance_id: String,
 
+    /// Group scoping the id; the instance's id becomes `group/instance_id`
+    #[serde(default)]
+    pub group: Option<String>,
+
     /// Template to use for this instance
     pub template_id: String,
 
-    /// Port override (uses template default if not specified)
+    /// Port override (a free port from the template range if not specified)
     #[serde(default)]
     pub port: Option<u16>,
 
@@ -73,14 +97,108 @@ pub struct InstanceConfig {
     /// Environment variable overrides
     #[serde(default)]
     pub env_vars: HashMap<String, String>,
+
+    /// Human-readable name (the template's display name if not specified)
+    #[serde(default)]
+    pub display_name: Option<String>,
+
+    /// Free-form operator notes
+    #[serde(default)]
+    pub notes: Option<String>,
+
+    /// Key-value metadata (distinct from tags, which are for selection)
+    #[serde(default)]
+    pub labels: HashMap<String, String>,
+
+    /// Key-value data for external tooling (e.g. a ticket or deploy SHA);
+    /// USM never interprets it or passes it to the process
+    #[serde(default)]
+    pub metadata: HashMap<String, String>,
+
+    /// Instances that must be healthy before this one starts
+    #[serde(default)]
+    pub depends_on: Vec<String>,
+
+    /// Cron-style start/stop times
+    #[serde(default)]
+    pub schedule: Option<ScheduleConfig>,
+
+    /// Restart policy for crashes (overrides the template's)
+    #[serde(default)]
+    pub restart: Option<RestartPolicy>,
+
+    /// Start command used instead of the template's (same placeholders)
+    #[serde(default)]
+    pub start_command_override: Option<String>,
+
+    /// Stop command used instead of the template's (same placeholders)
+    #[serde(default)]
+    pub stop_command_override: Option<String>,
+
+    /// CPU usage (percent of one core) the instance may not stay above
+    #[serde(default)]
+    pub cpu_limit_percent: Option<f64>,
+
+    /// Memory in MB the instance may not stay above
+    #[serde(default)]
+    pub memory_limit_mb: Option<u64>,
+
+    /// What to do when it stays over a limit (only warn if not specified)
+    #[serde(default)]
+    pub limit_action: Option<LimitAction>,
+}
+
+/// Partial update of an existing instance
+///
+/// Fields that are left out (or null) keep their current value.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct InstanceUpdate {
+    /// Environment variables to set (string) or unset (null)
+    #[serde(default)]
+    pub env_vars: HashMap<String, Option<String>>,
+
+    /// Replacement tags
+    #[serde(default)]
+    pub tags: Option<Vec<String>>,
+
+    /// Labels to set (string) or remove (null)
+    #[serde(default)]
+    pub labels: HashMap<String, Option<String>>,
+
+    /// Metadata keys to set (string) or remove (null)
+    #[serde(default)]
+    pub metadata: HashMap<String, Option<String>>,
+
+    /// New working directory
+    #[serde(default)]
+    pub working_dir: Option<PathBuf>,
+
+    /// New version identifier
+    #[serde(default)]
+    pub version: Option<String>,
+
+    /// New git branch
+    #[serde(default)]
+    pub git_branch: Option<String>,
+}
+
+impl InstanceUpdate {
+    /// Whether a running instance only picks the update up on restart
+    pub fn requires_restart(&self) -> bool {
+        !self.env_vars.is_empty() || self.working_dir.is_some()
+    }
 }
 
 /// A running service instance
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ServiceInstance {
-    /// Unique identifier
+    /// Unique identifier, `group/name` for instances in a group
     pub id: String,
 
+    /// Group the instance belongs to, if any
+    #[serde(default)]
+    pub group: Option<String>,
+
     /// Template this instance was created from
     pub template_id: String,
 
@@ -115,7 +233,55 @@ pub struct ServiceInstance {
     #[serde(default)]
     pub env_vars: HashMap<String, String>,
 
-    // === Runtime state (serialized for API, not persisted to disk) ===
+    /// Human-readable name, if one was given
+    #[serde(default)]
+    pub display_name: Option<String>,
+
+    /// Operator notes (e.g. "do not restart during business hours")
+    #[serde(default)]
+    pub notes: Option<String>,
+
+    /// Key-value metadata
+    #[serde(default)]
+    pub labels: HashMap<String, String>,
+
+    /// Key-value data for external tooling (see `InstanceConfig::metadata`)
+    #[serde(default)]
+    pub metadata: HashMap<String, String>,
+
+    /// Instances that must be healthy before this one starts
+    #[serde(default)]
+    pub depends_on: Vec<String>,
+
+    /// Cron-style start/stop times
+    #[serde(default)]
+    pub schedule: Option<ScheduleConfig>,
+
+    /// Restart policy for crashes (overrides the template's)
+    #[serde(default)]
+    pub restart: Option<RestartPolicy>,
+
+    /// Start command used instead of the template's
+    #[serde(default)]
+    pub start_command_override: Option<String>,
+
+    /// Stop command used instead of the template's
+    #[serde(default)]
+    pub stop_command_override: Option<String>,
+
+    /// CPU limit in percent of one core
+    #[serde(default)]
+    pub cpu_limit_percent: Option<f64>,
+
+    /// Memory limit in MB
+    #[serde(default)]
+    pub memory_limit_mb: Option<u64>,
+
+    /// What to do when it stays over a limit
+    #[serde(default)]
+    pub limit_action: Option<LimitAction>,
+
+    // === Runtime state (serialized for API, saved to the state file, not the config) ===
     /// Current status
     #[serde(default, skip_deserializing)]
     pub status: ServiceStatus,
@@ -128,13 +294,27 @@ pub struct ServiceInstance {
     #[serde(default, skip_deserializing)]
     pub started_at: Option<DateTime<Utc>>,
 
-    // === Metadata (persisted) ===
+    /// PID of the most recent run, kept after it exits so its captured
+    /// output can still be read
+    #[serde(default, skip_deserializing)]
+    pub last_pid: Option<u32>,
+
+    /// Exit code of the most recent run, when it crashed or died during
+    /// startup and the code was recorded
+    #[serde(default, skip_deserializing)]
+    pub last_exit_code: Option<i32>,
+
+    /// Why the most recent run ended unexpectedly or failed to start
+    #[serde(default, skip_deserializing)]
+    pub last_error: Option<String>,
+
+    // === Metadata (persisted as `_created_at`/`_created_via` in the config) ===
     /// When this instance was created
-    #[serde(default = "Utc::now", rename = "_created_at")]
+    #[serde(default = "Utc::now", alias = "_created_at")]
     pub created_at: DateTime<Utc>,
 
     /// How this instance was created ("api" or "config")
-    #[serde(default = "default_created_via", rename = "_created_via")]
+    #[serde(default = "default_created_via", alias = "_created_via")]
     pub created_via: String,
 }
 
@@ -142,6 +322,34 @@ fn default_created_via() -> String {
     "config".to_string()
 }
 
+/// The id an instance named `id` gets in `group`
+///
+/// Ids that already carry the group's prefix are returned unchanged.
+pub fn qualified_id(group: Option<&str>, id: &str) -> String {
+    match group {
+        Some(group) if !id.starts_with(&format!("{}/", group)) => format!("{}/{}", group, id),
+        _ => id.to_string(),
+    }
+}
+
+/// Group and qualified id for an instance config
+///
+/// Without an explicit group, an id like `projectA/api` puts the instance in
+/// group `projectA`. Group and name must both be non-empty and free of `/`.
+pub(crate) fn split_group(group: Option<&str>, id: &str) -> Result<(Option<String>, String)> {
+    let id = qualified_id(group, id);
+    let Some((group, name)) = id.split_once('/') else {
+        return Ok((None, id));
+    };
+    if group.is_empty() || name.is_empty() || name.contains('/') {
+        anyhow::bail!(
+            "Instance ID '{}' must be a name or group/name, without further '/'",
+            id
+        );
+    }
+    Ok((Some(group.to_string()), id))
+}
+
 impl ServiceInstance {
     /// Create a new instance from configuration
     pub fn from_config(config: InstanceConfig) -> Result<Self> {
@@ -149,16 +357,37 @@ impl ServiceInstance {
         if config.instance_id.is_empty() {
             anyhow::bail!("Instance ID cannot be empty");
         }
+        let (group, id) = split_group(config.group.as_deref(), &config.instance_id)?;
 
         if config.template_id.is_empty() {
             anyhow::bail!("Template ID cannot be empty");
         }
 
-        // Port will be assigned from template default if not specified
+        if let Some(ref schedule) = config.schedule {
+            schedule
+                .validate()
+                .map_err(|e| anyhow::anyhow!("Instance '{}': {}", config.instance_id, e))?;
+        }
+
+        let overrides = [
+            ("start_command_override", &config.start_command_override),
+            ("stop_command_override", &config.stop_command_override),
+        ];
+        for (field, command) in overrides {
+            if let Some(Err(e)) = command.as_deref().map(check_placeholders) {
+                anyhow::bail!("Instance '{}' {}: {}", config.instance_id, field, e);
+            }
+        }
+
+        check_limits(config.cpu_limit_percent, config.memory_limit_mb)
+            .map_err(|e| anyhow::anyhow!("Instance '{}': {}", config.instance_id, e))?;
+
+        // Callers assign a port before this; 0 marks one that was never set
         let port = config.port.unwrap_or(0);
 
         Ok(Self {
-            id: config.instance_id,
+            id,
+            group,
             template_id: config.template_id,
             port,
             working_dir: config.working_dir,
@@ -168,9 +397,24 @@ impl ServiceInstance {
             tags: config.tags,
             auto_start: config.auto_start,
             env_vars: config.env_vars,
+            display_name: config.display_name,
+            notes: config.notes,
+            labels: config.labels,
+            metadata: config.metadata,
+            depends_on: config.depends_on,
+            schedule: config.schedule,
+            restart: config.restart,
+            start_command_override: config.start_command_override,
+            stop_command_override: config.stop_command_override,
+            cpu_limit_percent: config.cpu_limit_percent,
+            memory_limit_mb: config.memory_limit_mb,
+            limit_action: config.limit_action,
             status: ServiceStatus::Stopped,
             pid: None,
             started_at: None,
+            last_pid: None,
+            last_exit_code: None,
+            last_error: None,
             created_at: Utc::now(),
             created_via: "api".to_string(),
         })
@@ -186,6 +430,13 @@ impl ServiceInstance {
         tags.iter().any(|t| self.has_tag(t))
     }
 
+    /// Check if this instance carries all of the given tags
+    ///
+    /// Like `matches_tags`, an empty list matches nothing.
+    pub fn matches_all_tags(&self, tags: &[&str]) -> bool {
+        !tags.is_empty() && tags.iter().all(|t| self.has_tag(t))
+    }
+
     /// Get uptime duration if running
     pub fn uptime(&self) -> Option<chrono::Duration> {
         self.started_at.map(|started| Utc::now() - started)
@@ -206,6 +457,31 @@ impl ServiceInstance {
             }
         })
     }
+
+    /// Record that the process exited unexpectedly, with its exit code if
+    /// the monitor knows it
+    pub fn record_exit(&mut self, exit_code: Option<i32>) {
+        self.last_exit_code = exit_code;
+        self.last_error = Some(match exit_code {
+            Some(code) => format!("Exited with code {}", code),
+            None => "Exited unexpectedly".to_string(),
+        });
+    }
+
+    /// Record a failed start, with the exit code if the process died while
+    /// its start was being verified
+    pub fn record_start_failure(&mut self, error: &anyhow::Error) {
+        self.last_exit_code = error
+            .downcast_ref::<crate::monitor::StartupFailed>()
+            .and_then(|failed| failed.exit_code);
+        self.last_error = Some(error.to_string());
+    }
+
+    /// Forget the previous run's failure, once a new run has started
+    pub fn clear_failure(&mut self) {
+        self.last_exit_code = None;
+        self.last_error = None;
+    }
 }
 
 #[cfg(test)]
@@ -216,6 +492,7 @@ mod tests {
     fn test_instance_from_config() {
         let config = InstanceConfig {
             instance_id: "test-instance".to_string(),
+            group: None,
             template_id: "test-template".to_string(),
             port: Some(8080),
             working_dir: Some(PathBuf::from("/opt/app")),
@@ -225,6 +502,18 @@ mod tests {
             tags: vec!["production".to_string(), "stable".to_string()],
             auto_start: true,
             env_vars: Default::default(),
+            display_name: None,
+            notes: None,
+            labels: Default::default(),
+            metadata: Default::default(),
+            depends_on: Vec::new(),
+            schedule: None,
+            restart: None,
+            start_command_override: None,
+            stop_command_override: None,
+            cpu_limit_percent: None,
+            memory_limit_mb: None,
+            limit_action: None,
         };
 
         let instance = ServiceInstance::from_config(config).unwrap();
@@ -238,10 +527,42 @@ mod tests {
         assert!(!instance.has_tag("development"));
     }
 
+    #[test]
+    fn test_instance_groups() {
+        let config = |id: &str, group: Option<&str>| -> InstanceConfig {
+            serde_json::from_value(serde_json::json!({
+                "instance_id": id,
+                "group": group,
+                "template_id": "t"
+            }))
+            .unwrap()
+        };
+        let grouped =
+            |id, group| ServiceInstance::from_config(config(id, group)).map(|i| (i.group, i.id));
+
+        assert_eq!(grouped("api", None).unwrap(), (None, "api".to_string()));
+        assert_eq!(
+            grouped("api", Some("projectA")).unwrap(),
+            (Some("projectA".to_string()), "projectA/api".to_string())
+        );
+        // Already qualified, with or without the group given again
+        for group in [None, Some("projectA")] {
+            assert_eq!(
+                grouped("projectA/api", group).unwrap(),
+                (Some("projectA".to_string()), "projectA/api".to_string())
+            );
+        }
+        assert!(grouped("projectB/api", Some("projectA")).is_err());
+        assert!(grouped("api", Some("")).is_err());
+        assert!(grouped("a/b/c", None).is_err());
+        assert!(grouped("api/", None).is_err());
+    }
+
     #[test]
     fn test_instance_tags() {
         let config = InstanceConfig {
             instance_id: "test".to_string(),
+            group: None,
             template_id: "test".to_string(),
             port: None,
             working_dir: None,
@@ -251,6 +572,18 @@ mod tests {
             tags: vec!["production".to_string(), "api".to_string()],
             auto_start: false,
             env_vars: Default::default(),
+            display_name: None,
+            notes: None,
+            labels: Default::default(),
+            metadata: Default::default(),
+            depends_on: Vec::new(),
+            schedule: None,
+            restart: None,
+            start_command_override: None,
+            stop_command_override: None,
+            cpu_limit_percent: None,
+            memory_limit_mb: None,
+            limit_action: None,
         };
 
         let instance = ServiceInstance::from_config(config).unwrap();
@@ -258,6 +591,39 @@ mod tests {
         assert!(instance.matches_tags(&["production"]));
         assert!(instance.matches_tags(&["development", "production"]));
         assert!(!instance.matches_tags(&["development", "staging"]));
+
+        assert!(instance.matches_all_tags(&["production"]));
+        assert!(!instance.matches_all_tags(&["development", "production"]));
+        assert!(!instance.matches_all_tags(&[]));
+    }
+
+    #[test]
+    fn test_notes_and_labels_serialization() {
+        let config: InstanceConfig = serde_json::from_str(
+            r#"{
+                "instance_id": "api",
+                "template_id": "web",
+                "notes": "owned by team X",
+                "labels": {"owner": "team-x"},
+                "metadata": {"deploy_sha": "abc123"}
+            }"#,
+        )
+        .unwrap();
+        let instance = ServiceInstance::from_config(config).unwrap();
+        assert_eq!(instance.notes.as_deref(), Some("owned by team X"));
+        assert_eq!(instance.labels.get("owner").unwrap(), "team-x");
+
+        let json = serde_json::to_value(&instance).unwrap();
+        assert_eq!(json["notes"], "owned by team X");
+        assert_eq!(json["labels"]["owner"], "team-x");
+        assert_eq!(json["metadata"]["deploy_sha"], "abc123");
+
+        // Both are optional
+        let config: InstanceConfig =
+            serde_json::from_str(r#"{"instance_id": "api", "template_id": "web"}"#).unwrap();
+        assert!(config.notes.is_none());
+        assert!(config.labels.is_empty());
+        assert!(config.metadata.is_empty());
     }
 
     #[test]
@@ -266,6 +632,19 @@ mod tests {
         assert_eq!(ServiceStatus::Stopped.to_string(), "stopped");
         assert_eq!(ServiceStatus::Error.to_string(), "error");
     }
+
+    #[test]
+    fn test_status_parse() {
+        assert_eq!(
+            "running".parse::<ServiceStatus>().unwrap(),
+            ServiceStatus::Running
+        );
+        assert_eq!(
+            "Stopped".parse::<ServiceStatus>().unwrap(),
+            ServiceStatus::Stopped
+        );
+        assert!("bogus".parse::<ServiceStatus>().is_err());
+    }
 }
 
 /// Property-based tests for ServiceInstance
@@ -304,6 +683,7 @@ mod property_tests {
         ) {
             let config = InstanceConfig {
                 instance_id,
+                group: None,
                 template_id,
                 port: Some(8080),
                 working_dir: None,
@@ -313,6 +693,18 @@ mod property_tests {
                 tags: tags.clone(),
                 auto_start: false,
                 env_vars: Default::default(),
+                display_name: None,
+                notes: None,
+                labels: Default::default(),
+                metadata: Default::default(),
+                depends_on: Vec::new(),
+                schedule: None,
+                restart: None,
+                start_command_override: None,
+                stop_command_override: None,
+                cpu_limit_percent: None,
+                memory_limit_mb: None,
+                limit_action: None,
             };
 
             let instance = ServiceInstance::from_config(config).unwrap();
@@ -333,6 +725,7 @@ mod property_tests {
         ) {
             let config = InstanceConfig {
                 instance_id,
+                group: None,
                 template_id,
                 port: Some(8080),
                 working_dir: None,
@@ -342,6 +735,18 @@ mod property_tests {
                 tags: tags.clone(),
                 auto_start: false,
                 env_vars: Default::default(),
+                display_name: None,
+                notes: None,
+                labels: Default::default(),
+                metadata: Default::default(),
+                depends_on: Vec::new(),
+                schedule: None,
+                restart: None,
+                start_command_override: None,
+                stop_command_override: None,
+                cpu_limit_percent: None,
+                memory_limit_mb: None,
+                limit_action: None,
             };
 
             let instance = ServiceInstance::from_config(config).unwrap();
@@ -362,6 +767,7 @@ mod property_tests {
         ) {
             let config = InstanceConfig {
                 instance_id,
+                group: None,
                 template_id,
                 port: Some(8080),
                 working_dir: None,
@@ -371,6 +777,18 @@ mod property_tests {
                 tags: tags.clone(),
                 auto_start: false,
                 env_vars: Default::default(),
+                display_name: None,
+                notes: None,
+                labels: Default::default(),
+                metadata: Default::default(),
+                depends_on: Vec::new(),
+                schedule: None,
+                restart: None,
+                start_command_override: None,
+                stop_command_override: None,
+                cpu_limit_percent: None,
+                memory_limit_mb: None,
+                limit_action: None,
             };
 
             let instance = ServiceInstance::from_config(config).unwrap();
@@ -396,6 +814,7 @@ mod property_tests {
         fn empty_instance_id_rejected(template_id in identifier_strategy()) {
             let config = InstanceConfig {
                 instance_id: "".to_string(),
+                group: None,
                 template_id,
                 port: Some(8080),
                 working_dir: None,
@@ -405,6 +824,18 @@ mod property_tests {
                 tags: vec![],
                 auto_start: false,
                 env_vars: Default::default(),
+                display_name: None,
+                notes: None,
+                labels: Default::default(),
+                metadata: Default::default(),
+                depends_on: Vec::new(),
+                schedule: None,
+                restart: None,
+                start_command_override: None,
+                stop_command_override: None,
+                cpu_limit_percent: None,
+                memory_limit_mb: None,
+                limit_action: None,
             };
 
             prop_assert!(ServiceInstance::from_config(config).is_err());
@@ -415,6 +846,7 @@ mod property_tests {
         fn empty_template_id_rejected(instance_id in identifier_strategy()) {
             let config = InstanceConfig {
                 instance_id,
+                group: None,
                 template_id: "".to_string(),
                 port: Some(8080),
                 working_dir: None,
@@ -424,6 +856,18 @@ mod property_tests {
                 tags: vec![],
                 auto_start: false,
                 env_vars: Default::default(),
+                display_name: None,
+                notes: None,
+                labels: Default::default(),
+                metadata: Default::default(),
+                depends_on: Vec::new(),
+                schedule: None,
+                restart: None,
+                start_command_override: None,
+                stop_command_override: None,
+                cpu_limit_percent: None,
+                memory_limit_mb: None,
+                limit_action: None,
             };
 
             prop_assert!(ServiceInstance::from_config(config).is_err());
@@ -438,6 +882,7 @@ mod property_tests {
         ) {
             let config = InstanceConfig {
                 instance_id: instance_id.clone(),
+                group: None,
                 template_id: template_id.clone(),
                 port: Some(port),
                 working_dir: None,
@@ -447,6 +892,18 @@ mod property_tests {
                 tags: vec![],
                 auto_start: false,
                 env_vars: Default::default(),
+                display_name: None,
+                notes: None,
+                labels: Default::default(),
+                metadata: Default::default(),
+                depends_on: Vec::new(),
+                schedule: None,
+                restart: None,
+                start_command_override: None,
+                stop_command_override: None,
+                cpu_limit_percent: None,
+                memory_limit_mb: None,
+                limit_action: None,
             };
 
             let instance = ServiceInstance::from_config(config).unwrap();
@@ -510,6 +967,7 @@ mod property_tests {
             let started = Utc::now() - chrono::Duration::seconds(secs_ago);
             let mut instance = ServiceInstance::from_config(InstanceConfig {
                 instance_id: "test".to_string(),
+                group: None,
                 template_id: "test".to_string(),
                 port: Some(8080),
                 working_dir: None,
@@ -519,6 +977,18 @@ mod property_tests {
                 tags: vec![],
                 auto_start: false,
                 env_vars: Default::default(),
+                display_name: None,
+                notes: None,
+                labels: Default::default(),
+                metadata: Default::default(),
+                depends_on: Vec::new(),
+                schedule: None,
+                restart: None,
+                start_command_override: None,
+                stop_command_override: None,
+                cpu_limit_percent: None,
+                memory_limit_mb: None,
+                limit_action: None,
             }).unwrap();
 
             instance.started_at = Some(started);
@@ -544,6 +1014,7 @@ mod property_tests {
             let started = Utc::now() - chrono::Duration::seconds(secs);
             let mut instance = ServiceInstance::from_config(InstanceConfig {
                 instance_id: "test".to_string(),
+                group: None,
                 template_id: "test".to_string(),
                 port: Some(8080),
                 working_dir: None,
@@ -553,6 +1024,18 @@ mod property_tests {
                 tags: vec![],
                 auto_start: false,
                 env_vars: Default::default(),
+                display_name: None,
+                notes: None,
+                labels: Default::default(),
+                metadata: Default::default(),
+                depends_on: Vec::new(),
+                schedule: None,
+                restart: None,
+                start_command_override: None,
+                stop_command_override: None,
+                cpu_limit_percent: None,
+                memory_limit_mb: None,
+                limit_action: None,
             })
             .unwrap();
 
@@ -571,4 +1054,31 @@ mod property_tests {
             );
         }
     }
+
+    #[test]
+    fn test_record_failures() {
+        let mut instance: ServiceInstance =
+            toml::from_str("id = \"api\"\ntemplate_id = \"svc\"\nport = 8000").unwrap();
+
+        let died: anyhow::Error = crate::monitor::StartupFailed {
+            pid: 4242,
+            exit_code: Some(127),
+            stderr_log: None,
+        }
+        .into();
+        instance.record_start_failure(&died);
+        assert_eq!(instance.last_exit_code, Some(127));
+        assert_eq!(
+            instance.last_error.as_deref(),
+            Some("Process 4242 started but immediately died with exit code 127")
+        );
+
+        instance.record_start_failure(&anyhow::anyhow!("Template 'svc' not found"));
+        assert_eq!(instance.last_exit_code, None);
+
+        instance.record_exit(None);
+        assert_eq!(instance.last_error.as_deref(), Some("Exited unexpectedly"));
+        instance.clear_failure();
+        assert_eq!(instance.last_error, None);
+    }
 }
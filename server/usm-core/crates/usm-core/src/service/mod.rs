@@ -4,6 +4,8 @@ mod instance;
 mod registry;
 mod template;
 
-pub use instance::{InstanceConfig, ServiceInstance, ServiceStatus};
-pub use registry::{InstanceRegistry, TemplateRegistry};
-pub use template::{ServiceCategory, ServiceTemplate};
+pub(crate) use instance::split_group;
+pub use instance::{qualified_id, InstanceConfig, InstanceUpdate, ServiceInstance, ServiceStatus};
+pub use registry::{InstanceFilter, InstanceRegistry, TagMatch, TemplateRegistry};
+pub(crate) use template::check_placeholders;
+pub use template::{EnvSource, EnvValue, ServiceCategory, ServiceTemplate};
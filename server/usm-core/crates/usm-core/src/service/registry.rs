@@ -3,8 +3,9 @@
 use std::collections::HashMap;
 
 use anyhow::Result;
+use serde::{Deserialize, Serialize};
 
-use super::{ServiceInstance, ServiceStatus, ServiceTemplate};
+use super::{InstanceUpdate, ServiceCategory, ServiceInstance, ServiceStatus, ServiceTemplate};
 
 /// Registry for service templates
 #[derive(Debug, Default)]
@@ -19,10 +20,13 @@ impl TemplateRegistry {
     }
 
     /// Register a new template
+    ///
+    /// Fails if the template's commands use an unknown `{placeholder}`.
     pub fn register(&mut self, template: ServiceTemplate) -> Result<()> {
         if self.templates.contains_key(&template.id) {
             anyhow::bail!("Template '{}' already exists", template.id);
         }
+        template.validate_placeholders()?;
 
         self.templates.insert(template.id.clone(), template);
         Ok(())
@@ -34,10 +38,27 @@ impl TemplateRegistry {
     }
 
     /// Remove a template by ID
+    ///
+    /// Fails while other templates extend it.
     pub fn remove(&mut self, id: &str) -> Result<()> {
-        if self.templates.remove(id).is_none() {
+        if !self.templates.contains_key(id) {
             anyhow::bail!("Template '{}' not found", id);
         }
+        let mut children: Vec<&str> = self
+            .templates
+            .values()
+            .filter(|t| t.extends.as_deref() == Some(id))
+            .map(|t| t.id.as_str())
+            .collect();
+        if !children.is_empty() {
+            children.sort();
+            anyhow::bail!(
+                "Cannot remove template '{}': extended by {}",
+                id,
+                children.join(", ")
+            );
+        }
+        self.templates.remove(id);
         Ok(())
     }
 
@@ -46,6 +67,15 @@ impl TemplateRegistry {
         self.templates.values().cloned().collect()
     }
 
+    /// List templates in a category
+    pub fn list_by_category(&self, category: ServiceCategory) -> Vec<ServiceTemplate> {
+        self.templates
+            .values()
+            .filter(|t| t.category == category)
+            .cloned()
+            .collect()
+    }
+
     /// Get the number of registered templates
     pub fn len(&self) -> usize {
         self.templates.len()
@@ -62,7 +92,95 @@ impl TemplateRegistry {
     }
 }
 
+/// How multiple tags in a filter are combined
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TagMatch {
+    /// Instance must carry at least one of the tags
+    #[default]
+    Any,
+    /// Instance must carry every tag
+    All,
+}
+
+impl TagMatch {
+    /// Whether an instance's tags satisfy `tags` under this mode
+    pub fn matches(self, instance: &ServiceInstance, tags: &[&str]) -> bool {
+        match self {
+            TagMatch::Any => instance.matches_tags(tags),
+            TagMatch::All => instance.matches_all_tags(tags),
+        }
+    }
+}
+
+impl std::str::FromStr for TagMatch {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "any" => Ok(TagMatch::Any),
+            "all" => Ok(TagMatch::All),
+            _ => anyhow::bail!("Unknown tag mode '{}' (expected 'any' or 'all')", s),
+        }
+    }
+}
+
+/// Combined criteria for selecting instances
+///
+/// Unset criteria match everything; set criteria are ANDed together.
+#[derive(Debug, Clone, Default)]
+pub struct InstanceFilter {
+    /// Only instances in this group
+    pub group: Option<String>,
+
+    /// Only instances of this template
+    pub template: Option<String>,
+
+    /// Only instances carrying these tags (combined per `tag_match`)
+    pub tags: Vec<String>,
+
+    /// How `tags` are combined
+    pub tag_match: TagMatch,
+
+    /// Only instances in this status
+    pub status: Option<ServiceStatus>,
+}
+
+impl InstanceFilter {
+    /// Check whether an instance satisfies every criterion
+    pub fn matches(&self, instance: &ServiceInstance) -> bool {
+        if self.group.is_some() && instance.group != self.group {
+            return false;
+        }
+
+        if let Some(ref template) = self.template {
+            if &instance.template_id != template {
+                return false;
+            }
+        }
+
+        if !self.tags.is_empty() {
+            let tags: Vec<&str> = self.tags.iter().map(String::as_str).collect();
+            if !self.tag_match.matches(instance, &tags) {
+                return false;
+            }
+        }
+
+        if let Some(status) = self.status {
+            if instance.status != status {
+                return false;
+            }
+        }
+
+        true
+    }
+}
+
 /// Registry for service instances
+///
+/// Keyed by instance id, which is `group/name` for grouped instances, so
+/// the same name can be used in several groups. Ports are shared across
+/// groups and must be unique over the whole registry.
 #[derive(Debug, Default)]
 pub struct InstanceRegistry {
     instances: HashMap<String, ServiceInstance>,
@@ -143,6 +261,127 @@ impl InstanceRegistry {
             .collect()
     }
 
+    /// List instances matching a combined filter
+    pub fn query(&self, filter: &InstanceFilter) -> Vec<ServiceInstance> {
+        self.instances
+            .values()
+            .filter(|i| filter.matches(i))
+            .cloned()
+            .collect()
+    }
+
+    /// Instances whose id, template id or one of whose tags contains
+    /// `query`, ignoring case, sorted by id
+    pub fn search(&self, query: &str) -> Vec<ServiceInstance> {
+        let query = query.trim().to_lowercase();
+        let contains = |s: &str| s.to_lowercase().contains(&query);
+        let mut found: Vec<ServiceInstance> = self
+            .instances
+            .values()
+            .filter(|i| {
+                contains(&i.id) || contains(&i.template_id) || i.tags.iter().any(|t| contains(t))
+            })
+            .cloned()
+            .collect();
+        found.sort_by(|a, b| a.id.cmp(&b.id));
+        found
+    }
+
+    /// Group an instance and its transitive dependencies into start tiers
+    ///
+    /// Every instance in a tier depends only on instances in earlier tiers.
+    /// The last tier holds just `id`. Fails on unknown instances and cycles.
+    pub fn dependency_tiers(&self, id: &str) -> Result<Vec<Vec<String>>> {
+        fn depth(
+            registry: &InstanceRegistry,
+            id: &str,
+            visiting: &mut Vec<String>,
+            depths: &mut HashMap<String, usize>,
+        ) -> Result<usize> {
+            if let Some(&d) = depths.get(id) {
+                return Ok(d);
+            }
+            if visiting.iter().any(|v| v == id) {
+                visiting.push(id.to_string());
+                anyhow::bail!("Dependency cycle: {}", visiting.join(" -> "));
+            }
+            let instance = registry
+                .instances
+                .get(id)
+                .ok_or_else(|| anyhow::anyhow!("Instance '{}' not found", id))?;
+
+            visiting.push(id.to_string());
+            let mut d = 0;
+            for dep in &instance.depends_on {
+                if !registry.instances.contains_key(dep) {
+                    anyhow::bail!("Instance '{}' depends on unknown instance '{}'", id, dep);
+                }
+                d = d.max(depth(registry, dep, visiting, depths)? + 1);
+            }
+            visiting.pop();
+
+            depths.insert(id.to_string(), d);
+            Ok(d)
+        }
+
+        let mut depths = HashMap::new();
+        let target = depth(self, id, &mut Vec::new(), &mut depths)?;
+
+        let mut tiers = vec![Vec::new(); target + 1];
+        for (instance_id, d) in depths {
+            tiers[d].push(instance_id);
+        }
+        for tier in &mut tiers {
+            tier.sort();
+        }
+        Ok(tiers)
+    }
+
+    /// Group `ids` into waves for a bulk start
+    ///
+    /// Each wave holds indices into `ids`, and every instance comes after
+    /// the ones among `ids` it depends on. Dependencies outside `ids`,
+    /// unknown instances and cycles don't constrain the order.
+    pub fn bulk_waves(&self, ids: &[String]) -> Vec<Vec<usize>> {
+        fn depth<'a>(
+            registry: &'a InstanceRegistry,
+            id: &'a str,
+            selected: &[String],
+            visiting: &mut Vec<&'a str>,
+            depths: &mut HashMap<&'a str, usize>,
+        ) -> usize {
+            if let Some(&d) = depths.get(id) {
+                return d;
+            }
+            let Some(instance) = registry.instances.get(id) else {
+                return 0;
+            };
+
+            visiting.push(id);
+            let mut d = 0;
+            for dep in &instance.depends_on {
+                if selected.contains(dep) && !visiting.contains(&dep.as_str()) {
+                    d = d.max(depth(registry, dep, selected, visiting, depths) + 1);
+                }
+            }
+            visiting.pop();
+
+            depths.insert(id, d);
+            d
+        }
+
+        let mut depths = HashMap::new();
+        let mut waves: Vec<Vec<usize>> = Vec::new();
+        for (index, id) in ids.iter().enumerate() {
+            let d = depth(self, id, ids, &mut Vec::new(), &mut depths);
+            if waves.len() <= d {
+                waves.resize(d + 1, Vec::new());
+            }
+            waves[d].push(index);
+        }
+        waves
+    }
+
     /// Check if any instances exist for a template
     pub fn has_instances_for_template(&self, template_id: &str) -> bool {
         self.instances
@@ -150,6 +389,14 @@ impl InstanceRegistry {
             .any(|i| i.template_id == template_id)
     }
 
+    /// Number of instances created from a template
+    pub fn count_for_template(&self, template_id: &str) -> usize {
+        self.instances
+            .values()
+            .filter(|i| i.template_id == template_id)
+            .count()
+    }
+
     /// Find an instance using a specific port
     pub fn find_by_port(&self, port: u16) -> Option<&ServiceInstance> {
         self.instances.values().find(|i| i.port == port)
@@ -193,6 +440,64 @@ impl InstanceRegistry {
             .collect()
     }
 
+    /// Apply a partial update to an instance
+    ///
+    /// Env vars, labels and metadata are merged key by key (null unsets a
+    /// key); the other fields replace the current value when given.
+    pub fn update(&mut self, id: &str, update: InstanceUpdate) -> Result<()> {
+        if let Some(key) = update
+            .env_vars
+            .keys()
+            .find(|k| k.is_empty() || k.contains('='))
+        {
+            anyhow::bail!("Invalid environment variable name '{}'", key);
+        }
+        if update.labels.contains_key("") {
+            anyhow::bail!("Invalid label name ''");
+        }
+        if update.metadata.contains_key("") {
+            anyhow::bail!("Invalid metadata key ''");
+        }
+
+        let instance = self
+            .instances
+            .get_mut(id)
+            .ok_or_else(|| anyhow::anyhow!("Instance '{}' not found", id))?;
+
+        for (key, value) in update.env_vars {
+            match value {
+                Some(value) => instance.env_vars.insert(key, value),
+                None => instance.env_vars.remove(&key),
+            };
+        }
+        if let Some(tags) = update.tags {
+            instance.tags = tags;
+        }
+        for (key, value) in update.labels {
+            match value {
+                Some(value) => instance.labels.insert(key, value),
+                None => instance.labels.remove(&key),
+            };
+        }
+        for (key, value) in update.metadata {
+            match value {
+                Some(value) => instance.metadata.insert(key, value),
+                None => instance.metadata.remove(&key),
+            };
+        }
+        if let Some(working_dir) = update.working_dir {
+            instance.working_dir = Some(working_dir);
+        }
+        if let Some(version) = update.version {
+            instance.version = Some(version);
+        }
+        if let Some(git_branch) = update.git_branch {
+            instance.git_branch = Some(git_branch);
+        }
+
+        Ok(())
+    }
+
     /// Update an instance's status
     pub fn update_status(
         &mut self,
@@ -207,6 +512,7 @@ impl InstanceRegistry {
 
         instance.status = status;
         instance.pid = pid;
+        instance.last_pid = pid.or(instance.last_pid);
 
         if status == ServiceStatus::Running && instance.started_at.is_none() {
             instance.started_at = Some(chrono::Utc::now());
@@ -221,7 +527,7 @@ impl InstanceRegistry {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::service::{InstanceConfig, ServiceCategory};
+    use crate::service::InstanceConfig;
 
     fn create_test_template(id: &str) -> ServiceTemplate {
         ServiceTemplate {
@@ -234,16 +540,28 @@ mod tests {
             stop_command: None,
             health_endpoint: None,
             health_timeout_ms: 5000,
+            stop_timeout_ms: 10000,
+            startup_verify_ms: 3000,
+            reload_signal: None,
+            extends: None,
             category: ServiceCategory::Core,
             supports_multiple: true,
+            max_instances: None,
             is_docker: false,
+            restart: None,
             default_env: Default::default(),
+            health_command: None,
+            health_expect_status: None,
+            health_expect_body_contains: None,
+            health_start_period_ms: None,
+            working_dir: None,
         }
     }
 
     fn create_test_instance(id: &str, port: u16) -> ServiceInstance {
         ServiceInstance::from_config(InstanceConfig {
             instance_id: id.to_string(),
+            group: None,
             template_id: "test".to_string(),
             port: Some(port),
             working_dir: None,
@@ -253,6 +571,18 @@ mod tests {
             tags: vec!["test".to_string()],
             auto_start: false,
             env_vars: Default::default(),
+            display_name: None,
+            notes: None,
+            labels: Default::default(),
+            metadata: Default::default(),
+            depends_on: Vec::new(),
+            schedule: None,
+            restart: None,
+            start_command_override: None,
+            stop_command_override: None,
+            cpu_limit_percent: None,
+            memory_limit_mb: None,
+            limit_action: None,
         })
         .unwrap()
     }
@@ -273,11 +603,56 @@ mod tests {
         let result = registry.register(create_test_template("test1"));
         assert!(result.is_err());
 
+        // A base can't go while templates extend it
+        let mut child = create_test_template("test2");
+        child.extends = Some("test1".to_string());
+        registry.register(child).unwrap();
+        let err = registry.remove("test1").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Cannot remove template 'test1': extended by test2"
+        );
+
+        // Filter by category
+        let mut database = create_test_template("db");
+        database.category = ServiceCategory::Database;
+        registry.register(database).unwrap();
+        let ids = |category| {
+            let mut ids: Vec<String> = registry
+                .list_by_category(category)
+                .into_iter()
+                .map(|t| t.id)
+                .collect();
+            ids.sort();
+            ids
+        };
+        assert_eq!(ids(ServiceCategory::Core), vec!["test1", "test2"]);
+        assert_eq!(ids(ServiceCategory::Database), vec!["db"]);
+        assert!(ids(ServiceCategory::Custom).is_empty());
+        registry.remove("db").unwrap();
+
         // Remove template
+        registry.remove("test2").unwrap();
         registry.remove("test1").unwrap();
         assert!(registry.is_empty());
     }
 
+    #[test]
+    fn test_register_rejects_unknown_placeholder() {
+        let mut registry = TemplateRegistry::new();
+
+        let mut template = create_test_template("typo");
+        template.start_command = "serve --port {porrt}".to_string();
+        let err = registry.register(template).unwrap_err();
+        assert!(err.to_string().contains("unknown placeholder '{porrt}'"));
+        assert!(registry.get("typo").is_none());
+
+        let mut template = create_test_template("ok");
+        template.start_command = "serve --port {port} --dir {working_dir}".to_string();
+        template.stop_command = Some("kill {pid}".to_string());
+        registry.register(template).unwrap();
+    }
+
     #[test]
     fn test_instance_registry() {
         let mut registry = InstanceRegistry::new();
@@ -337,4 +712,316 @@ mod tests {
         assert_eq!(counts.get(&ServiceStatus::Running), Some(&1));
         assert_eq!(counts.get(&ServiceStatus::Stopped), Some(&1));
     }
+
+    #[test]
+    fn test_instance_update() {
+        let mut registry = InstanceRegistry::new();
+        let mut instance = create_test_instance("inst1", 8001);
+        instance
+            .env_vars
+            .insert("KEEP".to_string(), "1".to_string());
+        instance
+            .env_vars
+            .insert("DROP".to_string(), "1".to_string());
+        instance.version = Some("1.0".to_string());
+        registry.add(instance).unwrap();
+
+        // Only env vars: merged, everything else untouched
+        let update = InstanceUpdate {
+            env_vars: HashMap::from([
+                ("NEW".to_string(), Some("2".to_string())),
+                ("DROP".to_string(), None),
+            ]),
+            ..Default::default()
+        };
+        assert!(update.requires_restart());
+        registry.update("inst1", update).unwrap();
+        let instance = registry.get("inst1").unwrap();
+        assert_eq!(instance.env_vars.len(), 2);
+        assert_eq!(instance.env_vars["KEEP"], "1");
+        assert_eq!(instance.env_vars["NEW"], "2");
+        assert_eq!(instance.tags, vec!["test"]);
+        assert_eq!(instance.version.as_deref(), Some("1.0"));
+
+        // Only metadata: env untouched, no restart needed
+        let update = InstanceUpdate {
+            tags: Some(vec!["prod".to_string()]),
+            labels: HashMap::from([("owner".to_string(), Some("team-x".to_string()))]),
+            metadata: HashMap::from([("ticket".to_string(), Some("OPS-7".to_string()))]),
+            version: Some("2.0".to_string()),
+            git_branch: Some("main".to_string()),
+            ..Default::default()
+        };
+        assert!(!update.requires_restart());
+        registry.update("inst1", update).unwrap();
+        let instance = registry.get("inst1").unwrap();
+        assert_eq!(instance.tags, vec!["prod"]);
+        assert_eq!(instance.labels["owner"], "team-x");
+        assert_eq!(instance.metadata["ticket"], "OPS-7");
+        assert!(!instance.env_vars.contains_key("owner"));
+        assert!(!instance.env_vars.contains_key("ticket"));
+        assert_eq!(instance.version.as_deref(), Some("2.0"));
+        assert_eq!(instance.git_branch.as_deref(), Some("main"));
+        assert_eq!(instance.env_vars.len(), 2);
+        assert!(instance.working_dir.is_none());
+
+        let update = InstanceUpdate {
+            working_dir: Some("/srv/app".into()),
+            ..Default::default()
+        };
+        assert!(update.requires_restart());
+        registry.update("inst1", update).unwrap();
+        assert_eq!(
+            registry.get("inst1").unwrap().working_dir,
+            Some("/srv/app".into())
+        );
+
+        // Bad env names change nothing
+        let update = InstanceUpdate {
+            env_vars: HashMap::from([("A=B".to_string(), Some("x".to_string()))]),
+            tags: Some(Vec::new()),
+            ..Default::default()
+        };
+        let err = registry.update("inst1", update).unwrap_err();
+        assert_eq!(err.to_string(), "Invalid environment variable name 'A=B'");
+        assert_eq!(registry.get("inst1").unwrap().tags, vec!["prod"]);
+
+        let update = InstanceUpdate {
+            labels: HashMap::from([("owner".to_string(), None)]),
+            metadata: HashMap::from([("ticket".to_string(), None)]),
+            ..Default::default()
+        };
+        registry.update("inst1", update).unwrap();
+        assert!(registry.get("inst1").unwrap().labels.is_empty());
+        assert!(registry.get("inst1").unwrap().metadata.is_empty());
+
+        let err = registry
+            .update("missing", InstanceUpdate::default())
+            .unwrap_err();
+        assert_eq!(err.to_string(), "Instance 'missing' not found");
+    }
+
+    #[test]
+    fn test_groups_scope_ids_not_ports() {
+        let mut registry = InstanceRegistry::new();
+        registry
+            .add(create_test_instance("projectA/api", 8001))
+            .unwrap();
+        registry
+            .add(create_test_instance("projectB/api", 8002))
+            .unwrap();
+        registry.add(create_test_instance("api", 8003)).unwrap();
+
+        // Same name in another group, but a port taken in any group
+        let err = registry
+            .add(create_test_instance("projectC/api", 8001))
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Port 8001 is already in use by instance 'projectA/api'"
+        );
+        assert!(registry
+            .add(create_test_instance("projectA/api", 8004))
+            .is_err());
+
+        let filter = InstanceFilter {
+            group: Some("projectB".to_string()),
+            ..Default::default()
+        };
+        let ids: Vec<String> = registry.query(&filter).into_iter().map(|i| i.id).collect();
+        assert_eq!(ids, vec!["projectB/api"]);
+    }
+
+    #[test]
+    fn test_instance_search() {
+        let mut registry = InstanceRegistry::new();
+        let mut api = create_test_instance("Billing-API", 8001);
+        api.tags = vec!["Payments".to_string()];
+        registry.add(api).unwrap();
+        registry
+            .add(create_test_instance("worker-1", 8002))
+            .unwrap();
+        let mut other = create_test_instance("cache", 8003);
+        other.template_id = "redis".to_string();
+        other.tags.clear();
+        registry.add(other).unwrap();
+
+        let ids = |query: &str| -> Vec<String> {
+            registry.search(query).into_iter().map(|i| i.id).collect()
+        };
+        // Id, case-insensitively
+        assert_eq!(ids("billing"), vec!["Billing-API"]);
+        // Tag
+        assert_eq!(ids("PAYMENT"), vec!["Billing-API"]);
+        // Template id ("test") and the "test" tag
+        assert_eq!(ids("tes"), vec!["Billing-API", "worker-1"]);
+        assert_eq!(ids("redis"), vec!["cache"]);
+        assert!(ids("nothing").is_empty());
+        // An empty query matches everything
+        assert_eq!(ids(" ").len(), 3);
+    }
+
+    #[test]
+    fn test_dependency_tiers() {
+        let mut registry = InstanceRegistry::new();
+
+        let db = create_test_instance("db", 8001);
+        let cache = create_test_instance("cache", 8002);
+        let mut api = create_test_instance("api", 8003);
+        api.depends_on = vec!["db".to_string(), "cache".to_string()];
+        let mut web = create_test_instance("web", 8004);
+        web.depends_on = vec!["api".to_string(), "db".to_string()];
+
+        registry.add(db).unwrap();
+        registry.add(cache).unwrap();
+        registry.add(api).unwrap();
+        registry.add(web).unwrap();
+
+        assert_eq!(
+            registry.dependency_tiers("web").unwrap(),
+            vec![vec!["cache", "db"], vec!["api"], vec!["web"]]
+        );
+        assert_eq!(registry.dependency_tiers("db").unwrap(), vec![vec!["db"]]);
+    }
+
+    #[test]
+    fn test_dependency_tiers_errors() {
+        let mut registry = InstanceRegistry::new();
+
+        let mut a = create_test_instance("a", 8001);
+        a.depends_on = vec!["b".to_string()];
+        let mut b = create_test_instance("b", 8002);
+        b.depends_on = vec!["a".to_string()];
+        let mut c = create_test_instance("c", 8003);
+        c.depends_on = vec!["missing".to_string()];
+
+        registry.add(a).unwrap();
+        registry.add(b).unwrap();
+        registry.add(c).unwrap();
+
+        let err = registry.dependency_tiers("a").unwrap_err().to_string();
+        assert!(err.contains("cycle"), "{}", err);
+
+        let err = registry.dependency_tiers("c").unwrap_err().to_string();
+        assert!(err.contains("unknown instance 'missing'"), "{}", err);
+    }
+
+    #[test]
+    fn test_bulk_waves() {
+        let mut registry = InstanceRegistry::new();
+
+        let db = create_test_instance("db", 8001);
+        let mut api = create_test_instance("api", 8002);
+        api.depends_on = vec!["db".to_string()];
+        let mut web = create_test_instance("web", 8003);
+        web.depends_on = vec!["api".to_string()];
+        let mut a = create_test_instance("a", 8004);
+        a.depends_on = vec!["b".to_string()];
+        let mut b = create_test_instance("b", 8005);
+        b.depends_on = vec!["a".to_string()];
+
+        registry.add(db).unwrap();
+        registry.add(api).unwrap();
+        registry.add(web).unwrap();
+        registry.add(a).unwrap();
+        registry.add(b).unwrap();
+
+        let ids = |names: &[&str]| names.iter().map(|n| n.to_string()).collect::<Vec<_>>();
+        assert_eq!(
+            registry.bulk_waves(&ids(&["web", "api", "db"])),
+            vec![vec![2], vec![1], vec![0]]
+        );
+
+        // Unselected dependencies, unknown ids and cycles don't hold anything back
+        assert_eq!(
+            registry.bulk_waves(&ids(&["web", "db", "missing"])),
+            vec![vec![0, 1, 2]]
+        );
+        let waves = registry.bulk_waves(&ids(&["a", "b"]));
+        assert_eq!(waves.concat().len(), 2);
+    }
+
+    fn query_ids(registry: &InstanceRegistry, filter: &InstanceFilter) -> Vec<String> {
+        let mut ids: Vec<String> = registry.query(filter).into_iter().map(|i| i.id).collect();
+        ids.sort();
+        ids
+    }
+
+    #[test]
+    fn test_instance_query() {
+        let mut registry = InstanceRegistry::new();
+
+        let mut api = create_test_instance("api", 8001);
+        api.template_id = "web".to_string();
+        api.tags = vec!["production".to_string(), "llm".to_string()];
+        api.status = ServiceStatus::Running;
+
+        let mut worker = create_test_instance("worker", 8002);
+        worker.template_id = "web".to_string();
+        worker.tags = vec!["production".to_string()];
+
+        let mut db = create_test_instance("db", 8003);
+        db.template_id = "postgres".to_string();
+        db.tags = vec!["llm".to_string()];
+        db.status = ServiceStatus::Running;
+
+        registry.add(api).unwrap();
+        registry.add(worker).unwrap();
+        registry.add(db).unwrap();
+
+        // Empty filter matches everything
+        assert_eq!(
+            query_ids(&registry, &InstanceFilter::default()),
+            vec!["api", "db", "worker"]
+        );
+
+        // Template only
+        let filter = InstanceFilter {
+            template: Some("web".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(query_ids(&registry, &filter), vec!["api", "worker"]);
+
+        // Tags, any vs all
+        let mut filter = InstanceFilter {
+            tags: vec!["production".to_string(), "llm".to_string()],
+            ..Default::default()
+        };
+        assert_eq!(query_ids(&registry, &filter), vec!["api", "db", "worker"]);
+        filter.tag_match = TagMatch::All;
+        assert_eq!(query_ids(&registry, &filter), vec!["api"]);
+
+        // Status only
+        let filter = InstanceFilter {
+            status: Some(ServiceStatus::Running),
+            ..Default::default()
+        };
+        assert_eq!(query_ids(&registry, &filter), vec!["api", "db"]);
+
+        // Template + status
+        let filter = InstanceFilter {
+            template: Some("web".to_string()),
+            status: Some(ServiceStatus::Stopped),
+            ..Default::default()
+        };
+        assert_eq!(query_ids(&registry, &filter), vec!["worker"]);
+
+        // Template + tag + status
+        let filter = InstanceFilter {
+            group: None,
+            template: Some("postgres".to_string()),
+            tags: vec!["llm".to_string()],
+            tag_match: TagMatch::Any,
+            status: Some(ServiceStatus::Running),
+        };
+        assert_eq!(query_ids(&registry, &filter), vec!["db"]);
+
+        // No match
+        let filter = InstanceFilter {
+            template: Some("postgres".to_string()),
+            tags: vec!["production".to_string()],
+            ..Default::default()
+        };
+        assert!(query_ids(&registry, &filter).is_empty());
+    }
 }
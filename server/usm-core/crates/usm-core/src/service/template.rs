@@ -1,8 +1,14 @@
 //! Service templates - blueprints for creating service instances
 
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
 use serde::{Deserialize, Serialize};
+use tracing::warn;
 
 use super::ServiceInstance;
+use crate::supervisor::RestartPolicy;
 
 /// Category for organizing services in the UI
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
@@ -16,6 +22,53 @@ pub enum ServiceCategory {
     Custom,
 }
 
+impl std::fmt::Display for ServiceCategory {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ServiceCategory::Core => write!(f, "core"),
+            ServiceCategory::Development => write!(f, "development"),
+            ServiceCategory::Database => write!(f, "database"),
+            ServiceCategory::Infrastructure => write!(f, "infrastructure"),
+            ServiceCategory::Custom => write!(f, "custom"),
+        }
+    }
+}
+
+impl std::str::FromStr for ServiceCategory {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "core" => Ok(ServiceCategory::Core),
+            "development" => Ok(ServiceCategory::Development),
+            "database" => Ok(ServiceCategory::Database),
+            "infrastructure" => Ok(ServiceCategory::Infrastructure),
+            "custom" => Ok(ServiceCategory::Custom),
+            _ => anyhow::bail!("Unknown category '{}'", s),
+        }
+    }
+}
+
+/// Where an instance's effective environment variable came from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EnvSource {
+    /// The template's `default_env`
+    Template,
+    /// The instance's own `env_vars`
+    Instance,
+}
+
+/// One entry of an instance's effective environment
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EnvValue {
+    pub value: String,
+    pub source: EnvSource,
+    /// Whether an instance value shadows a template default
+    #[serde(default)]
+    pub overrides_template: bool,
+}
+
 /// A service template defines how to start/stop a type of service
 ///
 /// Templates support variable substitution in commands:
@@ -23,6 +76,7 @@ pub enum ServiceCategory {
 /// - `{config}` - Path to the instance's config file
 /// - `{working_dir}` - The instance's working directory
 /// - `{pid}` - The process ID (for stop commands)
+/// - `{env.NAME}` - The instance's effective value of `NAME` (see `env`)
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ServiceTemplate {
     /// Unique identifier for this template
@@ -43,7 +97,7 @@ pub struct ServiceTemplate {
     pub port_range: Option<(u16, u16)>,
 
     /// Command template to start the service
-    /// Supports: {port}, {config}, {working_dir}
+    /// Supports: {port}, {config}, {working_dir}, {env.NAME}
     pub start_command: String,
 
     /// Optional custom stop command (defaults to SIGTERM)
@@ -51,15 +105,52 @@ pub struct ServiceTemplate {
     #[serde(default)]
     pub stop_command: Option<String>,
 
-    /// Health check endpoint template
+    /// Health check endpoint template: an `http://` URL (healthy on 2xx) or
+    /// a `tcp://host:port` address (healthy once it accepts connections)
     /// Supports: {port}
     #[serde(default)]
     pub health_endpoint: Option<String>,
 
+    /// Shell command used as the health check (healthy on exit status 0)
+    /// Supports: {port}. Takes precedence over `health_endpoint`.
+    #[serde(default)]
+    pub health_command: Option<String>,
+
+    /// Status code an `http://` health check must return (any 2xx if unset)
+    #[serde(default)]
+    pub health_expect_status: Option<u16>,
+
+    /// Text the body of an `http://` health check response must contain,
+    /// for endpoints that answer 200 while reporting themselves unhealthy
+    #[serde(default)]
+    pub health_expect_body_contains: Option<String>,
+
     /// Health check timeout in milliseconds
     #[serde(default = "default_health_timeout")]
     pub health_timeout_ms: u32,
 
+    /// Grace period after a start during which failed health checks are
+    /// expected and not held against the instance, in milliseconds (like
+    /// Docker's `start_period`)
+    #[serde(default)]
+    pub health_start_period_ms: Option<u32>,
+
+    /// How long a stopped process gets to exit after SIGTERM before it is
+    /// sent SIGKILL, in milliseconds
+    #[serde(default = "default_stop_timeout")]
+    pub stop_timeout_ms: u32,
+
+    /// How long a started process may take to be confirmed running, in
+    /// milliseconds. Checked every 100ms; used by monitors that verify
+    /// spawns (macOS).
+    #[serde(default = "default_startup_verify")]
+    pub startup_verify_ms: u32,
+
+    /// Signal that makes the service reload its config without a restart,
+    /// e.g. `SIGUSR1` (SIGHUP when unset)
+    #[serde(default)]
+    pub reload_signal: Option<String>,
+
     /// Category for UI organization
     #[serde(default)]
     pub category: ServiceCategory,
@@ -68,23 +159,92 @@ pub struct ServiceTemplate {
     #[serde(default)]
     pub supports_multiple: bool,
 
+    /// Most instances the template may have at once (no limit when unset)
+    ///
+    /// Only meaningful with `supports_multiple`; without it the cap is 1.
+    #[serde(default)]
+    pub max_instances: Option<usize>,
+
     /// Whether this is a Docker Compose service
+    ///
+    /// Instances are started with `docker compose up -d` and stopped with
+    /// `docker compose down` (see `crate::docker`); `start_command` and
+    /// `stop_command` are not used. The instance's `config_path` is the
+    /// compose file.
     #[serde(default)]
     pub is_docker: bool,
 
+    /// Restart policy for crashed instances (instances can override it)
+    #[serde(default)]
+    pub restart: Option<RestartPolicy>,
+
     /// Default environment variables
     #[serde(default)]
     pub default_env: std::collections::HashMap<String, String>,
+
+    /// Working directory for instances that don't set their own
+    #[serde(default)]
+    pub working_dir: Option<PathBuf>,
+
+    /// Base template this one inherits unset fields from (`extends` in the
+    /// config); the other fields already hold the inherited values
+    #[serde(default)]
+    pub extends: Option<String>,
 }
 
 fn default_health_timeout() -> u32 {
     5000
 }
 
+fn default_stop_timeout() -> u32 {
+    ServiceTemplate::DEFAULT_STOP_TIMEOUT_MS
+}
+
+fn default_startup_verify() -> u32 {
+    ServiceTemplate::DEFAULT_STARTUP_VERIFY_MS
+}
+
 impl ServiceTemplate {
+    /// Grace period between SIGTERM and SIGKILL when none is configured
+    pub const DEFAULT_STOP_TIMEOUT_MS: u32 = 10000;
+
+    /// Longest wait for a started process to be confirmed running
+    pub const DEFAULT_STARTUP_VERIFY_MS: u32 = 3000;
+
+    /// Resolve the working directory for an instance
+    ///
+    /// Precedence: instance > template > `fallback` (the `[defaults]`
+    /// working_dir). None means the process inherits USM's CWD.
+    pub fn resolve_working_dir(
+        &self,
+        instance: &ServiceInstance,
+        fallback: Option<&Path>,
+    ) -> Option<PathBuf> {
+        instance
+            .working_dir
+            .clone()
+            .or_else(|| self.working_dir.clone())
+            .or_else(|| fallback.map(Path::to_path_buf))
+    }
+
     /// Build the start command for a specific instance
     pub fn build_start_command(&self, instance: &ServiceInstance) -> String {
-        let mut cmd = self.start_command.clone();
+        self.build_start_command_in(instance, instance.working_dir.as_deref())
+    }
+
+    /// Build the start command, substituting `{working_dir}` with `working_dir`
+    ///
+    /// The instance's `start_command_override` replaces the template's
+    /// `start_command` when set.
+    pub fn build_start_command_in(
+        &self,
+        instance: &ServiceInstance,
+        working_dir: Option<&Path>,
+    ) -> String {
+        let mut cmd = instance
+            .start_command_override
+            .clone()
+            .unwrap_or_else(|| self.start_command.clone());
 
         cmd = cmd.replace("{port}", &instance.port.to_string());
 
@@ -94,13 +254,82 @@ impl ServiceTemplate {
             cmd = cmd.replace("{config}", "");
         }
 
-        if let Some(ref working_dir) = instance.working_dir {
+        if let Some(working_dir) = working_dir {
             cmd = cmd.replace("{working_dir}", &working_dir.display().to_string());
         } else {
             cmd = cmd.replace("{working_dir}", ".");
         }
 
-        cmd
+        substitute_env(&cmd, &self.env(instance), &instance.id)
+    }
+
+    /// Restart policy for an instance: its own, else the template's
+    pub fn restart_policy(&self, instance: &ServiceInstance) -> Option<RestartPolicy> {
+        instance
+            .restart
+            .clone()
+            .or_else(|| self.restart.clone())
+            .filter(RestartPolicy::enabled)
+    }
+
+    /// Merge `default_env` with the instance's `env_vars` (instance wins)
+    pub fn effective_env(&self, instance: &ServiceInstance) -> BTreeMap<String, EnvValue> {
+        let mut env: BTreeMap<String, EnvValue> = self
+            .default_env
+            .iter()
+            .map(|(key, value)| {
+                let entry = EnvValue {
+                    value: value.clone(),
+                    source: EnvSource::Template,
+                    overrides_template: false,
+                };
+                (key.clone(), entry)
+            })
+            .collect();
+
+        for (key, value) in &instance.env_vars {
+            let entry = EnvValue {
+                value: value.clone(),
+                source: EnvSource::Instance,
+                overrides_template: self.default_env.contains_key(key),
+            };
+            env.insert(key.clone(), entry);
+        }
+        env
+    }
+
+    /// Environment for an instance's process: `default_env` overridden by
+    /// the instance's `env_vars`
+    pub fn env(&self, instance: &ServiceInstance) -> HashMap<String, String> {
+        let mut env = self.default_env.clone();
+        env.extend(instance.env_vars.clone());
+        env
+    }
+
+    /// Check that `start_command`, `stop_command` and `health_endpoint` only
+    /// use known placeholders
+    pub fn validate_placeholders(&self) -> Result<()> {
+        let fields = [
+            ("start_command", Some(&self.start_command)),
+            ("stop_command", self.stop_command.as_ref()),
+            ("health_endpoint", self.health_endpoint.as_ref()),
+        ];
+        for (field, value) in fields {
+            if let Some(value) = value {
+                check_placeholders(value)
+                    .map_err(|e| anyhow::anyhow!("Template '{}' {}: {}", self.id, field, e))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Stop command for an instance: its `stop_command_override`, else the
+    /// template's `stop_command`
+    pub fn stop_command_for<'a>(&'a self, instance: &'a ServiceInstance) -> Option<&'a str> {
+        instance
+            .stop_command_override
+            .as_deref()
+            .or(self.stop_command.as_deref())
     }
 
     /// Build the health endpoint URL for a specific instance
@@ -118,20 +347,152 @@ impl ServiceTemplate {
         }
     }
 
+    /// Fail with a helpful message if `port` is outside `port_range`
+    pub fn check_port(&self, port: u16) -> Result<()> {
+        match self.port_range {
+            Some((min, max)) if !self.is_port_valid(port) => anyhow::bail!(
+                "Port {} is outside the port range {}-{} of template '{}'",
+                port,
+                min,
+                max,
+                self.id
+            ),
+            _ => Ok(()),
+        }
+    }
+
+    /// Most instances this template may have, if limited
+    pub fn instance_cap(&self) -> Option<usize> {
+        if self.supports_multiple {
+            self.max_instances
+        } else {
+            Some(1)
+        }
+    }
+
+    /// Fail if the template already has as many instances as it allows
+    pub fn check_instance_cap(&self, existing: usize) -> Result<()> {
+        if !self.supports_multiple && existing > 0 {
+            anyhow::bail!("Template '{}' does not support multiple instances", self.id);
+        }
+        match self.instance_cap() {
+            Some(cap) if existing >= cap => anyhow::bail!(
+                "Template '{}' instance limit reached: {} of max_instances = {} exist",
+                self.id,
+                existing,
+                cap
+            ),
+            _ => Ok(()),
+        }
+    }
+
+    /// What's left of an instance's health start period at `now`
+    ///
+    /// Zero once it has passed, and for templates without one or instances
+    /// that aren't running. Health checks failing before then don't count.
+    pub fn health_grace_remaining(
+        &self,
+        instance: &ServiceInstance,
+        now: chrono::DateTime<chrono::Utc>,
+    ) -> std::time::Duration {
+        let (Some(period), Some(started_at)) = (self.health_start_period_ms, instance.started_at)
+        else {
+            return std::time::Duration::ZERO;
+        };
+        let ends = started_at + chrono::Duration::milliseconds(i64::from(period));
+        (ends - now).to_std().unwrap_or_default()
+    }
+
     /// Get the next available port (simple increment from default)
     pub fn next_available_port(&self, used_ports: &[u16]) -> Option<u16> {
+        self.next_available_port_with(used_ports, |_| false)
+    }
+
+    /// Like `next_available_port`, also skipping ports `is_bound` reports as
+    /// taken outside USM (e.g. `ProcessMonitor::is_port_in_use`)
+    pub fn next_available_port_with(
+        &self,
+        used_ports: &[u16],
+        is_bound: impl Fn(u16) -> bool,
+    ) -> Option<u16> {
         let (min, max) = self
             .port_range
             .unwrap_or((self.default_port, self.default_port + 100));
 
-        (min..=max).find(|port| !used_ports.contains(port))
+        (min..=max).find(|&port| !used_ports.contains(&port) && !is_bound(port))
     }
 }
 
+/// Placeholders substituted in template commands, besides `{env.NAME}`
+const PLACEHOLDERS: &[&str] = &["port", "config", "working_dir", "pid"];
+
+/// Fail on the first unknown `{name}` placeholder in `command`
+///
+/// Only braces around a plain name count as placeholders, so shell syntax
+/// such as `${VAR}` or `awk '{print $1}'` passes through.
+pub(crate) fn check_placeholders(command: &str) -> Result<()> {
+    let mut rest = command;
+    while let Some(start) = rest.find('{') {
+        let is_shell_var = rest[..start].ends_with('$');
+        let after = &rest[start + 1..];
+        let Some(end) = after.find('}') else {
+            break;
+        };
+        let name = &after[..end];
+        rest = &after[end + 1..];
+
+        let is_name = !name.is_empty()
+            && name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.');
+        if is_shell_var || !is_name || PLACEHOLDERS.contains(&name) {
+            continue;
+        }
+        match name.strip_prefix("env.") {
+            Some(var) if !var.is_empty() && !var.contains('.') => continue,
+            _ => anyhow::bail!(
+                "unknown placeholder '{{{}}}' (expected {{port}}, {{config}}, {{working_dir}}, {{pid}} or {{env.NAME}})",
+                name
+            ),
+        }
+    }
+    Ok(())
+}
+
+/// Replace `{env.NAME}` placeholders with values from `env`
+///
+/// Unknown names are replaced with an empty string and logged.
+fn substitute_env(command: &str, env: &HashMap<String, String>, instance_id: &str) -> String {
+    const PREFIX: &str = "{env.";
+
+    let mut result = String::with_capacity(command.len());
+    let mut rest = command;
+    while let Some(start) = rest.find(PREFIX) {
+        let after = &rest[start + PREFIX.len()..];
+        let Some(end) = after.find('}') else {
+            break;
+        };
+        let name = &after[..end];
+        result.push_str(&rest[..start]);
+        match env.get(name) {
+            Some(value) => result.push_str(value),
+            None => warn!(
+                instance_id = %instance_id,
+                "Start command references undefined env var '{}', substituting empty string",
+                name
+            ),
+        }
+        rest = &after[end + 1..];
+    }
+    result.push_str(rest);
+    result
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use std::path::PathBuf;
+    use std::time::Duration;
 
     fn create_test_template() -> ServiceTemplate {
         ServiceTemplate {
@@ -145,10 +506,21 @@ mod tests {
             stop_command: Some("kill {pid}".to_string()),
             health_endpoint: Some("http://localhost:{port}/health".to_string()),
             health_timeout_ms: 5000,
+            stop_timeout_ms: 10000,
+            startup_verify_ms: 3000,
+            reload_signal: None,
+            extends: None,
             category: ServiceCategory::Core,
             supports_multiple: true,
+            max_instances: None,
             is_docker: false,
+            restart: None,
             default_env: Default::default(),
+            health_command: None,
+            health_expect_status: None,
+            health_expect_body_contains: None,
+            health_start_period_ms: None,
+            working_dir: None,
         }
     }
 
@@ -157,6 +529,7 @@ mod tests {
 
         ServiceInstance {
             id: "test-instance".to_string(),
+            group: None,
             template_id: "test-service".to_string(),
             port: 8001,
             working_dir: Some(PathBuf::from("/opt/app")),
@@ -166,14 +539,46 @@ mod tests {
             tags: vec!["production".to_string()],
             auto_start: false,
             env_vars: Default::default(),
+            display_name: None,
+            notes: None,
+            labels: Default::default(),
+            metadata: Default::default(),
+            depends_on: Vec::new(),
+            schedule: None,
+            restart: None,
+            start_command_override: None,
+            stop_command_override: None,
+            cpu_limit_percent: None,
+            memory_limit_mb: None,
+            limit_action: None,
             status: ServiceStatus::Stopped,
             pid: None,
             started_at: None,
+            last_pid: None,
+            last_exit_code: None,
+            last_error: None,
             created_at: chrono::Utc::now(),
             created_via: "config".to_string(),
         }
     }
 
+    #[test]
+    fn test_category_parse() {
+        assert_eq!(
+            "database".parse::<ServiceCategory>().unwrap(),
+            ServiceCategory::Database
+        );
+        assert_eq!(
+            "Core".parse::<ServiceCategory>().unwrap(),
+            ServiceCategory::Core
+        );
+        assert!("cache".parse::<ServiceCategory>().is_err());
+        assert_eq!(
+            ServiceCategory::Infrastructure.to_string(),
+            "infrastructure"
+        );
+    }
+
     #[test]
     fn test_build_start_command() {
         let template = create_test_template();
@@ -186,6 +591,144 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_build_start_command_env() {
+        let mut template = create_test_template();
+        template.start_command =
+            "serve --level {env.LOG_LEVEL} --region {env.REGION} --x={env.MISSING}".to_string();
+        template
+            .default_env
+            .insert("LOG_LEVEL".to_string(), "info".to_string());
+        template
+            .default_env
+            .insert("REGION".to_string(), "us".to_string());
+        let mut instance = create_test_instance();
+        instance
+            .env_vars
+            .insert("REGION".to_string(), "eu".to_string());
+
+        assert_eq!(
+            template.build_start_command(&instance),
+            "serve --level info --region eu --x="
+        );
+
+        let env = template.env(&instance);
+        assert_eq!(env.len(), 2);
+        assert_eq!(env["REGION"], "eu");
+
+        // An unterminated placeholder is left alone
+        template.start_command = "echo {env.LOG_LEVEL} {env.OOPS".to_string();
+        assert_eq!(
+            template.build_start_command(&instance),
+            "echo info {env.OOPS"
+        );
+    }
+
+    #[test]
+    fn test_validate_placeholders() {
+        let mut template = create_test_template();
+        template.validate_placeholders().unwrap();
+
+        // env placeholders and shell braces are fine
+        template.start_command =
+            "serve --port {port} --region {env.REGION} ${HOME} | awk '{print $1}'".to_string();
+        template.validate_placeholders().unwrap();
+
+        template.start_command = "serve --port {porrt}".to_string();
+        let err = template.validate_placeholders().unwrap_err().to_string();
+        assert!(
+            err.starts_with("Template 'test-service' start_command: unknown placeholder '{porrt}'"),
+            "{}",
+            err
+        );
+
+        template.start_command = "serve --port {port}".to_string();
+        template.stop_command = Some("kill {env.}".to_string());
+        let err = template.validate_placeholders().unwrap_err().to_string();
+        assert!(
+            err.contains("stop_command: unknown placeholder '{env.}'"),
+            "{}",
+            err
+        );
+
+        template.stop_command = None;
+        template.health_endpoint = Some("http://localhost:{Port}/health".to_string());
+        let err = template.validate_placeholders().unwrap_err().to_string();
+        assert!(
+            err.contains("health_endpoint: unknown placeholder '{Port}'"),
+            "{}",
+            err
+        );
+    }
+
+    #[test]
+    fn test_resolve_working_dir_precedence() {
+        let mut template = create_test_template();
+        let mut instance = create_test_instance();
+        let fallback = Path::new("/srv/default");
+
+        // Instance wins over everything
+        template.working_dir = Some(PathBuf::from("/srv/template"));
+        assert_eq!(
+            template.resolve_working_dir(&instance, Some(fallback)),
+            Some(PathBuf::from("/opt/app"))
+        );
+
+        // Then the template
+        instance.working_dir = None;
+        assert_eq!(
+            template.resolve_working_dir(&instance, Some(fallback)),
+            Some(PathBuf::from("/srv/template"))
+        );
+
+        // Then [defaults]
+        template.working_dir = None;
+        assert_eq!(
+            template.resolve_working_dir(&instance, Some(fallback)),
+            Some(PathBuf::from("/srv/default"))
+        );
+        let cmd = template.build_start_command_in(&instance, Some(fallback));
+        assert!(cmd.starts_with("python3 /srv/default/server.py"));
+
+        // Otherwise USM's CWD
+        assert_eq!(template.resolve_working_dir(&instance, None), None);
+        assert!(template
+            .build_start_command(&instance)
+            .starts_with("python3 ./server.py"));
+    }
+
+    #[test]
+    fn test_effective_env_sources() {
+        let mut template = create_test_template();
+        template
+            .default_env
+            .insert("LOG_LEVEL".to_string(), "info".to_string());
+        template
+            .default_env
+            .insert("WORKERS".to_string(), "4".to_string());
+        let mut instance = create_test_instance();
+        instance
+            .env_vars
+            .insert("LOG_LEVEL".to_string(), "debug".to_string());
+        instance
+            .env_vars
+            .insert("REGION".to_string(), "eu".to_string());
+
+        let env = template.effective_env(&instance);
+        assert_eq!(env.len(), 3);
+
+        let log_level = &env["LOG_LEVEL"];
+        assert_eq!(log_level.value, "debug");
+        assert_eq!(log_level.source, EnvSource::Instance);
+        assert!(log_level.overrides_template);
+
+        assert_eq!(env["WORKERS"].source, EnvSource::Template);
+        assert!(!env["WORKERS"].overrides_template);
+
+        assert_eq!(env["REGION"].source, EnvSource::Instance);
+        assert!(!env["REGION"].overrides_template);
+    }
+
     #[test]
     fn test_build_health_endpoint() {
         let template = create_test_template();
@@ -204,6 +747,65 @@ mod tests {
         assert!(template.is_port_valid(8099));
         assert!(!template.is_port_valid(7999));
         assert!(!template.is_port_valid(8100));
+
+        assert!(template.check_port(8099).is_ok());
+        assert_eq!(
+            template.check_port(9000).unwrap_err().to_string(),
+            "Port 9000 is outside the port range 8000-8099 of template 'test-service'"
+        );
+    }
+
+    #[test]
+    fn test_instance_cap() {
+        let mut template = create_test_template();
+        assert_eq!(template.instance_cap(), None);
+        assert!(template.check_instance_cap(100).is_ok());
+
+        template.max_instances = Some(3);
+        assert!(template.check_instance_cap(2).is_ok());
+        assert_eq!(
+            template.check_instance_cap(3).unwrap_err().to_string(),
+            "Template 'test-service' instance limit reached: 3 of max_instances = 3 exist"
+        );
+
+        // Single-instance templates are capped at 1 whatever max_instances says
+        template.supports_multiple = false;
+        assert_eq!(template.instance_cap(), Some(1));
+        assert!(template.check_instance_cap(0).is_ok());
+        assert_eq!(
+            template.check_instance_cap(1).unwrap_err().to_string(),
+            "Template 'test-service' does not support multiple instances"
+        );
+    }
+
+    #[test]
+    fn test_health_grace_remaining() {
+        let mut template = create_test_template();
+        let mut instance = create_test_instance();
+        let started = chrono::Utc::now();
+        instance.started_at = Some(started);
+        let at = |ms| started + chrono::Duration::milliseconds(ms);
+        assert_eq!(
+            template.health_grace_remaining(&instance, at(0)),
+            Duration::ZERO
+        );
+
+        template.health_start_period_ms = Some(5000);
+        assert_eq!(
+            template.health_grace_remaining(&instance, at(1500)),
+            Duration::from_millis(3500)
+        );
+        assert_eq!(
+            template.health_grace_remaining(&instance, at(6000)),
+            Duration::ZERO
+        );
+
+        // Not started: no grace
+        instance.started_at = None;
+        assert_eq!(
+            template.health_grace_remaining(&instance, at(0)),
+            Duration::ZERO
+        );
     }
 
     #[test]
@@ -222,6 +824,18 @@ mod tests {
             Some(8003)
         );
     }
+
+    #[test]
+    fn test_next_available_port_with_bound_ports() {
+        let template = create_test_template();
+
+        // Ports bound outside USM are skipped like registry ports
+        assert_eq!(
+            template.next_available_port_with(&[8000], |port| port == 8001),
+            Some(8002)
+        );
+        assert_eq!(template.next_available_port_with(&[], |_| true), None);
+    }
 }
 
 /// Property-based tests for ServiceTemplate
@@ -272,10 +886,21 @@ mod property_tests {
                 stop_command: None,
                 health_endpoint: None,
                 health_timeout_ms: 5000,
+                stop_timeout_ms: 10000,
+                startup_verify_ms: 3000,
+                reload_signal: None,
+                extends: None,
                 category: ServiceCategory::Core,
                 supports_multiple: false,
+                max_instances: None,
                 is_docker: false,
+                restart: None,
                 default_env: std::collections::HashMap::new(),
+                health_command: None,
+                health_expect_status: None,
+                health_expect_body_contains: None,
+                health_start_period_ms: None,
+                working_dir: None,
             };
 
             let expected = port >= min && port <= max;
@@ -300,10 +925,21 @@ mod property_tests {
                 stop_command: None,
                 health_endpoint: None,
                 health_timeout_ms: 5000,
+                stop_timeout_ms: 10000,
+                startup_verify_ms: 3000,
+                reload_signal: None,
+                extends: None,
                 category: ServiceCategory::Core,
                 supports_multiple: false,
+                max_instances: None,
                 is_docker: false,
+                restart: None,
                 default_env: std::collections::HashMap::new(),
+                health_command: None,
+                health_expect_status: None,
+                health_expect_body_contains: None,
+                health_start_period_ms: None,
+                working_dir: None,
             };
 
             prop_assert!(template.is_port_valid(port));
@@ -330,10 +966,21 @@ mod property_tests {
                 stop_command: None,
                 health_endpoint: None,
                 health_timeout_ms: 5000,
+                stop_timeout_ms: 10000,
+                startup_verify_ms: 3000,
+                reload_signal: None,
+                extends: None,
                 category: ServiceCategory::Core,
                 supports_multiple: true,
+                max_instances: None,
                 is_docker: false,
+                restart: None,
                 default_env: std::collections::HashMap::new(),
+                health_command: None,
+                health_expect_status: None,
+                health_expect_body_contains: None,
+                health_start_period_ms: None,
+                working_dir: None,
             };
 
             // Create list of used ports
@@ -364,10 +1011,21 @@ mod property_tests {
                 stop_command: None,
                 health_endpoint: None,
                 health_timeout_ms: 5000,
+                stop_timeout_ms: 10000,
+                startup_verify_ms: 3000,
+                reload_signal: None,
+                extends: None,
                 category: ServiceCategory::Core,
                 supports_multiple: true,
+                max_instances: None,
                 is_docker: false,
+                restart: None,
                 default_env: std::collections::HashMap::new(),
+                health_command: None,
+                health_expect_status: None,
+                health_expect_body_contains: None,
+                health_start_period_ms: None,
+                working_dir: None,
             };
 
             // Use all ports in range
@@ -392,14 +1050,26 @@ mod property_tests {
                 stop_command: None,
                 health_endpoint: None,
                 health_timeout_ms: 5000,
+                stop_timeout_ms: 10000,
+                startup_verify_ms: 3000,
+                reload_signal: None,
+                extends: None,
                 category: ServiceCategory::Core,
                 supports_multiple: false,
+                max_instances: None,
                 is_docker: false,
+                restart: None,
                 default_env: std::collections::HashMap::new(),
+                health_command: None,
+                health_expect_status: None,
+                health_expect_body_contains: None,
+                health_start_period_ms: None,
+                working_dir: None,
             };
 
             let instance = super::super::instance::ServiceInstance {
                 id: "test-instance".to_string(),
+                group: None,
                 template_id: "test".to_string(),
                 port,
                 working_dir: None,
@@ -409,9 +1079,24 @@ mod property_tests {
                 tags: vec![],
                 auto_start: false,
                 env_vars: std::collections::HashMap::new(),
+                display_name: None,
+                notes: None,
+                labels: Default::default(),
+                metadata: Default::default(),
+                depends_on: Vec::new(),
+                schedule: None,
+                restart: None,
+                start_command_override: None,
+                stop_command_override: None,
+                cpu_limit_percent: None,
+                memory_limit_mb: None,
+                limit_action: None,
                 status: super::super::instance::ServiceStatus::Stopped,
                 pid: None,
                 started_at: None,
+                last_pid: None,
+                last_exit_code: None,
+                last_error: None,
                 created_at: chrono::Utc::now(),
                 created_via: "test".to_string(),
             };
@@ -434,14 +1119,26 @@ mod property_tests {
                 stop_command: None,
                 health_endpoint: Some("http://localhost:{port}/health".to_string()),
                 health_timeout_ms: 5000,
+                stop_timeout_ms: 10000,
+                startup_verify_ms: 3000,
+                reload_signal: None,
+                extends: None,
                 category: ServiceCategory::Core,
                 supports_multiple: false,
+                max_instances: None,
                 is_docker: false,
+                restart: None,
                 default_env: std::collections::HashMap::new(),
+                health_command: None,
+                health_expect_status: None,
+                health_expect_body_contains: None,
+                health_start_period_ms: None,
+                working_dir: None,
             };
 
             let instance = super::super::instance::ServiceInstance {
                 id: "test".to_string(),
+                group: None,
                 template_id: "test".to_string(),
                 port,
                 working_dir: None,
@@ -451,9 +1148,24 @@ mod property_tests {
                 tags: vec![],
                 auto_start: false,
                 env_vars: std::collections::HashMap::new(),
+                display_name: None,
+                notes: None,
+                labels: Default::default(),
+                metadata: Default::default(),
+                depends_on: Vec::new(),
+                schedule: None,
+                restart: None,
+                start_command_override: None,
+                stop_command_override: None,
+                cpu_limit_percent: None,
+                memory_limit_mb: None,
+                limit_action: None,
                 status: super::super::instance::ServiceStatus::Stopped,
                 pid: None,
                 started_at: None,
+                last_pid: None,
+                last_exit_code: None,
+                last_error: None,
                 created_at: chrono::Utc::now(),
                 created_via: "test".to_string(),
             };
@@ -501,10 +1213,21 @@ mod property_tests {
                 stop_command: None,
                 health_endpoint: Some(format!("http://localhost:{}/health", port)),
                 health_timeout_ms: 5000,
+                stop_timeout_ms: 10000,
+                startup_verify_ms: 3000,
+                reload_signal: None,
+                extends: None,
                 category: ServiceCategory::Core,
                 supports_multiple: true,
+                max_instances: None,
                 is_docker: false,
+                restart: None,
                 default_env: std::collections::HashMap::new(),
+                health_command: None,
+                health_expect_status: None,
+                health_expect_body_contains: None,
+                health_start_period_ms: None,
+                working_dir: None,
             };
 
             let json = serde_json::to_string(&template).expect("JSON serialize failed");
@@ -0,0 +1,215 @@
+//! Runtime state that outlives the USM process
+//!
+//! Status, PIDs and start times aren't part of the config file. They are
+//! saved to a JSON file next to it (`services.toml` -> `services.state.json`)
+//! whenever an instance starts, stops or crashes, so a restarted USM can pick
+//! up the services it launched earlier. On load, an instance is re-attached
+//! only if its process is still alive and started when the saved one did (a
+//! PID reused after a reboot belongs to some other process); everything
+//! else comes back Stopped.
+
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use crate::service::{InstanceRegistry, ServiceStatus};
+
+/// Saved runtime fields of one instance
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct InstanceState {
+    pub status: ServiceStatus,
+    #[serde(default)]
+    pub pid: Option<u32>,
+    /// When the process `pid` started, in seconds since the Unix epoch (see
+    /// `ProcessMonitor::process_start_time`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pid_start_time: Option<u64>,
+    #[serde(default)]
+    pub started_at: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub last_pid: Option<u32>,
+    #[serde(default)]
+    pub last_exit_code: Option<i32>,
+    #[serde(default)]
+    pub last_error: Option<String>,
+}
+
+/// Contents of the runtime state file
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RuntimeState {
+    #[serde(default)]
+    pub instances: BTreeMap<String, InstanceState>,
+}
+
+impl RuntimeState {
+    /// Snapshot the runtime fields of every instance that has any
+    ///
+    /// `start_time` looks up when a PID's process started.
+    pub fn capture(instances: &InstanceRegistry, start_time: impl Fn(u32) -> Option<u64>) -> Self {
+        let instances = instances
+            .list()
+            .into_iter()
+            .filter(|i| {
+                i.status != ServiceStatus::Stopped || i.last_pid.is_some() || i.last_error.is_some()
+            })
+            .map(|i| {
+                let state = InstanceState {
+                    status: i.status,
+                    pid: i.pid,
+                    pid_start_time: i.pid.and_then(&start_time),
+                    started_at: i.started_at,
+                    last_pid: i.last_pid,
+                    last_exit_code: i.last_exit_code,
+                    last_error: i.last_error,
+                };
+                (i.id, state)
+            })
+            .collect();
+        Self { instances }
+    }
+
+    /// Apply saved state to freshly loaded instances
+    ///
+    /// Running instances are re-attached if `start_time` (None for a dead
+    /// PID) finds their PID's process started when the saved one did; a
+    /// live process without a match has reused the PID. Docker instances
+    /// (Running without a PID) are trusted, since their containers outlive
+    /// USM anyway. Anything else is left Stopped. Returns the ids of the
+    /// re-attached instances.
+    pub fn restore(
+        &self,
+        instances: &mut InstanceRegistry,
+        start_time: impl Fn(u32) -> Option<u64>,
+    ) -> Vec<String> {
+        let mut attached = Vec::new();
+        for (id, saved) in &self.instances {
+            let Some(instance) = instances.get_mut(id) else {
+                continue;
+            };
+            instance.last_pid = saved.last_pid;
+            instance.last_exit_code = saved.last_exit_code;
+            instance.last_error = saved.last_error.clone();
+            if saved.status != ServiceStatus::Running {
+                continue;
+            }
+            if let Some(pid) = saved.pid {
+                let current = start_time(pid);
+                if current.is_none() || current != saved.pid_start_time {
+                    if current.is_some() {
+                        warn!(instance_id = %id, pid, "PID now belongs to another process, not re-attaching");
+                    }
+                    continue;
+                }
+            }
+            instance.status = ServiceStatus::Running;
+            instance.pid = saved.pid;
+            instance.started_at = saved.started_at;
+            attached.push(id.clone());
+        }
+        attached
+    }
+
+    /// Read the state file; a missing or unreadable file is empty state
+    pub async fn load(path: &Path) -> Self {
+        let content = match tokio::fs::read_to_string(path).await {
+            Ok(content) => content,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Self::default(),
+            Err(e) => {
+                warn!(path = %path.display(), "Cannot read runtime state: {}", e);
+                return Self::default();
+            },
+        };
+        serde_json::from_str(&content).unwrap_or_else(|e| {
+            warn!(path = %path.display(), "Ignoring invalid runtime state: {}", e);
+            Self::default()
+        })
+    }
+
+    /// Write the state file atomically
+    pub async fn save(&self, path: &Path) -> Result<()> {
+        let content = serde_json::to_string_pretty(self)?;
+        crate::config::write_atomic(path, &content).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::service::ServiceInstance;
+
+    fn instance(id: &str, port: u16) -> ServiceInstance {
+        let toml = format!("id = \"{}\"\ntemplate_id = \"svc\"\nport = {}\n", id, port);
+        toml::from_str(&toml).unwrap()
+    }
+
+    fn registry() -> InstanceRegistry {
+        let mut registry = InstanceRegistry::new();
+        for (id, port) in [
+            ("alive", 9001),
+            ("dead", 9002),
+            ("docker", 9003),
+            ("idle", 9004),
+        ] {
+            registry.add(instance(id, port)).unwrap();
+        }
+        registry
+    }
+
+    #[tokio::test]
+    async fn test_round_trip_and_restore() {
+        let mut before = registry();
+        for (id, pid) in [("alive", Some(100)), ("dead", Some(200)), ("docker", None)] {
+            let instance = before.get_mut(id).unwrap();
+            instance.status = ServiceStatus::Running;
+            instance.pid = pid;
+            instance.last_pid = pid;
+            instance.started_at = Some(Utc::now());
+        }
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("services.state.json");
+        let state = RuntimeState::capture(&before, |pid| Some(u64::from(pid) * 10));
+        assert_eq!(state.instances.len(), 3); // "idle" has nothing to save
+        assert_eq!(state.instances["alive"].pid_start_time, Some(1000));
+        state.save(&path).await.unwrap();
+
+        let loaded = RuntimeState::load(&path).await;
+        assert_eq!(loaded, state);
+
+        let mut after = registry();
+        let attached = loaded.restore(&mut after, |pid| (pid == 100).then_some(1000));
+        assert_eq!(attached, vec!["alive", "docker"]);
+
+        let alive = after.get("alive").unwrap();
+        assert_eq!(alive.status, ServiceStatus::Running);
+        assert_eq!(alive.pid, Some(100));
+        assert!(alive.started_at.is_some());
+
+        let dead = after.get("dead").unwrap();
+        assert_eq!(dead.status, ServiceStatus::Stopped);
+        assert_eq!(dead.pid, None);
+        assert_eq!(dead.last_pid, Some(200)); // logs stay reachable
+
+        // Both PIDs alive, but reused by processes started since
+        let mut after = registry();
+        let attached = loaded.restore(&mut after, |pid| Some(u64::from(pid) * 10 + 5));
+        assert_eq!(attached, vec!["docker"]);
+        let alive = after.get("alive").unwrap();
+        assert_eq!(alive.status, ServiceStatus::Stopped);
+        assert_eq!(alive.pid, None);
+    }
+
+    #[tokio::test]
+    async fn test_load_missing_or_invalid() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("services.state.json");
+        assert_eq!(RuntimeState::load(&path).await, RuntimeState::default());
+
+        std::fs::write(&path, "{ not json").unwrap();
+        assert_eq!(RuntimeState::load(&path).await, RuntimeState::default());
+    }
+}
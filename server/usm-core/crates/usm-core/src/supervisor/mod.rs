@@ -0,0 +1,415 @@
+//! Automatic restarts of crashed instances
+//!
+//! A template or instance can carry a `restart` policy. The supervisor loop
+//! polls Running instances that have one; when an instance's process is no
+//! longer alive it is marked Error and restarted after a backoff that doubles
+//! with each consecutive crash (`backoff_ms`, `2 * backoff_ms`, ... up to
+//! `max_backoff_ms`). After `max_retries` restarts in a row the supervisor
+//! gives up and leaves the instance in Error.
+//!
+//! A crash counts as "in a row" unless the instance stayed up for at least
+//! `STABLE_AFTER` since its last restart, in which case the count starts over.
+//!
+//! Instances without a policy are left alone, as before.
+//!
+//! The same loop enforces resource limits. An instance with a
+//! `cpu_limit_percent` or `memory_limit_mb` that is over a limit for
+//! `LIMIT_SAMPLES` checks in a row gets an `Error` event and, depending on
+//! its `limit_action`, is restarted or stopped.
+
+use std::collections::HashMap;
+
+use anyhow::Result;
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+
+/// How long an instance must stay up after a restart before its crash count
+/// is reset
+pub const STABLE_AFTER: Duration = Duration::seconds(60);
+
+/// Consecutive over-limit samples before an instance's limit action is taken
+pub const LIMIT_SAMPLES: u32 = 3;
+
+/// When to restart an instance that died on its own
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum RestartMode {
+    /// Leave it stopped
+    #[default]
+    No,
+    /// Restart it whenever its process exits without USM stopping it
+    OnFailure,
+}
+
+/// Restart policy (`[templates.<id>.restart]` or `[instances.<id>.restart]`)
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RestartPolicy {
+    /// When to restart
+    #[serde(default)]
+    pub policy: RestartMode,
+
+    /// Consecutive restarts to attempt before giving up
+    #[serde(default = "default_max_retries")]
+    pub max_retries: u32,
+
+    /// Delay before the first restart; doubles with each consecutive crash
+    #[serde(default = "default_backoff_ms")]
+    pub backoff_ms: u64,
+
+    /// Upper bound on the delay between restarts
+    #[serde(default = "default_max_backoff_ms")]
+    pub max_backoff_ms: u64,
+}
+
+fn default_max_retries() -> u32 {
+    5
+}
+
+fn default_backoff_ms() -> u64 {
+    1000
+}
+
+fn default_max_backoff_ms() -> u64 {
+    60_000
+}
+
+impl Default for RestartPolicy {
+    fn default() -> Self {
+        Self {
+            policy: RestartMode::default(),
+            max_retries: default_max_retries(),
+            backoff_ms: default_backoff_ms(),
+            max_backoff_ms: default_max_backoff_ms(),
+        }
+    }
+}
+
+impl RestartPolicy {
+    /// Whether crashed instances should be restarted at all
+    pub fn enabled(&self) -> bool {
+        self.policy != RestartMode::No
+    }
+
+    /// Delay before restart number `attempt` (1-based)
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        let factor = 1u64 << attempt.saturating_sub(1).min(32);
+        let ms = self
+            .backoff_ms
+            .saturating_mul(factor)
+            .min(self.max_backoff_ms);
+        Duration::milliseconds(ms.min(i64::MAX as u64) as i64)
+    }
+}
+
+/// What to do with an instance that stays over its resource limits
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum LimitAction {
+    /// Only emit an `Error` event
+    #[default]
+    Warn,
+    /// Restart the instance
+    Restart,
+    /// Stop the instance
+    Stop,
+}
+
+impl std::fmt::Display for LimitAction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LimitAction::Warn => write!(f, "warn"),
+            LimitAction::Restart => write!(f, "restart"),
+            LimitAction::Stop => write!(f, "stop"),
+        }
+    }
+}
+
+/// Check that configured limits are usable
+pub fn check_limits(cpu_limit_percent: Option<f64>, memory_limit_mb: Option<u64>) -> Result<()> {
+    if let Some(cpu) = cpu_limit_percent {
+        if !cpu.is_finite() || cpu <= 0.0 {
+            anyhow::bail!("cpu_limit_percent must be a positive number, got {}", cpu);
+        }
+    }
+    if memory_limit_mb == Some(0) {
+        anyhow::bail!("memory_limit_mb must be greater than 0");
+    }
+    Ok(())
+}
+
+/// Which limits a sample broke, as a human-readable description
+///
+/// Returns None when the sample is within all configured limits.
+pub fn limit_breach(
+    cpu_limit_percent: Option<f64>,
+    memory_limit_mb: Option<u64>,
+    cpu_percent: f64,
+    memory_bytes: u64,
+) -> Option<String> {
+    let memory_mb = memory_bytes / (1024 * 1024);
+    let breaches: Vec<String> = [
+        cpu_limit_percent
+            .filter(|limit| cpu_percent > *limit)
+            .map(|limit| format!("CPU {:.1}% > {}%", cpu_percent, limit)),
+        memory_limit_mb
+            .filter(|limit| memory_mb > *limit)
+            .map(|limit| format!("memory {} MB > {} MB", memory_mb, limit)),
+    ]
+    .into_iter()
+    .flatten()
+    .collect();
+    (!breaches.is_empty()).then(|| breaches.join(", "))
+}
+
+/// What the supervisor decided about a crash
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CrashOutcome {
+    /// Restart number `attempt` is scheduled for `at`
+    Retry { attempt: u32, at: DateTime<Utc> },
+    /// `max_retries` restarts in a row already failed
+    GiveUp { attempts: u32 },
+}
+
+#[derive(Debug, Clone)]
+struct RestartState {
+    /// Consecutive crashes so far
+    attempts: u32,
+    /// When the next restart is due, if one is pending
+    due: Option<DateTime<Utc>>,
+    /// When the last restart was attempted
+    last_restart: Option<DateTime<Utc>>,
+}
+
+/// Tracks crash counts and pending restarts per instance
+///
+/// Time is always passed in, so callers (and tests) control the clock.
+#[derive(Debug, Clone, Default)]
+pub struct Supervisor {
+    states: HashMap<String, RestartState>,
+    /// Consecutive over-limit samples per instance
+    over_limit: HashMap<String, u32>,
+}
+
+impl Supervisor {
+    /// Create a supervisor with nothing pending
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record that an instance died (or failed to restart) at `now`
+    pub fn on_crash(
+        &mut self,
+        id: &str,
+        policy: &RestartPolicy,
+        now: DateTime<Utc>,
+    ) -> CrashOutcome {
+        let state = self.states.entry(id.to_string()).or_insert(RestartState {
+            attempts: 0,
+            due: None,
+            last_restart: None,
+        });
+        if state
+            .last_restart
+            .is_some_and(|last| now - last >= STABLE_AFTER)
+        {
+            state.attempts = 0;
+        }
+
+        state.attempts += 1;
+        if state.attempts > policy.max_retries {
+            let attempts = state.attempts - 1;
+            self.states.remove(id);
+            return CrashOutcome::GiveUp { attempts };
+        }
+
+        let at = now + policy.backoff(state.attempts);
+        state.due = Some(at);
+        CrashOutcome::Retry {
+            attempt: state.attempts,
+            at,
+        }
+    }
+
+    /// Instances whose restart is due at `now`, marked as restarted
+    pub fn due(&mut self, now: DateTime<Utc>) -> Vec<String> {
+        let mut due: Vec<String> = self
+            .states
+            .iter_mut()
+            .filter(|(_, state)| state.due.is_some_and(|at| at <= now))
+            .map(|(id, state)| {
+                state.due = None;
+                state.last_restart = Some(now);
+                id.clone()
+            })
+            .collect();
+        due.sort();
+        due
+    }
+
+    /// Whether a restart is pending for an instance
+    pub fn is_pending(&self, id: &str) -> bool {
+        self.states.get(id).is_some_and(|s| s.due.is_some())
+    }
+
+    /// Drop an instance's crash history, e.g. after it was stopped by hand
+    pub fn forget(&mut self, id: &str) {
+        self.states.remove(id);
+    }
+
+    /// Record a resource sample for an instance
+    ///
+    /// Returns true once it has been over its limits for `LIMIT_SAMPLES`
+    /// samples in a row; further over-limit samples in the same streak
+    /// return false, so the action is taken once per streak.
+    pub fn on_sample(&mut self, id: &str, over_limit: bool) -> bool {
+        if !over_limit {
+            self.over_limit.remove(id);
+            return false;
+        }
+        let count = self.over_limit.entry(id.to_string()).or_insert(0);
+        *count += 1;
+        *count == LIMIT_SAMPLES
+    }
+
+    /// Forget over-limit streaks of instances not in `ids`
+    pub fn retain_samples(&mut self, ids: &[String]) {
+        self.over_limit.retain(|id, _| ids.contains(id));
+    }
+
+    /// Start counting an instance's over-limit samples from scratch
+    pub fn reset_samples(&mut self, id: &str) {
+        self.over_limit.remove(id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn at(secs: i64) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2024, 1, 8, 9, 0, 0).unwrap() + Duration::seconds(secs)
+    }
+
+    fn policy(max_retries: u32) -> RestartPolicy {
+        RestartPolicy {
+            policy: RestartMode::OnFailure,
+            max_retries,
+            backoff_ms: 1000,
+            max_backoff_ms: 5000,
+        }
+    }
+
+    #[test]
+    fn test_backoff_doubles_up_to_max() {
+        let policy = policy(10);
+        let delays: Vec<i64> = (1..=5)
+            .map(|n| policy.backoff(n).num_milliseconds())
+            .collect();
+        assert_eq!(delays, vec![1000, 2000, 4000, 5000, 5000]);
+        assert_eq!(policy.backoff(200).num_milliseconds(), 5000);
+    }
+
+    #[test]
+    fn test_policy_from_toml() {
+        let policy: RestartPolicy = toml::from_str(r#"policy = "on-failure""#).unwrap();
+        assert!(policy.enabled());
+        assert_eq!(policy.max_retries, 5);
+        assert_eq!(policy.backoff_ms, 1000);
+        assert!(!RestartPolicy::default().enabled());
+    }
+
+    #[test]
+    fn test_retries_then_gives_up() {
+        let policy = policy(2);
+        let mut supervisor = Supervisor::new();
+
+        assert_eq!(
+            supervisor.on_crash("svc", &policy, at(0)),
+            CrashOutcome::Retry {
+                attempt: 1,
+                at: at(1)
+            }
+        );
+        assert!(supervisor.due(at(0)).is_empty());
+        assert_eq!(supervisor.due(at(1)), vec!["svc"]);
+        assert!(!supervisor.is_pending("svc"));
+
+        // Dies again right away: second attempt, longer backoff
+        assert_eq!(
+            supervisor.on_crash("svc", &policy, at(2)),
+            CrashOutcome::Retry {
+                attempt: 2,
+                at: at(4)
+            }
+        );
+        assert_eq!(supervisor.due(at(4)), vec!["svc"]);
+
+        assert_eq!(
+            supervisor.on_crash("svc", &policy, at(5)),
+            CrashOutcome::GiveUp { attempts: 2 }
+        );
+        assert!(!supervisor.is_pending("svc"));
+    }
+
+    #[test]
+    fn test_limit_streaks() {
+        let mut supervisor = Supervisor::new();
+        assert!(!supervisor.on_sample("svc", true));
+        assert!(!supervisor.on_sample("svc", true));
+        assert!(supervisor.on_sample("svc", true));
+        // Acted on once per streak
+        assert!(!supervisor.on_sample("svc", true));
+
+        // A sample within limits breaks the streak
+        supervisor.on_sample("svc", false);
+        assert!(!supervisor.on_sample("svc", true));
+        assert!(!supervisor.on_sample("svc", true));
+        assert!(supervisor.on_sample("svc", true));
+
+        supervisor.reset_samples("svc");
+        assert!(!supervisor.on_sample("svc", true));
+    }
+
+    #[test]
+    fn test_limit_breach_and_validation() {
+        const MB: u64 = 1024 * 1024;
+        assert_eq!(limit_breach(Some(50.0), Some(100), 10.0, 50 * MB), None);
+        assert_eq!(
+            limit_breach(None, Some(100), 90.0, 150 * MB).as_deref(),
+            Some("memory 150 MB > 100 MB")
+        );
+        assert_eq!(
+            limit_breach(Some(50.0), Some(100), 75.0, 150 * MB).as_deref(),
+            Some("CPU 75.0% > 50%, memory 150 MB > 100 MB")
+        );
+        assert_eq!(limit_breach(None, None, 400.0, 4096 * MB), None);
+
+        assert!(check_limits(Some(150.0), Some(512)).is_ok());
+        assert!(check_limits(Some(0.0), None).is_err());
+        assert!(check_limits(Some(f64::NAN), None).is_err());
+        assert!(check_limits(None, Some(0)).is_err());
+
+        let action: LimitAction = serde_json::from_str("\"restart\"").unwrap();
+        assert_eq!(action, LimitAction::Restart);
+        assert_eq!(LimitAction::default().to_string(), "warn");
+    }
+
+    #[test]
+    fn test_stable_run_resets_attempts() {
+        let policy = policy(1);
+        let mut supervisor = Supervisor::new();
+
+        supervisor.on_crash("svc", &policy, at(0));
+        assert_eq!(supervisor.due(at(1)), vec!["svc"]);
+
+        // Up for longer than STABLE_AFTER: the count starts over
+        assert_eq!(
+            supervisor.on_crash("svc", &policy, at(120)),
+            CrashOutcome::Retry {
+                attempt: 1,
+                at: at(121)
+            }
+        );
+    }
+}
@@ -3,15 +3,18 @@
 //! This crate provides C-compatible exports for integrating USM Core
 //! with Swift (macOS), Python, and other languages via FFI.
 
+use std::cell::RefCell;
 use std::ffi::{c_char, CStr, CString};
 use std::path::Path;
 use std::ptr;
-use std::sync::Arc;
+use std::sync::{Arc, OnceLock};
 
 use libc::c_int;
 use tokio::runtime::Runtime;
 use tokio::sync::RwLock;
+use tracing::warn;
 
+use usm_core::config::ShutdownPolicy;
 use usm_core::{ServiceStatus, UsmCore};
 
 /// Opaque handle to USM Core instance
@@ -32,6 +35,17 @@ pub struct CServiceInfo {
     pub memory_mb: u64,
 }
 
+/// C-compatible metrics for a single instance
+#[repr(C)]
+pub struct CInstanceMetrics {
+    pub cpu_percent: f64,
+    pub memory_bytes: u64,
+    pub memory_percent: f64,
+    pub threads: u32,
+    pub open_files: u32,
+    pub uptime_seconds: u64,
+}
+
 /// Array of service info for C
 #[repr(C)]
 pub struct CServiceArray {
@@ -49,6 +63,85 @@ const STATUS_STARTING: c_int = 3;
 const STATUS_STOPPING: c_int = 4;
 const STATUS_UNKNOWN: c_int = 5;
 
+/// Result of a service control function
+///
+/// On anything but `Ok`, `usm_last_error_message` describes what went wrong.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UsmError {
+    Ok = 0,
+    NotFound = 1,
+    AlreadyRunning = 2,
+    StartFailed = 3,
+    InvalidHandle = 4,
+    InvalidUtf8 = 5,
+    StopFailed = 6,
+    NullArgument = 7,
+}
+
+thread_local! {
+    static LAST_ERROR: RefCell<CString> = RefCell::new(CString::default());
+}
+
+/// Record the message for `usm_last_error_message` and return `code`
+fn fail(code: UsmError, message: impl Into<String>) -> UsmError {
+    let message = CString::new(message.into().replace('\0', " ")).unwrap_or_default();
+    LAST_ERROR.with(|last| *last.borrow_mut() = message);
+    code
+}
+
+/// Clear the last error and return `Ok`
+fn succeed() -> UsmError {
+    LAST_ERROR.with(|last| *last.borrow_mut() = CString::default());
+    UsmError::Ok
+}
+
+/// Classify a core error from a control function
+fn fail_with(e: impl std::fmt::Display, otherwise: UsmError) -> UsmError {
+    let message = e.to_string();
+    let code = if message.contains("not found") {
+        UsmError::NotFound
+    } else {
+        otherwise
+    };
+    fail(code, message)
+}
+
+/// Validate the common arguments of the control functions
+///
+/// # Safety
+/// `handle` and `instance_id` must each be null or valid
+unsafe fn control_args<'a>(
+    handle: *mut UsmHandle,
+    instance_id: *const c_char,
+) -> Result<(&'a UsmHandle, &'a str), UsmError> {
+    if handle.is_null() {
+        return Err(fail(UsmError::InvalidHandle, "Handle is null"));
+    }
+    if instance_id.is_null() {
+        return Err(fail(UsmError::NullArgument, "Instance ID is null"));
+    }
+    match CStr::from_ptr(instance_id).to_str() {
+        Ok(id) => Ok((&*handle, id)),
+        Err(e) => Err(fail(
+            UsmError::InvalidUtf8,
+            format!("Instance ID is not valid UTF-8: {}", e),
+        )),
+    }
+}
+
+/// Convert a string for C, replacing interior NULs with U+FFFD
+///
+/// `CString::new` rejects them; falling back to an empty string would hand
+/// Swift a valid-looking blank id or name instead.
+fn to_c_string(field: &str, value: String) -> CString {
+    if !value.contains('\0') {
+        return CString::new(value).expect("no interior NUL");
+    }
+    warn!(field, value = %value.escape_debug(), "Replacing NUL bytes in FFI string");
+    CString::new(value.replace('\0', "\u{FFFD}")).expect("NULs replaced")
+}
+
 fn status_to_int(status: ServiceStatus) -> c_int {
     match status {
         ServiceStatus::Stopped => STATUS_STOPPED,
@@ -105,6 +198,30 @@ pub unsafe extern "C" fn usm_destroy(handle: *mut UsmHandle) {
     }
 }
 
+/// Destroy a USM Core instance, first stopping managed instances
+///
+/// `policy`: 0 = leave everything running (same as `usm_destroy`),
+/// 1 = stop all running instances, 2 = stop instances tagged "ephemeral".
+///
+/// # Safety
+/// `handle` must be a valid pointer returned by `usm_create`
+#[no_mangle]
+pub unsafe extern "C" fn usm_destroy_with_shutdown(handle: *mut UsmHandle, policy: c_int) {
+    if handle.is_null() {
+        return;
+    }
+    let handle = Box::from_raw(handle);
+    let policy = match policy {
+        1 => ShutdownPolicy::StopAll,
+        2 => ShutdownPolicy::StopEphemeral,
+        _ => ShutdownPolicy::Leave,
+    };
+    handle.runtime.block_on(async {
+        let core = handle.core.read().await.clone();
+        core.shutdown(policy).await;
+    });
+}
+
 /// Get all service instances
 ///
 /// # Safety
@@ -117,17 +234,25 @@ pub unsafe extern "C" fn usm_get_services(handle: *const UsmHandle) -> *mut CSer
 
     let handle = &*handle;
 
+    // Snapshot the instances, then release the locks before the monitor calls
     let instances = handle.runtime.block_on(async {
-        let core = handle.core.read().await;
-        core.list_instances(None).await
+        let core = handle.core.read().await.clone();
+        let mut instances = Vec::new();
+        for instance in core.list_instances(None).await {
+            // Stopped instances report zero usage
+            let usage = core.resource_usage(&instance).await.unwrap_or((0.0, 0));
+            let display_name = core.display_name(&instance).await;
+            instances.push((instance, display_name, usage));
+        }
+        instances
     });
 
     let mut services: Vec<CServiceInfo> = Vec::with_capacity(instances.len());
 
-    for instance in instances {
-        let id = CString::new(instance.id.clone()).unwrap_or_default();
-        let template_id = CString::new(instance.template_id.clone()).unwrap_or_default();
-        let display_name = CString::new(instance.id.clone()).unwrap_or_default();
+    for (instance, display_name, (cpu_percent, memory_bytes)) in instances {
+        let id = to_c_string("id", instance.id);
+        let template_id = to_c_string("template_id", instance.template_id);
+        let display_name = to_c_string("display_name", display_name);
 
         services.push(CServiceInfo {
             id: id.into_raw(),
@@ -135,8 +260,8 @@ pub unsafe extern "C" fn usm_get_services(handle: *const UsmHandle) -> *mut CSer
             display_name: display_name.into_raw(),
             port: instance.port,
             status: status_to_int(instance.status),
-            cpu_percent: 0.0, // TODO: Get from metrics
-            memory_mb: 0,
+            cpu_percent,
+            memory_mb: memory_bytes / (1024 * 1024),
         });
     }
 
@@ -186,32 +311,34 @@ pub unsafe extern "C" fn usm_free_services(array: *mut CServiceArray) {
 
 /// Start a service instance
 ///
+/// Returns `AlreadyRunning` without touching an instance that's running.
+///
 /// # Safety
 /// `handle` must be valid, `instance_id` must be a null-terminated string
 #[no_mangle]
 pub unsafe extern "C" fn usm_start_service(
     handle: *mut UsmHandle,
     instance_id: *const c_char,
-) -> c_int {
-    if handle.is_null() || instance_id.is_null() {
-        return -1;
-    }
-
-    let handle = &*handle;
-    let id = match CStr::from_ptr(instance_id).to_str() {
-        Ok(s) => s,
-        Err(_) => return -1,
+) -> UsmError {
+    let (handle, id) = match control_args(handle, instance_id) {
+        Ok(args) => args,
+        Err(code) => return code,
     };
 
-    let result = handle.runtime.block_on(async {
+    handle.runtime.block_on(async {
         let core = handle.core.read().await;
-        core.start_instance(id).await
-    });
-
-    match result {
-        Ok(_) => 0,
-        Err(_) => -1,
-    }
+        match core.get_instance(id).await {
+            None => fail(UsmError::NotFound, format!("Instance '{}' not found", id)),
+            Some(i) if i.status == ServiceStatus::Running => fail(
+                UsmError::AlreadyRunning,
+                format!("Instance '{}' is already running", id),
+            ),
+            Some(_) => match core.start_instance(id).await {
+                Ok(()) => succeed(),
+                Err(e) => fail_with(e, UsmError::StartFailed),
+            },
+        }
+    })
 }
 
 /// Stop a service instance
@@ -222,26 +349,19 @@ pub unsafe extern "C" fn usm_start_service(
 pub unsafe extern "C" fn usm_stop_service(
     handle: *mut UsmHandle,
     instance_id: *const c_char,
-) -> c_int {
-    if handle.is_null() || instance_id.is_null() {
-        return -1;
-    }
-
-    let handle = &*handle;
-    let id = match CStr::from_ptr(instance_id).to_str() {
-        Ok(s) => s,
-        Err(_) => return -1,
+) -> UsmError {
+    let (handle, id) = match control_args(handle, instance_id) {
+        Ok(args) => args,
+        Err(code) => return code,
     };
 
-    let result = handle.runtime.block_on(async {
+    handle.runtime.block_on(async {
         let core = handle.core.read().await;
-        core.stop_instance(id).await
-    });
-
-    match result {
-        Ok(_) => 0,
-        Err(_) => -1,
-    }
+        match core.stop_instance(id).await {
+            Ok(()) => succeed(),
+            Err(e) => fail_with(e, UsmError::StopFailed),
+        }
+    })
 }
 
 /// Restart a service instance
@@ -252,25 +372,91 @@ pub unsafe extern "C" fn usm_stop_service(
 pub unsafe extern "C" fn usm_restart_service(
     handle: *mut UsmHandle,
     instance_id: *const c_char,
-) -> c_int {
+) -> UsmError {
+    let (handle, id) = match control_args(handle, instance_id) {
+        Ok(args) => args,
+        Err(code) => return code,
+    };
+
+    handle.runtime.block_on(async {
+        let core = handle.core.read().await;
+        match core.restart_instance(id).await {
+            Ok(()) => succeed(),
+            Err(e) => {
+                // An instance still running after a failed restart is one
+                // whose stop failed
+                let running = matches!(
+                    core.get_instance(id).await,
+                    Some(i) if i.status == ServiceStatus::Running
+                );
+                let code = if running {
+                    UsmError::StopFailed
+                } else {
+                    UsmError::StartFailed
+                };
+                fail_with(e, code)
+            },
+        }
+    })
+}
+
+/// Description of the last error from a control function on this thread
+///
+/// Empty after a successful call. The pointer stays valid until the next
+/// control function call on the same thread; copy the string to keep it.
+#[no_mangle]
+pub extern "C" fn usm_last_error_message() -> *const c_char {
+    LAST_ERROR.with(|last| last.borrow().as_ptr())
+}
+
+/// Get metrics for a single instance
+///
+/// Returns null if the instance isn't found or isn't running. A non-null
+/// result must be released with `usm_free_instance_metrics`.
+///
+/// # Safety
+/// `handle` must be valid, `instance_id` must be a null-terminated string
+#[no_mangle]
+pub unsafe extern "C" fn usm_get_instance_metrics(
+    handle: *const UsmHandle,
+    instance_id: *const c_char,
+) -> *mut CInstanceMetrics {
     if handle.is_null() || instance_id.is_null() {
-        return -1;
+        return ptr::null_mut();
     }
 
     let handle = &*handle;
     let id = match CStr::from_ptr(instance_id).to_str() {
         Ok(s) => s,
-        Err(_) => return -1,
+        Err(_) => return ptr::null_mut(),
     };
 
-    let result = handle.runtime.block_on(async {
+    let metrics = handle.runtime.block_on(async {
         let core = handle.core.read().await;
-        core.restart_instance(id).await
+        core.get_instance_metrics(id).await
     });
 
-    match result {
-        Ok(_) => 0,
-        Err(_) => -1,
+    match metrics {
+        Some(m) => Box::into_raw(Box::new(CInstanceMetrics {
+            cpu_percent: m.cpu_percent,
+            memory_bytes: m.memory_bytes,
+            memory_percent: m.memory_percent,
+            threads: m.threads,
+            open_files: m.open_files,
+            uptime_seconds: m.uptime_seconds,
+        })),
+        None => ptr::null_mut(),
+    }
+}
+
+/// Free instance metrics
+///
+/// # Safety
+/// `metrics` must be a valid pointer returned by `usm_get_instance_metrics`
+#[no_mangle]
+pub unsafe extern "C" fn usm_free_instance_metrics(metrics: *mut CInstanceMetrics) {
+    if !metrics.is_null() {
+        let _ = Box::from_raw(metrics);
     }
 }
 
@@ -280,9 +466,28 @@ pub extern "C" fn usm_get_server_port() -> u16 {
     8767 // Default USM Core server port
 }
 
-/// Get version string
+/// Get the USM Core version string, e.g. "0.1.0"
+///
+/// The string lives for the whole process; don't free it.
 #[no_mangle]
 pub extern "C" fn usm_version() -> *const c_char {
-    static VERSION: &[u8] = b"0.1.0\0";
-    VERSION.as_ptr() as *const c_char
+    static VERSION: OnceLock<CString> = OnceLock::new();
+    VERSION
+        .get_or_init(|| to_c_string("version", usm_core::VERSION.to_string()))
+        .as_ptr()
+}
+
+/// Get version, git commit and build time as JSON, the body of
+/// `GET /api/version`
+///
+/// The string lives for the whole process; don't free it.
+#[no_mangle]
+pub extern "C" fn usm_build_info() -> *const c_char {
+    static BUILD_INFO: OnceLock<CString> = OnceLock::new();
+    BUILD_INFO
+        .get_or_init(|| {
+            let json = serde_json::to_string(&usm_core::build_info()).unwrap_or_default();
+            to_c_string("build_info", json)
+        })
+        .as_ptr()
 }